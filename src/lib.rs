@@ -49,6 +49,7 @@ use alloc::string::{String, ToString};
 /// Common types and functions for easy import.
 pub mod prelude {
     pub use crate::load_image;
+    pub use crate::image_size;
     pub use crate::detect_type;
     pub use crate::ImageType;
 }
@@ -102,6 +103,17 @@ pub fn detect_type(data: &[u8]) -> ImageType {
     ImageType::Unknown
 }
 
+/// Reads the native size of an image in pixels. SVG documents report their
+/// root `width`/`height` (or `viewBox` size) rounded up.
+pub fn image_size(data: &[u8]) -> Option<(usize, usize)> {
+    match detect_type(data) {
+        ImageType::Svg => crate::svg::dimensions(data),
+        ImageType::Png => crate::png::dimensions(data),
+        ImageType::Tga => crate::tga::dimensions(data),
+        ImageType::Unknown => None,
+    }
+}
+
 /// Loads and rasterizes an image from raw bytes into a pixel buffer.
 ///
 /// The output is a `Vec<u32>` where each pixel is `0xAARRGGBB` (ARGB).
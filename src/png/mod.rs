@@ -14,6 +14,16 @@ use crate::utils::compat::{Vec, String, ToString, format, vec};
 
 const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
+/// Reads the native size of a PNG from its IHDR chunk.
+pub fn dimensions(data: &[u8]) -> Option<(usize, usize)> {
+    if data.len() < 24 || data[..8] != SIGNATURE || &data[12..16] != b"IHDR" {
+        return None;
+    }
+    let width = u32::from_be_bytes(data[16..20].try_into().ok()?) as usize;
+    let height = u32::from_be_bytes(data[20..24].try_into().ok()?) as usize;
+    Some((width, height))
+}
+
 /// Renders a PNG byte stream into a pixel buffer.
 ///
 /// # Arguments
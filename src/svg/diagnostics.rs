@@ -0,0 +1,55 @@
+//! Problems found while reading a document.
+
+use core::fmt;
+use crate::utils::compat::String;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The document breaks a rule of XML or SVG; the affected construct is
+    /// repaired or ignored.
+    Error,
+    /// The document is valid but something in it can't be honoured, such
+    /// as a reference to a missing element.
+    Warning,
+}
+
+/// How much of a malformed document to accept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    /// Fail on the first error.
+    Strict,
+    /// Recover from errors, report them, and render what remains.
+    #[default]
+    Lenient,
+}
+
+/// One problem, located by the path of the element it concerns (e.g.
+/// `/svg/g[2]/rect`) and by 1-based line and column in the source. Columns
+/// count characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub path: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        })
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} at line {}, column {}", self.severity, self.message, self.line, self.column)?;
+        if !self.path.is_empty() {
+            write!(f, " ({})", self.path)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,342 @@
+//! A parsed SVG document that can be inspected, edited and rendered repeatedly.
+
+use core::cell::{Ref, RefCell};
+use crate::utils::compat::{ToString, Vec};
+use crate::svg::ImageResolver;
+use crate::svg::diagnostics::{Diagnostic, ParseMode};
+use crate::svg::parser;
+use crate::svg::parser::tags::Tag;
+use crate::svg::rasterizer::canva::Canvas;
+use crate::svg::rasterizer::tags::text::Font;
+use crate::svg::tree::Node;
+use crate::svg::utils::coords::{Axis, Length, LengthContext, Unit, DEFAULT_DPI, DEFAULT_FONT_SIZE};
+use crate::svg::utils::data_uri::encode_data_uri;
+use crate::svg::utils::collect_defs;
+use crate::svg::utils::transform::{view_box_transform, Transform};
+
+/// Options for [`SvgDocument::render`].
+#[derive(Debug, Clone, Copy)]
+pub struct RenderOptions {
+    /// ARGB color the image is drawn over; `0` for a transparent background.
+    pub background: u32,
+    /// User units per inch, which absolute units such as `mm` and `pt` convert with.
+    pub dpi: f32,
+    /// Font size `em` and `rem` refer to when the document sets none.
+    pub font_size: f32,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self { background: 0xFFFF_FFFF, dpi: DEFAULT_DPI, font_size: DEFAULT_FONT_SIZE }
+    }
+}
+
+/// An SVG document parsed once, to be queried, changed and rendered at any
+/// size without parsing it again. The rendering tree is kept between renders
+/// at the same size and converted again after the document is changed.
+///
+/// ```
+/// use asvgard::svg::{RenderOptions, SvgDocument};
+///
+/// let mut document = SvgDocument::parse(br#"<svg viewBox="0 0 10 10"><rect id="r" width="10" height="10"/></svg>"#).unwrap();
+/// document.set_attribute("r", "fill", "red");
+/// let small = document.render(32, 32, &RenderOptions::default());
+/// let large = document.render(512, 512, &RenderOptions::default());
+/// ```
+#[derive(Debug, Clone)]
+pub struct SvgDocument {
+    root: Tag,
+    /// Fonts added for text, in order of preference.
+    fonts: Vec<Font>,
+    /// The last rendering tree and the context it was converted in.
+    tree: RefCell<Option<(LengthContext, Option<Node>)>>,
+}
+
+impl SvgDocument {
+    /// Parses a document, repairing broken markup the way browsers
+    /// tolerate it. Use [`parse_with`](Self::parse_with) to reject it
+    /// instead, or to find out what was repaired.
+    pub fn parse(data: &[u8]) -> Result<Self, Diagnostic> {
+        Self::parse_with(data, ParseMode::Lenient, &mut Vec::new())
+    }
+
+    /// Parses a document like [`parser::parse::parse_xml_with`], adding
+    /// warnings and recovered errors to `diagnostics`.
+    pub fn parse_with(data: &[u8], mode: ParseMode, diagnostics: &mut Vec<Diagnostic>) -> Result<Self, Diagnostic> {
+        let mut roots = parser::parse::parse_xml_with(data, mode, diagnostics)?;
+        Ok(Self::from_root(roots.swap_remove(0)))
+    }
+
+    /// Wraps an element tree built by hand or taken from another document.
+    pub fn from_root(root: Tag) -> Self {
+        Self { root, fonts: Vec::new(), tree: RefCell::new(None) }
+    }
+
+    pub fn root(&self) -> &Tag {
+        &self.root
+    }
+
+    pub fn root_mut(&mut self) -> &mut Tag {
+        self.invalidate();
+        &mut self.root
+    }
+
+    pub fn into_root(self) -> Tag {
+        self.root
+    }
+
+    /// Adds a font for text to be set in. Text uses it for the families it
+    /// names in `font-family`, and otherwise for characters its requested
+    /// fonts lack, before the fonts added after it and the bundled font.
+    pub fn add_font(&mut self, font: Font) {
+        self.invalidate();
+        self.fonts.push(font);
+    }
+
+    pub fn fonts(&self) -> &[Font] {
+        &self.fonts
+    }
+
+    /// The first element in document order with the given `id`.
+    pub fn element_by_id(&self, id: &str) -> Option<&Tag> {
+        find(&self.root, id)
+    }
+
+    pub fn element_by_id_mut(&mut self, id: &str) -> Option<&mut Tag> {
+        self.invalidate();
+        find_mut(&mut self.root, id)
+    }
+
+    /// Sets an attribute on the element with the given `id`, returning
+    /// `false` when there is no such element.
+    pub fn set_attribute(&mut self, id: &str, name: &str, value: &str) -> bool {
+        self.element_by_id_mut(id)
+            .map(|tag| tag.set_attribute(name, value))
+            .is_some()
+    }
+
+    /// The root `viewBox`, when it is valid.
+    pub fn view_box(&self) -> Option<[f32; 4]> {
+        self.root.view_box()
+    }
+
+    /// Intrinsic size: the root `width` and `height` at the default DPI and
+    /// font size, falling back to the `viewBox` size.
+    pub fn size(&self) -> Option<(f32, f32)> {
+        let view_box = self.view_box();
+        let lengths = LengthContext::default();
+        let length = |key: &str, axis: Axis, index: usize| root_length(&self.root, key, axis, &lengths)
+            .or(view_box.map(|vb| vb[index]))
+            .filter(|v| *v > 0.0);
+        Some((length("width", Axis::Horizontal, 2)?, length("height", Axis::Vertical, 3)?))
+    }
+
+    /// Checks attribute values and references, as
+    /// [`parser::validate::validate`] does.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        parser::validate::validate(core::slice::from_ref(&self.root))
+    }
+
+    /// Replaces external `<image>` hrefs with `data:` URIs of the bytes
+    /// `resolver` returns, so that later renders don't need it.
+    pub fn resolve_images(&mut self, resolver: ImageResolver) {
+        self.invalidate();
+        inline_images(&mut self.root, resolver);
+    }
+
+    /// The simplified rendering tree for a `width` x `height` pixel canvas.
+    pub fn tree(&self, width: f32, height: f32, options: &RenderOptions) -> Option<Node> {
+        self.cached_tree(&self.lengths(width, height, options)).clone()
+    }
+
+    /// Renders the document into a `width` x `height` pixel buffer.
+    pub fn render(&self, width: usize, height: usize, options: &RenderOptions) -> Vec<u32> {
+        let mut canvas = Canvas::with_background(width, height, options.background);
+        let lengths = self.lengths(width as f32, height as f32, options);
+        if let Some(tree) = &*self.cached_tree(&lengths) {
+            let transform = get_svg_transform(&self.root, width, height, lengths.viewport);
+            canvas.draw_node(tree, &collect_defs(&self.root), &transform);
+        }
+
+        canvas.data
+    }
+
+    /// The rendering tree in `lengths`, converted again only when the
+    /// document changed or was last rendered in another context.
+    fn cached_tree(&self, lengths: &LengthContext) -> Ref<'_, Option<Node>> {
+        let current = matches!(&*self.tree.borrow(), Some((cached, _)) if cached == lengths);
+        if !current {
+            let tree = Node::from_root(&self.root, &collect_defs(&self.root), &self.fonts, lengths);
+            *self.tree.borrow_mut() = Some((*lengths, tree));
+        }
+        Ref::map(self.tree.borrow(), |cache| match cache {
+            Some((_, tree)) => tree,
+            None => &None,
+        })
+    }
+
+    /// Drops the cached tree before the document is changed.
+    fn invalidate(&mut self) {
+        *self.tree.get_mut() = None;
+    }
+
+    /// The context of the root element's parent: its viewport is the
+    /// `viewBox`, else the root `width` and `height` in user units, else the
+    /// canvas.
+    fn lengths(&self, width: f32, height: f32, options: &RenderOptions) -> LengthContext {
+        let canvas = LengthContext {
+            dpi: options.dpi,
+            font_size: options.font_size,
+            root_font_size: options.font_size,
+            viewport: (width, height),
+            root_viewport: (width, height),
+        };
+        let (width, height) = match self.view_box() {
+            Some(view_box) => (view_box[2], view_box[3]),
+            None => (
+                root_length(&self.root, "width", Axis::Horizontal, &canvas).unwrap_or(width),
+                root_length(&self.root, "height", Axis::Vertical, &canvas).unwrap_or(height),
+            ),
+        };
+
+        let lengths = LengthContext { viewport: (width, height), root_viewport: (width, height), ..canvas };
+        LengthContext { root_font_size: lengths.for_element(&self.root).font_size, ..lengths }
+    }
+}
+
+fn find<'a>(tag: &'a Tag, id: &str) -> Option<&'a Tag> {
+    if tag.id() == Some(id) {
+        return Some(tag);
+    }
+    tag.children.iter().find_map(|child| find(child, id))
+}
+
+fn find_mut<'a>(tag: &'a mut Tag, id: &str) -> Option<&'a mut Tag> {
+    if tag.id() == Some(id) {
+        return Some(tag);
+    }
+    tag.children.iter_mut().find_map(|child| find_mut(child, id))
+}
+
+/// Replaces external `<image>` hrefs with `data:` URIs of the resolved bytes,
+/// so images inside `<use>`, patterns and markers render too.
+fn inline_images(tag: &mut Tag, resolver: ImageResolver) {
+    if tag.name == "image" {
+        for key in ["href", "xlink:href"] {
+            if let Some(href) = tag.params.get(key)
+                && !href.trim_start().starts_with("data:")
+                && let Some(bytes) = resolver(href.trim())
+            {
+                tag.params.insert(key.to_string(), encode_data_uri(&bytes));
+            }
+        }
+    }
+
+    for child in &mut tag.children {
+        inline_images(child, resolver);
+    }
+}
+
+/// The root `width` or `height` in user units. Percentages are of the
+/// canvas, so they count as unset.
+fn root_length(root: &Tag, key: &str, axis: Axis, lengths: &LengthContext) -> Option<f32> {
+    root.attribute(key)
+        .and_then(Length::parse)
+        .filter(|length| length.unit != Unit::Percent)
+        .map(|length| lengths.resolve(length, axis))
+        .filter(|v| *v > 0.0)
+}
+
+/// Maps the root user space onto the canvas through the `viewBox` and
+/// `preserveAspectRatio`, as any viewport is; `viewport` is its size in user
+/// units when there is no `viewBox`.
+fn get_svg_transform(svg_tag: &Tag, canvas_width: usize, canvas_height: usize, viewport: (f32, f32)) -> Transform {
+    let (width, height) = (canvas_width as f32, canvas_height as f32);
+    if let Some(view_box) = svg_tag.view_box() {
+        return view_box_transform(view_box, width, height, svg_tag.attribute("preserveAspectRatio"));
+    }
+
+    let (svg_width, svg_height) = viewport;
+    Transform::scale(width / svg_width, height / svg_height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svg::rasterizer::tags::text::test_font::FontBuilder;
+
+    fn render(svg: &str, width: usize, height: usize) -> Vec<u32> {
+        SvgDocument::parse(svg.as_bytes()).unwrap().render(width, height, &RenderOptions::default())
+    }
+
+    #[test]
+    fn test_root_view_box_is_centered_by_default() {
+        let pixels = render(r#"<svg viewBox="0,0,10,5"><rect width="10" height="5" fill="red"/></svg>"#, 10, 10);
+        assert_eq!(pixels[10], 0xFFFFFFFF);
+        assert_eq!(pixels[5 * 10], 0xFFFF0000);
+        assert_eq!(pixels[9 * 10], 0xFFFFFFFF);
+    }
+
+    #[test]
+    fn test_root_preserve_aspect_ratio() {
+        let svg = r#"<svg viewBox="0 0 10 5" preserveAspectRatio="xMinYMin slice"><rect width="2" height="5" fill="red"/></svg>"#;
+        let pixels = render(svg, 10, 10);
+        assert_eq!(pixels[9 * 10], 0xFFFF0000);
+        assert_eq!(pixels[9 * 10 + 9], 0xFFFFFFFF);
+
+        let stretched = render(&svg.replace("xMinYMin slice", "none"), 10, 10);
+        assert_eq!(stretched[9 * 10 + 1], 0xFFFF0000);
+        assert_eq!(stretched[9 * 10 + 2], 0xFFFFFFFF);
+    }
+
+    #[test]
+    fn test_edits_to_referenced_elements_show_in_the_next_render() {
+        let mut document = SvgDocument::parse(br##"<svg width="4" height="4">
+            <linearGradient id="g"><stop id="s" offset="0" stop-color="red"/></linearGradient>
+            <rect width="4" height="4" fill="url(#g)"/>
+        </svg>"##).unwrap();
+        assert_eq!(document.render(4, 4, &RenderOptions::default())[5], 0xFFFF0000);
+
+        assert!(document.set_attribute("s", "stop-color", "blue"));
+        assert_eq!(document.render(4, 4, &RenderOptions::default())[5], 0xFF0000FF);
+    }
+
+    #[test]
+    fn test_tree_is_kept_until_the_document_changes() {
+        let mut document = SvgDocument::parse(br#"<svg><rect id="r" width="50%" height="4" fill="red"/></svg>"#).unwrap();
+        let options = RenderOptions::default();
+        assert!(document.tree.borrow().is_none());
+        assert_eq!(document.render(4, 4, &options)[1], 0xFFFF0000);
+        assert!(document.tree.borrow().is_some());
+
+        // Another size converts the percentages again
+        let wide = document.render(8, 4, &options);
+        assert_eq!((wide[3], wide[4]), (0xFFFF0000, 0xFFFFFFFF));
+
+        assert!(document.set_attribute("r", "fill", "blue"));
+        assert!(document.tree.borrow().is_none());
+        assert_eq!(document.render(8, 4, &options)[3], 0xFF0000FF);
+
+        document.root_mut().children.clear();
+        assert_eq!(document.render(8, 4, &options)[3], 0xFFFFFFFF);
+    }
+
+    #[test]
+    fn test_added_fonts_set_text_before_the_bundled_font() {
+        // The added font has an `M` without an outline, so it draws nothing
+        let font = FontBuilder::new(2, 500).family("Blank").map(&[('M', 1)]).font();
+        assert_eq!(font.families(), ["Blank"]);
+        let options = RenderOptions::default();
+        let drawn = |document: &SvgDocument| document.render(20, 20, &options).iter().any(|&p| p != 0xFFFFFFFF);
+
+        let mut document = SvgDocument::parse(br#"<svg><text y="15" font-size="16">M</text></svg>"#).unwrap();
+        assert!(drawn(&document));
+        document.add_font(font.clone());
+        assert!(!drawn(&document));
+
+        // Characters the font lacks fall back to the bundled font
+        let mut document = SvgDocument::parse(br#"<svg><text y="15" font-size="16">W</text></svg>"#).unwrap();
+        document.add_font(font);
+        assert!(drawn(&document));
+    }
+}
@@ -3,87 +3,148 @@
 //! Handles parsing of SVG XML data into a tree of tags, and then rasterizing 
 //! those tags (paths, shapes) onto a pixel buffer.
 
+pub mod diagnostics;
+pub mod document;
 pub mod parser;
 pub mod rasterizer;
+pub mod tree;
 pub mod utils;
+#[cfg(test)]
+pub(crate) mod testing;
 
-use crate::utils::compat::{HashMap, String, ToString, Vec};
-use crate::svg::parser::tags::Tag;
-use crate::svg::utils::transform::Transform;
-use crate::svg::utils::get_id;
-use crate::svg::rasterizer::canva::Canvas;
+pub use document::{RenderOptions, SvgDocument};
+pub use rasterizer::tags::path::PathCommand;
+pub use rasterizer::tags::text::{text_to_path, Font};
+
+use crate::utils::compat::{format, String, Vec};
+use crate::svg::diagnostics::{Diagnostic, ParseMode, Severity};
+#[cfg(not(feature = "std"))]
 use crate::utils::compat::FloatExt;
 
+/// Loads the bytes behind an external `<image>` href, or `None` to leave it unrendered.
+pub type ImageResolver<'a> = &'a dyn Fn(&str) -> Option<Vec<u8>>;
+
 /// Renders an SVG byte stream into a pixel buffer.
 ///
+/// Markup errors are repaired leniently; use [`render_with_diagnostics`]
+/// with [`ParseMode::Strict`] to reject malformed documents.
+///
 /// # Arguments
 ///
 /// * `data` - Raw SVG file bytes (XML text).
 /// * `width` - Target width.
 /// * `height` - Target height.
 pub fn render(data: &[u8], width: usize, height: usize) -> Result<Vec<u32>, String> {
-    let mut svg_tags = parser::parse::load_xml(data);
-    if svg_tags.is_empty() {
-        return Err("No SVG tags found or invalid XML".to_string());
-    }
-    
-    // Render at requested resolution directly
-    let mut canvas = Canvas::new(width, height);
-    let mut defs_map: HashMap<String, Tag> = HashMap::new();
-    
-    traverse_recursive(&mut defs_map, &svg_tags[0]);
-    
-    let transform = get_svg_transform(&svg_tags[0], width, height);
-    
-    for tag in &mut svg_tags {
-        canvas.draw(tag, &defs_map, &transform);
-    }
-    
-    Ok(canvas.data)
+    Ok(parse(data)?.render(width, height, &RenderOptions::default()))
 }
 
-fn traverse_recursive(defs: &mut HashMap<String, Tag>, start: &Tag) {
-    if let Some(id) = get_id(start) {
-        defs.insert(id.clone(), start.clone());
-    }
+/// Renders an SVG byte stream like [`render`], loading `<image>` hrefs that
+/// are not `data:` URIs through `resolver`.
+pub fn render_with_resolver(data: &[u8], width: usize, height: usize, resolver: ImageResolver) -> Result<Vec<u32>, String> {
+    let mut document = parse(data)?;
+    document.resolve_images(resolver);
+    Ok(document.render(width, height, &RenderOptions::default()))
+}
+
+/// Intrinsic size of an SVG document: the root `width` and `height`, falling
+/// back to the `viewBox` size.
+pub fn dimensions(data: &[u8]) -> Option<(usize, usize)> {
+    let (width, height) = SvgDocument::parse(data).ok()?.size()?;
+    Some((width.ceil() as usize, height.ceil() as usize))
+}
 
-    for child in &start.children {
-        traverse_recursive(defs, child);
+/// Renders like [`render`], also checking attribute values and reporting
+/// every problem found.
+///
+/// In [`ParseMode::Strict`] any error fails the render, returning the
+/// diagnostics. In [`ParseMode::Lenient`] broken markup is repaired and
+/// invalid values fall back to their defaults, and the image comes back
+/// with the diagnostics; only a document without a root element fails.
+pub fn render_with_diagnostics(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    mode: ParseMode,
+) -> Result<(Vec<u32>, Vec<Diagnostic>), Vec<Diagnostic>> {
+    let mut diagnostics = Vec::new();
+    let document = match SvgDocument::parse_with(data, mode, &mut diagnostics) {
+        Ok(document) => document,
+        Err(error) => {
+            diagnostics.push(error);
+            return Err(diagnostics);
+        }
+    };
+
+    diagnostics.extend(document.validate());
+    if mode == ParseMode::Strict && diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        return Err(diagnostics);
     }
+    Ok((document.render(width, height, &RenderOptions::default()), diagnostics))
 }
 
-fn get_svg_transform(svg_tag: &Tag, canvas_width: usize, canvas_height: usize) -> Transform {
-    let viewbox = svg_tag.params.get("viewBox");
+fn parse(data: &[u8]) -> Result<SvgDocument, String> {
+    SvgDocument::parse(data).map_err(|e| format!("Invalid XML: {}", e))
+}
 
-    if let Some(vb) = viewbox {
-        let parts: Vec<f32> = vb.split_whitespace()
-            .filter_map(|s| s.parse().ok())
-            .collect();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::compat::ToString;
 
-        if parts.len() == 4 {
-            let vb_x = parts[0];
-            let vb_y = parts[1];
-            let vb_width = parts[2];
-            let vb_height = parts[3];
+    /// Markup found in editor exports and hand-written files that isn't
+    /// well-formed XML.
+    const SLOPPY: [&str; 4] = [
+        r#"<svg width="4" height="4"><text>a&nbsp;b</text><rect width="4" height="4" fill="red"/></svg>"#,
+        r#"<svg width="4" height="4" sodipodi:docname="x.svg"><rect inkscape:label="r" width="4" height="4" fill="red"/></svg>"#,
+        r#"<svg width=4 height=4><rect width=4 height=4 fill=red/></svg>"#,
+        r#"<svg width="4" height="4"><title>R & D</title><rect width="4" height="4" fill="red"/></svg>"#,
+    ];
 
-            let scale_x = canvas_width as f32 / vb_width;
-            let scale_y = canvas_height as f32 / vb_height;
-            let scale = scale_x.min(scale_y);
+    #[test]
+    fn test_convenience_entry_points_are_lenient() {
+        for svg in SLOPPY {
+            assert_eq!(render(svg.as_bytes(), 4, 4).unwrap(), [0xFFFF0000; 16], "{}", svg);
+            assert_eq!(crate::load_image(svg.as_bytes(), 4, 4).unwrap(), [0xFFFF0000; 16], "{}", svg);
+            assert_eq!(dimensions(svg.as_bytes()), Some((4, 4)));
+        }
+    }
 
-            return Transform::scale(scale, scale)
-                .then(&Transform::translate(-vb_x * scale, -vb_y * scale));
+    #[test]
+    fn test_strict_parsing_is_opt_in() {
+        for svg in SLOPPY {
+            assert!(render_with_diagnostics(svg.as_bytes(), 4, 4, ParseMode::Strict).is_err(), "{}", svg);
+            let (pixels, diagnostics) = render_with_diagnostics(svg.as_bytes(), 4, 4, ParseMode::default()).unwrap();
+            assert_eq!(pixels, [0xFFFF0000; 16]);
+            assert!(diagnostics.iter().any(|d| d.severity == Severity::Error), "{}", svg);
         }
     }
 
-    let svg_width = svg_tag.params.get("width")
-        .and_then(|w| w.parse::<f32>().ok())
-        .unwrap_or(canvas_width as f32);
-    let svg_height = svg_tag.params.get("height")
-        .and_then(|h| h.parse::<f32>().ok())
-        .unwrap_or(canvas_height as f32);
+    #[test]
+    fn test_diagnostics_locate_problems() {
+        let svg = b"<svg width=\"4\" height=\"4\">\n  <rect width=4 height=\"4\"/>\n  <circle r=\"-1\" fill=\"url(#none)\"/>\n</svg>";
+        let location = |d: &Diagnostic| (d.severity, d.path.clone(), d.line, d.column);
 
-    let scale_x = canvas_width as f32 / svg_width;
-    let scale_y = canvas_height as f32 / svg_height;
+        // Lenient parsing reports everything and still renders
+        let (_, diagnostics) = render_with_diagnostics(svg, 4, 4, ParseMode::Lenient).unwrap();
+        assert_eq!(diagnostics.iter().map(location).collect::<Vec<_>>(), [
+            (Severity::Error, String::from("/svg/rect"), 2, 15),
+            (Severity::Warning, String::from("/svg/circle"), 3, 3),
+            (Severity::Error, String::from("/svg/circle"), 3, 3),
+        ]);
+        assert_eq!(diagnostics[0].message, "expected a quoted value");
 
-    Transform::scale(scale_x, scale_y)
+        // Strict parsing stops at the first markup error
+        let errors = render_with_diagnostics(svg, 4, 4, ParseMode::Strict).unwrap_err();
+        assert_eq!(errors.iter().map(location).collect::<Vec<_>>(), [(Severity::Error, String::from("/svg/rect"), 2, 15)]);
+
+        // Invalid values in well-formed markup fail strict rendering too,
+        // with the warnings
+        let fixed = b"<svg width=\"4\" height=\"4\">\n  <circle r=\"-1\" fill=\"url(#none)\"/>\n</svg>";
+        let errors = render_with_diagnostics(fixed, 4, 4, ParseMode::Strict).unwrap_err();
+        assert_eq!(errors.iter().map(location).collect::<Vec<_>>(), [
+            (Severity::Warning, String::from("/svg/circle"), 2, 3),
+            (Severity::Error, String::from("/svg/circle"), 2, 3),
+        ]);
+        assert_eq!(errors[1].to_string(), "error: `r` must not be negative at line 2, column 3 (/svg/circle)");
+    }
 }
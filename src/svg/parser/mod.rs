@@ -1,2 +1,3 @@
 pub mod tags;
-pub mod parse;
\ No newline at end of file
+pub mod parse;
+pub mod validate;
\ No newline at end of file
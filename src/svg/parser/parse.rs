@@ -1,167 +1,1066 @@
-use crate::svg::parser::tags::Tag;
-use crate::utils::compat::{Vec, String, ToString};
+use crate::svg::diagnostics::{Diagnostic, ParseMode, Severity};
+use crate::svg::parser::tags::{Tag, TEXT_NODE};
+use crate::utils::compat::{HashMap, String, ToString, Vec, format};
 
+const SVG_NAMESPACE: &str = "http://www.w3.org/2000/svg";
+const XLINK_NAMESPACE: &str = "http://www.w3.org/1999/xlink";
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// Deepest chain of entity references expanded inside one another.
+const MAX_ENTITY_DEPTH: usize = 16;
+/// Most text entity expansion may add to one document, so a handful of
+/// nested declarations can't blow up into gigabytes.
+const MAX_ENTITY_EXPANSION: usize = 1 << 20;
+/// Deepest nesting of elements. Everything that walks the tree recurses, so
+/// deeper documents are rejected rather than risk overflowing the stack.
+const MAX_ELEMENT_DEPTH: usize = 256;
+
+/// Parses a document leniently, returning its root element. Errors are
+/// swallowed; use [`parse_xml_with`] to find out what went wrong.
 pub fn load_xml(data: &[u8]) -> Vec<Tag> {
-    let (_, total_tags, _) = load_xml_recursive(data, 0);
-    total_tags
+    parse_xml_with(data, ParseMode::Lenient, &mut Vec::new()).unwrap_or_default()
 }
 
-pub fn load_xml_recursive(data: &[u8], idx: usize) -> (usize, Vec<Tag>, String) {
-    let mut name = Vec::new();
-    let mut name_start = false;
-    let mut params_start = false;
-    let mut text_buffer = String::new();
-    let mut parent_content = String::new();
+/// Parses a document as a non-validating XML processor would, returning
+/// its root element, or the first well-formedness error.
+///
+/// Character and internally declared entity references are expanded,
+/// CDATA sections become text, and comments and processing instructions
+/// are dropped. Prefixes bound to the SVG namespace are removed from
+/// names and XLink attributes are always named `xlink:*`, whatever prefix
+/// the document used.
+pub fn parse_xml(data: &[u8]) -> Result<Vec<Tag>, Diagnostic> {
+    parse_xml_with(data, ParseMode::Strict, &mut Vec::new())
+}
 
-    let mut total_tags: Vec<Tag> = Vec::new();
-    let mut tag = Tag::new();
+/// Parses a document like [`parse_xml`], adding warnings, and in lenient
+/// mode the errors it recovered from, to `diagnostics`.
+///
+/// Lenient parsing closes unclosed or misnested elements, keeps
+/// undefined entity references as text, accepts unquoted and duplicate
+/// attributes, and keeps whatever was read before a fatal error. It fails
+/// only when no root element could be read.
+pub fn parse_xml_with(data: &[u8], mode: ParseMode, diagnostics: &mut Vec<Diagnostic>) -> Result<Vec<Tag>, Diagnostic> {
+    let mut context = Context {
+        mode,
+        diagnostics,
+        entities: HashMap::new(),
+        frames: Vec::new(),
+        namespaces: Vec::new(),
+        expanding: Vec::new(),
+        expanded: 0,
+        lines: (0, 1, 1),
+    };
+    let text = decode(data, &mut context)?;
+    let mut reader = Reader { doc: &text, src: &text, pos: 0, origin: None };
+
+    parse_prolog(&mut reader, &mut context)?;
+    loop {
+        if reader.eof() {
+            return reader.error("missing root element");
+        }
+        if reader.starts_with("<") && reader.rest()[1..].starts_with(is_name_start_char) {
+            break;
+        }
+        let error = reader.diagnostic(reader.pos, "expected the root element");
+        context.report(error)?;
+        // Skip to the next thing that looks like a start tag
+        reader.pos += 1;
+        reader.pos += reader.rest().find('<').unwrap_or(reader.rest().len());
+    }
+
+    context.frames.push(Frame::new(Tag::new(), String::new(), 0, String::new()));
+    let parsed = parse_content(&mut reader, &mut context, 1, false).and_then(|()| parse_epilog(&mut reader, &mut context));
+    if let Err(error) = parsed {
+        // Keep the elements read so far
+        context.report(error)?;
+        while context.frames.len() > 1 {
+            context.close_frame();
+        }
+    }
+
+    let root: Vec<Tag> = context.frames.pop()
+        .map(|frame| frame.tag.children.into_iter().filter(|t| !t.is_text_node()).collect())
+        .unwrap_or_default();
+    if root.is_empty() {
+        return reader.error("missing root element");
+    }
+    Ok(root)
+}
+
+/// What follows the root element: only comments, processing instructions
+/// and whitespace.
+fn parse_epilog(r: &mut Reader, context: &mut Context) -> Result<(), Diagnostic> {
+    loop {
+        r.skip_space();
+        if r.eof() {
+            return Ok(());
+        } else if r.eat("<!--") {
+            parse_comment(r, context)?;
+        } else if r.eat("<?") {
+            parse_pi(r)?;
+        } else {
+            return r.error("content after the root element");
+        }
+    }
+}
 
-    let mut i = idx;
-    while i < data.len() {
-        if data[i] == '<' as u8 {
-            if !text_buffer.is_empty() {
-                 parent_content.push_str(&text_buffer);
-                 text_buffer.clear();
+/// Elements whose character data is rendered and must keep its position
+/// relative to child elements (e.g. `<text>Hello <tspan>world</tspan></text>`).
+fn keeps_text_nodes(name: &str) -> bool {
+    matches!(name, "text" | "tspan" | "textPath" | "a")
+}
+
+/// Decodes the document to text following its byte order mark or
+/// encoding declaration, with line ends normalized to `\n`. Undecodable
+/// bytes become U+FFFD.
+fn decode(data: &[u8], context: &mut Context) -> Result<String, Diagnostic> {
+    let (text, problem) = match data {
+        [0xEF, 0xBB, 0xBF, rest @ ..] => utf8(rest),
+        [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        // `<?` without a byte order mark
+        [0x3C, 0x00, 0x3F, 0x00, ..] => utf16(data, u16::from_le_bytes),
+        [0x00, 0x3C, 0x00, 0x3F, ..] => utf16(data, u16::from_be_bytes),
+        _ => match declared_encoding(data).map(|e| e.to_ascii_lowercase()).as_deref() {
+            None | Some("utf-8" | "utf8") => utf8(data),
+            Some("us-ascii" | "ascii" | "iso-8859-1" | "latin1" | "iso_8859-1") => {
+                (data.iter().map(|&b| b as char).collect(), None)
             }
+            Some(other) => {
+                let (text, _) = utf8(data);
+                (text, Some(error_at(&format!("unsupported encoding `{}`", other), 1, 1)))
+            }
+        },
+    };
+    if let Some(problem) = problem {
+        context.report(problem)?;
+    }
 
-            if data[i + 1] == '/' as u8 {
-                if tag.name != "" {
-                    total_tags.push(tag.clone());
-                    tag.clear();
-                }
+    if !text.contains('\r') {
+        return Ok(text);
+    }
+    Ok(text.replace("\r\n", "\n").replace('\r', "\n"))
+}
 
-                while i < data.len() && data[i] != '>' as u8 {
-                    i += 1;
-                }
-                
-                return (i + 1, total_tags, parent_content.trim().to_string());
+fn utf8(data: &[u8]) -> (String, Option<Diagnostic>) {
+    match core::str::from_utf8(data) {
+        Ok(text) => (text.to_string(), None),
+        Err(e) => {
+            let valid = core::str::from_utf8(&data[..e.valid_up_to()]).unwrap_or_default();
+            let (line, column) = line_column(valid, valid.len());
+            (String::from_utf8_lossy(data).into_owned(), Some(error_at("invalid UTF-8", line, column)))
+        }
+    }
+}
 
-            } else if data[i + 1] == '!' as u8 {
-                while i < data.len() && data[i] != '>' as u8 {
-                    i += 1;
+fn utf16(data: &[u8], unit: fn([u8; 2]) -> u16) -> (String, Option<Diagnostic>) {
+    let units = data.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    let mut text = String::with_capacity(data.len() / 2);
+    let mut problem = None;
+    for c in char::decode_utf16(units) {
+        match c {
+            Ok(c) => text.push(c),
+            Err(_) => {
+                if problem.is_none() {
+                    let (line, column) = line_column(&text, text.len());
+                    problem = Some(error_at("invalid UTF-16", line, column));
                 }
-            } else {
-                name_start = true;
-                params_start = false;
+                text.push(char::REPLACEMENT_CHARACTER);
             }
+        }
+    }
+    (text, problem)
+}
 
-        } else if name_start {
-            if data[i] == ' ' as u8 || data[i] == '>' as u8 {
-                name_start = false;
-                tag.name = sanitize(String::from_utf8(name.clone()).unwrap());
-                name.clear();
+/// The `encoding` pseudo-attribute of an ASCII-compatible XML declaration.
+fn declared_encoding(data: &[u8]) -> Option<&str> {
+    let head = data.strip_prefix(b"<?xml")?;
+    let end = head.windows(2).position(|w| w == b"?>")?;
+    let decl = core::str::from_utf8(&head[..end]).ok()?;
+    let value = decl[decl.find("encoding")? + 8..].trim_start().strip_prefix('=')?.trim_start();
+    let quote = value.chars().next().filter(|&q| q == '"' || q == '\'')?;
+    let value = &value[1..];
+    Some(&value[..value.find(quote)?])
+}
 
-                if data[i] == '>' as u8 {
-                    params_start = false;
-                    i -= 1;
-                } else {
-                    params_start = true;
-                }
-            } else {
-                name.push(data[i]);
+/// 1-based line and column of byte `offset` in `text`.
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+fn error_at(message: &str, line: usize, column: usize) -> Diagnostic {
+    Diagnostic { severity: Severity::Error, message: message.to_string(), path: String::new(), line, column }
+}
+
+fn is_name_start_char(c: char) -> bool {
+    matches!(c,
+        ':' | 'A'..='Z' | '_' | 'a'..='z' | '\u{C0}'..='\u{D6}' | '\u{D8}'..='\u{F6}'
+        | '\u{F8}'..='\u{2FF}' | '\u{370}'..='\u{37D}' | '\u{37F}'..='\u{1FFF}'
+        | '\u{200C}'..='\u{200D}' | '\u{2070}'..='\u{218F}' | '\u{2C00}'..='\u{2FEF}'
+        | '\u{3001}'..='\u{D7FF}' | '\u{F900}'..='\u{FDCF}' | '\u{FDF0}'..='\u{FFFD}'
+        | '\u{10000}'..='\u{EFFFF}')
+}
+
+fn is_name_char(c: char) -> bool {
+    is_name_start_char(c)
+        || matches!(c, '-' | '.' | '0'..='9' | '\u{B7}' | '\u{300}'..='\u{36F}' | '\u{203F}'..='\u{2040}')
+}
+
+/// Characters allowed anywhere in a document.
+fn is_xml_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r' | '\u{20}'..='\u{D7FF}' | '\u{E000}'..='\u{FFFD}' | '\u{10000}'..)
+}
+
+fn is_space(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r')
+}
+
+/// A cursor over the document, or over the replacement text of an entity
+/// referenced from it.
+struct Reader<'a> {
+    /// The whole document, for reporting positions.
+    doc: &'a str,
+    src: &'a str,
+    pos: usize,
+    /// Where the outermost entity reference being expanded sits in `doc`.
+    origin: Option<usize>,
+}
+
+impl<'a> Reader<'a> {
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    fn eof(&self) -> bool {
+        self.pos >= self.src.len()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        self.rest().starts_with(s)
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        let found = self.starts_with(s);
+        if found {
+            self.pos += s.len();
+        }
+        found
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), Diagnostic> {
+        if self.eat(s) { Ok(()) } else { self.error(&format!("expected `{}`", s)) }
+    }
+
+    fn skip_space(&mut self) -> bool {
+        let start = self.pos;
+        while self.peek().is_some_and(is_space) {
+            self.pos += 1;
+        }
+        self.pos > start
+    }
+
+    fn require_space(&mut self) -> Result<(), Diagnostic> {
+        if self.skip_space() { Ok(()) } else { self.error("expected whitespace") }
+    }
+
+    fn name(&mut self) -> Result<&'a str, Diagnostic> {
+        let rest = self.rest();
+        if !rest.chars().next().is_some_and(is_name_start_char) {
+            return self.error("expected a name");
+        }
+        let len = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    /// Everything up to `end`, consuming the terminator too.
+    fn take_until(&mut self, end: &str, what: &str) -> Result<&'a str, Diagnostic> {
+        let rest = self.rest();
+        match rest.find(end) {
+            Some(len) => {
+                self.pos += len + end.len();
+                Ok(&rest[..len])
             }
+            None => self.error(&format!("unterminated {}", what)),
+        }
+    }
 
-        } else if data[i] == '>' as u8 {
-            if data[i - 1] == ']' as u8 {
-                i += 1;
-                continue;
+    fn quoted(&mut self) -> Result<&'a str, Diagnostic> {
+        match self.peek() {
+            Some(quote @ ('"' | '\'')) => {
+                self.pos += 1;
+                self.take_until(if quote == '"' { "\"" } else { "'" }, "quoted value")
             }
+            _ => self.error("expected a quoted value"),
+        }
+    }
 
-            name_start = false;
-            params_start = false;
+    /// An attribute value written without quotes, as HTML allows.
+    fn unquoted(&mut self) -> &'a str {
+        let rest = self.rest();
+        let len = rest.char_indices()
+            .find(|&(i, c)| is_space(c) || c == '>' || rest[i..].starts_with("/>"))
+            .map_or(rest.len(), |(i, _)| i);
+        self.pos += len;
+        &rest[..len]
+    }
 
-            let params = String::from_utf8(name.clone()).unwrap();
-            if !params.is_empty() {
-                let mut args = Vec::new();
-                let mut values = Vec::new();
-                handle_params(&params, &mut args, &mut values);
+    fn error<T>(&self, message: &str) -> Result<T, Diagnostic> {
+        Err(self.diagnostic(self.pos, message))
+    }
 
-                for j in 0..args.len() {
-                    tag.params.insert(
-                        sanitize(args[j].clone()),
-                        values[j].clone()
-                    );
-                }
+    fn diagnostic(&self, pos: usize, message: &str) -> Diagnostic {
+        let (line, column) = line_column(self.doc, self.origin.unwrap_or(pos));
+        error_at(message, line, column)
+    }
+}
+
+/// A general entity declared in the internal DTD subset.
+enum Entity {
+    Internal(String),
+    /// Declared with a system or public identifier. Non-validating
+    /// processors needn't read these, so references expand to nothing.
+    External,
+}
+
+fn parse_prolog(r: &mut Reader, context: &mut Context) -> Result<(), Diagnostic> {
+    if r.starts_with("<?xml") && r.rest()[5..].starts_with(is_space) {
+        let decl = r.take_until("?>", "XML declaration")?;
+        if !decl.contains("version") {
+            context.report(r.diagnostic(0, "XML declaration without a version"))?;
+        }
+    }
+
+    let mut seen_doctype = false;
+    loop {
+        r.skip_space();
+        if r.eat("<!--") {
+            parse_comment(r, context)?;
+        } else if r.eat("<?") {
+            parse_pi(r)?;
+        } else if r.starts_with("<!DOCTYPE") {
+            if seen_doctype {
+                return r.error("second document type declaration");
             }
+            r.pos += 9;
+            parse_doctype(r, context)?;
+            seen_doctype = true;
+        } else {
+            return Ok(());
+        }
+    }
+}
+
+fn parse_comment(r: &mut Reader, context: &mut Context) -> Result<(), Diagnostic> {
+    let start = r.pos;
+    let body = r.take_until("-->", "comment")?;
+    if body.contains("--") || body.ends_with('-') {
+        context.report(r.diagnostic(start, "`--` inside a comment"))?;
+    }
+    Ok(())
+}
 
-            name.clear();
+fn parse_pi(r: &mut Reader) -> Result<(), Diagnostic> {
+    let target = r.name()?;
+    if target.eq_ignore_ascii_case("xml") {
+        return r.error("XML declaration not at the start of the document");
+    }
+    r.take_until("?>", "processing instruction")?;
+    Ok(())
+}
 
-            if data[i - 1] == '/' as u8 {
-                total_tags.push(tag.clone());
-                tag.clear();
+/// `<!DOCTYPE name ExternalID? [internal subset]? >`, from after the keyword.
+fn parse_doctype(r: &mut Reader, context: &mut Context) -> Result<(), Diagnostic> {
+    r.require_space()?;
+    r.name()?;
+    r.skip_space();
+    parse_external_id(r)?;
+    r.skip_space();
+
+    if r.eat("[") {
+        loop {
+            r.skip_space();
+            if r.eat("]") {
+                break;
+            } else if r.eat("<!--") {
+                parse_comment(r, context)?;
+            } else if r.eat("<?") {
+                parse_pi(r)?;
+            } else if r.eat("<!ENTITY") {
+                parse_entity_decl(r, context)?;
+            } else if r.starts_with("<!ELEMENT") || r.starts_with("<!ATTLIST") || r.starts_with("<!NOTATION") {
+                skip_markup_decl(r)?;
+            } else if r.eat("%") {
+                // Parameter entities only matter to validation
+                r.name()?;
+                r.expect(";")?;
+            } else if r.eof() {
+                return r.error("unterminated document type declaration");
             } else {
-                if !tag.name.is_empty() {
-                    let (next_i, children, content) = load_xml_recursive(data, i + 1);
-                    tag.children = children;
-                    tag.text_content = content;
-                    total_tags.push(tag.clone());
-                    tag.clear();
-
-                    i = next_i;
-                    continue;
-                }
+                return r.error("unexpected content in the document type declaration");
             }
+        }
+        r.skip_space();
+    }
+    r.expect(">")
+}
 
-        } else if params_start {
-            name.push(data[i]);
-        } else {
-            text_buffer.push(data[i] as char);
+/// `SYSTEM "uri"` or `PUBLIC "id" "uri"`, if present.
+fn parse_external_id(r: &mut Reader) -> Result<bool, Diagnostic> {
+    if r.eat("SYSTEM") {
+        r.require_space()?;
+        r.quoted()?;
+    } else if r.eat("PUBLIC") {
+        r.require_space()?;
+        r.quoted()?;
+        // The system literal is optional only in notation declarations
+        if r.skip_space() && matches!(r.peek(), Some('"' | '\'')) {
+            r.quoted()?;
         }
+    } else {
+        return Ok(false);
+    }
+    Ok(true)
+}
 
-        i += 1;
+fn parse_entity_decl(r: &mut Reader, context: &mut Context) -> Result<(), Diagnostic> {
+    r.require_space()?;
+    let parameter = r.eat("%");
+    if parameter {
+        r.require_space()?;
     }
+    let name = r.name()?;
+    r.require_space()?;
+
+    let entity = if parse_external_id(r)? {
+        r.skip_space();
+        if r.eat("NDATA") {
+            r.require_space()?;
+            r.name()?;
+        }
+        Entity::External
+    } else {
+        let start = r.pos;
+        let literal = r.quoted()?;
+        // Character references are replaced at declaration, entity
+        // references only when the entity is used
+        let mut value = String::with_capacity(literal.len());
+        let mut rest = literal;
+        while let Some(amp) = rest.find('&') {
+            value.push_str(&rest[..amp]);
+            rest = &rest[amp..];
+            if rest.starts_with("&#") {
+                let (c, len) = match parse_reference(&rest[1..]) {
+                    Ok((Reference::Char(c), len)) => (c, len),
+                    _ => return Err(r.diagnostic(start, "invalid character reference")),
+                };
+                value.push(c);
+                rest = &rest[len + 1..];
+            } else {
+                value.push('&');
+                rest = &rest[1..];
+            }
+        }
+        value.push_str(rest);
+        Entity::Internal(value)
+    };
+    r.skip_space();
+    r.expect(">")?;
 
-    (i, total_tags, parent_content.trim().to_string())
+    // The first declaration of an entity is binding
+    if !parameter && !context.entities.contains_key(name) {
+        context.entities.insert(name.to_string(), entity);
+    }
+    Ok(())
 }
 
-fn handle_params(p0: &String, args: &mut Vec<String>, values: &mut Vec<String>) {
-    let mut arg = String::new();
-    let mut value = String::new();
+fn skip_markup_decl(r: &mut Reader) -> Result<(), Diagnostic> {
+    let mut quote = None;
+    for (i, c) in r.rest().char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '>') => {
+                r.pos += i + 1;
+                return Ok(());
+            }
+            _ => {}
+        }
+    }
+    r.error("unterminated markup declaration")
+}
 
-    let p0 = p0.as_bytes();
+enum Reference<'a> {
+    Char(char),
+    Entity(&'a str),
+}
 
-    let mut getting_value = false;
-    let mut getting_arg = true;
+/// Parses the reference at the start of `s`, just after its `&`, and
+/// returns it with the length of its text including the `;`.
+fn parse_reference(s: &str) -> Result<(Reference<'_>, usize), &'static str> {
+    let end = s.find(';').ok_or("unterminated reference")?;
+    let body = &s[..end];
+    if let Some(number) = body.strip_prefix('#') {
+        let value = match number.strip_prefix('x') {
+            Some(hex) if !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()) => u32::from_str_radix(hex, 16).ok(),
+            Some(_) => None,
+            None if !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()) => number.parse().ok(),
+            None => None,
+        };
+        let c = value.and_then(char::from_u32).filter(|&c| is_xml_char(c)).ok_or("invalid character reference")?;
+        return Ok((Reference::Char(c), end + 1));
+    }
 
-    let mut value_start = '*';
-    for i in 0..p0.len() {
+    let mut chars = body.chars();
+    if !chars.next().is_some_and(is_name_start_char) || !chars.all(is_name_char) {
+        return Err("invalid entity reference");
+    }
+    Ok((Reference::Entity(body), end + 1))
+}
+
+fn predefined_entity(name: &str) -> Option<char> {
+    match name {
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "amp" => Some('&'),
+        "apos" => Some('\''),
+        "quot" => Some('"'),
+        _ => None,
+    }
+}
+
+/// An element whose content is still being read.
+struct Frame {
+    tag: Tag,
+    /// The name as written, which the end tag must repeat.
+    qname: String,
+    /// Length of the namespace stack before this element's declarations.
+    scope: usize,
+    /// Location in the tree for diagnostics, like `/svg/g[2]`.
+    path: String,
+    /// Character data since the last child element.
+    text: String,
+    /// All of the element's character data, for `text_content`.
+    content: String,
+}
+
+impl Frame {
+    fn new(tag: Tag, qname: String, scope: usize, path: String) -> Self {
+        Frame { tag, qname, scope, path, text: String::new(), content: String::new() }
+    }
+
+    fn flush_text(&mut self) {
+        if self.text.is_empty() {
+            return;
+        }
+        self.content.push_str(&self.text);
+        let mut node = Tag::new();
+        node.name = TEXT_NODE.to_string();
+        node.text_content = core::mem::take(&mut self.text);
+        self.tag.children.push(node);
+    }
+
+    fn push_child(&mut self, child: Tag) {
+        self.flush_text();
+        self.tag.children.push(child);
+    }
+
+    /// Path of a new child element named `name`.
+    fn child_path(&self, name: &str) -> String {
+        let index = self.tag.children.iter().filter(|c| c.name == name).count() + 1;
+        if index > 1 { format!("{}/{}[{}]", self.path, name, index) } else { format!("{}/{}", self.path, name) }
+    }
+
+    fn finish(mut self) -> Tag {
+        self.flush_text();
+        if !keeps_text_nodes(&self.tag.name) {
+            self.tag.children.retain(|c| !c.is_text_node());
+        }
+        self.tag.text_content = self.content.trim().to_string();
+        self.tag
+    }
+}
+
+struct Context<'a> {
+    mode: ParseMode,
+    diagnostics: &'a mut Vec<Diagnostic>,
+    entities: HashMap<String, Entity>,
+    /// Open elements, innermost last, above a frame collecting the root.
+    frames: Vec<Frame>,
+    /// In-scope prefix bindings, innermost last; `""` is the default.
+    namespaces: Vec<(String, String)>,
+    /// Entities being expanded, to catch recursion.
+    expanding: Vec<String>,
+    expanded: usize,
+    /// Last located byte offset with its line and column, so locating
+    /// elements in order stays linear.
+    lines: (usize, usize, usize),
+}
 
-        if getting_arg {
-            value_start = '*';
-            if p0[i] != '=' as u8 {
-                arg.push(p0[i] as char);
+impl Context<'_> {
+    /// Records a problem, or in strict mode fails on it if it's an error.
+    /// Problems without a path are put at the innermost open element.
+    fn report(&mut self, mut diagnostic: Diagnostic) -> Result<(), Diagnostic> {
+        if diagnostic.path.is_empty() {
+            diagnostic.path = self.frames.last().map(|f| f.path.clone()).unwrap_or_default();
+        }
+        if self.mode == ParseMode::Strict && diagnostic.severity == Severity::Error {
+            return Err(diagnostic);
+        }
+        self.diagnostics.push(diagnostic);
+        Ok(())
+    }
+
+    fn locate(&mut self, doc: &str, offset: usize) -> (usize, usize) {
+        let (from, mut line, mut column) = if offset >= self.lines.0 { self.lines } else { (0, 1, 1) };
+        for c in doc[from..offset].chars() {
+            if c == '\n' {
+                line += 1;
+                column = 1;
             } else {
-                args.push(arg.clone());
-                arg.clear();
-                getting_arg = false;
-                getting_value = true;
-            }
-        } else if getting_value {
-            if value_start == '*' && value.len() == 0 {
-                if p0[i] == '"' as u8 || p0[i] == '\'' as u8 {
-                    value_start = p0[i] as char;
+                column += 1;
+            }
+        }
+        self.lines = (offset, line, column);
+        (line, column)
+    }
+
+    fn lookup(&self, prefix: &str) -> Option<&str> {
+        if let Some((_, uri)) = self.namespaces.iter().rev().find(|(p, _)| p == prefix) {
+            return Some(uri);
+        }
+        match prefix {
+            "xml" => Some(XML_NAMESPACE),
+            // Often used without a declaration in SVG embedded in HTML
+            "xlink" => Some(XLINK_NAMESPACE),
+            _ => None,
+        }
+    }
+
+    /// The name an element or attribute is stored under: SVG names lose
+    /// their prefix and XLink names get the conventional one.
+    fn resolve(&self, qname: &str) -> Result<String, String> {
+        let Some((prefix, local)) = qname.split_once(':') else { return Ok(qname.to_string()) };
+        if prefix.is_empty() || local.is_empty() || local.contains(':') {
+            return Err(format!("invalid qualified name `{}`", qname));
+        }
+        if prefix == "xmlns" {
+            return Ok(qname.to_string());
+        }
+        match self.lookup(prefix) {
+            Some(SVG_NAMESPACE) => Ok(local.to_string()),
+            Some(XLINK_NAMESPACE) => Ok(format!("xlink:{}", local)),
+            Some(_) => Ok(qname.to_string()),
+            None => Err(format!("unbound namespace prefix `{}`", prefix)),
+        }
+    }
+
+    fn push_text(&mut self, text: &str) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.text.push_str(text);
+        }
+    }
+
+    fn close_frame(&mut self) {
+        let Some(frame) = self.frames.pop() else { return };
+        self.namespaces.truncate(frame.scope);
+        let tag = frame.finish();
+        if let Some(parent) = self.frames.last_mut() {
+            parent.push_child(tag);
+        }
+    }
+
+    /// Replacement text of an internal entity, guarding against recursion
+    /// and runaway expansion. `None` when there is nothing to expand.
+    fn enter_entity(&mut self, r: &Reader, start: usize, name: &str) -> Result<Option<String>, Diagnostic> {
+        let value = match self.entities.get(name) {
+            Some(Entity::Internal(value)) => value.clone(),
+            Some(Entity::External) => {
+                let mut warning = r.diagnostic(start, &format!("external entity `{}` is not loaded", name));
+                warning.severity = Severity::Warning;
+                self.report(warning)?;
+                return Ok(None);
+            }
+            None => {
+                self.report(r.diagnostic(start, &format!("undefined entity `{}`", name)))?;
+                self.push_text(&format!("&{};", name));
+                return Ok(None);
+            }
+        };
+        if self.expanding.iter().any(|e| e == name) {
+            self.report(r.diagnostic(start, &format!("entity `{}` references itself", name)))?;
+            return Ok(None);
+        }
+        self.expanded += value.len();
+        if self.expanding.len() >= MAX_ENTITY_DEPTH || self.expanded > MAX_ENTITY_EXPANSION {
+            self.report(r.diagnostic(start, "entity expansion limit exceeded"))?;
+            return Ok(None);
+        }
+        self.expanding.push(name.to_string());
+        Ok(Some(value))
+    }
+}
+
+/// Reads content into `context.frames` until the frame stack is back down
+/// to `floor` entries after a complete element, or, for entity
+/// replacement text, until the input ends.
+fn parse_content(r: &mut Reader, context: &mut Context, floor: usize, to_end: bool) -> Result<(), Diagnostic> {
+    loop {
+        if !to_end && context.frames.len() == floor && context.frames[floor - 1].tag.children.iter().any(|t| !t.is_text_node()) {
+            return Ok(());
+        }
+        if r.eof() {
+            if context.frames.len() > floor {
+                let open = &context.frames[context.frames.len() - 1].qname;
+                context.report(r.diagnostic(r.pos, &format!("`<{}>` is never closed", open)))?;
+                while context.frames.len() > floor {
+                    context.close_frame();
                 }
+            }
+            return Ok(());
+        }
 
+        let start = r.pos;
+        if r.eat("<!--") {
+            parse_comment(r, context)?;
+        } else if r.eat("<![CDATA[") {
+            let text = r.take_until("]]>", "CDATA section")?;
+            context.push_text(text);
+        } else if r.eat("<?") {
+            parse_pi(r)?;
+        } else if r.eat("</") {
+            let name = r.name()?;
+            r.skip_space();
+            r.expect(">")?;
+            let open = context.frames[floor..].iter().rposition(|f| f.qname == name).map(|i| i + floor);
+            if open == Some(context.frames.len() - 1) {
+                context.close_frame();
+                continue;
+            }
+
+            let message = match context.frames[floor..].last() {
+                Some(innermost) => format!("expected `</{}>`, found `</{}>`", innermost.qname, name),
+                None => format!("unexpected end tag `</{}>`", name),
+            };
+            context.report(r.diagnostic(start, &message))?;
+            // Close everything opened inside the named element, or ignore
+            // an end tag matching nothing
+            if let Some(open) = open {
+                while context.frames.len() > open {
+                    context.close_frame();
+                }
+            }
+        } else if r.starts_with("<!") {
+            context.report(r.diagnostic(start, "unexpected markup declaration"))?;
+            skip_markup_decl(r)?;
+        } else if r.eat("<") {
+            parse_start_tag(r, context, start)?;
+        } else if r.eat("&") {
+            let (reference, len) = match parse_reference(r.rest()) {
+                Ok(parsed) => parsed,
+                Err(message) => {
+                    context.report(r.diagnostic(start, message))?;
+                    context.push_text("&");
+                    continue;
+                }
+            };
+            r.pos += len;
+            match reference {
+                Reference::Char(c) => {
+                    let mut buf = [0; 4];
+                    context.push_text(c.encode_utf8(&mut buf));
+                }
+                Reference::Entity(name) => {
+                    if let Some(c) = predefined_entity(name) {
+                        let mut buf = [0; 4];
+                        context.push_text(c.encode_utf8(&mut buf));
+                    } else if let Some(value) = context.enter_entity(r, start, name)? {
+                        // The replacement text is parsed as content and must
+                        // close every element it opens
+                        let mut inner = Reader { doc: r.doc, src: &value, pos: 0, origin: Some(r.origin.unwrap_or(start)) };
+                        let depth = context.frames.len();
+                        parse_content(&mut inner, context, depth, true)?;
+                        context.expanding.pop();
+                    }
+                }
+            }
+        } else {
+            let rest = r.rest();
+            let len = rest.find(['<', '&']).unwrap_or(rest.len());
+            let text = &rest[..len];
+            if let Some(i) = text.find("]]>") {
+                context.report(r.diagnostic(start + i, "`]]>` in character data"))?;
+            }
+            if let Some(i) = text.find(|c| !is_xml_char(c)) {
+                context.report(r.diagnostic(start + i, "invalid character"))?;
+            }
+            r.pos += len;
+            context.push_text(text);
+        }
+    }
+}
+
+/// Reads a start or empty-element tag, from after its `<`.
+fn parse_start_tag(r: &mut Reader, context: &mut Context, start: usize) -> Result<(), Diagnostic> {
+    let qname = r.name()?;
+    let path = context.frames.last().map(|parent| parent.child_path(qname)).unwrap_or_default();
+    let located = |mut diagnostic: Diagnostic| {
+        diagnostic.path = path.clone();
+        diagnostic
+    };
+
+    let mut attributes: Vec<(&str, String)> = Vec::new();
+    let empty = loop {
+        let spaced = r.skip_space();
+        if r.eat("/>") {
+            break true;
+        }
+        if r.eat(">") {
+            break false;
+        }
+        if r.eof() {
+            return r.error("unterminated start tag");
+        }
+        if !spaced {
+            context.report(located(r.diagnostic(r.pos, "expected whitespace before an attribute")))?;
+        }
+
+        let attribute_start = r.pos;
+        let name = r.name()?;
+        r.skip_space();
+        let raw = if r.eat("=") {
+            r.skip_space();
+            if matches!(r.peek(), Some('"' | '\'')) {
+                r.quoted()?
             } else {
-                if p0[i] == value_start as u8 {
-                    values.push(value.clone());
-                    value.clear();
-                    getting_arg = true;
-                    getting_value = false;
-                } else {
-                    value.push(p0[i] as char);
+                context.report(located(r.diagnostic(r.pos, "expected a quoted value")))?;
+                r.unquoted()
+            }
+        } else {
+            context.report(located(r.diagnostic(r.pos, &format!("attribute `{}` has no value", name))))?;
+            ""
+        };
+
+        let mut value = String::with_capacity(raw.len());
+        normalize_attribute(raw, context, &|message| located(r.diagnostic(attribute_start, message)), &mut value)?;
+        if attributes.iter().any(|(n, _)| *n == name) {
+            // The first value is kept
+            context.report(located(r.diagnostic(attribute_start, &format!("duplicate attribute `{}`", name))))?;
+            continue;
+        }
+        attributes.push((name, value));
+    };
+
+    let scope = context.namespaces.len();
+    for (name, value) in &attributes {
+        if *name == "xmlns" {
+            context.namespaces.push((String::new(), value.clone()));
+        } else if let Some(prefix) = name.strip_prefix("xmlns:") {
+            if value.is_empty() {
+                let message = format!("namespace prefix `{}` bound to an empty name", prefix);
+                context.report(located(r.diagnostic(start, &message)))?;
+                continue;
+            }
+            context.namespaces.push((prefix.to_string(), value.clone()));
+        }
+    }
+
+    let mut tag = Tag::new();
+    (tag.line, tag.column) = context.locate(r.doc, r.origin.unwrap_or(start));
+    tag.name = match context.resolve(qname) {
+        Ok(name) => name,
+        Err(message) => {
+            context.report(located(r.diagnostic(start, &message)))?;
+            qname.to_string()
+        }
+    };
+    for (name, value) in attributes {
+        let name = match context.resolve(name) {
+            Ok(resolved) => resolved,
+            Err(message) => {
+                context.report(located(r.diagnostic(start, &message)))?;
+                name.to_string()
+            }
+        };
+        tag.params.insert(name, value);
+    }
+
+    // The bottom frame collects the root and is not an element
+    if context.frames.len() > MAX_ELEMENT_DEPTH {
+        return Err(located(r.diagnostic(start, &format!("elements nested more than {} deep", MAX_ELEMENT_DEPTH))));
+    }
+    if empty {
+        context.namespaces.truncate(scope);
+        let tag = Frame::new(tag, String::new(), scope, path).finish();
+        if let Some(parent) = context.frames.last_mut() {
+            parent.push_child(tag);
+        }
+    } else {
+        context.frames.push(Frame::new(tag, qname.to_string(), scope, path));
+    }
+    Ok(())
+}
+
+/// Expands references in an attribute value and turns each whitespace
+/// character into a space, as for CDATA attributes. `problem` locates
+/// diagnostics at the attribute.
+fn normalize_attribute(
+    raw: &str,
+    context: &mut Context,
+    problem: &dyn Fn(&str) -> Diagnostic,
+    out: &mut String,
+) -> Result<(), Diagnostic> {
+    let mut rest = raw;
+    while let Some(i) = rest.find(['&', '<', '\t', '\n', '\r']) {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        match rest.as_bytes()[0] {
+            b'<' => {
+                context.report(problem("`<` in an attribute value"))?;
+                out.push('<');
+                rest = &rest[1..];
+            }
+            b'&' => {
+                let (reference, len) = match parse_reference(&rest[1..]) {
+                    Ok(parsed) => parsed,
+                    Err(message) => {
+                        context.report(problem(message))?;
+                        out.push('&');
+                        rest = &rest[1..];
+                        continue;
+                    }
+                };
+                match reference {
+                    Reference::Char(c) => out.push(c),
+                    Reference::Entity(name) => match predefined_entity(name) {
+                        Some(c) => out.push(c),
+                        None => {
+                            let value = match context.entities.get(name) {
+                                Some(Entity::Internal(value)) => value.clone(),
+                                Some(Entity::External) => {
+                                    let message = format!("external entity `{}` in an attribute value", name);
+                                    context.report(problem(&message))?;
+                                    String::new()
+                                }
+                                None => {
+                                    context.report(problem(&format!("undefined entity `{}`", name)))?;
+                                    out.push_str(&rest[..len + 1]);
+                                    String::new()
+                                }
+                            };
+                            if context.expanding.iter().any(|e| e == name) {
+                                context.report(problem(&format!("entity `{}` references itself", name)))?;
+                            } else if !value.is_empty() {
+                                context.expanded += value.len();
+                                if context.expanding.len() >= MAX_ENTITY_DEPTH || context.expanded > MAX_ENTITY_EXPANSION {
+                                    context.report(problem("entity expansion limit exceeded"))?;
+                                } else {
+                                    context.expanding.push(name.to_string());
+                                    normalize_attribute(&value, context, problem, out)?;
+                                    context.expanding.pop();
+                                }
+                            }
+                        }
+                    },
                 }
+                rest = &rest[len + 1..];
+            }
+            _ => {
+                out.push(' ');
+                rest = &rest[1..];
             }
         }
     }
+    out.push_str(rest);
+    Ok(())
 }
 
-pub fn sanitize(s: String) -> String {
-    s.replace(' ', "")
-        .replace('\n', "")
-        .replace('\t', "")
-        .replace('\r', "")
-        .replace('/', "")
-        .replace('<', "")
-        .replace('>', "")
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nested(depth: usize) -> String {
+        format!("<svg>{}{}</svg>", "<g>".repeat(depth - 1), "</g>".repeat(depth - 1))
+    }
+
+    fn depth(tag: &Tag) -> usize {
+        1 + tag.children.iter().map(depth).max().unwrap_or(0)
+    }
+
+    #[test]
+    fn test_element_depth_limit() {
+        let root = parse_xml(nested(MAX_ELEMENT_DEPTH).as_bytes()).unwrap();
+        assert_eq!(depth(&root[0]), MAX_ELEMENT_DEPTH);
+
+        let error = parse_xml(nested(MAX_ELEMENT_DEPTH + 1).as_bytes()).unwrap_err();
+        assert!(error.message.contains("nested more than"));
+        assert_eq!((error.line, error.column), (1, 6 + 3 * (MAX_ELEMENT_DEPTH - 1)));
+
+        // Lenient parsing keeps what was read before
+        let mut diagnostics = Vec::new();
+        let data = nested(MAX_ELEMENT_DEPTH + 1);
+        let root = parse_xml_with(data.as_bytes(), ParseMode::Lenient, &mut diagnostics).unwrap();
+        assert_eq!(depth(&root[0]), MAX_ELEMENT_DEPTH);
+        assert_eq!(diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn test_cdata_becomes_text() {
+        let root = parse_xml(b"<svg><text><![CDATA[a < b & <c>]]></text></svg>").unwrap();
+        assert_eq!(root[0].children[0].text_content, "a < b & <c>");
+        assert!(parse_xml(b"<svg><text><![CDATA[open</text></svg>").is_err());
+    }
+
+    #[test]
+    fn test_utf16_documents() {
+        let text = "<svg><text>h\u{e9}llo \u{1F600}</text></svg>";
+        let le: Vec<u8> = [0xFF, 0xFE].into_iter()
+            .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        let be: Vec<u8> = [0xFE, 0xFF].into_iter()
+            .chain(text.encode_utf16().flat_map(u16::to_be_bytes))
+            .collect();
+        for data in [le, be] {
+            let root = parse_xml(&data).unwrap();
+            assert_eq!(root[0].children[0].text_content, "h\u{e9}llo \u{1F600}");
+        }
+
+        // A declaration without a byte order mark
+        let declared: Vec<u8> = r#"<?xml version="1.0" encoding="UTF-16"?><svg/>"#
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        assert_eq!(parse_xml(&declared).unwrap()[0].name, "svg");
+    }
+
+    #[test]
+    fn test_namespaces() {
+        let root = parse_xml(br##"<s:svg xmlns:s="http://www.w3.org/2000/svg" xmlns:l="http://www.w3.org/1999/xlink" xmlns:i="urn:other">
+            <s:use l:href="#a" i:label="x"/>
+            <i:thing xmlns:s="urn:not-svg"><s:rect/></i:thing>
+            <use xlink:href="#b"/>
+        </s:svg>"##).unwrap();
+        let svg = &root[0];
+        assert_eq!(svg.name, "svg");
+        assert_eq!(svg.children[0].name, "use");
+        assert_eq!(svg.children[0].params.get("xlink:href").map(String::as_str), Some("#a"));
+        assert_eq!(svg.children[0].params.get("i:label").map(String::as_str), Some("x"));
+        // Rebinding the prefix in a nested scope
+        assert_eq!(svg.children[1].name, "i:thing");
+        assert_eq!(svg.children[1].children[0].name, "s:rect");
+        // `xlink` may be used undeclared
+        assert_eq!(svg.children[2].params.get("xlink:href").map(String::as_str), Some("#b"));
+
+        let error = parse_xml(b"<svg><x:rect/></svg>").unwrap_err();
+        assert!(error.message.contains("unbound namespace prefix"));
+        assert_eq!((error.line, error.column), (1, 6));
+    }
 }
@@ -1,4 +1,11 @@
-use crate::utils::compat::{HashMap, String, Vec};
+use crate::utils::compat::{HashMap, String, ToString, Vec};
+use crate::svg::utils::color::{parse_paint, Paint};
+use crate::svg::utils::coords::{Axis, LengthContext};
+use crate::svg::utils::style::get_property;
+use crate::svg::utils::transform::{parse_transform, parse_view_box, Transform};
+
+/// Name given to character data nodes kept inside text content elements.
+pub const TEXT_NODE: &str = "#text";
 
 #[derive(Debug, Clone)]
 pub struct Tag {
@@ -6,6 +13,16 @@ pub struct Tag {
     pub params: HashMap<String, String>,
     pub text_content: String,
     pub children: Vec<Tag>,
+    /// Where the start tag begins in the source, 1-based; 0 for elements
+    /// that didn't come from a document.
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Default for Tag {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Tag {
@@ -15,13 +32,66 @@ impl Tag {
             params: HashMap::new(),
             text_content: String::new(),
             children: Vec::new(),
+            line: 0,
+            column: 0,
         }
     }
 
+    pub fn is_text_node(&self) -> bool {
+        self.name == TEXT_NODE
+    }
+
+    /// The attribute as written, without looking at `style`.
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(|s| s.as_str())
+    }
+
+    /// Sets an attribute, returning its previous value.
+    pub fn set_attribute(&mut self, name: &str, value: &str) -> Option<String> {
+        self.params.insert(name.to_string(), value.to_string())
+    }
+
+    pub fn remove_attribute(&mut self, name: &str) -> Option<String> {
+        self.params.remove(name)
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.attribute("id")
+    }
+
+    /// A presentation property as a plain number, with a `style` declaration
+    /// taking precedence over the attribute. `px` is accepted as a unit.
+    pub fn number(&self, name: &str) -> Option<f32> {
+        get_property(self, name)?.trim_end_matches("px").trim_end().parse().ok()
+    }
+
+    /// A presentation property as a length in user units, resolved in
+    /// `lengths` with percentages taken along `axis`.
+    pub fn length(&self, name: &str, lengths: &LengthContext, axis: Axis) -> Option<f32> {
+        lengths.length(get_property(self, name)?, axis)
+    }
+
+    /// A paint property such as `fill` or `stroke`, unresolved: references
+    /// to paint servers come back as [`Paint::Reference`].
+    pub fn paint(&self, name: &str) -> Option<Paint> {
+        get_property(self, name).map(parse_paint)
+    }
+
+    /// The element's `transform`, or the identity.
+    pub fn transform(&self) -> Transform {
+        parse_transform(self)
+    }
+
+    pub fn view_box(&self) -> Option<[f32; 4]> {
+        parse_view_box(self.attribute("viewBox")?)
+    }
+
     pub fn clear(&mut self) {
         self.name = String::new();
         self.params.clear();
         self.text_content = String::new();
         self.children.clear();
+        self.line = 0;
+        self.column = 0;
     }
 }
\ No newline at end of file
@@ -0,0 +1,337 @@
+use crate::svg::diagnostics::{Diagnostic, Severity};
+use crate::svg::parser::tags::Tag;
+use crate::svg::utils::color::{is_paint_server, try_parse_color, try_parse_paint, Paint};
+use crate::svg::utils::coords::{number_end, Length, Unit};
+use crate::utils::compat::{format, HashMap, String, ToString, Vec};
+
+/// Checks attribute values against their grammars and reports what the
+/// renderer would silently replace with a default: malformed numbers,
+/// lengths, colors, transforms and path data, negative sizes, and
+/// references to elements that don't exist or can't be used. Values are
+/// read with the renderer's own parsers, so whatever passes here renders.
+pub fn validate(roots: &[Tag]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut ids = HashMap::new();
+    for root in roots {
+        collect_ids(root, &mut ids);
+    }
+    for root in roots {
+        let path = format!("/{}", root.name);
+        check_element(root, &path, &ids, &mut diagnostics);
+    }
+    diagnostics
+}
+
+/// Maps each id to the first element carrying it.
+fn collect_ids<'a>(tag: &'a Tag, ids: &mut HashMap<&'a str, &'a Tag>) {
+    if let Some(id) = tag.params.get("id")
+        && !ids.contains_key(id.as_str())
+    {
+        ids.insert(id, tag);
+    }
+    for child in &tag.children {
+        collect_ids(child, ids);
+    }
+}
+
+fn diagnostic(tag: &Tag, path: &str, severity: Severity, message: String) -> Diagnostic {
+    Diagnostic { severity, message, path: path.to_string(), line: tag.line, column: tag.column }
+}
+
+fn check_element(tag: &Tag, path: &str, ids: &HashMap<&str, &Tag>, diagnostics: &mut Vec<Diagnostic>) {
+    if let Some(id) = tag.params.get("id")
+        && ids.get(id.as_str()).is_some_and(|first| !core::ptr::eq(*first, tag))
+    {
+        diagnostics.push(diagnostic(tag, path, Severity::Warning, format!("duplicate id `{}`", id)));
+    }
+
+    // Sorted so reports come out in the same order every time
+    let mut params: Vec<(&String, &String)> = tag.params.iter().collect();
+    params.sort();
+    for (name, value) in params {
+        if let Some((severity, message)) = check_attribute(tag, name, value, ids) {
+            diagnostics.push(diagnostic(tag, path, severity, message));
+        }
+    }
+
+    if let Some(style) = tag.params.get("style") {
+        let declarations = style.split(';').filter_map(|d| d.split_once(':'));
+        for (name, value) in declarations {
+            let value = value.trim().trim_end_matches("!important").trim();
+            // `transform` and `d` follow CSS syntax in style sheets
+            if matches!(name.trim(), "transform" | "d") {
+                continue;
+            }
+            if let Some((severity, message)) = check_attribute(tag, name.trim(), value, ids) {
+                diagnostics.push(diagnostic(tag, path, severity, format!("{} in `style`", message)));
+            }
+        }
+    }
+
+    let mut seen: Vec<(&str, usize)> = Vec::new();
+    for child in tag.children.iter().filter(|c| !c.is_text_node()) {
+        let index = match seen.iter_mut().find(|(name, _)| *name == child.name) {
+            Some((_, count)) => {
+                *count += 1;
+                *count
+            }
+            None => {
+                seen.push((&child.name, 1));
+                1
+            }
+        };
+        let child_path = if index > 1 {
+            format!("{}/{}[{}]", path, child.name, index)
+        } else {
+            format!("{}/{}", path, child.name)
+        };
+        check_element(child, &child_path, ids, diagnostics);
+    }
+}
+
+/// The problem with one attribute or style declaration, if any.
+fn check_attribute(tag: &Tag, name: &str, value: &str, ids: &HashMap<&str, &Tag>) -> Option<(Severity, String)> {
+    let value = value.trim();
+    if value == "inherit" {
+        return None;
+    }
+    let error = |message: String| Some((Severity::Error, message));
+    let invalid_unless = |valid: bool| if valid { None } else { error(format!("invalid value `{}` for `{}`", value, name)) };
+    let is_text = matches!(tag.name.as_str(), "text" | "tspan");
+
+    match name {
+        "x" | "y" | "dx" | "dy" if is_text => invalid_unless(
+            value.split(|c: char| c == ',' || c.is_whitespace()).filter(|v| !v.is_empty()).all(|v| length(v).is_some()),
+        ),
+        "x" | "y" | "cx" | "cy" | "x1" | "y1" | "x2" | "y2" | "fx" | "fy" | "dx" | "dy" => invalid_unless(length(value).is_some()),
+        "width" | "height" | "r" | "rx" | "ry" | "fr" | "stroke-width" => match length(value) {
+            None if value == "auto" && name != "r" && name != "stroke-width" => None,
+            Some(v) if v < 0.0 => error(format!("`{}` must not be negative", name)),
+            v => invalid_unless(v.is_some()),
+        },
+        "opacity" | "fill-opacity" | "stroke-opacity" | "stop-opacity" | "flood-opacity" => {
+            invalid_unless(number(value.strip_suffix('%').unwrap_or(value)).is_some())
+        }
+        "stroke-miterlimit" => match number(value) {
+            Some(v) if v < 1.0 => error("`stroke-miterlimit` must be at least 1".to_string()),
+            v => invalid_unless(v.is_some()),
+        },
+        "fill" | "stroke" => {
+            // A fallback color covers a missing paint server
+            let fallback = value.find(')').is_some_and(|close| !value[close + 1..].trim().is_empty());
+            invalid_unless(is_paint(value)).or_else(|| if fallback { None } else { paint_reference_error(name, value, ids) })
+        }
+        "stop-color" | "flood-color" | "lighting-color" | "color" => invalid_unless(is_color(value)),
+        "clip-path" | "mask" | "filter" | "marker" | "marker-start" | "marker-mid" | "marker-end" => {
+            missing_references(name, value, ids)
+        }
+        "transform" | "gradientTransform" | "patternTransform" => invalid_unless(is_transform(value)),
+        "viewBox" => match numbers(value).as_deref() {
+            Some([_, _, w, h]) if *w < 0.0 || *h < 0.0 => error("`viewBox` must not be negative".to_string()),
+            Some(numbers) => invalid_unless(numbers.len() == 4),
+            None => invalid_unless(false),
+        },
+        "d" if tag.name == "path" => path_data_error(value).and_then(|offset| {
+            error(format!("invalid path data at `{}`", value[offset..].chars().take(12).collect::<String>()))
+        }),
+        "points" if matches!(tag.name.as_str(), "polygon" | "polyline") => match numbers(value) {
+            Some(points) if points.len() % 2 == 1 => error("odd number of coordinates in `points`".to_string()),
+            points => invalid_unless(points.is_some()),
+        },
+        "href" | "xlink:href" if tag.name != "image" && tag.name != "a" => {
+            let id = value.strip_prefix('#').filter(|id| !ids.contains_key(id))?;
+            Some((Severity::Warning, format!("`{}` refers to missing element `#{}`", name, id)))
+        }
+        _ => None,
+    }
+}
+
+/// A whole value that is a number.
+fn number(value: &str) -> Option<f32> {
+    Length::parse(value).filter(|length| length.unit == Unit::None).map(|length| length.number)
+}
+
+/// A whole value that is a number with an optional unit.
+fn length(value: &str) -> Option<f32> {
+    Length::parse(value).map(|length| length.number)
+}
+
+fn missing_references(name: &str, value: &str, ids: &HashMap<&str, &Tag>) -> Option<(Severity, String)> {
+    let mut rest = value;
+    while let Some(start) = rest.find("url(") {
+        let inner = &rest[start + 4..];
+        let close = inner.find(')')?;
+        let target = inner[..close].trim().trim_matches(|c| c == '"' || c == '\'');
+        if let Some(id) = target.strip_prefix('#')
+            && !ids.contains_key(id)
+        {
+            return Some((Severity::Warning, format!("`{}` refers to missing element `#{}`", name, id)));
+        }
+        rest = &inner[close + 1..];
+    }
+    None
+}
+
+/// A paint server reference that is missing or points at something else.
+fn paint_reference_error(name: &str, value: &str, ids: &HashMap<&str, &Tag>) -> Option<(Severity, String)> {
+    let Some(Paint::Reference(id)) = try_parse_paint(value) else { return None };
+    match ids.get(id.as_str()) {
+        None => Some((Severity::Warning, format!("`{}` refers to missing element `#{}`", name, id))),
+        Some(target) if !is_paint_server(&target.name) => Some((
+            Severity::Warning,
+            format!("`{}` refers to `#{}`, a `{}` rather than a paint server", name, id, target.name),
+        )),
+        Some(_) => None,
+    }
+}
+
+/// Length of the number at the start of `s`, as lengths are read.
+fn number_len(s: &str) -> Option<usize> {
+    let end = number_end(s);
+    s[..end].parse::<f32>().ok().map(|_| end)
+}
+
+/// Numbers separated by whitespace and optional commas. `None` if anything
+/// else is in the way.
+fn numbers(s: &str) -> Option<Vec<f32>> {
+    let mut values = Vec::new();
+    let mut rest = s.trim_start();
+    while !rest.is_empty() {
+        let len = number_len(rest)?;
+        values.push(rest[..len].parse().ok()?);
+        rest = rest[len..].trim_start();
+        if let Some(after) = rest.strip_prefix(',') {
+            rest = after.trim_start();
+            if rest.is_empty() {
+                return None;
+            }
+        }
+    }
+    Some(values)
+}
+
+fn is_paint(value: &str) -> bool {
+    matches!(value, "context-fill" | "context-stroke") || is_color(value) || try_parse_paint(value).is_some()
+}
+
+fn is_color(value: &str) -> bool {
+    value.eq_ignore_ascii_case("currentcolor") || try_parse_color(value).is_some()
+}
+
+fn is_transform(value: &str) -> bool {
+    let mut rest = value.trim();
+    while !rest.is_empty() {
+        let name_len = rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len());
+        let name = &rest[..name_len];
+        let Some(inner) = rest[name_len..].trim_start().strip_prefix('(') else { return false };
+        let Some(close) = inner.find(')') else { return false };
+        let Some(args) = numbers(&inner[..close]) else { return false };
+        let arity_ok = match name {
+            "matrix" => args.len() == 6,
+            "translate" | "scale" => matches!(args.len(), 1 | 2),
+            "rotate" => matches!(args.len(), 1 | 3),
+            "skewX" | "skewY" => args.len() == 1,
+            _ => false,
+        };
+        if !arity_ok {
+            return false;
+        }
+        rest = inner[close + 1..].trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+    }
+    true
+}
+
+/// Byte offset of the first error in path data. Everything before it
+/// still renders.
+fn path_data_error(d: &str) -> Option<usize> {
+    let skip_space = |i: usize| i + d[i..].len() - d[i..].trim_start().len();
+    let skip_separator = |i: usize| {
+        let i = skip_space(i);
+        if d[i..].starts_with(',') { skip_space(i + 1) } else { i }
+    };
+
+    let mut i = skip_space(0);
+    if i < d.len() && !d[i..].starts_with(['M', 'm']) {
+        return Some(i);
+    }
+    while i < d.len() {
+        let command = d.as_bytes()[i];
+        let arity = match command.to_ascii_lowercase() {
+            b'z' => 0,
+            b'h' | b'v' => 1,
+            b'm' | b'l' | b't' => 2,
+            b's' | b'q' => 4,
+            b'c' => 6,
+            b'a' => 7,
+            _ => return Some(i),
+        };
+        i = skip_space(i + 1);
+
+        // One or more argument groups, unless the command takes none;
+        // groups repeat while numbers follow
+        let mut first = true;
+        while arity > 0 && (first || number_len(&d[i..]).is_some()) {
+            for k in 0..arity {
+                if k > 0 {
+                    i = skip_separator(i);
+                }
+                let len = if command.eq_ignore_ascii_case(&b'a') && (k == 3 || k == 4) {
+                    d[i..].starts_with(['0', '1']).then_some(1)
+                } else {
+                    number_len(&d[i..])
+                };
+                match len {
+                    Some(len) => i += len,
+                    None => return Some(i),
+                }
+            }
+            first = false;
+            i = skip_separator(i);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svg::parser::parse::parse_xml;
+
+    fn messages(svg: &str) -> Vec<String> {
+        validate(&parse_xml(svg.as_bytes()).unwrap()).into_iter().map(|d| d.message).collect::<Vec<_>>()
+    }
+
+    #[test]
+    fn test_numbers_read_like_the_renderer() {
+        assert!(messages(r#"<svg><circle r="5." cx=".5e1" cy="2em"/></svg>"#).is_empty());
+        assert!(messages(r#"<svg><polygon points="1. 2, 3.5 4"/></svg>"#).is_empty());
+        assert_eq!(messages(r#"<svg><circle r="5.." /></svg>"#), ["invalid value `5..` for `r`"]);
+    }
+
+    #[test]
+    fn test_colors_read_like_the_renderer() {
+        assert!(messages(r#"<svg><rect fill="rgb(255, 0, 0)" stroke="currentColor"/></svg>"#).is_empty());
+        assert_eq!(messages(r##"<svg><rect fill="#12"/></svg>"##), ["invalid value `#12` for `fill`"]);
+        assert_eq!(messages(r#"<svg><rect style="fill: rgb(1, 2)"/></svg>"#), ["invalid value `rgb(1, 2)` for `fill` in `style`"]);
+    }
+
+    #[test]
+    fn test_paint_references() {
+        let svg = r##"<svg><g id="g"/><linearGradient id="l"/>
+            <rect fill="url(#l)" stroke="url(#g)"/><rect fill="url(#missing)"/><rect fill="url(#missing) red"/></svg>"##;
+        assert_eq!(messages(svg), [
+            "`stroke` refers to `#g`, a `g` rather than a paint server",
+            "`fill` refers to missing element `#missing`",
+        ]);
+    }
+
+    #[test]
+    fn test_duplicate_ids() {
+        let svg = r#"<svg><rect id="a"/><g id="a"><circle id="a"/></g></svg>"#;
+        assert_eq!(messages(svg), ["duplicate id `a`", "duplicate id `a`"]);
+        let root = parse_xml(svg.as_bytes()).unwrap();
+        let diagnostics = validate(&root);
+        assert_eq!(diagnostics.iter().map(|d| d.path.as_str()).collect::<Vec<_>>(), ["/svg/g", "/svg/g/circle"]);
+    }
+}
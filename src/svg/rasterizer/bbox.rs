@@ -0,0 +1,166 @@
+use crate::svg::parser::tags::Tag;
+use crate::svg::rasterizer::raster::{Bounds, Point};
+use crate::svg::rasterizer::tags::path::{apply_transform_to_path, commands_bounds, PathCommand};
+use crate::svg::rasterizer::tags::text::{text_outline, Font};
+use crate::svg::tree::{shape_path, viewport};
+use crate::svg::utils::coords::{Axis, LengthContext};
+use crate::svg::utils::transform::{parse_transform, Transform};
+use crate::utils::compat::{Vec, vec};
+use crate::svg::utils::Defs;
+
+/// Largest `<use>` nesting followed while measuring.
+const MAX_USE_DEPTH: usize = 16;
+
+/// Geometry bounding box of an element and its descendants, mapped
+/// through `transform`. The element's own `transform` attribute is not
+/// applied, matching the user space its filter or paint works in.
+/// Lengths resolve in `lengths`, the context of the element itself.
+pub(crate) fn element_bbox(
+    tag: &Tag,
+    defs: &Defs,
+    fonts: &[Font],
+    transform: &Transform,
+    lengths: &LengthContext,
+) -> Option<Bounds> {
+    let mut measure = Measure { defs, fonts, out: Vec::new() };
+    measure.collect(tag, transform, lengths, 0);
+    commands_bounds(measure.out.iter())
+}
+
+/// Geometry collected while walking an element, with the document
+/// resources it is measured against.
+struct Measure<'a> {
+    defs: &'a Defs<'a>,
+    fonts: &'a [Font],
+    out: Vec<PathCommand>,
+}
+
+impl Measure<'_> {
+    fn collect(
+        &mut self,
+        tag: &Tag,
+        transform: &Transform,
+        lengths: &LengthContext,
+        depth: usize,
+    ) {
+        let number = |key: &str, axis: Axis| lengths.attribute(tag, key, axis).unwrap_or(0.0);
+        let geometry = match tag.name.as_str() {
+            "image" => {
+                let (w, h) = (number("width", Axis::Horizontal), number("height", Axis::Vertical));
+                if w <= 0.0 || h <= 0.0 {
+                    return;
+                }
+                let (x, y) = (number("x", Axis::Horizontal), number("y", Axis::Vertical));
+                vec![
+                    PathCommand::MoveTo(Point { x, y }),
+                    PathCommand::LineTo(Point { x: x + w, y }),
+                    PathCommand::LineTo(Point { x: x + w, y: y + h }),
+                    PathCommand::LineTo(Point { x, y: y + h }),
+                ]
+            }
+            "text" => {
+                self.out.extend(text_outline(tag, self.defs, self.fonts, transform, lengths));
+                return;
+            }
+            "use" => {
+                let Some(target) = tag.params.get("href")
+                    .or_else(|| tag.params.get("xlink:href"))
+                    .and_then(|link| self.defs.get(link.trim().trim_start_matches('#')).copied())
+                else {
+                    return;
+                };
+                if depth >= MAX_USE_DEPTH {
+                    return;
+                }
+
+                // A symbol is measured like a nested <svg> of the size of the <use>
+                let placed = transform.then(&Transform::translate(number("x", Axis::Horizontal), number("y", Axis::Vertical)));
+                if matches!(target.name.as_str(), "symbol" | "svg") {
+                    let size = (lengths.attribute(tag, "width", Axis::Horizontal), lengths.attribute(tag, "height", Axis::Vertical));
+                    self.collect_viewport(target, size, &placed.then(&parse_transform(target)), &lengths.for_element(target), depth + 1);
+                } else {
+                    self.collect_child(target, &placed, lengths, depth + 1);
+                }
+                return;
+            }
+            "defs" | "clipPath" | "mask" | "marker" | "pattern" | "filter" | "symbol"
+            | "linearGradient" | "radialGradient" | "conicGradient" | "meshgradient" | "meshGradient" => return,
+            _ => shape_path(tag, lengths).unwrap_or_default(),
+        };
+        self.out.extend(apply_transform_to_path(&geometry, transform));
+
+        // Containers measure their children in their own coordinate systems
+        for child in &tag.children {
+            self.collect_child(child, transform, lengths, depth);
+        }
+    }
+
+    /// Measures `child` of an element drawn through `transform` in `lengths`;
+    /// the content of a nested `<svg>` is mapped into its viewport.
+    fn collect_child(
+        &mut self,
+        child: &Tag,
+        transform: &Transform,
+        lengths: &LengthContext,
+        depth: usize,
+    ) {
+        let transform = transform.then(&parse_transform(child));
+        let lengths = lengths.for_element(child);
+        if child.name == "svg" {
+            self.collect_viewport(child, (None, None), &transform, &lengths, depth);
+        } else {
+            self.collect(child, &transform, &lengths, depth);
+        }
+    }
+
+    /// Measures the content of a nested `<svg>`, in its own context `lengths`,
+    /// mapped into its viewport; `size` is given by a `<use>` instancing it.
+    fn collect_viewport(
+        &mut self,
+        tag: &Tag,
+        size: (Option<f32>, Option<f32>),
+        transform: &Transform,
+        lengths: &LengthContext,
+        depth: usize,
+    ) {
+        let Some(viewport) = viewport(tag, size, lengths) else {
+            return;
+        };
+        let inner = transform.then(&viewport.transform);
+        for child in &tag.children {
+            self.collect_child(child, &inner, &viewport.lengths, depth);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svg::parser::parse::parse_xml;
+    use crate::svg::utils::collect_defs;
+
+    fn use_bbox(svg: &str) -> Option<Bounds> {
+        let root = parse_xml(svg.as_bytes()).unwrap().remove(0);
+        let defs = collect_defs(&root);
+        let used = root.children.iter().find(|child| child.name == "use").unwrap();
+        element_bbox(used, &defs, &[], &Transform::identity(), &LengthContext::default())
+    }
+
+    #[test]
+    fn test_use_of_a_symbol_is_measured_at_its_size() {
+        let bounds = use_bbox(r##"<svg>
+            <symbol id="s" viewBox="0 0 2 2"><rect width="1" height="2"/></symbol>
+            <use href="#s" x="2" y="3" width="4" height="4"/>
+        </svg>"##).unwrap();
+        assert_eq!((bounds.x, bounds.y, bounds.width, bounds.height), (2.0, 3.0, 2.0, 4.0));
+    }
+
+    #[test]
+    fn test_use_of_a_shape_is_offset_and_transformed() {
+        let bounds = use_bbox(r##"<svg>
+            <rect id="r" width="2" height="1" transform="scale(2)"/>
+            <use href="#r" x="5"/>
+        </svg>"##).unwrap();
+        assert_eq!((bounds.x, bounds.y, bounds.width, bounds.height), (5.0, 0.0, 4.0, 2.0));
+    }
+}
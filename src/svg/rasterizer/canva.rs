@@ -1,17 +1,21 @@
-use crate::utils::compat::{HashMap, String, Vec};
+use crate::utils::compat::Vec;
+#[cfg(not(feature = "std"))]
 use crate::utils::compat::FloatExt;
 use alloc::vec;
 use crate::svg::parser::tags::Tag;
 use crate::svg::rasterizer::tags::path::draw_path;
-use crate::svg::rasterizer::tags::polygon::draw_polygon;
-use crate::svg::rasterizer::tags::rect::draw_rect;
-use crate::svg::rasterizer::tags::circle::draw_circle;
-use crate::svg::rasterizer::tags::ellipse::draw_ellipse;
-use crate::svg::rasterizer::tags::line::draw_line;
-use crate::svg::rasterizer::tags::polyline::draw_polyline;
-use crate::svg::rasterizer::tags::r#use::draw_use;
-use crate::svg::rasterizer::tags::text::draw_text;
-use crate::svg::utils::transform::{parse_transform, Transform};
+use crate::svg::rasterizer::tags::image::draw_image;
+use crate::svg::rasterizer::tags::text::{draw_text, Font};
+use crate::svg::tree::{Filter, Node, NodeKind};
+use crate::svg::utils::coords::LengthContext;
+use crate::svg::utils::transform::Transform;
+use crate::svg::utils::color::Paint;
+use crate::svg::rasterizer::filters;
+use crate::svg::rasterizer::filters::blend::BlendMode;
+use crate::svg::rasterizer::simd::blend_scanline_mode;
+use crate::svg::rasterizer::raster::{Bounds, Point};
+use crate::svg::rasterizer::tags::path::{fill_path, PathCommand};
+use crate::svg::utils::Defs;
 
 pub struct Canvas {
     pub width: usize,
@@ -21,79 +25,172 @@ pub struct Canvas {
 
 impl Canvas {
     pub fn new(width: usize, height: usize) -> Self {
-        Self {
-            width,
-            height,
-            data: vec![0xFFFF_FFFF; width * height],
-        }
+        Self::with_background(width, height, 0xFFFF_FFFF)
     }
 
     pub fn new_transparent(width: usize, height: usize) -> Self {
+        Self::with_background(width, height, 0x0000_0000)
+    }
+
+    pub fn with_background(width: usize, height: usize, color: u32) -> Self {
         Self {
             width,
             height,
-            data: vec![0x0000_0000; width * height],
+            data: vec![color; width * height],
         }
     }
 
-    pub fn draw(&mut self, tag: &mut Tag, defs: &HashMap<String, Tag>, transform: &Transform) {
+    /// Converts `tag` into a rendering tree, its lengths resolved in
+    /// `lengths` and its text set in `fonts`, and draws it.
+    pub fn draw(&mut self, tag: &Tag, defs: &Defs, fonts: &[Font], transform: &Transform, lengths: &LengthContext) {
+        if let Some(node) = Node::from_tag(tag, defs, fonts, lengths) {
+            self.draw_node(&node, defs, transform);
+        }
+    }
+
+    pub fn draw_node(&mut self, node: &Node, defs: &Defs, transform: &Transform) {
+        // Blending and isolation composite the element as a single layer
+        if node.blend_mode != BlendMode::Normal || node.isolate {
+            let mut layer = Self::new_transparent(self.width, self.height);
+            layer.draw_unblended(node, defs, transform);
+            blend_scanline_mode(&mut self.data, &layer.data, node.blend_mode);
+            return;
+        }
+
+        self.draw_unblended(node, defs, transform);
+    }
+
+    /// Draws `node` through its filter, if any, and clipped to its viewport.
+    fn draw_unblended(&mut self, node: &Node, defs: &Defs, transform: &Transform) {
+        if let Some(clip) = &node.clip {
+            self.draw_clipped(node, clip, defs, transform);
+            return;
+        }
+
+        self.draw_unclipped(node, defs, transform);
+    }
 
-        match &*tag.name {
-            "clipPath" | "defs" | "linearGradient" | "radialGradient" |
-            "pattern" | "mask" | "marker" | "filter" => return,
+    fn draw_unclipped(&mut self, node: &Node, defs: &Defs, transform: &Transform) {
+        match &node.filter {
+            Some(filter) => self.draw_filtered(node, filter, defs, transform),
+            None => self.draw_content(node, defs, transform),
+        }
+    }
 
-            _ => {}
+    /// Renders `node` offscreen over the device bounds of `clip`, then masks
+    /// it to the clip rectangle and composites it.
+    fn draw_clipped(&mut self, node: &Node, clip: &Bounds, defs: &Defs, transform: &Transform) {
+        let outline = region_outline(clip);
+        let corners = outline.iter().filter_map(|command| match command {
+            PathCommand::MoveTo(p) | PathCommand::LineTo(p) => Some(transform.apply(p.x, p.y)),
+            _ => None,
+        });
+        let (mut min_x, mut min_y) = (f32::INFINITY, f32::INFINITY);
+        let (mut max_x, mut max_y) = (f32::NEG_INFINITY, f32::NEG_INFINITY);
+        for (x, y) in corners {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+        let (min_x, min_y) = (min_x.floor().max(0.0), min_y.floor().max(0.0));
+        let (max_x, max_y) = (max_x.ceil().min(self.width as f32), max_y.ceil().min(self.height as f32));
+        if max_x <= min_x || max_y <= min_y {
+            return;
         }
 
-        if let Some(filter_id_raw) = tag.params.get("filter") {
-             let filter_id = if filter_id_raw.starts_with("url(#") && filter_id_raw.ends_with(")") {
-                 &filter_id_raw[5..filter_id_raw.len()-1]
-             } else {
-                 filter_id_raw
-             };
+        let (w, h) = ((max_x - min_x) as usize, (max_y - min_y) as usize);
+        let offset = Transform::translate(-min_x, -min_y).then(transform);
+
+        let mut layer = Self::new_transparent(w, h);
+        layer.draw_unclipped(node, defs, &offset);
 
-             if let Some(filter_tag) = defs.get(filter_id) {
-                 let mut temp_canvas = Self::new_transparent(self.width, self.height);
-                 
-                 let mut tag_clone = tag.clone();
-                 tag_clone.params.remove("filter");
-                 
-                 temp_canvas.draw(&mut tag_clone, defs, transform);
-                 
-                 let filtered_data = crate::svg::rasterizer::filters::apply_filter(
-                     &temp_canvas.data, 
-                     self.width, 
-                     self.height, 
-                     filter_tag, 
-                     defs
-                 );
-                 
-                 self.add_buffer(&filtered_data, 0, 0, self.width, self.height);
-                 return;
-             }
+        let mask = region_mask(clip, &offset, w, h);
+        for (pixel, &coverage) in layer.data.iter_mut().zip(&mask) {
+            let alpha = ((*pixel >> 24) * coverage as u32 + 127) / 255;
+            *pixel = (*pixel & 0x00FFFFFF) | (alpha << 24);
         }
 
-        let local_transform = parse_transform(tag);
-        let combined = transform.then(&local_transform);
+        self.add_buffer(&layer.data, min_x as isize, min_y as isize, w, h);
+    }
+
+    /// Draws the element itself and its children, without its effects.
+    fn draw_content(&mut self, node: &Node, defs: &Defs, transform: &Transform) {
+        let combined = transform.then(&node.transform);
 
-        match tag.name.as_str() {
-            "path" => { draw_path(tag, defs, self, &combined); }
-            "rect" => { draw_rect(tag, defs, self, &combined); }
-            "polygon" => { draw_polygon(tag, defs, self, &combined); }
-            "circle" => { draw_circle(tag, defs, self, &combined); }
-            "ellipse" => { draw_ellipse(tag, defs, self, &combined); }
-            "line" => { draw_line(tag, defs, self, &combined); }
-            "polyline" => { draw_polyline(tag, defs, self, &combined); }
-            "use" => { draw_use(tag, defs, self, &combined); }
-            "text" => { draw_text(tag, defs, self, &combined); }
-            _ => {}
+        match &node.kind {
+            NodeKind::Group => {}
+            NodeKind::Path(path) => draw_path(path, defs, self, &combined),
+            NodeKind::Text(spans) => draw_text(spans, defs, self, &combined),
+            NodeKind::Image(image) => draw_image(image, self, &combined),
         }
 
-        for child in &mut tag.children {
-            self.draw(child, defs, &combined);
+        for child in &node.children {
+            self.draw_node(child, defs, &combined);
         }
     }
 
+    /// Renders `node` into a buffer covering its filter regions, runs each
+    /// filter of the chain over the previous one's result and composites it.
+    fn draw_filtered(&mut self, node: &Node, filter: &Filter, defs: &Defs, transform: &Transform) {
+        let (chain, bbox) = (&filter.chain, filter.bbox);
+        let user = transform.then(&node.transform);
+
+        // An empty bounding box with bounding box units disables the element
+        let Some(regions) = chain.iter()
+            .map(|filter_tag| filters::filter_region(filter_tag, bbox, &filter.lengths))
+            .collect::<Option<Vec<Bounds>>>()
+        else {
+            return;
+        };
+
+        let (mut min_x, mut min_y) = (f32::INFINITY, f32::INFINITY);
+        let (mut max_x, mut max_y) = (f32::NEG_INFINITY, f32::NEG_INFINITY);
+        for region in &regions {
+            for (x, y) in region_outline(region).iter().filter_map(|command| match command {
+                PathCommand::MoveTo(p) | PathCommand::LineTo(p) => Some(user.apply(p.x, p.y)),
+                _ => None,
+            }) {
+                min_x = min_x.min(x);
+                min_y = min_y.min(y);
+                max_x = max_x.max(x);
+                max_y = max_y.max(y);
+            }
+        }
+        let (min_x, min_y) = (min_x.floor().max(0.0), min_y.floor().max(0.0));
+        let (max_x, max_y) = (max_x.ceil().min(self.width as f32), max_y.ceil().min(self.height as f32));
+        if max_x <= min_x || max_y <= min_y {
+            return;
+        }
+
+        let (w, h) = ((max_x - min_x) as usize, (max_y - min_y) as usize);
+        let offset = Transform::translate(-min_x, -min_y);
+        let to_buffer = offset.then(&user);
+
+        let mut layer = Self::new_transparent(w, h);
+        layer.draw_content(node, defs, &offset.then(transform));
+
+        let mut data = layer.data;
+        for (filter_tag, region) in chain.iter().zip(&regions) {
+            let space = filters::FilterSpace::new(
+                to_buffer.clone(),
+                bbox,
+                filter_tag.params.get("primitiveUnits").map(|s| s.as_str()),
+                filter.lengths,
+            );
+            let filtered = filters::apply_filter(&data, w, h, filter_tag, defs, &filter.fonts, &space);
+
+            // Nothing escapes the filter region, even where the buffer rounds outward
+            let mask = region_mask(region, &to_buffer, w, h);
+            data = filtered.iter().zip(&mask).map(|(&pixel, &coverage)| {
+                let alpha = ((pixel >> 24) * coverage as u32 + 127) / 255;
+                (pixel & 0x00FFFFFF) | (alpha << 24)
+            }).collect();
+        }
+
+        self.add_buffer(&data, min_x as isize, min_y as isize, w, h);
+    }
+
      pub fn add_buffer(
         &mut self,
         color_map: &[u32],
@@ -161,4 +258,124 @@ impl Canvas {
     }
 }
 
+/// Coverage of `region`, mapped by `to_buffer`, over a `width` x `height`
+/// buffer. Only the part inside the buffer is rasterized, however large the
+/// region is.
+fn region_mask(region: &Bounds, to_buffer: &Transform, width: usize, height: usize) -> Vec<u8> {
+    // Axis-aligned regions need no rasterizer: coverage is separable
+    if to_buffer.b == 0.0 && to_buffer.c == 0.0 {
+        let (x0, y0) = to_buffer.apply(region.x, region.y);
+        let (x1, y1) = to_buffer.apply(region.x + region.width, region.y + region.height);
+        let span = |a: f32, b: f32, size: usize| -> Vec<f32> {
+            let (lo, hi) = (a.min(b), a.max(b));
+            (0..size).map(|i| (hi.min(i as f32 + 1.0) - lo.max(i as f32)).clamp(0.0, 1.0)).collect()
+        };
+        let (columns, rows) = (span(x0, x1, width), span(y0, y1, height));
+        let mut mask = Vec::with_capacity(width * height);
+        for row in &rows {
+            mask.extend(columns.iter().map(|column| (row * column * 255.0).round() as u8));
+        }
+        return mask;
+    }
+
+    let corners = [
+        (region.x, region.y),
+        (region.x + region.width, region.y),
+        (region.x + region.width, region.y + region.height),
+        (region.x, region.y + region.height),
+    ];
+    let polygon = clip_polygon(corners.iter().map(|&(x, y)| to_buffer.apply(x, y)).collect(), width as f32, height as f32);
+    let Some((&(x, y), rest)) = polygon.split_first() else {
+        return vec![0; width * height];
+    };
 
+    let mut outline = vec![PathCommand::MoveTo(Point { x, y })];
+    outline.extend(rest.iter().map(|&(x, y)| PathCommand::LineTo(Point { x, y })));
+    outline.push(PathCommand::ClosePath);
+    let mut mask = Canvas::new_transparent(width, height);
+    fill_path(&mut mask, &outline, &Paint::Solid(0xFF000000));
+    mask.data.iter().map(|pixel| (pixel >> 24) as u8).collect()
+}
+
+/// Clips a convex polygon to the rectangle from the origin to
+/// (`width`, `height`), one edge at a time.
+fn clip_polygon(mut polygon: Vec<(f32, f32)>, width: f32, height: f32) -> Vec<(f32, f32)> {
+    // Signed distance inside each edge
+    let edges: [&dyn Fn((f32, f32)) -> f32; 4] = [
+        &|p| p.0,
+        &|p| width - p.0,
+        &|p| p.1,
+        &|p| height - p.1,
+    ];
+    for inside in edges {
+        let mut clipped = Vec::with_capacity(polygon.len() + 1);
+        for (i, &current) in polygon.iter().enumerate() {
+            let previous = polygon[(i + polygon.len() - 1) % polygon.len()];
+            let (dc, dp) = (inside(current), inside(previous));
+            if (dc >= 0.0) != (dp >= 0.0) {
+                let t = dp / (dp - dc);
+                clipped.push((previous.0 + t * (current.0 - previous.0), previous.1 + t * (current.1 - previous.1)));
+            }
+            if dc >= 0.0 {
+                clipped.push(current);
+            }
+        }
+        polygon = clipped;
+    }
+    polygon
+}
+
+/// Closed outline of a filter region or clip rectangle.
+fn region_outline(region: &Bounds) -> [PathCommand; 5] {
+    let (x, y, w, h) = (region.x, region.y, region.width, region.height);
+    [
+        PathCommand::MoveTo(Point { x, y }),
+        PathCommand::LineTo(Point { x: x + w, y }),
+        PathCommand::LineTo(Point { x: x + w, y: y + h }),
+        PathCommand::LineTo(Point { x, y: y + h }),
+        PathCommand::ClosePath,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_axis_aligned_region_mask_covers_partial_pixels() {
+        let region = Bounds { x: 0.5, y: 0.0, width: 2.0, height: 1.0 };
+        let mask = region_mask(&region, &Transform::identity(), 4, 2);
+        assert_eq!(mask, [128, 255, 128, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_rotated_region_mask_is_clipped_to_the_buffer() {
+        let region = Bounds { x: -1e7, y: -1e7, width: 2e7, height: 2e7 };
+        let mask = region_mask(&region, &Transform::rotate(30.0), 4, 4);
+        assert!(mask.iter().all(|&coverage| coverage == 255));
+    }
+
+    #[test]
+    fn test_huge_filter_region_renders() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="20" height="20">
+            <filter id="f" filterUnits="userSpaceOnUse" x="0" y="0" width="1e7" height="1e7">
+                <feOffset dx="1" dy="1"/>
+            </filter>
+            <rect width="10" height="10" fill="red" filter="url(#f)"/>
+        </svg>"#;
+        let pixels = crate::svg::render(svg, 20, 20).unwrap();
+        assert_eq!(pixels[5 * 20 + 5], 0xFFFF0000);
+    }
+
+    #[test]
+    fn test_huge_symbol_viewport_renders() {
+        let svg = br##"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="20" height="20">
+            <symbol id="s" viewBox="0 0 10 10" preserveAspectRatio="none">
+                <rect width="10" height="10" fill="blue"/>
+            </symbol>
+            <use xlink:href="#s" width="1e9" height="1e9"/>
+        </svg>"##;
+        let pixels = crate::svg::render(svg, 20, 20).unwrap();
+        assert_eq!(pixels[10 * 20 + 10], 0xFF0000FF);
+    }
+}
@@ -0,0 +1,200 @@
+use crate::svg::parser::tags::Tag;
+use crate::svg::rasterizer::filters::{pack, unpack};
+use crate::utils::compat::Vec;
+#[cfg(not(feature = "std"))]
+use crate::utils::compat::FloatExt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+    Hue,
+    Saturation,
+    Color,
+    Luminosity,
+}
+
+impl BlendMode {
+    pub fn parse(s: &str) -> Option<Self> {
+        Some(match s.trim() {
+            "normal" => BlendMode::Normal,
+            "multiply" => BlendMode::Multiply,
+            "screen" => BlendMode::Screen,
+            "overlay" => BlendMode::Overlay,
+            "darken" => BlendMode::Darken,
+            "lighten" => BlendMode::Lighten,
+            "color-dodge" => BlendMode::ColorDodge,
+            "color-burn" => BlendMode::ColorBurn,
+            "hard-light" => BlendMode::HardLight,
+            "soft-light" => BlendMode::SoftLight,
+            "difference" => BlendMode::Difference,
+            "exclusion" => BlendMode::Exclusion,
+            "hue" => BlendMode::Hue,
+            "saturation" => BlendMode::Saturation,
+            "color" => BlendMode::Color,
+            "luminosity" => BlendMode::Luminosity,
+            _ => return None,
+        })
+    }
+
+    /// Blends straight source color `cs` onto backdrop `cb`, per the
+    /// Compositing and Blending spec.
+    pub fn blend(self, cb: [f32; 3], cs: [f32; 3]) -> [f32; 3] {
+        let separable = |f: fn(f32, f32) -> f32| [f(cb[0], cs[0]), f(cb[1], cs[1]), f(cb[2], cs[2])];
+        match self {
+            BlendMode::Normal => cs,
+            BlendMode::Multiply => separable(|b, s| b * s),
+            BlendMode::Screen => separable(screen),
+            BlendMode::Overlay => separable(|b, s| hard_light(s, b)),
+            BlendMode::Darken => separable(f32::min),
+            BlendMode::Lighten => separable(f32::max),
+            BlendMode::ColorDodge => separable(|b, s| {
+                if b == 0.0 { 0.0 } else if s >= 1.0 { 1.0 } else { (b / (1.0 - s)).min(1.0) }
+            }),
+            BlendMode::ColorBurn => separable(|b, s| {
+                if b >= 1.0 { 1.0 } else if s <= 0.0 { 0.0 } else { 1.0 - ((1.0 - b) / s).min(1.0) }
+            }),
+            BlendMode::HardLight => separable(hard_light),
+            BlendMode::SoftLight => separable(|b, s| {
+                if s <= 0.5 {
+                    b - (1.0 - 2.0 * s) * b * (1.0 - b)
+                } else {
+                    let d = if b <= 0.25 { ((16.0 * b - 12.0) * b + 4.0) * b } else { b.sqrt() };
+                    b + (2.0 * s - 1.0) * (d - b)
+                }
+            }),
+            BlendMode::Difference => separable(|b, s| (b - s).abs()),
+            BlendMode::Exclusion => separable(|b, s| b + s - 2.0 * b * s),
+            BlendMode::Hue => set_lum(set_sat(cs, sat(cb)), lum(cb)),
+            BlendMode::Saturation => set_lum(set_sat(cb, sat(cs)), lum(cb)),
+            BlendMode::Color => set_lum(cs, lum(cb)),
+            BlendMode::Luminosity => set_lum(cb, lum(cs)),
+        }
+    }
+}
+
+/// Blends `in` (source) onto `in2` (backdrop) with the `mode` attribute.
+pub fn apply(input: &[u32], input2: &[u32], tag: &Tag) -> Vec<u32> {
+    let mode = tag.params.get("mode").and_then(|s| BlendMode::parse(s)).unwrap_or(BlendMode::Normal);
+    input.iter().zip(input2).map(|(&s, &b)| pack(blend_premultiplied(mode, unpack(b), unpack(s)))).collect()
+}
+
+/// Composites premultiplied `sp` over `bp` using `mode`.
+pub(crate) fn blend_premultiplied(mode: BlendMode, bp: [f32; 4], sp: [f32; 4]) -> [f32; 4] {
+    let (a_s, a_b) = (sp[3], bp[3]);
+    let straight = |c: [f32; 4]| if c[3] > 0.0 { [c[0] / c[3], c[1] / c[3], c[2] / c[3]] } else { [0.0; 3] };
+
+    let mixed = mode.blend(straight(bp), straight(sp));
+    let mut out = [0.0; 4];
+    for i in 0..3 {
+        // Source over backdrop, with the overlap taking the blended color
+        out[i] = (1.0 - a_b) * sp[i] + (1.0 - a_s) * bp[i] + a_s * a_b * mixed[i].clamp(0.0, 1.0);
+    }
+    out[3] = a_s + a_b - a_s * a_b;
+    out
+}
+
+fn screen(b: f32, s: f32) -> f32 {
+    b + s - b * s
+}
+
+fn hard_light(b: f32, s: f32) -> f32 {
+    if s <= 0.5 { b * 2.0 * s } else { screen(b, 2.0 * s - 1.0) }
+}
+
+fn lum(c: [f32; 3]) -> f32 {
+    0.3 * c[0] + 0.59 * c[1] + 0.11 * c[2]
+}
+
+fn clip_color(c: [f32; 3]) -> [f32; 3] {
+    let l = lum(c);
+    let n = c[0].min(c[1]).min(c[2]);
+    let x = c[0].max(c[1]).max(c[2]);
+    let mut c = c;
+    if n < 0.0 {
+        c = c.map(|v| l + (v - l) * l / (l - n));
+    }
+    if x > 1.0 {
+        c = c.map(|v| l + (v - l) * (1.0 - l) / (x - l));
+    }
+    c
+}
+
+fn set_lum(c: [f32; 3], l: f32) -> [f32; 3] {
+    let d = l - lum(c);
+    clip_color(c.map(|v| v + d))
+}
+
+fn sat(c: [f32; 3]) -> f32 {
+    c[0].max(c[1]).max(c[2]) - c[0].min(c[1]).min(c[2])
+}
+
+fn set_sat(c: [f32; 3], s: f32) -> [f32; 3] {
+    let max = c[0].max(c[1]).max(c[2]);
+    let min = c[0].min(c[1]).min(c[2]);
+    if max <= min {
+        return [0.0; 3];
+    }
+    c.map(|v| (v - min) * s / (max - min))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svg::testing::render_filter;
+    use crate::utils::compat::format;
+
+    /// Blends a red square over a `backdrop` flood and returns the pixel inside the square.
+    fn blend(mode: &str, backdrop: &str) -> u32 {
+        render_filter(
+            &format!(r#"<feFlood flood-color="{}" result="b"/><feBlend in="SourceGraphic" in2="b" mode="{}"/>"#, backdrop, mode),
+            r#"<rect x="2" y="2" width="6" height="6" fill="red"/>"#,
+            10,
+        )[5 * 10 + 5]
+    }
+
+    #[test]
+    fn test_separable_modes() {
+        assert_eq!(blend("normal", "blue"), 0xFFFF0000);
+        assert_eq!(blend("multiply", "yellow"), 0xFFFF0000);
+        assert_eq!(blend("multiply", "blue"), 0xFF000000);
+        assert_eq!(blend("screen", "blue"), 0xFFFF00FF);
+        assert_eq!(blend("darken", "#808080"), 0xFF800000);
+        assert_eq!(blend("lighten", "#808080"), 0xFFFF8080);
+        assert_eq!(blend("difference", "white"), 0xFF00FFFF);
+        assert_eq!(blend("exclusion", "white"), 0xFF00FFFF);
+        // An unknown mode is normal
+        assert_eq!(blend("bogus", "blue"), 0xFFFF0000);
+    }
+
+    #[test]
+    fn test_non_separable_modes() {
+        // Red's hue and saturation with grey's luminosity
+        let color = blend("color", "#808080");
+        assert!(color >> 16 & 0xFF > color >> 8 & 0xFF);
+        assert_eq!(color >> 8 & 0xFF, color & 0xFF);
+        // Grey's luminosity keeps nothing of red but its lightness
+        let luminosity = blend("luminosity", "#808080");
+        assert_eq!(luminosity >> 16 & 0xFF, luminosity & 0xFF);
+    }
+
+    #[test]
+    fn test_premultiplied_blending_with_transparency() {
+        let red = [1.0, 0.0, 0.0, 1.0];
+        // Over a transparent backdrop every mode shows the source
+        assert_eq!(blend_premultiplied(BlendMode::Multiply, [0.0; 4], red), red);
+        assert_eq!(blend_premultiplied(BlendMode::Difference, [0.0; 4], [0.0; 4]), [0.0; 4]);
+        assert_eq!(BlendMode::parse(" color-dodge "), Some(BlendMode::ColorDodge));
+        assert_eq!(BlendMode::parse("Multiply"), None);
+    }
+}
@@ -0,0 +1,64 @@
+use crate::svg::parser::tags::Tag;
+use crate::svg::rasterizer::filters::{pack_straight, parse_numbers, unpack_straight};
+use crate::utils::compat::Vec;
+#[cfg(not(feature = "std"))]
+use crate::utils::compat::FloatExt;
+
+/// Applies a 5x4 color matrix (`type` matrix, saturate, hueRotate or
+/// luminanceToAlpha) to unpremultiplied colors, in place.
+pub fn apply(mut buffer: Vec<u32>, tag: &Tag) -> Vec<u32> {
+    let values = tag.params.get("values").map(|s| parse_numbers(s)).unwrap_or_default();
+    let matrix = match tag.params.get("type").map(|s| s.trim()).unwrap_or("matrix") {
+        "saturate" => saturate(values.first().copied().unwrap_or(1.0)),
+        "hueRotate" => hue_rotate(values.first().copied().unwrap_or(0.0).to_radians()),
+        "luminanceToAlpha" => [
+            0.0, 0.0, 0.0, 0.0, 0.0,
+            0.0, 0.0, 0.0, 0.0, 0.0,
+            0.0, 0.0, 0.0, 0.0, 0.0,
+            0.2125, 0.7154, 0.0721, 0.0, 0.0,
+        ],
+        // A malformed matrix is ignored
+        _ => match <[f32; 20]>::try_from(values.as_slice()) {
+            Ok(matrix) => matrix,
+            Err(_) => return buffer,
+        },
+    };
+
+    for pixel in buffer.iter_mut() {
+        let c = unpack_straight(*pixel);
+        let row = |r: usize| {
+            let m = &matrix[r * 5..r * 5 + 5];
+            m[0] * c[0] + m[1] * c[1] + m[2] * c[2] + m[3] * c[3] + m[4]
+        };
+        *pixel = pack_straight([row(0), row(1), row(2), row(3)]);
+    }
+    buffer
+}
+
+fn saturate(s: f32) -> [f32; 20] {
+    [
+        0.213 + 0.787 * s, 0.715 - 0.715 * s, 0.072 - 0.072 * s, 0.0, 0.0,
+        0.213 - 0.213 * s, 0.715 + 0.285 * s, 0.072 - 0.072 * s, 0.0, 0.0,
+        0.213 - 0.213 * s, 0.715 - 0.715 * s, 0.072 + 0.928 * s, 0.0, 0.0,
+        0.0, 0.0, 0.0, 1.0, 0.0,
+    ]
+}
+
+fn hue_rotate(angle: f32) -> [f32; 20] {
+    let (sin, cos) = (angle.sin(), angle.cos());
+    [
+        0.213 + cos * 0.787 - sin * 0.213,
+        0.715 - cos * 0.715 - sin * 0.715,
+        0.072 - cos * 0.072 + sin * 0.928,
+        0.0, 0.0,
+        0.213 - cos * 0.213 + sin * 0.143,
+        0.715 + cos * 0.285 + sin * 0.140,
+        0.072 - cos * 0.072 - sin * 0.283,
+        0.0, 0.0,
+        0.213 - cos * 0.213 - sin * 0.787,
+        0.715 - cos * 0.715 + sin * 0.715,
+        0.072 + cos * 0.928 + sin * 0.072,
+        0.0, 0.0,
+        0.0, 0.0, 0.0, 1.0, 0.0,
+    ]
+}
@@ -0,0 +1,72 @@
+use crate::svg::parser::tags::Tag;
+use crate::svg::rasterizer::filters::{number, pack_straight, parse_numbers, unpack_straight};
+use crate::utils::compat::Vec;
+#[cfg(not(feature = "std"))]
+use crate::utils::compat::FloatExt;
+
+/// One `feFuncR`/`G`/`B`/`A` transfer function.
+enum TransferFunction {
+    Identity,
+    Table(Vec<f32>),
+    Discrete(Vec<f32>),
+    Linear { slope: f32, intercept: f32 },
+    Gamma { amplitude: f32, exponent: f32, offset: f32 },
+}
+
+impl TransferFunction {
+    fn from_tag(tag: &Tag) -> Self {
+        let number = |key: &str, default: f32| number(tag, key).unwrap_or(default);
+        let table = || tag.params.get("tableValues").map(|s| parse_numbers(s)).unwrap_or_default();
+
+        match tag.params.get("type").map(|s| s.trim()) {
+            Some("table") => TransferFunction::Table(table()),
+            Some("discrete") => TransferFunction::Discrete(table()),
+            Some("linear") => TransferFunction::Linear {
+                slope: number("slope", 1.0),
+                intercept: number("intercept", 0.0),
+            },
+            Some("gamma") => TransferFunction::Gamma {
+                amplitude: number("amplitude", 1.0),
+                exponent: number("exponent", 1.0),
+                offset: number("offset", 0.0),
+            },
+            _ => TransferFunction::Identity,
+        }
+    }
+
+    fn apply(&self, c: f32) -> f32 {
+        match self {
+            // An empty table is the identity
+            TransferFunction::Identity => c,
+            TransferFunction::Table(v) | TransferFunction::Discrete(v) if v.is_empty() => c,
+            TransferFunction::Table(v) if v.len() == 1 => v[0],
+            TransferFunction::Table(v) => {
+                let n = (v.len() - 1) as f32;
+                let k = ((c * n).floor() as usize).min(v.len() - 2);
+                v[k] + (c - k as f32 / n) * n * (v[k + 1] - v[k])
+            }
+            TransferFunction::Discrete(v) => {
+                let n = v.len();
+                v[((c * n as f32).floor() as usize).min(n - 1)]
+            }
+            TransferFunction::Linear { slope, intercept } => slope * c + intercept,
+            TransferFunction::Gamma { amplitude, exponent, offset } => amplitude * c.powf(*exponent) + offset,
+        }
+    }
+}
+
+/// Remaps each unpremultiplied channel through its transfer function, in
+/// place.
+pub fn apply(mut buffer: Vec<u32>, tag: &Tag) -> Vec<u32> {
+    let func = |name: &str| tag.children.iter()
+        .rfind(|c| c.name == name)
+        .map(TransferFunction::from_tag)
+        .unwrap_or(TransferFunction::Identity);
+    let funcs = [func("feFuncR"), func("feFuncG"), func("feFuncB"), func("feFuncA")];
+
+    for pixel in buffer.iter_mut() {
+        let c = unpack_straight(*pixel);
+        *pixel = pack_straight([0, 1, 2, 3].map(|i| funcs[i].apply(c[i]).clamp(0.0, 1.0)));
+    }
+    buffer
+}
@@ -0,0 +1,121 @@
+use crate::svg::parser::tags::Tag;
+use crate::svg::rasterizer::filters::{number, pack, pack_straight, parse_numbers, unpack, unpack_straight};
+use crate::utils::compat::{Vec, vec};
+
+/// Convolves the premultiplied input with `kernelMatrix`, an
+/// `orderX`x`orderY` kernel applied rotated by 180 degrees as in the spec.
+pub fn apply(input: &[u32], width: usize, height: usize, tag: &Tag) -> Vec<u32> {
+    let order = tag.params.get("order").map(|s| parse_numbers(s)).unwrap_or_default();
+    let (order_x, order_y) = match order.as_slice() {
+        [] => (3, 3),
+        [o] => (*o as usize, *o as usize),
+        [ox, oy, ..] => (*ox as usize, *oy as usize),
+    };
+
+    // An invalid kernel disables the effect
+    let kernel = tag.params.get("kernelMatrix").map(|s| parse_numbers(s)).unwrap_or_default();
+    if order_x == 0 || order_y == 0 || order_x.checked_mul(order_y) != Some(kernel.len()) {
+        return vec![0; width * height];
+    }
+    let target_x = number(tag, "targetX").map(|t| t as usize).unwrap_or(order_x / 2);
+    let target_y = number(tag, "targetY").map(|t| t as usize).unwrap_or(order_y / 2);
+    if target_x >= order_x || target_y >= order_y {
+        return vec![0; width * height];
+    }
+
+    let sum: f32 = kernel.iter().sum();
+    let divisor = match number(tag, "divisor") {
+        Some(d) if d != 0.0 => d,
+        _ if sum != 0.0 => sum,
+        _ => 1.0,
+    };
+    let bias = number(tag, "bias").unwrap_or(0.0);
+    let preserve_alpha = tag.params.get("preserveAlpha").map(|s| s.trim()) == Some("true");
+    let edge_mode = tag.params.get("edgeMode").map(|s| s.trim()).unwrap_or("duplicate");
+
+    // With preserveAlpha the colors convolve unpremultiplied
+    let pixels: Vec<[f32; 4]> = input.iter()
+        .map(|&c| if preserve_alpha { unpack_straight(c) } else { unpack(c) })
+        .collect();
+    let sample = |x: isize, y: isize| -> [f32; 4] {
+        let (w, h) = (width as isize, height as isize);
+        let (x, y) = match edge_mode {
+            "wrap" => (x.rem_euclid(w), y.rem_euclid(h)),
+            "none" if x < 0 || y < 0 || x >= w || y >= h => return [0.0; 4],
+            _ => (x.clamp(0, w - 1), y.clamp(0, h - 1)),
+        };
+        pixels[y as usize * width + x as usize]
+    };
+
+    let mut output = Vec::with_capacity(input.len());
+    for y in 0..height as isize {
+        for x in 0..width as isize {
+            let mut acc = [0.0f32; 4];
+            for j in 0..order_y {
+                for i in 0..order_x {
+                    let k = kernel[(order_y - 1 - j) * order_x + (order_x - 1 - i)];
+                    if k == 0.0 {
+                        continue;
+                    }
+                    let p = sample(x - target_x as isize + i as isize, y - target_y as isize + j as isize);
+                    for c in 0..4 {
+                        acc[c] += p[c] * k;
+                    }
+                }
+            }
+
+            let out = if preserve_alpha {
+                let alpha = unpack(input[y as usize * width + x as usize])[3];
+                let channel = |c: usize| (acc[c] / divisor + bias).clamp(0.0, 1.0);
+                pack_straight([channel(0), channel(1), channel(2), alpha])
+            } else {
+                // `max` first so an overflowing kernel cannot make the bound NaN
+                #[allow(clippy::manual_clamp)]
+                let a = (acc[3] / divisor + bias).max(0.0).min(1.0);
+                let channel = |c: usize| (acc[c] / divisor + bias * a).clamp(0.0, a);
+                pack([channel(0), channel(1), channel(2), a])
+            };
+            output.push(out);
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::svg::testing::render_filter;
+    use crate::utils::compat::Vec;
+
+    const RECT: &str = r#"<rect x="2" y="2" width="6" height="6" fill="red"/>"#;
+
+    fn convolve(attributes: &str) -> Vec<u32> {
+        render_filter(&crate::utils::compat::format!("<feConvolveMatrix {}/>", attributes), RECT, 10)
+    }
+
+    #[test]
+    fn test_identity_kernel_keeps_the_image() {
+        let pixels = convolve(r#"kernelMatrix="0 0 0 0 1 0 0 0 0""#);
+        assert_eq!(pixels[5 * 10 + 5], 0xFFFF0000);
+        assert_eq!(pixels[5 * 10 + 1], 0xFFFFFFFF);
+    }
+
+    #[test]
+    fn test_kernel_is_applied_rotated() {
+        // The kernel is flipped, so a tap on the left of the target reads the pixel to its right
+        let pixels = convolve(r#"kernelMatrix="0 0 0 1 0 0 0 0 0""#);
+        assert_eq!(pixels[5 * 10 + 1], 0xFFFF0000);
+        assert_eq!(pixels[5 * 10 + 7], 0xFFFFFFFF);
+    }
+
+    #[test]
+    fn test_invalid_kernels_disable_the_effect() {
+        for attributes in [
+            r#"order="1e30" kernelMatrix="1""#,
+            r#"order="4294967296 4294967296" kernelMatrix="1""#,
+            r#"order="-3" kernelMatrix="1""#,
+            r#"kernelMatrix="1 2 3""#,
+        ] {
+            assert!(convolve(attributes).iter().all(|&pixel| pixel == 0xFFFFFFFF), "{}", attributes);
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use crate::svg::parser::tags::Tag;
+use crate::svg::rasterizer::raster::Bounds;
+use crate::svg::utils::coords::{Axis, Length, LengthContext, Unit};
+use crate::utils::compat::{String, ToString, Vec, format, vec};
+use crate::svg::utils::Defs;
+
+/// Resolves a `filter` value into the chain of `<filter>` elements to run,
+/// in order. `url()` references are looked up in `defs`; CSS filter
+/// functions are compiled into equivalent primitives whose filter region
+/// grows `bbox` to fit blurs and shadows, with their lengths resolved in
+/// the element's `lengths`. Shadows without a color take the element's
+/// `color`.
+///
+/// Returns `None` for `none`, for invalid values and for references to
+/// anything but a `<filter>`, all of which leave the element unfiltered.
+pub(crate) fn resolve_filter_list(value: &str, defs: &Defs, bbox: Option<Bounds>, lengths: &LengthContext, color: u32) -> Option<Vec<Tag>> {
+    let value = value.trim();
+    if value.is_empty() || value == "none" {
+        return None;
+    }
+    let lookup = |id: &str| defs.get(id).filter(|f| f.name == "filter").map(|&f| f.clone());
+
+    // A bare id is accepted for compatibility with older documents
+    if !value.contains('(') {
+        return lookup(value).map(|f| vec![f]);
+    }
+
+    let mut chain = Vec::new();
+    let mut rest = value;
+    while !rest.is_empty() {
+        let open = rest.find('(')?;
+        let close = matching_paren(rest, open)?;
+        let name = rest[..open].trim().to_ascii_lowercase();
+        let args = rest[open + 1..close].trim();
+
+        let filter = if name == "url" {
+            let target = args.trim_matches(|c| c == '"' || c == '\'').trim();
+            lookup(target.strip_prefix('#')?)?
+        } else {
+            compile_function(&name, args, bbox?, lengths, color)?
+        };
+        chain.push(filter);
+        rest = rest[close + 1..].trim_start();
+    }
+    Some(chain)
+}
+
+/// Index of the `)` closing the `(` at `open`.
+fn matching_paren(s: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in s.char_indices().skip_while(|&(i, _)| i < open) {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Splits function arguments on top-level whitespace and commas.
+fn split_args(args: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in args.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            c if depth == 0 && (c.is_whitespace() || c == ',') => {
+                if start < i {
+                    parts.push(&args[start..i]);
+                }
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    if start < args.len() {
+        parts.push(&args[start..]);
+    }
+    parts
+}
+
+/// A CSS length in user units. Percentages are not allowed, and numbers
+/// need a unit unless they are zero.
+fn parse_css_length(s: &str, lengths: &LengthContext) -> Option<f32> {
+    let length = Length::parse(s)?;
+    match length.unit {
+        Unit::Percent => None,
+        Unit::None => (length.number == 0.0).then_some(0.0),
+        _ => Some(lengths.resolve(length, Axis::Horizontal)),
+    }
+}
+
+/// A non-negative number or percentage, `default` when absent.
+fn parse_amount(args: &str, default: f32) -> Option<f32> {
+    if args.is_empty() {
+        return Some(default);
+    }
+    let amount = match args.strip_suffix('%') {
+        Some(n) => n.trim().parse::<f32>().ok()? / 100.0,
+        None => args.parse().ok()?,
+    };
+    (amount >= 0.0).then_some(amount)
+}
+
+/// An angle in degrees; unitless zero is allowed.
+fn parse_angle(args: &str) -> Option<f32> {
+    if args.is_empty() {
+        return Some(0.0);
+    }
+    let units = [("deg", 1.0), ("grad", 0.9), ("rad", 180.0 / core::f32::consts::PI), ("turn", 360.0)];
+    for (unit, degrees) in units {
+        if let Some(n) = args.strip_suffix(unit) {
+            return n.parse::<f32>().ok().map(|n| n * degrees);
+        }
+    }
+    args.parse().ok().filter(|&n: &f32| n == 0.0)
+}
+
+fn element(name: &str, params: &[(&str, String)], children: Vec<Tag>) -> Tag {
+    let mut tag = Tag::new();
+    tag.name = name.to_string();
+    for (key, value) in params {
+        tag.params.insert(key.to_string(), value.clone());
+    }
+    tag.children = children;
+    tag
+}
+
+/// `feComponentTransfer` applying the same function to r, g and b, or to
+/// alpha alone.
+fn transfer(alpha: bool, params: &[(&str, String)]) -> Tag {
+    let channels: &[&str] = if alpha { &["feFuncA"] } else { &["feFuncR", "feFuncG", "feFuncB"] };
+    let funcs = channels.iter().map(|name| element(name, params, Vec::new())).collect();
+    element("feComponentTransfer", &[], funcs)
+}
+
+fn color_matrix(kind: &str, values: String) -> Tag {
+    element("feColorMatrix", &[("type", kind.to_string()), ("values", values)], Vec::new())
+}
+
+/// Compiles one CSS filter function into a `<filter>` with the equivalent
+/// primitives from the Filter Effects spec.
+fn compile_function(name: &str, args: &str, bbox: Bounds, lengths: &LengthContext, current_color: u32) -> Option<Tag> {
+    // How far the result can reach past the bounding box, in user units
+    let mut reach = (0.0f32, 0.0f32, 0.0f32, 0.0f32);
+
+    let primitive = match name {
+        "blur" => {
+            let sigma = if args.is_empty() { 0.0 } else { parse_css_length(args, lengths)? };
+            if sigma < 0.0 {
+                return None;
+            }
+            reach = (3.0 * sigma, 3.0 * sigma, 3.0 * sigma, 3.0 * sigma);
+            element("feGaussianBlur", &[("stdDeviation", sigma.to_string())], Vec::new())
+        }
+        "brightness" => {
+            let amount = parse_amount(args, 1.0)?;
+            transfer(false, &[("type", "linear".to_string()), ("slope", amount.to_string())])
+        }
+        "contrast" => {
+            let amount = parse_amount(args, 1.0)?;
+            transfer(false, &[
+                ("type", "linear".to_string()),
+                ("slope", amount.to_string()),
+                ("intercept", (0.5 - 0.5 * amount).to_string()),
+            ])
+        }
+        "grayscale" => {
+            let amount = parse_amount(args, 1.0)?.min(1.0);
+            color_matrix("saturate", (1.0 - amount).to_string())
+        }
+        "hue-rotate" => color_matrix("hueRotate", parse_angle(args)?.to_string()),
+        "invert" => {
+            let amount = parse_amount(args, 1.0)?.min(1.0);
+            transfer(false, &[("type", "table".to_string()), ("tableValues", format!("{} {}", amount, 1.0 - amount))])
+        }
+        "opacity" => {
+            let amount = parse_amount(args, 1.0)?.min(1.0);
+            transfer(true, &[("type", "table".to_string()), ("tableValues", format!("0 {}", amount))])
+        }
+        "saturate" => color_matrix("saturate", parse_amount(args, 1.0)?.to_string()),
+        "sepia" => {
+            let s = 1.0 - parse_amount(args, 1.0)?.min(1.0);
+            let matrix = [
+                0.393 + 0.607 * s, 0.769 - 0.769 * s, 0.189 - 0.189 * s, 0.0, 0.0,
+                0.349 - 0.349 * s, 0.686 + 0.314 * s, 0.168 - 0.168 * s, 0.0, 0.0,
+                0.272 - 0.272 * s, 0.534 - 0.534 * s, 0.131 + 0.869 * s, 0.0, 0.0,
+                0.0, 0.0, 0.0, 1.0, 0.0,
+            ];
+            let values: Vec<String> = matrix.iter().map(|v| v.to_string()).collect();
+            color_matrix("matrix", values.join(" "))
+        }
+        "drop-shadow" => {
+            // Two or three lengths, with the color before or after them
+            let mut args = split_args(args);
+            let color = match (args.first(), args.last()) {
+                (Some(first), _) if parse_css_length(first, lengths).is_none() => Some(args.remove(0)),
+                (_, Some(last)) if parse_css_length(last, lengths).is_none() => args.pop(),
+                _ => None,
+            };
+            let offsets = args.into_iter().map(|arg| parse_css_length(arg, lengths)).collect::<Option<Vec<f32>>>()?;
+            let (dx, dy, radius) = match offsets.as_slice() {
+                [dx, dy] => (*dx, *dy, 0.0),
+                [dx, dy, radius] if *radius >= 0.0 => (*dx, *dy, *radius),
+                _ => return None,
+            };
+            // The blur radius is twice the standard deviation
+            let sigma = radius / 2.0;
+            reach = (
+                (3.0 * sigma - dx).max(0.0),
+                (3.0 * sigma - dy).max(0.0),
+                (3.0 * sigma + dx).max(0.0),
+                (3.0 * sigma + dy).max(0.0),
+            );
+            // Without a color the shadow is drawn in currentColor
+            let (flood_color, flood_opacity) = match color.filter(|c| !c.eq_ignore_ascii_case("currentcolor")) {
+                Some(color) => (color.to_string(), 1.0),
+                None => {
+                    let [a, r, g, b] = current_color.to_be_bytes();
+                    (format!("rgb({}, {}, {})", r, g, b), a as f32 / 255.0)
+                }
+            };
+            element("feDropShadow", &[
+                ("dx", dx.to_string()),
+                ("dy", dy.to_string()),
+                ("stdDeviation", sigma.to_string()),
+                ("flood-color", flood_color),
+                ("flood-opacity", flood_opacity.to_string()),
+            ], Vec::new())
+        }
+        _ => return None,
+    };
+
+    // The default region, grown by however far the effect reaches
+    let (left, top, right, bottom) = reach;
+    let x = bbox.x - 0.1 * bbox.width - left;
+    let y = bbox.y - 0.1 * bbox.height - top;
+    Some(element("filter", &[
+        ("filterUnits", "userSpaceOnUse".to_string()),
+        ("x", x.to_string()),
+        ("y", y.to_string()),
+        ("width", (1.2 * bbox.width + left + right).to_string()),
+        ("height", (1.2 * bbox.height + top + bottom).to_string()),
+    ], vec![primitive]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn primitive(value: &str) -> Option<Tag> {
+        let bbox = Bounds { x: 0.0, y: 0.0, width: 10.0, height: 10.0 };
+        let lengths = LengthContext::default().with_font_size(20.0);
+        let mut chain = resolve_filter_list(value, &Defs::new(), Some(bbox), &lengths, 0x80FF0000)?;
+        Some(chain.remove(0).children.remove(0))
+    }
+
+    #[test]
+    fn test_lengths_in_any_unit() {
+        let blur = primitive("blur(1em)").unwrap();
+        assert_eq!(blur.params.get("stdDeviation").map(String::as_str), Some("20"));
+
+        let shadow = primitive("drop-shadow(0 1pt .5em)").unwrap();
+        assert_eq!(shadow.params.get("dy").map(String::as_str), Some("1.3333334"));
+        assert_eq!(shadow.params.get("stdDeviation").map(String::as_str), Some("5"));
+    }
+
+    #[test]
+    fn test_invalid_lengths_drop_the_filter() {
+        assert!(primitive("blur(3)").is_none());
+        assert!(primitive("blur(10%)").is_none());
+        assert!(primitive("blur(-1px)").is_none());
+        assert!(primitive("drop-shadow(1px)").is_none());
+        assert!(primitive("blur(0)").is_some());
+    }
+
+    #[test]
+    fn test_drop_shadow_defaults_to_current_color() {
+        let param = |tag: &Tag, key: &str| tag.params.get(key).cloned();
+        let shadow = primitive("drop-shadow(1px 1px)").unwrap();
+        assert_eq!(param(&shadow, "flood-color").as_deref(), Some("rgb(255, 0, 0)"));
+        assert_eq!(param(&shadow, "flood-opacity").and_then(|o| o.parse::<f32>().ok()), Some(128.0 / 255.0));
+
+        let explicit = primitive("drop-shadow(blue 1px 1px)").unwrap();
+        assert_eq!(param(&explicit, "flood-color").as_deref(), Some("blue"));
+        let current = primitive("drop-shadow(1px 1px currentColor)").unwrap();
+        assert_eq!(param(&current, "flood-color").as_deref(), Some("rgb(255, 0, 0)"));
+    }
+
+    #[test]
+    fn test_drop_shadow_color_is_inherited_from_style() {
+        let svg = br#"<svg width="10" height="10" style="color: blue">
+            <rect width="4" height="4" fill="red" style="filter: drop-shadow(5px 5px)"/>
+        </svg>"#;
+        let pixels = crate::svg::render(svg, 10, 10).unwrap();
+        assert_eq!(pixels[0], 0xFFFF0000);
+        assert_eq!(pixels[7 * 10 + 7], 0xFF0000FF);
+    }
+}
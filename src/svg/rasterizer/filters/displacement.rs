@@ -0,0 +1,33 @@
+use crate::svg::parser::tags::Tag;
+use crate::svg::rasterizer::filters::{number, unpack_straight, FilterSpace};
+use crate::utils::compat::Vec;
+#[cfg(not(feature = "std"))]
+use crate::utils::compat::FloatExt;
+
+/// Moves each pixel of `in` by `scale` times the `in2` channels picked by
+/// `xChannelSelector` and `yChannelSelector`, centred on 0.5. The map is
+/// read unpremultiplied.
+pub fn apply(input: &[u32], map: &[u32], width: usize, height: usize, tag: &Tag, space: &FilterSpace) -> Vec<u32> {
+    let scale = number(tag, "scale").unwrap_or(0.0);
+    let channel = |key: &str| match tag.params.get(key).map(|s| s.trim()) {
+        Some("R") => 0,
+        Some("G") => 1,
+        Some("B") => 2,
+        _ => 3,
+    };
+    let (scale_x, scale_y) = space.scale(scale, scale);
+    let (x_channel, y_channel) = (channel("xChannelSelector"), channel("yChannelSelector"));
+
+    let mut output = Vec::with_capacity(input.len());
+    for y in 0..height {
+        for x in 0..width {
+            let m = unpack_straight(map[y * width + x]);
+            let sx = (x as f32 + scale_x * (m[x_channel] - 0.5)).round();
+            let sy = (y as f32 + scale_y * (m[y_channel] - 0.5)).round();
+
+            let inside = sx >= 0.0 && sy >= 0.0 && sx < width as f32 && sy < height as f32;
+            output.push(if inside { input[sy as usize * width + sx as usize] } else { 0 });
+        }
+    }
+    output
+}
@@ -0,0 +1,32 @@
+use crate::svg::parser::tags::Tag;
+use crate::svg::rasterizer::filters::flood::flood_color;
+use crate::svg::rasterizer::filters::{gaussian, number, offset, parse_numbers, premultiply, source_over, FilterSpace};
+use crate::utils::compat::Vec;
+
+/// Blurs and offsets the input's alpha, tints it with `flood-color`, and
+/// draws the input on top.
+pub fn apply(input: &[u32], width: usize, height: usize, tag: &Tag, space: &FilterSpace) -> Vec<u32> {
+    let number = |key: &str, default: f32| number(tag, key).unwrap_or(default);
+    let std_dev = tag.params.get("stdDeviation").map(|s| parse_numbers(s)).unwrap_or_default();
+    let (sigma_x, sigma_y) = match std_dev.as_slice() {
+        [] => (2.0, 2.0),
+        [s] => (*s, *s),
+        [sx, sy, ..] => (*sx, *sy),
+    };
+
+    let color = premultiply(flood_color(tag));
+    let alpha: Vec<u32> = input.iter().map(|&c| c & 0xFF000000).collect();
+    let (sigma_x, sigma_y) = space.scale(sigma_x.max(0.0), sigma_y.max(0.0));
+    let (dx, dy) = space.vector(number("dx", 2.0), number("dy", 2.0));
+    let blurred = gaussian::blur(&alpha, width, height, sigma_x, sigma_y);
+    let shadow = offset::shift(&blurred, width, height, dx, dy);
+
+    // Scale every premultiplied channel of the color by the shadow's alpha
+    let mut output: Vec<u32> = shadow.iter().map(|&c| {
+        let a = c >> 24;
+        let channel = |shift: u32| (((color >> shift) & 0xFF) * a + 127) / 255;
+        (channel(24) << 24) | (channel(16) << 16) | (channel(8) << 8) | channel(0)
+    }).collect();
+    source_over(&mut output, input);
+    output
+}
@@ -0,0 +1,51 @@
+use crate::svg::parser::tags::Tag;
+use crate::svg::utils::style::get_property;
+use crate::svg::rasterizer::filters::{parse_number, premultiply};
+use crate::svg::utils::color::parse_color_value;
+use crate::utils::compat::{Vec, vec};
+#[cfg(not(feature = "std"))]
+use crate::utils::compat::FloatExt;
+
+pub fn apply(width: usize, height: usize, tag: &Tag) -> Vec<u32> {
+    vec![premultiply(flood_color(tag)); width * height]
+}
+
+/// `flood-color` combined with `flood-opacity`, shared with `feDropShadow`.
+pub(crate) fn flood_color(tag: &Tag) -> u32 {
+    let color = get_property(tag, "flood-color").map(parse_color_value).unwrap_or(0xFF000000);
+    let opacity = get_property(tag, "flood-opacity")
+        .and_then(parse_number)
+        .unwrap_or(1.0)
+        .clamp(0.0, 1.0);
+
+    let alpha = (((color >> 24) & 0xFF) as f32 * opacity).round() as u32;
+    (color & 0x00FFFFFF) | (alpha << 24)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::svg::testing::render_filter;
+    use crate::utils::compat::{format, Vec};
+
+    fn flood(attributes: &str) -> Vec<u32> {
+        render_filter(&format!("<feFlood {}/>", attributes), r#"<rect width="1" height="1"/>"#, 4)
+    }
+
+    #[test]
+    fn test_flood_fills_the_region() {
+        assert!(flood(r#"flood-color="lime""#).iter().all(|&pixel| pixel == 0xFF00FF00));
+        assert!(flood("").iter().all(|&pixel| pixel == 0xFF000000));
+    }
+
+    #[test]
+    fn test_flood_properties_from_style() {
+        assert_eq!(flood(r#"flood-color="blue" style="flood-color: lime; flood-opacity: 0.5""#)[0], 0xFF7FFF7F);
+    }
+
+    #[test]
+    fn test_flood_opacity_is_clamped() {
+        assert_eq!(flood(r#"flood-color="lime" flood-opacity="1e30""#)[0], 0xFF00FF00);
+        assert_eq!(flood(r#"flood-color="lime" flood-opacity="-1e30""#)[0], 0xFFFFFFFF);
+        assert_eq!(flood(r#"flood-color="lime" flood-opacity="NaN""#)[0], 0xFF00FF00);
+    }
+}
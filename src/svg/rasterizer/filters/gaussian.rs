@@ -1,189 +1,197 @@
 use crate::svg::parser::tags::Tag;
+use crate::svg::rasterizer::filters::{pack, parse_numbers, unpack, FilterSpace};
 use core::f32::consts::PI;
 use crate::utils::compat::FloatExt;
 use crate::utils::compat::Vec;
 
-pub fn apply(input: &[u32], width: usize, height: usize, tag: &Tag) -> Vec<u32> {
-    let std_dev_str = tag.params.get("stdDeviation").map(|s| s.as_str()).unwrap_or("0");
-    let std_devs: Vec<f32> = std_dev_str
-        .split_whitespace()
-        .filter_map(|s| s.parse().ok())
-        .collect();
-
-    let (sigma_x, sigma_y) = if std_devs.is_empty() {
-        (0.0, 0.0)
-    } else if std_devs.len() == 1 {
-        (std_devs[0], std_devs[0])
-    } else {
-        (std_devs[0], std_devs[1])
+/// Below this deviation three box blurs visibly miss the Gaussian's shape,
+/// so the kernel is sampled exactly instead.
+const BOX_BLUR_MIN_SIGMA: f32 = 2.0;
+
+pub fn apply(input: &[u32], width: usize, height: usize, tag: &Tag, space: &FilterSpace) -> Vec<u32> {
+    let std_devs = tag.params.get("stdDeviation").map(|s| parse_numbers(s)).unwrap_or_default();
+    let (sigma_x, sigma_y) = match std_devs.as_slice() {
+        [] => (0.0, 0.0),
+        [s] => (*s, *s),
+        [sx, sy, ..] => (*sx, *sy),
     };
 
-    if sigma_x == 0.0 && sigma_y == 0.0 {
+    let (sigma_x, sigma_y) = space.scale(sigma_x, sigma_y);
+    blur(input, width, height, sigma_x, sigma_y)
+}
+
+/// Blurs premultiplied pixels with separate horizontal and vertical
+/// standard deviations, in pixels. Everything outside the buffer counts as
+/// transparent.
+pub(crate) fn blur(input: &[u32], width: usize, height: usize, sigma_x: f32, sigma_y: f32) -> Vec<u32> {
+    if width == 0 || height == 0 || (sigma_x <= 0.0 && sigma_y <= 0.0) {
         return input.to_vec();
     }
 
-    // Horizontal Pass
-    let temp = if sigma_x > 0.0 {
-        box_blur(input, width, height, sigma_x, true)
-    } else {
-        input.to_vec()
-    };
+    let mut pixels: Vec<[f32; 4]> = input.iter().map(|&c| unpack(c)).collect();
+    let mut scratch = Vec::new();
 
-    // Vertical Pass
-    if sigma_y > 0.0 {
-        box_blur(&temp, width, height, sigma_y, false)
-    } else {
-        temp
+    // Horizontal pass, row by row in place
+    if sigma_x > 0.0 {
+        let pass = Pass::new(sigma_x);
+        for row in pixels.chunks_exact_mut(width) {
+            pass.run(row, &mut scratch);
+        }
     }
-}
 
-// Using 3-pass Box Blur to approximate Gaussian
-// http://blog.ivank.net/fastest-gaussian-blur.html
-fn box_blur(src: &[u32], w: usize, h: usize, sigma: f32, horizontal: bool) -> Vec<u32> {
-    let mut boxes = boxes_for_gauss(sigma, 3);
-    let mut out = src.to_vec();
-    let mut temp = src.to_vec();
-
-    for &b in &boxes {
-        let r = (b - 1) / 2;
-        if horizontal {
-            box_blur_h(&out, &mut temp, w, h, r);
-        } else {
-            box_blur_t(&out, &mut temp, w, h, r);
+    // Vertical pass, through a column copy
+    if sigma_y > 0.0 {
+        let pass = Pass::new(sigma_y);
+        let mut column = Vec::with_capacity(height);
+        for x in 0..width {
+            column.clear();
+            column.extend((0..height).map(|y| pixels[y * width + x]));
+            pass.run(&mut column, &mut scratch);
+            for (y, &p) in column.iter().enumerate() {
+                pixels[y * width + x] = p;
+            }
         }
-        out.copy_from_slice(&temp);
     }
-    out
-}
-
-fn boxes_for_gauss(sigma: f32, n: usize) -> Vec<isize> {
-    let w_ideal = (12.0 * sigma * sigma / n as f32 + 1.0).sqrt();
-    let mut wl = w_ideal.floor() as isize;
-    if wl % 2 == 0 { wl -= 1; }
-    let wu = wl + 2;
 
-    let m_ideal = (12.0 * sigma * sigma - n as f32 * wl as f32 * wl as f32 - 4.0 * n as f32 * wl as f32 - 3.0 * n as f32) / (-4.0 * wl as f32 - 4.0);
-    let m = m_ideal.round() as usize;
+    pixels.into_iter().map(pack).collect()
+}
 
-    let mut sizes = Vec::new();
-    for i in 0..n {
-        sizes.push(if i < m { wl } else { wu });
-    }
-    sizes
+/// One direction of the blur.
+enum Pass {
+    /// Normalized weights of an exact kernel, centred on the middle tap.
+    Kernel(Vec<f32>),
+    /// Three successive box blurs as `(left, right)` extents, per the
+    /// Filter Effects spec's approximation.
+    Boxes([(usize, usize); 3]),
 }
 
-fn box_blur_h(scl: &[u32], tcl: &mut [u32], w: usize, h: usize, r: isize) {
-    let iarr = 1.0 / (r + r + 1) as f32;
-    
-    for i in 0..h {
-        let ti = i * w;
-        let li = ti;
-        let ri = ti + w - 1;
-        
-        let fv = scl[ti];
-        let lv = scl[ri];
-        
-        let (mut val_a, mut val_r, mut val_g, mut val_b) = unpack(fv);
-        
-        val_a *= (r + 1) as f32;
-        val_r *= (r + 1) as f32;
-        val_g *= (r + 1) as f32;
-        val_b *= (r + 1) as f32;
-
-        for j in 0..r {
-            let (a, red, g, b) = unpack(scl[ti + j as usize]);
-            val_a += a; val_r += red; val_g += g; val_b += b;
-        }
-        
-        for j in 0..=r {
-            let (a, red, g, b) = unpack(scl[ri.min(ti + (j as usize))]);
-            val_a += a; val_r += red; val_g += g; val_b += b;
+impl Pass {
+    fn new(sigma: f32) -> Self {
+        if sigma < BOX_BLUR_MIN_SIGMA {
+            let radius = (3.0 * sigma).ceil().max(1.0) as isize;
+            let mut weights: Vec<f32> = (-radius..=radius)
+                .map(|i| (-((i * i) as f32) / (2.0 * sigma * sigma)).exp())
+                .collect();
+            let total: f32 = weights.iter().sum();
+            weights.iter_mut().for_each(|w| *w /= total);
+            return Pass::Kernel(weights);
         }
-        
-        // Initial Window setup above is slightly wrong for generic box blur, 
-        // standard impl involves moving window.
-        // Let's switch to a simpler accumulating sliding window.
-        
-        // Reset
-        let mut val_a = 0.0; let mut val_r = 0.0; let mut val_g = 0.0; let mut val_b = 0.0;
-
-        // Pre-fill
-        for j in -r..=r {
-            let idx = ti + (j.clamp(0, (w - 1) as isize) as usize);
-            let (a, red, g, b) = unpack(scl[idx]);
-            val_a += a; val_r += red; val_g += g; val_b += b;
-        }
-
-        for j in 0..w {
-            tcl[ti + j] = pack(val_a * iarr, val_r * iarr, val_g * iarr, val_b * iarr);
 
-            let p_out_idx = ti + ((j as isize - r).clamp(0, (w - 1) as isize) as usize);
-            let p_in_idx = ti + ((j as isize + r + 1).clamp(0, (w - 1) as isize) as usize);
-
-            let (out_a, out_r, out_g, out_b) = unpack(scl[p_out_idx]);
-            let (in_a, in_r, in_g, in_b) = unpack(scl[p_in_idx]);
+        let d = (sigma * 3.0 * (2.0 * PI).sqrt() / 4.0 + 0.5).floor() as usize;
+        if d % 2 == 1 {
+            let r = d / 2;
+            Pass::Boxes([(r, r); 3])
+        } else {
+            // Two boxes offset either way, then one of size d + 1 centred
+            let r = d / 2;
+            Pass::Boxes([(r, r - 1), (r - 1, r), (r, r)])
+        }
+    }
 
-            val_a = val_a - out_a + in_a;
-            val_r = val_r - out_r + in_r;
-            val_g = val_g - out_g + in_g;
-            val_b = val_b - out_b + in_b;
+    fn run(&self, line: &mut [[f32; 4]], scratch: &mut Vec<[f32; 4]>) {
+        scratch.clear();
+        scratch.extend_from_slice(line);
+        match self {
+            Pass::Kernel(weights) => {
+                let radius = weights.len() / 2;
+                for (i, out) in line.iter_mut().enumerate() {
+                    let mut acc = [0.0; 4];
+                    let from = i.saturating_sub(radius);
+                    let to = (i + radius).min(scratch.len() - 1);
+                    for (j, p) in scratch[from..=to].iter().enumerate() {
+                        let w = weights[from + j + radius - i];
+                        for c in 0..4 {
+                            acc[c] += p[c] * w;
+                        }
+                    }
+                    *out = acc;
+                }
+            }
+            Pass::Boxes(boxes) => {
+                for (n, &(left, right)) in boxes.iter().enumerate() {
+                    if n > 0 {
+                        scratch.copy_from_slice(line);
+                    }
+                    box_blur(scratch, line, left, right);
+                }
+            }
         }
     }
 }
 
-fn box_blur_t(scl: &[u32], tcl: &mut [u32], w: usize, h: usize, r: isize) {
-    let iarr = 1.0 / (r + r + 1) as f32;
-
-    for i in 0..w {
-        let ti = i;
-        let li = ti;
-        let ri = ti + (h - 1) * w;
-
-        let mut val_a = 0.0; let mut val_r = 0.0; let mut val_g = 0.0; let mut val_b = 0.0;
+/// Averages `src` over `[i - left, i + right]` into `dst` with a running sum.
+fn box_blur(src: &[[f32; 4]], dst: &mut [[f32; 4]], left: usize, right: usize) {
+    let n = src.len();
+    let scale = 1.0 / (left + right + 1) as f32;
+    let mut sum = [0.0f32; 4];
+    for p in &src[..right.min(n - 1) + 1] {
+        for c in 0..4 {
+            sum[c] += p[c];
+        }
+    }
 
-         // Pre-fill
-         for j in -r..=r {
-            let y = j.clamp(0, (h - 1) as isize) as usize;
-            let idx = ti + y * w;
-            let (a, red, g, b) = unpack(scl[idx]);
-            val_a += a; val_r += red; val_g += g; val_b += b;
+    for (i, out) in dst.iter_mut().enumerate() {
+        *out = sum.map(|v| v * scale);
+        if let Some(p) = src.get(i + right + 1) {
+            for c in 0..4 {
+                sum[c] += p[c];
+            }
+        }
+        if let Some(p) = i.checked_sub(left).map(|j| &src[j]) {
+            for c in 0..4 {
+                sum[c] -= p[c];
+            }
         }
+    }
+}
 
-        for j in 0..h {
-            tcl[ti + j * w] = pack(val_a * iarr, val_r * iarr, val_g * iarr, val_b * iarr);
+#[cfg(test)]
+mod tests {
+    use crate::svg::testing::render_filter;
+    use crate::utils::compat::{format, Vec};
 
-            let y_out = (j as isize - r).clamp(0, (h - 1) as isize) as usize;
-            let y_in = (j as isize + r + 1).clamp(0, (h - 1) as isize) as usize;
+    fn blur(std_deviation: &str, source: &str) -> Vec<u32> {
+        render_filter(&format!(r#"<feGaussianBlur stdDeviation="{}"/>"#, std_deviation), source, 11)
+    }
 
-            let p_out_idx = ti + y_out * w;
-            let p_in_idx = ti + y_in * w;
+    /// Opacity of a black pixel over the white canvas.
+    fn alpha(pixel: u32) -> u32 {
+        0xFF - (pixel & 0xFF)
+    }
 
-            let (out_a, out_r, out_g, out_b) = unpack(scl[p_out_idx]);
-            let (in_a, in_r, in_g, in_b) = unpack(scl[p_in_idx]);
+    #[test]
+    fn test_small_sigma_uses_the_exact_kernel() {
+        let pixels = blur("1", r#"<rect x="5" y="5" width="1" height="1" fill="black"/>"#);
+        // Products of the normalised Gaussian weights 0.399 and 0.242
+        assert!(alpha(pixels[5 * 11 + 5]).abs_diff(41) <= 1);
+        assert!(alpha(pixels[5 * 11 + 6]).abs_diff(25) <= 1);
+        assert!(alpha(pixels[6 * 11 + 6]).abs_diff(15) <= 1);
+        assert_eq!(pixels[5 * 11 + 4], pixels[5 * 11 + 6]);
+        assert_eq!(pixels[4 * 11 + 5], pixels[6 * 11 + 5]);
+    }
 
-            val_a = val_a - out_a + in_a;
-            val_r = val_r - out_r + in_r;
-            val_g = val_g - out_g + in_g;
-            val_b = val_b - out_b + in_b;
-        }
+    #[test]
+    fn test_blur_per_axis() {
+        let pixels = blur("1 0", r#"<rect x="5" y="5" width="1" height="1" fill="black"/>"#);
+        assert_ne!(pixels[5 * 11 + 6], 0xFFFFFFFF);
+        assert_eq!(pixels[6 * 11 + 5], 0xFFFFFFFF);
     }
-}
 
-#[inline]
-fn unpack(c: u32) -> (f32, f32, f32, f32) {
-    (
-        ((c >> 24) & 0xFF) as f32,
-        ((c >> 16) & 0xFF) as f32,
-        ((c >> 8) & 0xFF) as f32,
-        (c & 0xFF) as f32,
-    )
-}
+    #[test]
+    fn test_colors_are_blurred_premultiplied() {
+        // Transparent black around the square must not darken its blurred edge
+        let pixels = blur("1.5", r#"<rect x="3" y="3" width="5" height="5" fill="red"/>"#);
+        for &pixel in &pixels[5 * 11..6 * 11] {
+            assert!(pixel >> 16 & 0xFF >= 0xFE);
+            assert!((pixel >> 8 & 0xFF).abs_diff(pixel & 0xFF) <= 1);
+        }
+        assert_ne!(pixels[5 * 11 + 1], 0xFFFFFFFF);
+    }
 
-#[inline]
-fn pack(a: f32, r: f32, g: f32, b: f32) -> u32 {
-    let ia = a.round().clamp(0.0, 255.0) as u32;
-    let ir = r.round().clamp(0.0, 255.0) as u32;
-    let ig = g.round().clamp(0.0, 255.0) as u32;
-    let ib = b.round().clamp(0.0, 255.0) as u32;
-    (ia << 24) | (ir << 16) | (ig << 8) | ib
+    #[test]
+    fn test_large_sigma_spreads_evenly() {
+        let pixels = blur("3", r#"<rect x="5" y="5" width="1" height="1" fill="black"/>"#);
+        assert!(alpha(pixels[5 * 11 + 5]) >= alpha(pixels[5 * 11 + 7]));
+        assert_eq!(pixels[5 * 11 + 2], pixels[5 * 11 + 8]);
+    }
 }
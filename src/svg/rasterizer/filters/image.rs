@@ -0,0 +1,123 @@
+use crate::svg::parser::tags::Tag;
+use crate::svg::rasterizer::canva::Canvas;
+use crate::svg::rasterizer::filters::{premultiply, FilterSpace};
+use crate::svg::rasterizer::tags::image::{draw_image, load_image_element};
+use crate::svg::rasterizer::tags::text::Font;
+use crate::svg::utils::transform::Transform;
+use crate::utils::compat::{ToString, Vec, format};
+use crate::svg::utils::Defs;
+
+/// Renders an `feImage`: either an element referenced as `#id`, drawn in the
+/// filtered element's user space, or a `data:` image placed like `<image>`
+/// in primitive units.
+pub fn apply(
+    width: usize,
+    height: usize,
+    tag: &Tag,
+    defs: &Defs,
+    fonts: &[Font],
+    space: &FilterSpace,
+) -> Vec<u32> {
+    render(width, height, tag, defs, fonts, space).into_iter().map(premultiply).collect()
+}
+
+/// Draws the image onto a straight-alpha canvas of the buffer's size.
+fn render(
+    width: usize,
+    height: usize,
+    tag: &Tag,
+    defs: &Defs,
+    fonts: &[Font],
+    space: &FilterSpace,
+) -> Vec<u32> {
+    let mut canvas = Canvas::new_transparent(width, height);
+    let Some(href) = tag.params.get("href").or_else(|| tag.params.get("xlink:href")).map(|s| s.trim()) else {
+        return canvas.data;
+    };
+
+    if let Some(id) = href.strip_prefix('#') {
+        if let Some(&element) = defs.get(id) {
+            // The referenced element can't pull in a filter again
+            let mut element = element.clone();
+            element.params.remove("filter");
+            canvas.draw(&element, defs, fonts, &space.user, &space.lengths);
+        }
+        return canvas.data;
+    }
+
+    let mut image = Tag::new();
+    image.name = "image".to_string();
+    image.params.insert("href".to_string(), href.to_string());
+    if let Some(aspect) = tag.params.get("preserveAspectRatio") {
+        image.params.insert("preserveAspectRatio".to_string(), aspect.clone());
+    }
+
+    // Without a size the image fills the filter region
+    if tag.params.contains_key("width") || tag.params.contains_key("height") {
+        for key in ["x", "y", "width", "height"] {
+            if let Some(value) = tag.params.get(key) {
+                image.params.insert(key.to_string(), value.clone());
+            }
+        }
+        if let Some(image) = load_image_element(&image, &space.primitive_lengths()) {
+            draw_image(&image, &mut canvas, &space.primitive);
+        }
+    } else {
+        image.params.insert("width".to_string(), format!("{}", width));
+        image.params.insert("height".to_string(), format!("{}", height));
+        if let Some(image) = load_image_element(&image, &space.lengths) {
+            draw_image(&image, &mut canvas, &Transform::identity());
+        }
+    }
+    canvas.data
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::svg::testing::{ink_bounds, red_blue_image, render, render_filter};
+    use crate::utils::compat::{format, Vec};
+
+    fn fe_image(attributes: &str) -> Vec<u32> {
+        render_filter(&format!(r#"<feImage href="{}" {}/>"#, red_blue_image(), attributes), "", 8)
+    }
+
+    #[test]
+    fn test_image_fills_the_filter_region() {
+        // Centred and fitted inside by default
+        let pixels = fe_image("");
+        assert_eq!(ink_bounds(&pixels, 8), Some((0, 2, 7, 5)));
+        assert_eq!(pixels[3 * 8 + 1], 0xFFFF0000);
+        assert_eq!(pixels[3 * 8 + 6], 0xFF0000FF);
+    }
+
+    #[test]
+    fn test_preserve_aspect_ratio() {
+        let stretched = fe_image(r#"preserveAspectRatio="none""#);
+        assert_eq!(ink_bounds(&stretched, 8), Some((0, 0, 7, 7)));
+        assert_eq!((stretched[1], stretched[7 * 8 + 6]), (0xFFFF0000, 0xFF0000FF));
+
+        let top = fe_image(r#"preserveAspectRatio="xMidYMin meet""#);
+        assert_eq!(ink_bounds(&top, 8), Some((0, 0, 7, 3)));
+
+        // Slicing keeps the middle of the image, half red and half blue
+        let sliced = fe_image(r#"preserveAspectRatio="xMidYMid slice""#);
+        assert_eq!(ink_bounds(&sliced, 8), Some((0, 0, 7, 7)));
+        assert_eq!((sliced[0], sliced[7 * 8 + 7]), (0xFFFF0000, 0xFF0000FF));
+    }
+
+    #[test]
+    fn test_sized_image_is_placed_in_primitive_units() {
+        let pixels = fe_image(r#"x="2" y="4" width="4" height="2""#);
+        assert_eq!(ink_bounds(&pixels, 8), Some((2, 4, 5, 5)));
+    }
+
+    #[test]
+    fn test_element_reference() {
+        let pixels = render(r##"<svg width="8" height="8">
+            <filter id="f" filterUnits="userSpaceOnUse" x="0" y="0" width="8" height="8"><feImage href="#r"/></filter>
+            <rect id="r" x="6" y="6" width="2" height="2" fill="lime" filter="url(#f)"/>
+        </svg>"##, 8, 8);
+        assert_eq!(ink_bounds(&pixels, 8), Some((6, 6, 7, 7)));
+        assert_eq!(pixels[7 * 8 + 7], 0xFF00FF00);
+    }
+}
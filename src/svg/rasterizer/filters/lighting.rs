@@ -0,0 +1,271 @@
+use crate::svg::parser::tags::Tag;
+use crate::svg::utils::style::get_property;
+use crate::svg::rasterizer::filters::{number, pack, parse_numbers, unpack, FilterSpace};
+use crate::svg::utils::color::parse_color_value;
+use crate::svg::utils::transform::Transform;
+use crate::utils::compat::{Vec, vec};
+#[cfg(not(feature = "std"))]
+use crate::utils::compat::FloatExt;
+
+/// A light source, with positions already in buffer pixels.
+enum Light {
+    Distant {
+        direction: [f32; 3],
+    },
+    Point {
+        position: [f32; 3],
+    },
+    Spot {
+        position: [f32; 3],
+        /// Unit vector from the light toward `pointsAt`.
+        axis: [f32; 3],
+        exponent: f32,
+        /// Cosine of `limitingConeAngle`, if any.
+        cone: Option<f32>,
+    },
+}
+
+impl Light {
+    /// Reads the first light source child of a lighting primitive.
+    fn from_children(tag: &Tag, transform: &Transform) -> Option<Self> {
+        let (sx, sy) = transform.get_scale();
+        let z_scale = (sx + sy) / 2.0;
+
+        tag.children.iter().find_map(|light| {
+            let number = |key: &str, default: f32| number(light, key).unwrap_or(default);
+            let point = |x: &str, y: &str, z: &str| {
+                let (dx, dy) = transform.apply(number(x, 0.0), number(y, 0.0));
+                [dx, dy, number(z, 0.0) * z_scale]
+            };
+
+            match light.name.as_str() {
+                "feDistantLight" => {
+                    let azimuth = number("azimuth", 0.0).to_radians();
+                    let elevation = number("elevation", 0.0).to_radians();
+                    Some(Light::Distant {
+                        direction: [
+                            azimuth.cos() * elevation.cos(),
+                            azimuth.sin() * elevation.cos(),
+                            elevation.sin(),
+                        ],
+                    })
+                }
+                "fePointLight" => Some(Light::Point { position: point("x", "y", "z") }),
+                "feSpotLight" => {
+                    let position = point("x", "y", "z");
+                    let target = point("pointsAtX", "pointsAtY", "pointsAtZ");
+                    Some(Light::Spot {
+                        position,
+                        axis: normalize(sub(target, position)),
+                        exponent: number("specularExponent", 1.0),
+                        cone: super::number(light, "limitingConeAngle").map(|angle| angle.abs().to_radians().cos()),
+                    })
+                }
+                _ => None,
+            }
+        })
+    }
+
+    /// Unit vector from the surface point toward the light, and the light
+    /// color reaching it.
+    fn at(&self, surface: [f32; 3], color: [f32; 3]) -> ([f32; 3], [f32; 3]) {
+        match self {
+            Light::Distant { direction } => (*direction, color),
+            Light::Point { position } => (normalize(sub(*position, surface)), color),
+            Light::Spot { position, axis, exponent, cone } => {
+                let l = normalize(sub(*position, surface));
+                let minus_l_dot_s = -dot(l, *axis);
+                if minus_l_dot_s <= 0.0 || cone.is_some_and(|cos| minus_l_dot_s < cos) {
+                    return (l, [0.0; 3]);
+                }
+                let factor = minus_l_dot_s.powf(*exponent);
+                (l, color.map(|c| c * factor))
+            }
+        }
+    }
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = dot(v, v).sqrt();
+    if length > 0.0 { v.map(|c| c / length) } else { v }
+}
+
+/// Lights the input's alpha channel, read as a height map, with
+/// `feDiffuseLighting` or `feSpecularLighting`.
+pub fn apply(input: &[u32], width: usize, height: usize, tag: &Tag, space: &FilterSpace) -> Vec<u32> {
+    let Some(light) = Light::from_children(tag, &space.primitive) else { return vec![0; width * height] };
+    let number = |key: &str, default: f32| number(tag, key).unwrap_or(default);
+
+    let surface_scale = number("surfaceScale", 1.0);
+    let specular = tag.name == "feSpecularLighting";
+    let constant = if specular { number("specularConstant", 1.0) } else { number("diffuseConstant", 1.0) };
+    let exponent = number("specularExponent", 1.0).clamp(1.0, 128.0);
+    let light_color = get_property(tag, "lighting-color").map(parse_color_value).unwrap_or(0xFFFFFFFF);
+    let [lr, lg, lb, _] = unpack(light_color | 0xFF000000);
+
+    // Sobel neighbours sit `step` pixels apart when kernelUnitLength is set
+    let step = tag.params.get("kernelUnitLength")
+        .map(|s| parse_numbers(s))
+        .and_then(|v| v.first().copied())
+        .filter(|&k| k > 0.0)
+        .map(|k| space.scale(k, k).0.round().max(1.0) as usize)
+        .unwrap_or(1);
+
+    let alpha: Vec<f32> = input.iter().map(|&c| ((c >> 24) & 0xFF) as f32 / 255.0).collect();
+    let at = |x: usize, y: usize| alpha[y * width + x];
+
+    let mut output = Vec::with_capacity(input.len());
+    for y in 0..height {
+        for x in 0..width {
+            let normal = surface_normal(&at, x, y, width, height, step, surface_scale);
+            let surface = [x as f32, y as f32, surface_scale * at(x, y)];
+            let (l, [cr, cg, cb]) = light.at(surface, [lr, lg, lb]);
+
+            let pixel = if specular {
+                let h = normalize([l[0], l[1], l[2] + 1.0]);
+                let factor = constant * dot(normal, h).max(0.0).powf(exponent);
+                let (r, g, b) = ((cr * factor).min(1.0), (cg * factor).min(1.0), (cb * factor).min(1.0));
+                pack([r, g, b, r.max(g).max(b)])
+            } else {
+                let factor = constant * dot(normal, l).max(0.0);
+                pack([cr * factor, cg * factor, cb * factor, 1.0])
+            };
+            output.push(pixel);
+        }
+    }
+    output
+}
+
+/// Unit surface normal from Sobel gradients of the height map. At the
+/// borders the missing neighbours drop out and the weights follow the
+/// spec's edge kernels.
+fn surface_normal(
+    at: &impl Fn(usize, usize) -> f32,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    step: usize,
+    surface_scale: f32,
+) -> [f32; 3] {
+    let left = x.checked_sub(step);
+    let right = Some(x + step).filter(|&r| r < width);
+    let up = y.checked_sub(step);
+    let down = Some(y + step).filter(|&d| d < height);
+
+    // Horizontal gradient: columns either side, rows weighted 1-2-1
+    let (x0, x1) = (left.unwrap_or(x), right.unwrap_or(x));
+    let rows = [(up, 1.0), (Some(y), 2.0), (down, 1.0)];
+    let mut gx = 0.0;
+    let mut row_weight = 0.0;
+    for (row, weight) in rows {
+        if let Some(row) = row {
+            gx += weight * (at(x1, row) - at(x0, row));
+            row_weight += weight;
+        }
+    }
+
+    let (y0, y1) = (up.unwrap_or(y), down.unwrap_or(y));
+    let columns = [(left, 1.0), (Some(x), 2.0), (right, 1.0)];
+    let mut gy = 0.0;
+    let mut column_weight = 0.0;
+    for (column, weight) in columns {
+        if let Some(column) = column {
+            gy += weight * (at(column, y1) - at(column, y0));
+            column_weight += weight;
+        }
+    }
+
+    let span_x = ((x1 - x0) / step) as f32;
+    let span_y = ((y1 - y0) / step) as f32;
+    let nx = if span_x > 0.0 { -surface_scale * 2.0 / (span_x * row_weight) * gx } else { 0.0 };
+    let ny = if span_y > 0.0 { -surface_scale * 2.0 / (span_y * column_weight) * gy } else { 0.0 };
+    normalize([nx, ny, 1.0])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::svg::testing::render_filter;
+    use crate::utils::compat::Vec;
+
+    /// Lights a flat, opaque 10x10 surface.
+    fn light(primitive: &str) -> Vec<u32> {
+        render_filter(primitive, r#"<rect width="10" height="10"/>"#, 10)
+    }
+
+    fn red(pixel: u32) -> u32 {
+        (pixel >> 16) & 0xFF
+    }
+
+    #[test]
+    fn test_diffuse_distant_light() {
+        let overhead = light(r#"<feDiffuseLighting lighting-color="red"><feDistantLight elevation="90"/></feDiffuseLighting>"#);
+        assert_eq!(overhead[5 * 10 + 5], 0xFFFF0000);
+
+        // N.L = sin(30deg) on a flat surface
+        let low = light(r#"<feDiffuseLighting lighting-color="red" diffuseConstant="1"><feDistantLight elevation="30"/></feDiffuseLighting>"#);
+        assert!(red(low[5 * 10 + 5]).abs_diff(0x80) <= 1);
+
+        let grazing = light(r#"<feDiffuseLighting><feDistantLight elevation="0"/></feDiffuseLighting>"#);
+        assert_eq!(grazing[5 * 10 + 5], 0xFF000000);
+    }
+
+    #[test]
+    fn test_point_light_falls_off_away_from_it() {
+        let pixels = light(r#"<feDiffuseLighting lighting-color="red"><fePointLight x="5" y="5" z="3"/></feDiffuseLighting>"#);
+        assert!(red(pixels[5 * 10 + 5]) > red(pixels[5 * 10 + 8]));
+        assert!(red(pixels[5 * 10 + 8]) > red(pixels[9]));
+    }
+
+    #[test]
+    fn test_spot_light_cone() {
+        let pixels = light(r#"<feDiffuseLighting lighting-color="red"><feSpotLight x="5" y="5" z="5" pointsAtX="5" pointsAtY="5" limitingConeAngle="20"/></feDiffuseLighting>"#);
+        assert!(red(pixels[5 * 10 + 5]) > 0xE0);
+        assert_eq!(pixels[0], 0xFF000000);
+
+        // Without a cone, the spot still dims away from its axis
+        let open = light(r#"<feDiffuseLighting lighting-color="red"><feSpotLight x="5" y="5" z="5" pointsAtX="5" pointsAtY="5" specularExponent="8"/></feDiffuseLighting>"#);
+        assert!(red(open[0]) > 0 && red(open[0]) < red(open[5 * 10 + 5]));
+    }
+
+    #[test]
+    fn test_specular_highlight() {
+        // Specular output is transparent where unlit, so it shows the white canvas there
+        let overhead = light(r#"<feSpecularLighting lighting-color="red"><feDistantLight elevation="90"/></feSpecularLighting>"#);
+        assert_eq!(overhead[5 * 10 + 5], 0xFFFF0000);
+
+        let broad = light(r#"<feSpecularLighting lighting-color="red" specularExponent="1"><feDistantLight elevation="45"/></feSpecularLighting>"#);
+        let sharp = light(r#"<feSpecularLighting lighting-color="red" specularExponent="20"><feDistantLight elevation="45"/></feSpecularLighting>"#);
+        // N.H is cos(22.5deg) raised to the exponent; the red light covers
+        // that much of the white canvas
+        let green = |pixel: u32| (pixel >> 8) & 0xFF;
+        assert!(green(broad[5 * 10 + 5]).abs_diff(19) <= 2);
+        assert!(green(sharp[5 * 10 + 5]).abs_diff(203) <= 2);
+    }
+
+    #[test]
+    fn test_lighting_color_from_style() {
+        let pixels = light(r#"<feDiffuseLighting lighting-color="blue" style="lighting-color: red"><feDistantLight elevation="90"/></feDiffuseLighting>"#);
+        assert_eq!(pixels[5 * 10 + 5], 0xFFFF0000);
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        // Without a light source the result is transparent
+        assert!(light("<feDiffuseLighting/>").iter().all(|&pixel| pixel == 0xFFFFFFFF));
+
+        // Non-finite numbers fall back to their defaults
+        let fallback = light(r#"<feDiffuseLighting lighting-color="red" diffuseConstant="inf"><feDistantLight elevation="90" azimuth="NaN"/></feDiffuseLighting>"#);
+        assert_eq!(fallback[5 * 10 + 5], 0xFFFF0000);
+        let unlimited = light(r#"<feDiffuseLighting lighting-color="red"><feSpotLight x="5" y="5" z="5" pointsAtX="5" pointsAtY="5" limitingConeAngle="NaN"/></feDiffuseLighting>"#);
+        assert_ne!(unlimited[0], 0xFF000000);
+    }
+}
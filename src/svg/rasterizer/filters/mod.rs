@@ -1,62 +1,486 @@
 pub mod gaussian;
 pub mod offset;
 pub mod merge;
+pub mod flood;
+pub mod composite;
+pub mod blend;
+pub mod color_matrix;
+pub mod component_transfer;
+pub mod morphology;
+pub mod tile;
+pub mod image;
+pub mod drop_shadow;
+pub mod turbulence;
+pub mod convolve;
+pub mod displacement;
+pub mod lighting;
+pub mod css;
 
 use crate::svg::parser::tags::Tag;
-use crate::utils::compat::{HashMap, String, ToString, Vec, format, vec};
+use crate::svg::rasterizer::raster::Bounds;
+use crate::svg::rasterizer::tags::text::Font;
+use crate::svg::utils::coords::{parse_length, Axis, LengthContext};
+use crate::svg::utils::transform::Transform;
+use crate::utils::compat::{HashMap, Vec, vec};
+#[cfg(not(feature = "std"))]
+use crate::utils::compat::FloatExt;
+use crate::svg::utils::Defs;
 
+/// Pixel rectangle `(x, y, width, height)` a primitive's result covers.
+pub type Region = (usize, usize, usize, usize);
+
+/// Maps filter primitive attributes into pixels of the filter buffer.
+pub struct FilterSpace {
+    /// User space of the filtered element to buffer pixels.
+    pub user: Transform,
+    /// Primitive units (user space, or the bounding box for
+    /// `primitiveUnits="objectBoundingBox"`) to buffer pixels.
+    pub primitive: Transform,
+    pub bbox_units: bool,
+    /// Context user-space lengths resolve in.
+    pub lengths: LengthContext,
+}
+
+impl FilterSpace {
+    /// Filter space for an element whose user space maps to the buffer
+    /// through `user`, with bounding box `bbox`.
+    pub fn new(user: Transform, bbox: Option<Bounds>, primitive_units: Option<&str>, lengths: LengthContext) -> Self {
+        let bbox = bbox.filter(|_| primitive_units.map(|s| s.trim()) == Some("objectBoundingBox"));
+        let primitive = match &bbox {
+            Some(b) => user.then(&Transform::translate(b.x, b.y)).then(&Transform::scale(b.width, b.height)),
+            None => user.clone(),
+        };
+        Self { user, primitive, bbox_units: bbox.is_some(), lengths }
+    }
+
+    /// Scales lengths along x and y, such as `stdDeviation`, into pixels.
+    pub fn scale(&self, x: f32, y: f32) -> (f32, f32) {
+        let (sx, sy) = self.primitive.get_scale();
+        (x * sx, y * sy)
+    }
+
+    /// Maps an offset such as `dx`/`dy` into pixels.
+    pub fn vector(&self, dx: f32, dy: f32) -> (f32, f32) {
+        self.primitive.apply_no_translate(dx, dy)
+    }
+
+    /// The context lengths in primitive units resolve in, where percentages
+    /// of a bounding box are fractions of it.
+    pub fn primitive_lengths(&self) -> LengthContext {
+        if self.bbox_units { self.lengths.with_viewport(1.0, 1.0) } else { self.lengths }
+    }
+
+    /// Parses a coordinate in primitive units, percentages taken along `axis`.
+    pub fn length(&self, s: &str, axis: Axis) -> f32 {
+        self.primitive_lengths().length(s, axis).unwrap_or(0.0)
+    }
+}
+
+/// The filter effects region in user space, from the filter's `x`, `y`,
+/// `width` and `height` in `filterUnits`. Defaults to the bounding box grown
+/// by 10% on each side; `None` when bounding box units have no box to use.
+pub fn filter_region(filter_tag: &Tag, bbox: Option<Bounds>, lengths: &LengthContext) -> Option<Bounds> {
+    let attr = |key: &str| filter_tag.params.get(key).map(|s| s.trim());
+    let user_units = attr("filterUnits") == Some("userSpaceOnUse");
+
+    let region = if user_units {
+        let length = |key: &str, default: &str, axis: Axis| attr(key)
+            .and_then(|s| lengths.length(s, axis))
+            .or_else(|| lengths.length(default, axis))
+            .unwrap_or(0.0);
+        Bounds {
+            x: length("x", "-10%", Axis::Horizontal),
+            y: length("y", "-10%", Axis::Vertical),
+            width: length("width", "120%", Axis::Horizontal),
+            height: length("height", "120%", Axis::Vertical),
+        }
+    } else {
+        let bbox = bbox.filter(|b| b.width > 0.0 && b.height > 0.0)?;
+        let fraction = |key: &str, default: f32| attr(key).map(|s| parse_length(s, default, 1.0)).unwrap_or(default);
+        Bounds {
+            x: bbox.x + fraction("x", -0.1) * bbox.width,
+            y: bbox.y + fraction("y", -0.1) * bbox.height,
+            width: fraction("width", 1.2) * bbox.width,
+            height: fraction("height", 1.2) * bbox.height,
+        }
+    };
+    Some(region).filter(|r| r.width > 0.0 && r.height > 0.0)
+}
+
+/// Node index of `SourceGraphic` in the filter graph; primitives follow
+/// `SourceAlpha` from [`FIRST_PRIMITIVE`].
+const SOURCE_GRAPHIC: usize = 0;
+const SOURCE_ALPHA: usize = 1;
+const FIRST_PRIMITIVE: usize = 2;
+
+/// A filter primitive with its inputs resolved to graph nodes.
+struct Node<'a> {
+    tag: &'a Tag,
+    /// `in`, then `in2`, or the `feMergeNode` inputs in order.
+    inputs: Vec<usize>,
+}
+
+/// Resolves every primitive's `in`/`in2` references ahead of time. A
+/// missing or unknown name refers to the previous result, or to
+/// `SourceGraphic` for the first primitive.
+fn build_graph(filter_tag: &Tag) -> Vec<Node<'_>> {
+    let mut named: HashMap<&str, usize> = HashMap::new();
+    let mut nodes = Vec::with_capacity(filter_tag.children.len());
+
+    for (i, primitive) in filter_tag.children.iter().enumerate() {
+        let previous = if i == 0 { SOURCE_GRAPHIC } else { FIRST_PRIMITIVE + i - 1 };
+        let resolve = |tag: &Tag, key: &str| match tag.params.get(key).map(|s| s.trim()) {
+            Some("SourceGraphic") => SOURCE_GRAPHIC,
+            Some("SourceAlpha") => SOURCE_ALPHA,
+            Some(name) => named.get(name).copied().unwrap_or(previous),
+            None => previous,
+        };
+
+        let inputs = match primitive.name.as_str() {
+            "feFlood" | "feImage" | "feTurbulence" => Vec::new(),
+            "feComposite" | "feBlend" | "feDisplacementMap" => vec![resolve(primitive, "in"), resolve(primitive, "in2")],
+            "feMerge" => primitive.children.iter()
+                .filter(|child| child.name == "feMergeNode")
+                .map(|child| resolve(child, "in"))
+                .collect(),
+            _ => vec![resolve(primitive, "in")],
+        };
+        if let Some(result) = primitive.params.get("result") {
+            named.insert(result.trim(), FIRST_PRIMITIVE + i);
+        }
+        nodes.push(Node { tag: primitive, inputs });
+    }
+    nodes
+}
+
+/// Runs the primitives of `filter_tag` over the straight-alpha
+/// `source_graphic`, returning the straight-alpha result.
+///
+/// Results are kept premultiplied. Primitives that don't contribute to the
+/// final result are skipped, each buffer is dropped after its last reader,
+/// and per-pixel primitives work in place on inputs nobody else reads.
 pub fn apply_filter(
-    source_graphic: &Vec<u32>,
+    source_graphic: &[u32],
     width: usize,
     height: usize,
     filter_tag: &Tag,
-    _defs: &HashMap<String, Tag>,
+    defs: &Defs,
+    fonts: &[Font],
+    space: &FilterSpace,
 ) -> Vec<u32> {
-    let mut results: HashMap<String, Vec<u32>> = HashMap::new();
-    
-    results.insert("SourceGraphic".to_string(), source_graphic.clone());
-    
-    let source_alpha: Vec<u32> = source_graphic.iter().map(|&c| {
-
-        let a = (c >> 24) & 0xFF;
-        (a << 24) // 0xAA000000
-    }).collect();
-    results.insert("SourceAlpha".to_string(), source_alpha);
+    let nodes = build_graph(filter_tag);
+    let node_count = FIRST_PRIMITIVE + nodes.len();
+    let last = if nodes.is_empty() { SOURCE_GRAPHIC } else { node_count - 1 };
+    let full = (0, 0, width, height);
 
+    // Walk back from the result to find the live nodes and count their readers
+    let mut live = vec![false; node_count];
+    let mut readers = vec![0usize; node_count];
+    live[last] = true;
+    readers[last] = 1;
+    for i in (0..nodes.len()).rev() {
+        if live[FIRST_PRIMITIVE + i] {
+            for &input in &nodes[i].inputs {
+                live[input] = true;
+                readers[input] += 1;
+            }
+        }
+    }
+    // SourceAlpha is derived from SourceGraphic when first read
+    if live[SOURCE_ALPHA] {
+        readers[SOURCE_GRAPHIC] += 1;
+    }
 
-    let mut last_result_name = "SourceGraphic".to_string();
+    let mut buffers: Vec<Option<Vec<u32>>> = vec![None; node_count];
+    let mut regions: Vec<Region> = vec![full; node_count];
+    buffers[SOURCE_GRAPHIC] = Some(source_graphic.iter().map(|&c| premultiply(c)).collect());
 
-    for primitive in &filter_tag.children {
+    for (i, node) in nodes.iter().enumerate() {
+        let index = FIRST_PRIMITIVE + i;
+        if !live[index] {
+            continue;
+        }
+        if node.inputs.contains(&SOURCE_ALPHA) && buffers[SOURCE_ALPHA].is_none() {
+            let source = buffers[SOURCE_GRAPHIC].as_deref().unwrap_or_default();
+            buffers[SOURCE_ALPHA] = Some(source.iter().map(|&c| c & 0xFF000000).collect());
+            release(&mut buffers, &mut readers, SOURCE_GRAPHIC);
+        }
 
-        let in_attr = primitive.params.get("in").cloned();
-        let input_name = in_attr.as_deref().unwrap_or(&last_result_name);
-        
-        let input_buffer = results.get(input_name).cloned().unwrap_or_else(|| vec![0; width * height]);
+        let primitive = node.tag;
+        let region = primitive_region(primitive, space, width, height);
 
-        let output_buffer = match primitive.name.as_str() {
-            "feGaussianBlur" => {
-                gaussian::apply(&input_buffer, width, height, primitive)
-            }
-            "feOffset" => {
-                offset::apply(&input_buffer, width, height, primitive)
-            }
-            "feMerge" => {
-                merge::apply(&input_buffer, width, height, primitive, &results, source_graphic)
+        let mut output = match primitive.name.as_str() {
+            // Per-pixel primitives take over their input when it has no other reader
+            "feColorMatrix" | "feComponentTransfer" => {
+                let first = node.inputs[0];
+                let buffer = match readers[first] {
+                    1 => buffers[first].take().unwrap_or_default(),
+                    _ => buffers[first].clone().unwrap_or_default(),
+                };
+                if primitive.name == "feColorMatrix" {
+                    color_matrix::apply(buffer, primitive)
+                } else {
+                    component_transfer::apply(buffer, primitive)
+                }
             }
-            _ => {
-                input_buffer
+            name => {
+                let input = |k: usize| buffers[node.inputs[k]].as_deref().unwrap_or_default();
+                match name {
+                    "feGaussianBlur" => gaussian::apply(input(0), width, height, primitive, space),
+                    "feOffset" => offset::apply(input(0), width, height, primitive, space),
+                    "feMerge" => {
+                        let layers: Vec<&[u32]> = (0..node.inputs.len()).map(input).collect();
+                        merge::apply(&layers, width, height)
+                    }
+                    "feFlood" => flood::apply(width, height, primitive),
+                    "feComposite" => composite::apply(input(0), input(1), primitive),
+                    "feBlend" => blend::apply(input(0), input(1), primitive),
+                    "feMorphology" => morphology::apply(input(0), width, height, primitive, space),
+                    "feTile" => tile::apply(input(0), width, height, regions[node.inputs[0]]),
+                    "feImage" => image::apply(width, height, primitive, defs, fonts, space),
+                    "feDropShadow" => drop_shadow::apply(input(0), width, height, primitive, space),
+                    "feTurbulence" => turbulence::apply(width, height, primitive, &space.user),
+                    "feConvolveMatrix" => convolve::apply(input(0), width, height, primitive),
+                    "feDiffuseLighting" | "feSpecularLighting" => lighting::apply(input(0), width, height, primitive, space),
+                    "feDisplacementMap" => displacement::apply(input(0), input(1), width, height, primitive, space),
+                    _ => input(0).to_vec(),
+                }
             }
         };
-        
-        if let Some(result_name) = primitive.params.get("result") {
-            results.insert(result_name.clone(), output_buffer.clone());
-            last_result_name = result_name.clone();
+        clip_to_region(&mut output, width, region);
+
+        for &input in &node.inputs {
+            release(&mut buffers, &mut readers, input);
+        }
+        buffers[index] = Some(output);
+        regions[index] = region;
+    }
+
+    let result = buffers[last].take().unwrap_or_default();
+    result.into_iter().map(unpremultiply).collect()
+}
+
+/// Drops a node's buffer once its last reader has run.
+fn release(buffers: &mut [Option<Vec<u32>>], readers: &mut [usize], node: usize) {
+    readers[node] = readers[node].saturating_sub(1);
+    if readers[node] == 0 {
+        buffers[node] = None;
+    }
+}
+
+/// The primitive subregion from `x`, `y`, `width` and `height` in primitive
+/// units, defaulting to the whole buffer (the filter region).
+fn primitive_region(primitive: &Tag, space: &FilterSpace, width: usize, height: usize) -> Region {
+    let (w, h) = (width as f32, height as f32);
+    let to_buffer = &space.primitive;
+    let Some(from_buffer) = to_buffer.inverse() else { return (0, 0, 0, 0) };
+
+    // The buffer's extent in primitive units supplies missing attributes
+    let corners = [(0.0, 0.0), (w, 0.0), (w, h), (0.0, h)].map(|(x, y)| from_buffer.apply(x, y));
+    let min_x = corners.iter().fold(f32::INFINITY, |m, c| m.min(c.0));
+    let min_y = corners.iter().fold(f32::INFINITY, |m, c| m.min(c.1));
+    let max_x = corners.iter().fold(f32::NEG_INFINITY, |m, c| m.max(c.0));
+    let max_y = corners.iter().fold(f32::NEG_INFINITY, |m, c| m.max(c.1));
+
+    let attr = |key: &str, axis: Axis| primitive.params.get(key).map(|s| space.length(s, axis));
+    let x = attr("x", Axis::Horizontal).unwrap_or(min_x);
+    let y = attr("y", Axis::Vertical).unwrap_or(min_y);
+    let rw = attr("width", Axis::Horizontal).unwrap_or(max_x - x);
+    let rh = attr("height", Axis::Vertical).unwrap_or(max_y - y);
+    if rw <= 0.0 || rh <= 0.0 {
+        return (0, 0, 0, 0);
+    }
+
+    let corners = [(x, y), (x + rw, y), (x + rw, y + rh), (x, y + rh)].map(|(x, y)| to_buffer.apply(x, y));
+    let x0 = corners.iter().fold(f32::INFINITY, |m, c| m.min(c.0)).round().clamp(0.0, w);
+    let y0 = corners.iter().fold(f32::INFINITY, |m, c| m.min(c.1)).round().clamp(0.0, h);
+    let x1 = corners.iter().fold(f32::NEG_INFINITY, |m, c| m.max(c.0)).round().clamp(x0, w);
+    let y1 = corners.iter().fold(f32::NEG_INFINITY, |m, c| m.max(c.1)).round().clamp(y0, h);
+    (x0 as usize, y0 as usize, (x1 - x0) as usize, (y1 - y0) as usize)
+}
+
+fn clip_to_region(buffer: &mut [u32], width: usize, (x, y, w, h): Region) {
+    if width == 0 {
+        return;
+    }
+    for (row, pixels) in buffer.chunks_exact_mut(width).enumerate() {
+        if row < y || row >= y + h {
+            pixels.fill(0);
         } else {
-            let temp_name = format!("__internal_{}", primitive.name);
-            results.insert(temp_name.clone(), output_buffer.clone());
-            last_result_name = temp_name;
+            pixels[..x.min(width)].fill(0);
+            pixels[(x + w).min(width)..].fill(0);
         }
     }
-    
-    results.get(&last_result_name).cloned().unwrap_or_else(|| source_graphic.clone())
+}
+
+/// Parses one number, rejecting infinities and NaN so they can't spread
+/// through a primitive's arithmetic.
+pub(crate) fn parse_number(s: &str) -> Option<f32> {
+    s.trim().parse().ok().filter(|n: &f32| n.is_finite())
+}
+
+/// The number in the `key` attribute of a primitive, see [`parse_number`].
+pub(crate) fn number(tag: &Tag, key: &str) -> Option<f32> {
+    tag.params.get(key).and_then(|s| parse_number(s))
+}
+
+/// Parses a list of numbers separated by whitespace and/or commas.
+pub(crate) fn parse_numbers(s: &str) -> Vec<f32> {
+    s.split(|c: char| c.is_whitespace() || c == ',')
+        .filter_map(parse_number)
+        .collect()
+}
+
+/// Converts straight-alpha ARGB to premultiplied ARGB.
+#[inline]
+pub(crate) fn premultiply(c: u32) -> u32 {
+    let a = c >> 24;
+    match a {
+        0 => 0,
+        255 => c,
+        _ => {
+            let channel = |shift: u32| (((c >> shift) & 0xFF) * a + 127) / 255;
+            (a << 24) | (channel(16) << 16) | (channel(8) << 8) | channel(0)
+        }
+    }
+}
+
+/// Converts premultiplied ARGB back to straight alpha.
+#[inline]
+pub(crate) fn unpremultiply(c: u32) -> u32 {
+    let a = c >> 24;
+    match a {
+        0 => 0,
+        255 => c,
+        _ => {
+            let channel = |shift: u32| ((((c >> shift) & 0xFF) * 255 + a / 2) / a).min(255);
+            (a << 24) | (channel(16) << 16) | (channel(8) << 8) | channel(0)
+        }
+    }
+}
+
+/// Unpacks premultiplied ARGB into premultiplied `[r, g, b, a]` in `0..=1`.
+#[inline]
+pub(crate) fn unpack(c: u32) -> [f32; 4] {
+    [
+        ((c >> 16) & 0xFF) as f32 / 255.0,
+        ((c >> 8) & 0xFF) as f32 / 255.0,
+        (c & 0xFF) as f32 / 255.0,
+        ((c >> 24) & 0xFF) as f32 / 255.0,
+    ]
+}
+
+/// Packs premultiplied `[r, g, b, a]`, keeping colors within alpha.
+#[inline]
+pub(crate) fn pack(c: [f32; 4]) -> u32 {
+    // NaN, from degenerate arithmetic upstream, packs as 0
+    let finite = |v: f32| if v.is_nan() { 0.0 } else { v };
+    let a = (finite(c[3]) * 255.0).round().clamp(0.0, 255.0);
+    let byte = |v: f32| (finite(v) * 255.0).round().clamp(0.0, a) as u32;
+    ((a as u32) << 24) | (byte(c[0]) << 16) | (byte(c[1]) << 8) | byte(c[2])
+}
+
+/// Unpacks premultiplied ARGB into straight `[r, g, b, a]`.
+#[inline]
+pub(crate) fn unpack_straight(c: u32) -> [f32; 4] {
+    let [r, g, b, a] = unpack(c);
+    if a <= 0.0 {
+        return [0.0; 4];
+    }
+    [r / a, g / a, b / a, a]
+}
+
+/// Packs straight `[r, g, b, a]` into premultiplied ARGB.
+#[inline]
+pub(crate) fn pack_straight(c: [f32; 4]) -> u32 {
+    let unit = |v: f32| if v.is_nan() { 0.0 } else { v.clamp(0.0, 1.0) };
+    let a = unit(c[3]);
+    pack([unit(c[0]) * a, unit(c[1]) * a, unit(c[2]) * a, a])
+}
+
+/// Composites premultiplied `source` over `dest` in place.
+pub(crate) fn source_over(dest: &mut [u32], source: &[u32]) {
+    for (d, &s) in dest.iter_mut().zip(source) {
+        let inverse = 255 - (s >> 24);
+        if inverse == 255 {
+            continue;
+        }
+        let channel = |shift: u32| (((*d >> shift) & 0xFF) * inverse + 127) / 255 + ((s >> shift) & 0xFF);
+        *d = (channel(24).min(255) << 24) | (channel(16).min(255) << 16) | (channel(8).min(255) << 8) | channel(0).min(255);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svg::testing::{ink_bounds, render, render_filter};
+
+    #[test]
+    fn test_pack_treats_nan_as_zero() {
+        assert_eq!(pack([f32::NAN; 4]), 0);
+        assert_eq!(pack([0.5, f32::NAN, 0.0, 1.0]), 0xFF80_0000);
+        assert_eq!(pack_straight([1.0, 1.0, 1.0, f32::NAN]), 0);
+        assert_eq!(pack_straight([f32::NAN, 1.0, 0.0, 1.0]), 0xFF00_FF00);
+    }
+
+    #[test]
+    fn test_parse_numbers_skips_non_finite() {
+        assert_eq!(parse_numbers("1, inf NaN 2"), [1.0, 2.0]);
+        assert_eq!(parse_number(" 2.5 "), Some(2.5));
+        assert_eq!(parse_number("-infinity"), None);
+        assert_eq!(parse_number("1e39"), None);
+    }
+
+    #[test]
+    fn test_shared_input_is_not_modified_in_place() {
+        // The color matrix must not recolor `red` while the merge still reads it
+        let pixels = render_filter(
+            r#"<feFlood flood-color="red" result="red"/>
+               <feColorMatrix in="red" values="0 0 0 0 0  0 0 0 0 0  1 0 0 0 0  0 0 0 1 0" result="blue"/>
+               <feMerge><feMergeNode in="blue"/><feMergeNode in="red"/></feMerge>"#,
+            "",
+            4,
+        );
+        assert!(pixels.iter().all(|&p| p == 0xFFFF0000));
+    }
+
+    #[test]
+    fn test_source_alpha_and_unused_results() {
+        let pixels = render_filter(
+            r#"<feFlood flood-color="lime" result="unused"/>
+               <feOffset in="SourceAlpha" dx="2" dy="0" result="shadow"/>
+               <feMerge><feMergeNode in="shadow"/><feMergeNode in="SourceGraphic"/></feMerge>"#,
+            r#"<rect x="0" y="0" width="3" height="4" fill="red"/>"#,
+            6,
+        );
+        assert_eq!(pixels[6 + 1], 0xFFFF0000);
+        assert_eq!(pixels[6 + 4], 0xFF000000);
+        assert_eq!(pixels[6 + 5], 0xFFFFFFFF);
+    }
+
+    #[test]
+    fn test_primitive_subregions() {
+        let flood = render_filter(r#"<feFlood flood-color="blue" x="2" y="3" width="4" height="2"/>"#, "", 8);
+        assert_eq!(ink_bounds(&flood, 8), Some((2, 3, 5, 4)));
+
+        // Missing attributes default to the whole region, and the input's
+        // subregion is kept by primitives that read it
+        let offset = render_filter(
+            r#"<feFlood flood-color="blue" width="3"/><feOffset dx="2" dy="1" y="0" height="4"/>"#,
+            "",
+            8,
+        );
+        assert_eq!(ink_bounds(&offset, 8), Some((2, 1, 4, 3)));
+    }
+
+    #[test]
+    fn test_subregions_in_bounding_box_units() {
+        let pixels = render(r#"<svg width="10" height="10">
+            <filter id="f" primitiveUnits="objectBoundingBox">
+                <feFlood flood-color="blue" x="25%" y="0.5" width="0.5" height="50%"/>
+            </filter>
+            <rect x="2" y="2" width="4" height="4" filter="url(#f)"/>
+        </svg>"#, 10, 10);
+        assert_eq!(ink_bounds(&pixels, 10), Some((3, 4, 4, 5)));
+    }
 }
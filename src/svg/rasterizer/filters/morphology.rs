@@ -0,0 +1,90 @@
+use crate::svg::parser::tags::Tag;
+use crate::svg::rasterizer::filters::{pack, parse_numbers, unpack, FilterSpace};
+use crate::utils::compat::Vec;
+#[cfg(not(feature = "std"))]
+use crate::utils::compat::FloatExt;
+
+/// `erode` takes the per-channel minimum over a (2rx+1)x(2ry+1) window,
+/// `dilate` the maximum.
+pub fn apply(input: &[u32], width: usize, height: usize, tag: &Tag, space: &FilterSpace) -> Vec<u32> {
+    let radius = tag.params.get("radius").map(|s| parse_numbers(s)).unwrap_or_default();
+    let (rx, ry) = match radius.as_slice() {
+        [] => (0.0, 0.0),
+        [r] => (*r, *r),
+        [rx, ry, ..] => (*rx, *ry),
+    };
+    // Negative radii disable the effect; zero leaves the input alone
+    if rx < 0.0 || ry < 0.0 {
+        return input.iter().map(|_| 0).collect();
+    }
+    let (rx, ry) = space.scale(rx, ry);
+    let (rx, ry) = (rx.round() as usize, ry.round() as usize);
+    if rx == 0 && ry == 0 {
+        return input.to_vec();
+    }
+
+    let dilate = tag.params.get("operator").map(|s| s.trim()) == Some("dilate");
+    let pick = |a: f32, b: f32| if dilate { a.max(b) } else { a.min(b) };
+
+    let pixels: Vec<[f32; 4]> = input.iter().map(|&c| unpack(c)).collect();
+
+    // The window is separable: rows first, then columns
+    let mut rows = pixels.clone();
+    for y in 0..height {
+        for x in 0..width {
+            let (from, to) = (x.saturating_sub(rx), x.saturating_add(rx).min(width - 1));
+            let mut acc = pixels[y * width + from];
+            for sx in from + 1..=to {
+                let p = pixels[y * width + sx];
+                acc = [0, 1, 2, 3].map(|i| pick(acc[i], p[i]));
+            }
+            rows[y * width + x] = acc;
+        }
+    }
+
+    let mut output = Vec::with_capacity(input.len());
+    for y in 0..height {
+        for x in 0..width {
+            let (from, to) = (y.saturating_sub(ry), y.saturating_add(ry).min(height - 1));
+            let mut acc = rows[from * width + x];
+            for sy in from + 1..=to {
+                let p = rows[sy * width + x];
+                acc = [0, 1, 2, 3].map(|i| pick(acc[i], p[i]));
+            }
+            output.push(pack(acc));
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::svg::testing::render_filter;
+    use crate::utils::compat::{format, Vec};
+
+    fn morphology(attributes: &str) -> Vec<u32> {
+        render_filter(&format!("<feMorphology {}/>", attributes), r#"<rect x="3" y="3" width="4" height="4" fill="red"/>"#, 10)
+    }
+
+    #[test]
+    fn test_dilate_and_erode() {
+        let dilated = morphology(r#"operator="dilate" radius="1""#);
+        assert_eq!(dilated[5 * 10 + 2], 0xFFFF0000);
+        assert_eq!(dilated[5 * 10 + 1], 0xFFFFFFFF);
+
+        let eroded = morphology(r#"operator="erode" radius="1""#);
+        assert_eq!(eroded[5 * 10 + 3], 0xFFFFFFFF);
+        assert_eq!(eroded[5 * 10 + 4], 0xFFFF0000);
+
+        // Radii apply per axis
+        let wide = morphology(r#"operator="dilate" radius="2 0""#);
+        assert_eq!(wide[5 * 10 + 1], 0xFFFF0000);
+        assert_eq!(wide[2 * 10 + 5], 0xFFFFFFFF);
+    }
+
+    #[test]
+    fn test_huge_radii() {
+        assert!(morphology(r#"operator="dilate" radius="1e30""#).iter().all(|&pixel| pixel == 0xFFFF0000));
+        assert!(morphology(r#"operator="erode" radius="1e30""#).iter().all(|&pixel| pixel == 0xFFFFFFFF));
+    }
+}
@@ -1,12 +1,21 @@
 use crate::svg::parser::tags::Tag;
-use crate::utils::compat::{Vec, vec, FloatExt};
+use crate::svg::rasterizer::filters::{number, FilterSpace};
+use crate::utils::compat::{Vec, vec};
+#[cfg(not(feature = "std"))]
+use crate::utils::compat::FloatExt;
 
-pub fn apply(input: &[u32], width: usize, height: usize, tag: &Tag) -> Vec<u32> {
-    let dx = tag.params.get("dx").and_then(|s| s.parse::<f32>().ok()).unwrap_or(0.0);
-    let dy = tag.params.get("dy").and_then(|s| s.parse::<f32>().ok()).unwrap_or(0.0);
+pub fn apply(input: &[u32], width: usize, height: usize, tag: &Tag, space: &FilterSpace) -> Vec<u32> {
+    let (dx, dy) = (number(tag, "dx").unwrap_or(0.0), number(tag, "dy").unwrap_or(0.0));
 
-    let idx = dx.round() as isize;
-    let idy = dy.round() as isize;
+    let (dx, dy) = space.vector(dx, dy);
+    shift(input, width, height, dx, dy)
+}
+
+/// Moves the image by (`dx`, `dy`) pixels, leaving transparency behind.
+pub(crate) fn shift(input: &[u32], width: usize, height: usize, dx: f32, dy: f32) -> Vec<u32> {
+    // Anything moved further than the buffer's size leaves it entirely
+    let idx = dx.round().clamp(-(width as f32), width as f32) as isize;
+    let idy = dy.round().clamp(-(height as f32), height as f32) as isize;
 
     if idx == 0 && idy == 0 {
         return input.to_vec();
@@ -29,3 +38,29 @@ pub fn apply(input: &[u32], width: usize, height: usize, tag: &Tag) -> Vec<u32>
 
     output
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::svg::testing::render_filter;
+    use crate::utils::compat::{format, Vec};
+
+    fn offset(attributes: &str) -> Vec<u32> {
+        render_filter(&format!("<feOffset {}/>", attributes), r#"<rect x="2" y="2" width="6" height="6" fill="red"/>"#, 10)
+    }
+
+    #[test]
+    fn test_offset_moves_the_input() {
+        let pixels = offset(r#"dx="2" dy="-1""#);
+        assert_eq!(pixels[5 * 10 + 3], 0xFFFFFFFF);
+        assert_eq!(pixels[5 * 10 + 9], 0xFFFF0000);
+        assert_eq!(pixels[10 + 5], 0xFFFF0000);
+        assert_eq!(pixels[7 * 10 + 5], 0xFFFFFFFF);
+    }
+
+    #[test]
+    fn test_huge_and_invalid_offsets() {
+        assert!(offset(r#"dx="1e30""#).iter().all(|&pixel| pixel == 0xFFFFFFFF));
+        assert!(offset(r#"dy="-1e30""#).iter().all(|&pixel| pixel == 0xFFFFFFFF));
+        assert_eq!(offset(r#"dx="inf" dy="NaN""#), offset(""));
+    }
+}
@@ -0,0 +1,20 @@
+use crate::svg::rasterizer::filters::Region;
+use crate::utils::compat::{Vec, vec};
+
+/// Repeats the `source` region of the input across the whole buffer.
+pub fn apply(input: &[u32], width: usize, height: usize, source: Region) -> Vec<u32> {
+    let (sx, sy, sw, sh) = source;
+    if sw == 0 || sh == 0 {
+        return vec![0; width * height];
+    }
+
+    let mut output = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let ty = sy + (y as isize - sy as isize).rem_euclid(sh as isize) as usize;
+        for x in 0..width {
+            let tx = sx + (x as isize - sx as isize).rem_euclid(sw as isize) as usize;
+            output.push(input[ty * width + tx]);
+        }
+    }
+    output
+}
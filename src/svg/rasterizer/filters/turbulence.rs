@@ -0,0 +1,230 @@
+use crate::svg::parser::tags::Tag;
+use crate::svg::rasterizer::filters::{number, pack_straight, parse_numbers};
+use crate::svg::utils::transform::Transform;
+use crate::utils::compat::{Vec, vec};
+#[cfg(not(feature = "std"))]
+use crate::utils::compat::FloatExt;
+
+// Constants of the reference implementation in the Filter Effects spec
+const B_SIZE: usize = 0x100;
+const BM: i32 = 0xff;
+const PERLIN_N: i32 = 0x1000;
+/// Most octaves summed; browsers stop at a similar count.
+const MAX_OCTAVES: u32 = 10;
+const RAND_M: i64 = 2147483647;
+const RAND_A: i64 = 16807;
+const RAND_Q: i64 = 127773;
+const RAND_R: i64 = 2836;
+
+/// Lattice of the spec's Perlin noise, seeded by `seed`.
+struct Noise {
+    lattice: [usize; B_SIZE + B_SIZE + 2],
+    gradient: [[[f32; 2]; B_SIZE + B_SIZE + 2]; 4],
+}
+
+#[derive(Clone, Copy)]
+struct Stitch {
+    width: i32,
+    height: i32,
+    wrap_x: i32,
+    wrap_y: i32,
+}
+
+fn random(seed: i64) -> i64 {
+    let result = RAND_A * (seed % RAND_Q) - RAND_R * (seed / RAND_Q);
+    if result <= 0 { result + RAND_M } else { result }
+}
+
+impl Noise {
+    fn new(seed: i64) -> Self {
+        let mut seed = if seed <= 0 { -(seed % (RAND_M - 1)) + 1 } else { seed };
+        if seed > RAND_M - 1 {
+            seed = RAND_M - 1;
+        }
+
+        let mut noise = Noise {
+            lattice: [0; B_SIZE + B_SIZE + 2],
+            gradient: [[[0.0; 2]; B_SIZE + B_SIZE + 2]; 4],
+        };
+        for k in 0..4 {
+            for i in 0..B_SIZE {
+                noise.lattice[i] = i;
+                for j in 0..2 {
+                    seed = random(seed);
+                    noise.gradient[k][i][j] = ((seed % (B_SIZE + B_SIZE) as i64) - B_SIZE as i64) as f32 / B_SIZE as f32;
+                }
+                let [gx, gy] = noise.gradient[k][i];
+                let s = (gx * gx + gy * gy).sqrt();
+                if s > 0.0 {
+                    noise.gradient[k][i] = [gx / s, gy / s];
+                }
+            }
+        }
+        for i in (1..B_SIZE).rev() {
+            seed = random(seed);
+            let j = (seed % B_SIZE as i64) as usize;
+            noise.lattice.swap(i, j);
+        }
+        for i in 0..B_SIZE + 2 {
+            noise.lattice[B_SIZE + i] = noise.lattice[i];
+            for k in 0..4 {
+                noise.gradient[k][B_SIZE + i] = noise.gradient[k][i];
+            }
+        }
+        noise
+    }
+
+    fn noise2(&self, channel: usize, x: f32, y: f32, stitch: Option<&Stitch>) -> f32 {
+        let t = x + PERLIN_N as f32;
+        let (mut bx0, rx0) = (t as i32, t - (t as i32) as f32);
+        let mut bx1 = bx0.wrapping_add(1);
+        let t = y + PERLIN_N as f32;
+        let (mut by0, ry0) = (t as i32, t - (t as i32) as f32);
+        let mut by1 = by0.wrapping_add(1);
+        let (rx1, ry1) = (rx0 - 1.0, ry0 - 1.0);
+
+        // Wrap before masking so stitching can take effect, as browsers do
+        if let Some(stitch) = stitch {
+            if bx0 >= stitch.wrap_x { bx0 = bx0.wrapping_sub(stitch.width); }
+            if bx1 >= stitch.wrap_x { bx1 = bx1.wrapping_sub(stitch.width); }
+            if by0 >= stitch.wrap_y { by0 = by0.wrapping_sub(stitch.height); }
+            if by1 >= stitch.wrap_y { by1 = by1.wrapping_sub(stitch.height); }
+        }
+        let (bx0, bx1, by0, by1) = ((bx0 & BM) as usize, (bx1 & BM) as usize, (by0 & BM) as usize, (by1 & BM) as usize);
+
+        let i = self.lattice[bx0];
+        let j = self.lattice[bx1];
+        let b00 = self.lattice[i + by0];
+        let b10 = self.lattice[j + by0];
+        let b01 = self.lattice[i + by1];
+        let b11 = self.lattice[j + by1];
+
+        let s_curve = |t: f32| t * t * (3.0 - 2.0 * t);
+        let lerp = |t: f32, a: f32, b: f32| a + t * (b - a);
+        let (sx, sy) = (s_curve(rx0), s_curve(ry0));
+        let dot = |b: usize, rx: f32, ry: f32| {
+            let q = self.gradient[channel][b];
+            rx * q[0] + ry * q[1]
+        };
+
+        let a = lerp(sx, dot(b00, rx0, ry0), dot(b10, rx1, ry0));
+        let b = lerp(sx, dot(b01, rx0, ry1), dot(b11, rx1, ry1));
+        lerp(sy, a, b)
+    }
+}
+
+/// Generates Perlin `turbulence` or `fractalNoise` in the filtered
+/// element's user space.
+pub fn apply(width: usize, height: usize, tag: &Tag, transform: &Transform) -> Vec<u32> {
+    let Some(device_to_user) = transform.inverse() else { return vec![0; width * height] };
+
+    let base = tag.params.get("baseFrequency").map(|s| parse_numbers(s)).unwrap_or_default();
+    let (mut freq_x, mut freq_y) = match base.as_slice() {
+        [] => (0.0, 0.0),
+        [f] => (*f, *f),
+        [fx, fy, ..] => (*fx, *fy),
+    };
+    if freq_x < 0.0 || freq_y < 0.0 {
+        return vec![0; width * height];
+    }
+    // Octaves past the cap are finer than a pixel and only cost time
+    let octaves = tag.params.get("numOctaves").and_then(|s| s.trim().parse::<u32>().ok()).unwrap_or(1).min(MAX_OCTAVES);
+    let seed = number(tag, "seed").unwrap_or(0.0).round() as i64;
+    let fractal = tag.params.get("type").map(|s| s.trim()) == Some("fractalNoise");
+    let stitch_tiles = tag.params.get("stitchTiles").map(|s| s.trim()) == Some("stitch");
+
+    // The tile is the primitive subregion, or else the whole buffer, in user space
+    let attr = |key: &str| number(tag, key);
+    let (x0, y0) = device_to_user.apply(0.0, 0.0);
+    let (x1, y1) = device_to_user.apply(width as f32, height as f32);
+    let tile_x = attr("x").unwrap_or(x0.min(x1));
+    let tile_y = attr("y").unwrap_or(y0.min(y1));
+    let tile_w = attr("width").unwrap_or((x1 - x0).abs());
+    let tile_h = attr("height").unwrap_or((y1 - y0).abs());
+
+    let stitch = if stitch_tiles && tile_w > 0.0 && tile_h > 0.0 {
+        // Snap the frequencies so the tile borders line up
+        let adjust = |freq: f32, size: f32| {
+            if freq == 0.0 {
+                return freq;
+            }
+            let lo = (size * freq).floor() / size;
+            let hi = (size * freq).ceil() / size;
+            if lo > 0.0 && freq / lo < hi / freq { lo } else { hi }
+        };
+        freq_x = adjust(freq_x, tile_w);
+        freq_y = adjust(freq_y, tile_h);
+
+        let width = (tile_w * freq_x + 0.5) as i32;
+        let height = (tile_h * freq_y + 0.5) as i32;
+        Some(Stitch {
+            width,
+            height,
+            wrap_x: (tile_x * freq_x + PERLIN_N.saturating_add(width) as f32) as i32,
+            wrap_y: (tile_y * freq_y + PERLIN_N.saturating_add(height) as f32) as i32,
+        })
+    } else {
+        None
+    };
+
+    let noise = Noise::new(seed);
+    let turbulence = |channel: usize, x: f32, y: f32| {
+        let mut stitch = stitch;
+        let (mut vx, mut vy) = (x * freq_x, y * freq_y);
+        let (mut sum, mut ratio) = (0.0, 1.0);
+        for _ in 0..octaves {
+            let n = noise.noise2(channel, vx, vy, stitch.as_ref());
+            sum += if fractal { n } else { n.abs() } / ratio;
+            vx *= 2.0;
+            vy *= 2.0;
+            ratio *= 2.0;
+            if let Some(s) = stitch.as_mut() {
+                s.width = s.width.saturating_mul(2);
+                s.wrap_x = s.wrap_x.saturating_mul(2).saturating_sub(PERLIN_N);
+                s.height = s.height.saturating_mul(2);
+                s.wrap_y = s.wrap_y.saturating_mul(2).saturating_sub(PERLIN_N);
+            }
+        }
+        if fractal { (sum + 1.0) / 2.0 } else { sum }
+    };
+
+    let mut output = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let (ux, uy) = device_to_user.apply(x as f32, y as f32);
+            output.push(pack_straight([0, 1, 2, 3].map(|channel| turbulence(channel, ux, uy).clamp(0.0, 1.0))));
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turbulence(attributes: &[(&str, &str)]) -> Tag {
+        let mut tag = Tag::new();
+        tag.name = "feTurbulence".into();
+        for (key, value) in attributes {
+            tag.set_attribute(key, value);
+        }
+        tag
+    }
+
+    #[test]
+    fn test_huge_octave_count_is_capped() {
+        let tag = turbulence(&[("baseFrequency", "0.05"), ("numOctaves", "4000000000")]);
+        let capped = apply(8, 8, &tag, &Transform::identity());
+        let tag = turbulence(&[("baseFrequency", "0.05"), ("numOctaves", "10")]);
+        assert_eq!(capped, apply(8, 8, &tag, &Transform::identity()));
+    }
+
+    #[test]
+    fn test_stitching_a_huge_tile_does_not_overflow() {
+        let tag = turbulence(&[
+            ("baseFrequency", "1000"), ("numOctaves", "10"), ("stitchTiles", "stitch"),
+            ("x", "0"), ("y", "0"), ("width", "1e9"), ("height", "1e9"),
+        ]);
+        assert_eq!(apply(4, 4, &tag, &Transform::identity()).len(), 16);
+    }
+}
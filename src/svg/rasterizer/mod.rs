@@ -1,4 +1,5 @@
 pub mod canva;
+pub(crate) mod bbox;
 pub mod tags;
 pub mod raster;
 pub mod filters;
@@ -359,3 +359,88 @@ impl PathRasterizer {
         }
     }
 }
+
+/// The part of a buffer of `width` x `height` pixels, drawn at the whole
+/// device position (`x`, `y`), that lies over a canvas of `canvas_width` x
+/// `canvas_height`, keeping the buffer's last column spare for coverage
+/// spilling past the right edge. Returns the columns and rows cut off at the
+/// start and the size left, or `None` when nothing shows.
+pub(crate) fn visible_buffer(
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    canvas_width: usize,
+    canvas_height: usize,
+) -> Option<(f32, f32, usize, usize)> {
+    // Measured from the visible start, which stays precise for huge buffers
+    let (skip_x, skip_y) = (x.max(0.0) - x, y.max(0.0) - y);
+    let visible_width = (width - skip_x).min(canvas_width as f32 + 1.0 - x.max(0.0));
+    let visible_height = (height - skip_y).min(canvas_height as f32 - y.max(0.0));
+    if !(visible_width > 1.0 && visible_height > 0.0) {
+        return None;
+    }
+    Some((skip_x, skip_y, visible_width as usize, visible_height as usize))
+}
+
+/// Moves `lines` into the window of `width` x `height` at (`x`, `y`). Parts
+/// above or below it are dropped; parts to either side are pushed onto its
+/// left or right edge, where they still carry their winding across the row.
+/// Returns the vertical lines and the others, split as `insert_line` does.
+pub(crate) fn clip_lines<'a>(
+    lines: impl IntoIterator<Item = &'a Line>,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+) -> (Vec<Line>, Vec<Line>) {
+    let (right, bottom) = (x + width, y + height);
+    let (mut v_lines, mut m_lines) = (Vec::new(), Vec::new());
+    let mut push = |line: Line| {
+        if line.dx == 0.0 { v_lines.push(line) } else { m_lines.push(line) }
+    };
+
+    for line in lines {
+        let (x0, y0, x1, y1) = (line.x0, line.y0, line.x1, line.y1);
+        if !(line.dx.is_finite() && line.dy.is_finite()) || line.dy == 0.0 || y0.max(y1) <= y || y0.min(y1) >= bottom {
+            continue;
+        }
+
+        if x0.min(x1) >= x && x0.max(x1) <= right && y0.min(y1) >= y && y0.max(y1) <= bottom {
+            let mut moved = line.clone();
+            moved.x0 -= x;
+            moved.y0 -= y;
+            moved.x1 -= x;
+            moved.y1 -= y;
+            push(moved);
+            continue;
+        }
+
+        // The span inside the rows, interpolated from the coordinates rather
+        // than a parameter, which loses the window on very long lines
+        let (sy, ey) = (y0.clamp(y, bottom), y1.clamp(y, bottom));
+        let x_at = |at: f32| if at == y0 { x0 } else if at == y1 { x1 } else { x0 + (at - y0) * (x1 - x0) / (y1 - y0) };
+        let (sx, ex) = (x_at(sy), x_at(ey));
+
+        // Cut where it crosses either side
+        let mut points = [(sx, sy), (ex, ey), (ex, ey), (ex, ey)];
+        let mut count = 1;
+        for side in [x, right] {
+            if side > sx.min(ex) && side < sx.max(ex) {
+                points[count] = (side, sy + (side - sx) * (ey - sy) / (ex - sx));
+                count += 1;
+            }
+        }
+        points[1..count].sort_unstable_by(|a, b| (a.0 - sx).abs().total_cmp(&(b.0 - sx).abs()));
+
+        for span in points[..=count].windows(2) {
+            let ((sx, sy), (ex, ey)) = (span[0], span[1]);
+            if sy == ey {
+                continue;
+            }
+            let (sx, ex) = (sx.clamp(x, right) - x, ex.clamp(x, right) - x);
+            push(Line::new(sx, sy - y, ex, ey - y, 1.0));
+        }
+    }
+    (v_lines, m_lines)
+}
@@ -14,6 +14,8 @@ use std::arch::aarch64::*;
 #[cfg(not(feature = "std"))]
 use core::arch::aarch64::*;
 
+use crate::svg::rasterizer::filters::blend::{blend_premultiplied, BlendMode};
+
 #[cfg(test)]
 use crate::utils::compat::Vec;
 
@@ -57,6 +59,60 @@ pub fn blend_scanline(dst: &mut [u32], src: &[u32]) {
     blend_scanline_scalar(dst, src, len);
 }
 
+/// Blends source pixels onto the destination with a CSS blend mode, both
+/// straight-alpha ARGB. The simple separable modes are vectorised; the rest
+/// go pixel by pixel.
+pub fn blend_scanline_mode(dst: &mut [u32], src: &[u32], mode: BlendMode) {
+    if mode == BlendMode::Normal {
+        return blend_scanline(dst, src);
+    }
+    let len = dst.len().min(src.len());
+    if len == 0 {
+        return;
+    }
+
+    if has_vector_path(mode) {
+        #[cfg(target_arch = "x86_64")]
+        {
+            #[cfg(feature = "std")]
+            {
+                if is_x86_feature_detected!("sse2") {
+                    unsafe {
+                        blend_mode_sse2(dst, src, len, mode);
+                        return;
+                    }
+                }
+            }
+            #[cfg(not(feature = "std"))]
+            {
+                unsafe {
+                    blend_mode_sse2(dst, src, len, mode);
+                    return;
+                }
+            }
+        }
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            unsafe {
+                blend_mode_neon(dst, src, len, mode);
+                return;
+            }
+        }
+    }
+
+    blend_mode_scalar(dst, src, len, mode);
+}
+
+/// Modes whose blend function is a few vector operations per channel.
+fn has_vector_path(mode: BlendMode) -> bool {
+    matches!(
+        mode,
+        BlendMode::Multiply | BlendMode::Screen | BlendMode::Darken
+            | BlendMode::Lighten | BlendMode::Difference | BlendMode::Exclusion
+    )
+}
+
 fn blend_scanline_scalar(dst: &mut [u32], src: &[u32], len: usize) {
     for i in 0..len {
         let s = src[i];
@@ -65,6 +121,15 @@ fn blend_scanline_scalar(dst: &mut [u32], src: &[u32], len: usize) {
     }
 }
 
+fn blend_mode_scalar(dst: &mut [u32], src: &[u32], len: usize, mode: BlendMode) {
+    for (d, &s) in dst[..len].iter_mut().zip(&src[..len]) {
+        // A transparent source leaves the backdrop as it is in every mode
+        if s >> 24 != 0 {
+            *d = blend_mode_pixel(mode, *d, s);
+        }
+    }
+}
+
 #[inline(always)]
 fn blend_pixel(dst: u32, src: u32) -> u32 {
     let src_a = ((src >> 24) & 0xFF) as f32 / 255.0;
@@ -92,6 +157,71 @@ fn blend_pixel(dst: u32, src: u32) -> u32 {
     (a << 24) | (r << 16) | (g << 8) | b
 }
 
+#[inline]
+fn blend_mode_pixel(mode: BlendMode, dst: u32, src: u32) -> u32 {
+    let premultiplied = |c: u32| {
+        let a = ((c >> 24) & 0xFF) as f32 / 255.0;
+        let channel = |shift: u32| ((c >> shift) & 0xFF) as f32 / 255.0 * a;
+        [channel(16), channel(8), channel(0), a]
+    };
+    let [r, g, b, a] = blend_premultiplied(mode, premultiplied(dst), premultiplied(src));
+    if a <= 0.0 {
+        return 0;
+    }
+
+    let byte = |v: f32| (v * 255.0 + 0.5).clamp(0.0, 255.0) as u32;
+    (byte(a) << 24) | (byte(r / a) << 16) | (byte(g / a) << 8) | byte(b / a)
+}
+
+/// Loads four ARGB pixels as `[b, g, r, a]` lanes of floats in `0..=255`.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn load_sse2(ptr: *const u32) -> [__m128; 4] {
+    let int = unsafe { _mm_loadu_si128(ptr as *const __m128i) };
+
+    let zero = _mm_setzero_si128();
+    let lo = _mm_unpacklo_epi8(int, zero);
+    let hi = _mm_unpackhi_epi8(int, zero);
+
+    let mut f0 = _mm_cvtepi32_ps(_mm_unpacklo_epi16(lo, zero));
+    let mut f1 = _mm_cvtepi32_ps(_mm_unpackhi_epi16(lo, zero));
+    let mut f2 = _mm_cvtepi32_ps(_mm_unpacklo_epi16(hi, zero));
+    let mut f3 = _mm_cvtepi32_ps(_mm_unpackhi_epi16(hi, zero));
+
+    _MM_TRANSPOSE4_PS(&mut f0, &mut f1, &mut f2, &mut f3);
+    [f0, f1, f2, f3]
+}
+
+/// Clamps `[b, g, r, a]` lanes to `0..=255` and stores them as four pixels.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn store_sse2(ptr: *mut u32, [b, g, r, a]: [__m128; 4]) {
+    let zeros = _mm_set1_ps(0.0);
+    let scale_255 = _mm_set1_ps(255.0);
+
+    let i_b = _mm_cvtps_epi32(_mm_min_ps(_mm_max_ps(b, zeros), scale_255));
+    let i_g = _mm_cvtps_epi32(_mm_min_ps(_mm_max_ps(g, zeros), scale_255));
+    let i_r = _mm_cvtps_epi32(_mm_min_ps(_mm_max_ps(r, zeros), scale_255));
+    let i_a = _mm_cvtps_epi32(_mm_min_ps(_mm_max_ps(a, zeros), scale_255));
+
+    let t0 = _mm_unpacklo_epi32(i_b, i_g);
+    let t1 = _mm_unpackhi_epi32(i_b, i_g);
+    let t2 = _mm_unpacklo_epi32(i_r, i_a);
+    let t3 = _mm_unpackhi_epi32(i_r, i_a);
+
+    let pixel0 = _mm_unpacklo_epi64(t0, t2);
+    let pixel1 = _mm_unpackhi_epi64(t0, t2);
+    let pixel2 = _mm_unpacklo_epi64(t1, t3);
+    let pixel3 = _mm_unpackhi_epi64(t1, t3);
+
+    let p01_16 = _mm_packs_epi32(pixel0, pixel1);
+    let p23_16 = _mm_packs_epi32(pixel2, pixel3);
+
+    let result = _mm_packus_epi16(p01_16, p23_16);
+
+    unsafe { _mm_storeu_si128(ptr as *mut __m128i, result) };
+}
+
 #[cfg(target_arch = "x86_64")]
 #[target_feature(enable = "sse2")]
 unsafe fn blend_scanline_sse2(dst: &mut [u32], src: &[u32], len: usize) {
@@ -101,55 +231,14 @@ unsafe fn blend_scanline_sse2(dst: &mut [u32], src: &[u32], len: usize) {
     let scale_255 = _mm_set1_ps(255.0);
     let ones = _mm_set1_ps(1.0);
     let epsilon = _mm_set1_ps(0.001);
-    let zeros = _mm_set1_ps(0.0);
 
     while i + 4 <= len {
         // Pointer arithmetic is unsafe
         let s_ptr = unsafe { src.as_ptr().add(i) };
         let d_ptr = unsafe { dst.as_mut_ptr().add(i) };
 
-        // Load 4 pixels (128 bits)
-        let s_int = unsafe { _mm_loadu_si128(s_ptr as *const __m128i) };
-        let d_int = unsafe { _mm_loadu_si128(d_ptr as *const __m128i) };
-
-        let zero = _mm_setzero_si128();
-        let s_lo = _mm_unpacklo_epi8(s_int, zero);
-        let s_hi = _mm_unpackhi_epi8(s_int, zero);
-        let d_lo = _mm_unpacklo_epi8(d_int, zero);
-        let d_hi = _mm_unpackhi_epi8(d_int, zero);
-
-        let s_p0 = _mm_unpacklo_epi16(s_lo, zero);
-        let s_p1 = _mm_unpackhi_epi16(s_lo, zero);
-        let s_p2 = _mm_unpacklo_epi16(s_hi, zero);
-        let s_p3 = _mm_unpackhi_epi16(s_hi, zero);
-
-        let d_p0 = _mm_unpacklo_epi16(d_lo, zero);
-        let d_p1 = _mm_unpackhi_epi16(d_lo, zero);
-        let d_p2 = _mm_unpacklo_epi16(d_hi, zero);
-        let d_p3 = _mm_unpackhi_epi16(d_hi, zero);
-
-        let mut s_f0 = _mm_cvtepi32_ps(s_p0);
-        let mut s_f1 = _mm_cvtepi32_ps(s_p1);
-        let mut s_f2 = _mm_cvtepi32_ps(s_p2);
-        let mut s_f3 = _mm_cvtepi32_ps(s_p3);
-
-        let mut d_f0 = _mm_cvtepi32_ps(d_p0);
-        let mut d_f1 = _mm_cvtepi32_ps(d_p1);
-        let mut d_f2 = _mm_cvtepi32_ps(d_p2);
-        let mut d_f3 = _mm_cvtepi32_ps(d_p3);
-
-        _MM_TRANSPOSE4_PS(&mut s_f0, &mut s_f1, &mut s_f2, &mut s_f3);
-        _MM_TRANSPOSE4_PS(&mut d_f0, &mut d_f1, &mut d_f2, &mut d_f3);
-
-        let src_b = s_f0;
-        let src_g = s_f1;
-        let src_r = s_f2;
-        let src_a_byte = s_f3;
-
-        let dst_b = d_f0;
-        let dst_g = d_f1;
-        let dst_r = d_f2;
-        let dst_a_byte = d_f3;
+        let [src_b, src_g, src_r, src_a_byte] = unsafe { load_sse2(s_ptr) };
+        let [dst_b, dst_g, dst_r, dst_a_byte] = unsafe { load_sse2(d_ptr) };
 
         let src_a = _mm_mul_ps(src_a_byte, scale_inv_255);
         let dst_a = _mm_mul_ps(dst_a_byte, scale_inv_255);
@@ -169,33 +258,8 @@ unsafe fn blend_scanline_sse2(dst: &mut [u32], src: &[u32], len: usize) {
         let out_b = _mm_div_ps(out_b_num, safe_out_a);
 
         let out_a_scaled = _mm_mul_ps(out_a, scale_255);
-        
-        let r_clamped = _mm_min_ps(_mm_max_ps(out_r, zeros), scale_255);
-        let g_clamped = _mm_min_ps(_mm_max_ps(out_g, zeros), scale_255);
-        let b_clamped = _mm_min_ps(_mm_max_ps(out_b, zeros), scale_255);
-        let a_clamped = _mm_min_ps(_mm_max_ps(out_a_scaled, zeros), scale_255);
-
-        let i_b = _mm_cvtps_epi32(b_clamped);
-        let i_g = _mm_cvtps_epi32(g_clamped);
-        let i_r = _mm_cvtps_epi32(r_clamped);
-        let i_a = _mm_cvtps_epi32(a_clamped);
-
-        let t0 = _mm_unpacklo_epi32(i_b, i_g);
-        let t1 = _mm_unpackhi_epi32(i_b, i_g);
-        let t2 = _mm_unpacklo_epi32(i_r, i_a);
-        let t3 = _mm_unpackhi_epi32(i_r, i_a);
-
-        let pixel0 = _mm_unpacklo_epi64(t0, t2);
-        let pixel1 = _mm_unpackhi_epi64(t0, t2);
-        let pixel2 = _mm_unpacklo_epi64(t1, t3);
-        let pixel3 = _mm_unpackhi_epi64(t1, t3);
-        
-        let p01_16 = _mm_packs_epi32(pixel0, pixel1);
-        let p23_16 = _mm_packs_epi32(pixel2, pixel3);
-        
-        let result = _mm_packus_epi16(p01_16, p23_16);
-        
-        unsafe { _mm_storeu_si128(d_ptr as *mut __m128i, result) };
+
+        unsafe { store_sse2(d_ptr, [out_b, out_g, out_r, out_a_scaled]) };
 
         i += 4;
     }
@@ -203,6 +267,123 @@ unsafe fn blend_scanline_sse2(dst: &mut [u32], src: &[u32], len: usize) {
     blend_scanline_scalar(&mut dst[i..], &src[i..], len - i);
 }
 
+/// The blend function `B(cb, cs)` of a mode with a vector path, on
+/// channels in `0..=255`.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+fn separable_sse2(mode: BlendMode, cb: __m128, cs: __m128) -> __m128 {
+    let inv_255 = _mm_set1_ps(1.0 / 255.0);
+    let product = _mm_mul_ps(_mm_mul_ps(cb, cs), inv_255);
+    match mode {
+        BlendMode::Multiply => product,
+        BlendMode::Screen => _mm_sub_ps(_mm_add_ps(cb, cs), product),
+        BlendMode::Darken => _mm_min_ps(cb, cs),
+        BlendMode::Lighten => _mm_max_ps(cb, cs),
+        BlendMode::Difference => _mm_sub_ps(_mm_max_ps(cb, cs), _mm_min_ps(cb, cs)),
+        BlendMode::Exclusion => _mm_sub_ps(_mm_add_ps(cb, cs), _mm_add_ps(product, product)),
+        _ => cs,
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn blend_mode_sse2(dst: &mut [u32], src: &[u32], len: usize, mode: BlendMode) {
+    let mut i = 0;
+
+    let scale_inv_255 = _mm_set1_ps(1.0 / 255.0);
+    let scale_255 = _mm_set1_ps(255.0);
+    let epsilon = _mm_set1_ps(0.001);
+
+    while i + 4 <= len {
+        let s_ptr = unsafe { src.as_ptr().add(i) };
+        let d_ptr = unsafe { dst.as_mut_ptr().add(i) };
+
+        let [src_b, src_g, src_r, src_a_byte] = unsafe { load_sse2(s_ptr) };
+        let [dst_b, dst_g, dst_r, dst_a_byte] = unsafe { load_sse2(d_ptr) };
+
+        let src_a = _mm_mul_ps(src_a_byte, scale_inv_255);
+        let dst_a = _mm_mul_ps(dst_a_byte, scale_inv_255);
+
+        // Weights of the source alone, the backdrop alone and their overlap
+        let both = _mm_mul_ps(src_a, dst_a);
+        let src_only = _mm_sub_ps(src_a, both);
+        let dst_only = _mm_sub_ps(dst_a, both);
+        let out_a = _mm_add_ps(src_a, dst_only);
+        let safe_out_a = _mm_max_ps(out_a, epsilon);
+
+        let mix = |cb: __m128, cs: __m128| {
+            let blended = separable_sse2(mode, cb, cs);
+            let sum = _mm_add_ps(
+                _mm_add_ps(_mm_mul_ps(cs, src_only), _mm_mul_ps(cb, dst_only)),
+                _mm_mul_ps(blended, both),
+            );
+            _mm_div_ps(sum, safe_out_a)
+        };
+        let out_r = mix(dst_r, src_r);
+        let out_g = mix(dst_g, src_g);
+        let out_b = mix(dst_b, src_b);
+        let out_a_scaled = _mm_mul_ps(out_a, scale_255);
+
+        unsafe { store_sse2(d_ptr, [out_b, out_g, out_r, out_a_scaled]) };
+
+        i += 4;
+    }
+
+    blend_mode_scalar(&mut dst[i..], &src[i..], len - i, mode);
+}
+
+/// Loads four ARGB pixels as `[b, g, r, a]` lanes of floats in `0..=255`.
+#[cfg(target_arch = "aarch64")]
+unsafe fn load_neon(ptr: *const u32) -> [float32x4_t; 4] {
+    let raw = unsafe { vld1q_u8(ptr as *const u8) };
+
+    let low = unsafe { vmovl_u8(vget_low_u8(raw)) };
+    let high = unsafe { vmovl_u8(vget_high_u8(raw)) };
+
+    let f0 = unsafe { vcvtq_f32_u32(vmovl_u16(vget_low_u16(low))) };
+    let f1 = unsafe { vcvtq_f32_u32(vmovl_u16(vget_high_u16(low))) };
+    let f2 = unsafe { vcvtq_f32_u32(vmovl_u16(vget_low_u16(high))) };
+    let f3 = unsafe { vcvtq_f32_u32(vmovl_u16(vget_high_u16(high))) };
+
+    let uzp01 = unsafe { vuzpq_f32(f0, f1) };
+    let uzp23 = unsafe { vuzpq_f32(f2, f3) };
+    let uzp_b_r = unsafe { vuzpq_f32(uzp01.0, uzp23.0) };
+    let uzp_g_a = unsafe { vuzpq_f32(uzp01.1, uzp23.1) };
+    [uzp_b_r.0, uzp_g_a.0, uzp_b_r.1, uzp_g_a.1]
+}
+
+/// Clamps `[b, g, r, a]` lanes to `0..=255` and stores them as four pixels.
+#[cfg(target_arch = "aarch64")]
+unsafe fn store_neon(ptr: *mut u32, [b, g, r, a]: [float32x4_t; 4]) {
+    let max_255 = unsafe { vdupq_n_f32(255.0) };
+    let zero = unsafe { vdupq_n_f32(0.0) };
+
+    let i_r = unsafe { vcvtq_u32_f32(vminq_f32(vmaxq_f32(r, zero), max_255)) };
+    let i_g = unsafe { vcvtq_u32_f32(vminq_f32(vmaxq_f32(g, zero), max_255)) };
+    let i_b = unsafe { vcvtq_u32_f32(vminq_f32(vmaxq_f32(b, zero), max_255)) };
+    let i_a = unsafe { vcvtq_u32_f32(vminq_f32(vmaxq_f32(a, zero), max_255)) };
+
+    let zip_br = unsafe { vzipq_u32(i_b, i_r) };
+    let zip_ga = unsafe { vzipq_u32(i_g, i_a) };
+    let res01 = unsafe { vzipq_u32(zip_br.0, zip_ga.0) };
+    let res23 = unsafe { vzipq_u32(zip_br.1, zip_ga.1) };
+
+    let p0_16 = unsafe { vqmovn_u32(res01.0) };
+    let p1_16 = unsafe { vqmovn_u32(res01.1) };
+    let p2_16 = unsafe { vqmovn_u32(res23.0) };
+    let p3_16 = unsafe { vqmovn_u32(res23.1) };
+
+    let p01_16 = unsafe { vcombine_u16(p0_16, p1_16) };
+    let p23_16 = unsafe { vcombine_u16(p2_16, p3_16) };
+
+    let p01_8 = unsafe { vqmovn_u16(p01_16) };
+    let p23_8 = unsafe { vqmovn_u16(p23_16) };
+
+    let final_vec = unsafe { vcombine_u8(p01_8, p23_8) };
+
+    unsafe { vst1q_u8(ptr as *mut u8, final_vec) };
+}
+
 #[cfg(target_arch = "aarch64")]
 unsafe fn blend_scanline_neon(dst: &mut [u32], src: &[u32], len: usize) {
     let mut i = 0;
@@ -215,56 +396,8 @@ unsafe fn blend_scanline_neon(dst: &mut [u32], src: &[u32], len: usize) {
         let s_ptr = unsafe { src.as_ptr().add(i) };
         let d_ptr = unsafe { dst.as_mut_ptr().add(i) };
 
-        let s_raw = unsafe { vld1q_u8(s_ptr as *const u8) };
-        let d_raw = unsafe { vld1q_u8(d_ptr as *const u8) };
-
-        let s_low = unsafe { vmovl_u8(vget_low_u8(s_raw)) };
-        let s_high = unsafe { vmovl_u8(vget_high_u8(s_raw)) };
-        let d_low = unsafe { vmovl_u8(vget_low_u8(d_raw)) };
-        let d_high = unsafe { vmovl_u8(vget_high_u8(d_raw)) };
-        
-        let s_p0 = unsafe { vmovl_u16(vget_low_u16(s_low)) };
-        let s_p1 = unsafe { vmovl_u16(vget_high_u16(s_low)) };
-        let s_p2 = unsafe { vmovl_u16(vget_low_u16(s_high)) };
-        let s_p3 = unsafe { vmovl_u16(vget_high_u16(s_high)) };
-        
-        let d_p0 = unsafe { vmovl_u16(vget_low_u16(d_low)) };
-        let d_p1 = unsafe { vmovl_u16(vget_high_u16(d_low)) };
-        let d_p2 = unsafe { vmovl_u16(vget_low_u16(d_high)) };
-        let d_p3 = unsafe { vmovl_u16(vget_high_u16(d_high)) };
-
-        let mut s_f0 = unsafe { vcvtq_f32_u32(s_p0) };
-        let mut s_f1 = unsafe { vcvtq_f32_u32(s_p1) };
-        let mut s_f2 = unsafe { vcvtq_f32_u32(s_p2) };
-        let mut s_f3 = unsafe { vcvtq_f32_u32(s_p3) };
-
-        let mut d_f0 = unsafe { vcvtq_f32_u32(d_p0) };
-        let mut d_f1 = unsafe { vcvtq_f32_u32(d_p1) };
-        let mut d_f2 = unsafe { vcvtq_f32_u32(d_p2) };
-        let mut d_f3 = unsafe { vcvtq_f32_u32(d_p3) };
-
-        let uzp01 = unsafe { vuzpq_f32(s_f0, s_f1) };
-        let uzp23 = unsafe { vuzpq_f32(s_f2, s_f3) };
-        let br01 = uzp01.0;
-        let ga01 = uzp01.1;
-        let br23 = uzp23.0;
-        let ga23 = uzp23.1;
-        let uzp_b_r = unsafe { vuzpq_f32(br01, br23) };
-        let uzp_g_a = unsafe { vuzpq_f32(ga01, ga23) };
-        
-        let src_b = uzp_b_r.0;
-        let src_r = uzp_b_r.1;
-        let src_g = uzp_g_a.0;
-        let src_a_byte = uzp_g_a.1;
-
-        let d_uzp01 = unsafe { vuzpq_f32(d_f0, d_f1) };
-        let d_uzp23 = unsafe { vuzpq_f32(d_f2, d_f3) };
-        let d_uzp_b_r = unsafe { vuzpq_f32(d_uzp01.0, d_uzp23.0) };
-        let d_uzp_g_a = unsafe { vuzpq_f32(d_uzp01.1, d_uzp23.1) };
-        let dst_b = d_uzp_b_r.0;
-        let dst_r = d_uzp_b_r.1;
-        let dst_g = d_uzp_g_a.0;
-        let dst_a_byte = d_uzp_g_a.1;
+        let [src_b, src_g, src_r, src_a_byte] = unsafe { load_neon(s_ptr) };
+        let [dst_b, dst_g, dst_r, dst_a_byte] = unsafe { load_neon(d_ptr) };
 
         let src_a = unsafe { vmulq_f32(src_a_byte, inv_255) };
         let dst_a = unsafe { vmulq_f32(dst_a_byte, inv_255) };
@@ -284,38 +417,8 @@ unsafe fn blend_scanline_neon(dst: &mut [u32], src: &[u32], len: usize) {
         let out_b = unsafe { vdivq_f32(out_b_num, safe_out_a) };
         
         let res_a = unsafe { vmulq_f32(out_a, vdupq_n_f32(255.0)) };
-        let max_255 = unsafe { vdupq_n_f32(255.0) };
-        let zero = unsafe { vdupq_n_f32(0.0) };
-        
-        let r_c = unsafe { vminq_f32(vmaxq_f32(out_r, zero), max_255) };
-        let g_c = unsafe { vminq_f32(vmaxq_f32(out_g, zero), max_255) };
-        let b_c = unsafe { vminq_f32(vmaxq_f32(out_b, zero), max_255) };
-        let a_c = unsafe { vminq_f32(vmaxq_f32(res_a, zero), max_255) };
-
-        let i_r = unsafe { vcvtq_u32_f32(r_c) };
-        let i_g = unsafe { vcvtq_u32_f32(g_c) };
-        let i_b = unsafe { vcvtq_u32_f32(b_c) };
-        let i_a = unsafe { vcvtq_u32_f32(a_c) };
-
-        let zip_br = unsafe { vzipq_u32(i_b, i_r) };
-        let zip_ga = unsafe { vzipq_u32(i_g, i_a) };
-        let res01 = unsafe { vzipq_u32(zip_br.0, zip_ga.0) }; 
-        let res23 = unsafe { vzipq_u32(zip_br.1, zip_ga.1) };
-        
-        let p0_16 = unsafe { vqmovn_u32(res01.0) };
-        let p1_16 = unsafe { vqmovn_u32(res01.1) };
-        let p2_16 = unsafe { vqmovn_u32(res23.0) };
-        let p3_16 = unsafe { vqmovn_u32(res23.1) };
-        
-        let p01_16 = unsafe { vcombine_u16(p0_16, p1_16) };
-        let p23_16 = unsafe { vcombine_u16(p2_16, p3_16) };
-        
-        let p01_8 = unsafe { vqmovn_u16(p01_16) };
-        let p23_8 = unsafe { vqmovn_u16(p23_16) };
-        
-        let final_vec = unsafe { vcombine_u8(p01_8, p23_8) };
-        
-        unsafe { vst1q_u8(d_ptr as *mut u8, final_vec) };
+
+        unsafe { store_neon(d_ptr, [out_b, out_g, out_r, res_a]) };
 
         i += 4;
     }
@@ -323,6 +426,69 @@ unsafe fn blend_scanline_neon(dst: &mut [u32], src: &[u32], len: usize) {
     blend_scanline_scalar(&mut dst[i..], &src[i..], len - i);
 }
 
+/// The blend function `B(cb, cs)` of a mode with a vector path, on
+/// channels in `0..=255`.
+#[cfg(target_arch = "aarch64")]
+unsafe fn separable_neon(mode: BlendMode, cb: float32x4_t, cs: float32x4_t) -> float32x4_t {
+    let product = unsafe { vmulq_f32(vmulq_f32(cb, cs), vdupq_n_f32(1.0 / 255.0)) };
+    unsafe {
+        match mode {
+            BlendMode::Multiply => product,
+            BlendMode::Screen => vsubq_f32(vaddq_f32(cb, cs), product),
+            BlendMode::Darken => vminq_f32(cb, cs),
+            BlendMode::Lighten => vmaxq_f32(cb, cs),
+            BlendMode::Difference => vabdq_f32(cb, cs),
+            BlendMode::Exclusion => vsubq_f32(vaddq_f32(cb, cs), vaddq_f32(product, product)),
+            _ => cs,
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn blend_mode_neon(dst: &mut [u32], src: &[u32], len: usize, mode: BlendMode) {
+    let mut i = 0;
+
+    let inv_255 = unsafe { vdupq_n_f32(1.0 / 255.0) };
+    let epsilon = unsafe { vdupq_n_f32(0.001) };
+
+    while i + 4 <= len {
+        let s_ptr = unsafe { src.as_ptr().add(i) };
+        let d_ptr = unsafe { dst.as_mut_ptr().add(i) };
+
+        let [src_b, src_g, src_r, src_a_byte] = unsafe { load_neon(s_ptr) };
+        let [dst_b, dst_g, dst_r, dst_a_byte] = unsafe { load_neon(d_ptr) };
+
+        let src_a = unsafe { vmulq_f32(src_a_byte, inv_255) };
+        let dst_a = unsafe { vmulq_f32(dst_a_byte, inv_255) };
+
+        // Weights of the source alone, the backdrop alone and their overlap
+        let both = unsafe { vmulq_f32(src_a, dst_a) };
+        let src_only = unsafe { vsubq_f32(src_a, both) };
+        let dst_only = unsafe { vsubq_f32(dst_a, both) };
+        let out_a = unsafe { vaddq_f32(src_a, dst_only) };
+        let safe_out_a = unsafe { vmaxq_f32(out_a, epsilon) };
+
+        let mix = |cb: float32x4_t, cs: float32x4_t| unsafe {
+            let blended = separable_neon(mode, cb, cs);
+            let sum = vaddq_f32(
+                vaddq_f32(vmulq_f32(cs, src_only), vmulq_f32(cb, dst_only)),
+                vmulq_f32(blended, both),
+            );
+            vdivq_f32(sum, safe_out_a)
+        };
+        let out_r = mix(dst_r, src_r);
+        let out_g = mix(dst_g, src_g);
+        let out_b = mix(dst_b, src_b);
+        let res_a = unsafe { vmulq_f32(out_a, vdupq_n_f32(255.0)) };
+
+        unsafe { store_neon(d_ptr, [out_b, out_g, out_r, res_a]) };
+
+        i += 4;
+    }
+
+    blend_mode_scalar(&mut dst[i..], &src[i..], len - i, mode);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -358,4 +524,28 @@ mod tests {
             assert!((b as i32 - 127).abs() <= 2, "Blue mismatch at {}: got {}", i, b);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_blend_modes_match_scalar() {
+        let modes = [
+            BlendMode::Multiply, BlendMode::Screen, BlendMode::Darken,
+            BlendMode::Lighten, BlendMode::Difference, BlendMode::Exclusion,
+        ];
+        let src: Vec<u32> = (0..13u32).map(|i| ((i * 20) << 24) | 0x00E0_9944 ^ (i * 0x0011_0513)).collect();
+        let backdrop: Vec<u32> = (0..13u32).map(|i| (0xFF - i * 9) << 24 | 0x003A_7BD5 ^ (i * 0x0007_1103)).collect();
+
+        for mode in modes {
+            let mut vector = backdrop.clone();
+            blend_scanline_mode(&mut vector, &src, mode);
+            let mut scalar = backdrop.clone();
+            blend_mode_scalar(&mut scalar, &src, src.len(), mode);
+
+            for (i, (&v, &s)) in vector.iter().zip(&scalar).enumerate() {
+                for shift in [0, 8, 16, 24] {
+                    let (v, s) = ((v >> shift) & 0xFF, (s >> shift) & 0xFF);
+                    assert!((v as i32 - s as i32).abs() <= 1, "Mismatch at {} in shift {}: {} vs {}", i, shift, v, s);
+                }
+            }
+        }
+    }
+}
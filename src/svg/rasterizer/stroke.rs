@@ -1,8 +1,9 @@
 use crate::svg::rasterizer::canva::Canvas;
 use crate::svg::rasterizer::dda::Rasterizer;
-use crate::svg::rasterizer::raster::{Bounds, Line, PathRasterizer};
+use crate::svg::rasterizer::raster::{clip_lines, visible_buffer, Line, PathRasterizer};
 use crate::svg::rasterizer::tags::path::generate_color_map;
 use crate::svg::utils::color::Paint;
+#[cfg(not(feature = "std"))]
 use crate::utils::compat::FloatExt;
 use crate::utils::compat::Vec;
 
@@ -125,26 +126,11 @@ fn make_line(x0: f32, y0: f32, x1: f32, y1: f32) -> Line {
     Line::new(x0, y0, x1, y1, 1.0)
 }
 
-fn translate_lines(lines: &[Line], dx: f32, dy: f32) -> Vec<Line> {
-    lines.iter().map(|line| {
-        let mut new_line = line.clone();
-        new_line.x0 -= dx;
-        new_line.y0 -= dy;
-        new_line.x1 -= dx;
-        new_line.y1 -= dy;
-        new_line
-    }).collect()
-}
-
 pub fn draw_stroke(
     map: &mut Canvas,
     rasterizer: &PathRasterizer,
     stroke: Paint,
     stroke_width: f32,
-    bbox_x: f32,
-    bbox_y: f32,
-    bbox_w: f32,
-    bbox_h: f32,
 ) {
     let stroke_outline_segments = create_stroke_outline(&rasterizer.lines, stroke_width);
 
@@ -173,23 +159,19 @@ pub fn draw_stroke(
         stroke_path_rasterizer.insert_line(line.x0, line.y0, line.x1, line.y1, 1.0);
     }
 
-    // Use floor for integer alignment
-    let draw_x = x_min.floor() as isize;
-    let draw_y = y_min.floor() as isize;
-    
-    let offset_x = draw_x as f32;
-    let offset_y = draw_y as f32;
-    
-    // Bounds relative to the offset
-    let r_w = (x_max - offset_x).ceil() as usize + 1;
-    let r_h = (y_max - offset_y).ceil() as usize + 1;
-
-    if r_w == 0 || r_h == 0 {
+    // Use floor for integer alignment, rasterizing only what is over the canvas
+    let draw_x = x_min.floor();
+    let draw_y = y_min.floor();
+    let Some((skip_x, skip_y, r_w, r_h)) = visible_buffer(
+        draw_x, draw_y, (x_max - draw_x).ceil() + 1.0, (y_max - draw_y).ceil() + 1.0, map.width, map.height,
+    ) else {
         return;
-    }
-    
-    let local_v = translate_lines(&stroke_path_rasterizer.v_lines, offset_x, offset_y);
-    let local_m = translate_lines(&stroke_path_rasterizer.m_lines, offset_x, offset_y);
+    };
+
+    let offset_x = draw_x + skip_x;
+    let offset_y = draw_y + skip_y;
+    let lines = stroke_path_rasterizer.v_lines.iter().chain(&stroke_path_rasterizer.m_lines);
+    let (local_v, local_m) = clip_lines(lines, offset_x, offset_y, (r_w - 1) as f32, r_h as f32);
 
     let mut stroke_renderer = Rasterizer::new(r_w, r_h);
     let stroke_bitmap = stroke_renderer
@@ -203,11 +185,7 @@ pub fn draw_stroke(
         r_h,
         offset_x,
         offset_y,
-        bbox_x,
-        bbox_y,
-        bbox_w,
-        bbox_h,
     );
 
-    map.add_buffer(&stroke_color_map, draw_x, draw_y, r_w, r_h);
+    map.add_buffer(&stroke_color_map, offset_x as isize, offset_y as isize, r_w, r_h);
 }
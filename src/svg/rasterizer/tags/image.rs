@@ -0,0 +1,264 @@
+use crate::{detect_type, load_image, png, tga, ImageType};
+use crate::svg::document::{RenderOptions, SvgDocument};
+use crate::svg::parser::tags::Tag;
+use crate::svg::utils::style::get_property;
+use crate::svg::rasterizer::canva::Canvas;
+use crate::svg::rasterizer::raster::{Bounds, Point};
+use crate::svg::rasterizer::tags::path::{apply_transform_to_path, fill_path, PathCommand};
+use crate::svg::utils::color::Paint;
+use crate::svg::utils::coords::{Axis, LengthContext};
+use crate::svg::utils::data_uri::parse_data_uri;
+use crate::svg::utils::transform::{view_box_transform, Transform};
+use crate::utils::compat::{Box, Vec, vec};
+#[cfg(not(feature = "std"))]
+use crate::utils::compat::FloatExt;
+use crate::utils::image::{sample_bilinear, sample_nearest};
+
+/// Largest edge an embedded SVG is rasterized at, in device pixels.
+const MAX_RASTER_SIZE: f32 = 4096.0;
+
+/// An `<image>` decoded and placed in user space.
+#[derive(Debug, Clone)]
+pub struct Image {
+    source: ImageSource,
+    /// Intrinsic size, which `content` maps from.
+    size: (f32, f32),
+    /// Maps the intrinsic size into the viewport, through `preserveAspectRatio`.
+    content: Transform,
+    /// The viewport and the placed image (which `slice` overflows) intersected.
+    clip: Bounds,
+    opacity: f32,
+    pixelated: bool,
+}
+
+#[derive(Debug, Clone)]
+enum ImageSource {
+    /// A PNG or TGA decoded at its native size.
+    Raster { pixels: Vec<u32>, width: usize, height: usize },
+    /// An SVG, rendered at device resolution when drawn.
+    Svg(Box<SvgDocument>),
+}
+
+/// Decodes an `<image>` whose `href` is a `data:` URI holding a PNG, TGA or
+/// SVG and places it, with lengths resolved in `lengths`. External hrefs are
+/// inlined beforehand by [`crate::svg::render_with_resolver`]; `None` when
+/// the image is missing, broken or has no area.
+pub fn load_image_element(tag: &Tag, lengths: &LengthContext) -> Option<Image> {
+    let data = tag.params.get("href")
+        .or_else(|| tag.params.get("xlink:href"))
+        .and_then(|href| parse_data_uri(href))?;
+
+    let (source, intrinsic) = match detect_type(&data) {
+        ImageType::Png => decode_raster(&data, png::dimensions(&data))?,
+        ImageType::Tga => decode_raster(&data, tga::dimensions(&data))?,
+        ImageType::Svg => {
+            let document = SvgDocument::parse(&data).ok()?;
+            let intrinsic = document.size().map(|(w, h)| (w.ceil(), h.ceil()));
+            (ImageSource::Svg(Box::new(document)), intrinsic)
+        }
+        ImageType::Unknown => return None,
+    };
+
+    let length = |key: &str, axis: Axis| lengths.attribute(tag, key, axis);
+    let x = length("x", Axis::Horizontal).unwrap_or(0.0);
+    let y = length("y", Axis::Vertical).unwrap_or(0.0);
+
+    // A missing width or height follows the intrinsic aspect ratio
+    let (width, height) = match (length("width", Axis::Horizontal), length("height", Axis::Vertical), intrinsic) {
+        (Some(w), Some(h), _) => (w, h),
+        (Some(w), None, Some((iw, ih))) => (w, w * ih / iw),
+        (None, Some(h), Some((iw, ih))) => (h * iw / ih, h),
+        (None, None, Some(size)) => size,
+        _ => return None,
+    };
+    if width <= 0.0 || height <= 0.0 {
+        return None;
+    }
+
+    // An SVG without an intrinsic size stretches over the viewport
+    let (iw, ih) = intrinsic.unwrap_or((width, height));
+    let preserve_aspect_ratio = tag.params.get("preserveAspectRatio").map(|s| s.trim());
+    let content = Transform::translate(x, y)
+        .then(&view_box_transform([0.0, 0.0, iw, ih], width, height, preserve_aspect_ratio));
+
+    let (ix0, iy0) = content.apply(0.0, 0.0);
+    let (ix1, iy1) = content.apply(iw, ih);
+    let left = x.max(ix0.min(ix1));
+    let top = y.max(iy0.min(iy1));
+    let right = (x + width).min(ix0.max(ix1));
+    let bottom = (y + height).min(iy0.max(iy1));
+    if right <= left || bottom <= top {
+        return None;
+    }
+
+    let opacity = get_property(tag, "opacity")
+        .and_then(|s| s.parse::<f32>().ok())
+        .filter(|opacity| !opacity.is_nan())
+        .unwrap_or(1.0)
+        .clamp(0.0, 1.0);
+    let pixelated = matches!(
+        get_property(tag, "image-rendering"),
+        Some("pixelated") | Some("crisp-edges") | Some("optimizeSpeed")
+    );
+
+    Some(Image {
+        source,
+        size: (iw, ih),
+        content,
+        clip: Bounds { x: left, y: top, width: right - left, height: bottom - top },
+        opacity,
+        pixelated,
+    })
+}
+
+/// Decodes a PNG or TGA of the given `dimensions` at its native size.
+fn decode_raster(data: &[u8], dimensions: Option<(usize, usize)>) -> Option<(ImageSource, Option<(f32, f32)>)> {
+    let (width, height) = dimensions.filter(|&(w, h)| w > 0 && h > 0)?;
+    let pixels = load_image(data, width, height).ok()?;
+    Some((ImageSource::Raster { pixels, width, height }, Some((width as f32, height as f32))))
+}
+
+/// Draws an image loaded by [`load_image_element`] through `transform`.
+pub fn draw_image(image: &Image, canvas: &mut Canvas, transform: &Transform) {
+    let (iw, ih) = image.size;
+    let image_to_device = transform.then(&image.content);
+    let Some(device_to_image) = image_to_device.inverse() else { return };
+
+    // Raster formats were decoded at their native size; SVG renders at device resolution
+    let rendered;
+    let (pixels, pw, ph) = match &image.source {
+        ImageSource::Raster { pixels, width, height } => (pixels, *width, *height),
+        ImageSource::Svg(document) => {
+            let (sx, sy) = image_to_device.get_scale();
+            let pw = (iw * sx).ceil().clamp(1.0, MAX_RASTER_SIZE) as usize;
+            let ph = (ih * sy).ceil().clamp(1.0, MAX_RASTER_SIZE) as usize;
+            rendered = document.render(pw, ph, &RenderOptions { background: 0, ..RenderOptions::default() });
+            (&rendered, pw, ph)
+        }
+    };
+
+    let Bounds { x: left, y: top, width, height } = image.clip;
+    let (right, bottom) = (left + width, top + height);
+    let corners = [(left, top), (right, top), (right, bottom), (left, bottom)].map(|(x, y)| transform.apply(x, y));
+    let min_x = corners.iter().fold(f32::INFINITY, |m, c| m.min(c.0)).floor().max(0.0);
+    let min_y = corners.iter().fold(f32::INFINITY, |m, c| m.min(c.1)).floor().max(0.0);
+    let max_x = corners.iter().fold(f32::NEG_INFINITY, |m, c| m.max(c.0)).ceil().min(canvas.width as f32);
+    let max_y = corners.iter().fold(f32::NEG_INFINITY, |m, c| m.max(c.1)).ceil().min(canvas.height as f32);
+    if max_x <= min_x || max_y <= min_y {
+        return;
+    }
+
+    let (w, h) = ((max_x - min_x) as usize, (max_y - min_y) as usize);
+    let clip = vec![
+        PathCommand::MoveTo(Point { x: left, y: top }),
+        PathCommand::LineTo(Point { x: right, y: top }),
+        PathCommand::LineTo(Point { x: right, y: bottom }),
+        PathCommand::LineTo(Point { x: left, y: bottom }),
+        PathCommand::ClosePath,
+    ];
+    let mut layer = Canvas::new_transparent(w, h);
+    let offset = Transform::translate(-min_x, -min_y).then(transform);
+    fill_path(&mut layer, &apply_transform_to_path(&clip, &offset), &Paint::Solid(0xFF000000));
+
+    let (scale_x, scale_y) = (pw as f32 / iw, ph as f32 / ih);
+
+    // The layer holds clip coverage in its alpha channel; replace it with image pixels
+    for (i, pixel) in layer.data.iter_mut().enumerate() {
+        let coverage = *pixel >> 24;
+        if coverage == 0 {
+            continue;
+        }
+
+        let (u, v) = device_to_image.apply(min_x + (i % w) as f32 + 0.5, min_y + (i / w) as f32 + 0.5);
+        let color = if image.pixelated {
+            sample_nearest(pixels, pw, ph, u * scale_x, v * scale_y)
+        } else {
+            sample_bilinear(pixels, pw, ph, u * scale_x, v * scale_y)
+        };
+
+        let alpha = ((color >> 24) as f32 * coverage as f32 / 255.0 * image.opacity).round() as u32;
+        *pixel = (color & 0x00FFFFFF) | (alpha << 24);
+    }
+
+    canvas.add_buffer(&layer.data, min_x as isize, min_y as isize, w, h);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::svg::testing::{red_blue_image, render};
+    use crate::svg::utils::data_uri::parse_data_uri;
+    use crate::utils::compat::{format, Vec};
+
+    fn render_image(attributes: &str) -> Vec<u32> {
+        render(&format!(r#"<svg width="8" height="8"><image href="{}" image-rendering="pixelated" {}/></svg>"#, red_blue_image(), attributes), 8, 8)
+    }
+
+    #[test]
+    fn test_data_uri_image_fills_its_viewport() {
+        let pixels = render_image(r#"width="8" height="8" preserveAspectRatio="none""#);
+        assert_eq!(pixels[3], 0xFFFF0000);
+        assert_eq!(pixels[4], 0xFF0000FF);
+        assert_eq!(pixels[7 * 8 + 7], 0xFF0000FF);
+    }
+
+    #[test]
+    fn test_preserve_aspect_ratio() {
+        // meet centres the 2:1 image vertically
+        let meet = render_image(r#"width="8" height="8""#);
+        assert_eq!(meet[8], 0xFFFFFFFF);
+        assert_eq!(meet[2 * 8], 0xFFFF0000);
+        assert_eq!(meet[5 * 8 + 7], 0xFF0000FF);
+        assert_eq!(meet[6 * 8 + 7], 0xFFFFFFFF);
+
+        let bottom = render_image(r#"width="8" height="8" preserveAspectRatio="xMidYMax meet""#);
+        assert_eq!(bottom[3 * 8], 0xFFFFFFFF);
+        assert_eq!(bottom[4 * 8], 0xFFFF0000);
+
+        // slice fills the box and crops what overflows it
+        let left = render_image(r#"width="8" height="8" preserveAspectRatio="xMinYMid slice""#);
+        assert!(left.iter().all(|&pixel| pixel == 0xFFFF0000));
+        let right = render_image(r#"width="8" height="8" preserveAspectRatio="xMaxYMid slice""#);
+        assert!(right.iter().all(|&pixel| pixel == 0xFF0000FF));
+    }
+
+    #[test]
+    fn test_missing_size_follows_the_intrinsic_aspect_ratio() {
+        let pixels = render_image(r#"width="8""#);
+        assert_eq!(pixels[3 * 8], 0xFFFF0000);
+        assert_eq!(pixels[4 * 8], 0xFFFFFFFF);
+    }
+
+    #[test]
+    fn test_rendering_and_opacity_from_style() {
+        let smooth = render(&format!(r#"<svg width="8" height="4"><image href="{}" width="8" height="4"/></svg>"#, red_blue_image()), 8, 4);
+        let styled = render(&format!(
+            r#"<svg width="8" height="4"><image href="{}" width="8" height="4" style="image-rendering: pixelated"/></svg>"#,
+            red_blue_image()
+        ), 8, 4);
+        assert_ne!(smooth[2 * 8 + 3], 0xFFFF0000);
+        assert_eq!(styled[2 * 8 + 3], 0xFFFF0000);
+        assert_eq!(styled[2 * 8 + 4], 0xFF0000FF);
+
+        let hidden = render_image(r#"width="8" height="8" opacity="1" style="opacity: 0""#);
+        assert!(hidden.iter().all(|&pixel| pixel == 0xFFFFFFFF));
+        // An unusable opacity is ignored rather than painting nothing
+        assert_eq!(render_image(r#"width="8" height="8" opacity="NaN""#)[2 * 8], 0xFFFF0000);
+    }
+
+    #[test]
+    fn test_external_hrefs_go_through_the_resolver() {
+        let svg = br#"<svg width="8" height="4"><image href="pic.tga" width="8" height="4" image-rendering="pixelated"/></svg>"#;
+        assert!(crate::svg::render(svg, 8, 4).unwrap().iter().all(|&pixel| pixel == 0xFFFFFFFF));
+
+        let resolver = |href: &str| (href == "pic.tga").then(|| parse_data_uri(&red_blue_image()).unwrap());
+        let pixels = crate::svg::render_with_resolver(svg, 8, 4, &resolver).unwrap();
+        assert_eq!(pixels[0], 0xFFFF0000);
+        assert_eq!(pixels[7], 0xFF0000FF);
+    }
+
+    #[test]
+    fn test_broken_data_is_skipped() {
+        let broken = r#"<svg width="4" height="4"><image href="data:image/png;base64,iVBORw0KGgo=" width="4" height="4"/></svg>"#;
+        assert!(render(broken, 4, 4).iter().all(|&pixel| pixel == 0xFFFFFFFF));
+    }
+}
@@ -1,9 +1,17 @@
 use crate::svg::parser::tags::Tag;
+use crate::svg::utils::style::get_property;
+use crate::svg::rasterizer::raster::Bounds;
 use crate::svg::utils::color::parse_color_value;
-use crate::svg::utils::coords::parse_length;
+use crate::svg::utils::coords::{parse_length, Axis, LengthContext};
+use crate::svg::utils::transform::Transform;
+#[cfg(not(feature = "std"))]
 use crate::utils::compat::FloatExt;
-use crate::utils::compat::Vec;
+use crate::utils::compat::{Vec, vec};
 use core::cmp::Ordering;
+use crate::svg::utils::Defs;
+
+/// Longest `href` chain followed when collecting template attributes.
+const MAX_TEMPLATE_DEPTH: usize = 16;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum GradientUnits {
@@ -11,14 +19,50 @@ pub enum GradientUnits {
     ObjectBoundingBox,
 }
 
+impl GradientUnits {
+    /// The context lengths in these units resolve in: percentages of a
+    /// bounding box are fractions of it.
+    pub(crate) fn lengths(&self, lengths: &LengthContext) -> LengthContext {
+        match self {
+            GradientUnits::UserSpaceOnUse => *lengths,
+            GradientUnits::ObjectBoundingBox => lengths.with_viewport(1.0, 1.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpreadMethod {
+    Pad,
+    Reflect,
+    Repeat,
+}
+
+impl SpreadMethod {
+    /// Maps a gradient parameter outside `0..=1` back into that range.
+    pub fn apply(&self, t: f32) -> f32 {
+        match self {
+            SpreadMethod::Pad => t.clamp(0.0, 1.0),
+            SpreadMethod::Repeat => t - t.floor(),
+            SpreadMethod::Reflect => {
+                let t = t - 2.0 * (t / 2.0).floor();
+                if t > 1.0 { 2.0 - t } else { t }
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct LinearGradient {
     pub x1: f32,
     pub y1: f32,
     pub x2: f32,
     pub y2: f32,
-    pub stops: Vec<GradientStop>,
+    pub ramp: ColorRamp,
     pub units: GradientUnits,
+    pub spread: SpreadMethod,
+    /// `gradientTransform`, mapping gradient space to the units' coordinate system.
+    pub transform: Transform,
+    device_to_gradient: Option<Transform>,
 }
 
 #[derive(Debug, Clone)]
@@ -28,116 +72,223 @@ pub struct GradientStop {
 }
 
 impl LinearGradient {
-    pub fn scale(&mut self, scale: f32) {
-        if self.units == GradientUnits::UserSpaceOnUse {
-            self.x1 *= scale;
-            self.y1 *= scale;
-            self.x2 *= scale;
-            self.y2 *= scale;
-        }
+    /// Resolves the gradient for an element with user-space bounding box
+    /// `bbox`, drawn through `transform`.
+    pub fn prepare(&mut self, transform: &Transform, bbox: Bounds) {
+        self.device_to_gradient = device_to_gradient(&self.units, &self.transform, transform, bbox);
     }
 
-    pub fn interpolate(&self, x: f32, y: f32, bbox_x: f32, bbox_y: f32, bbox_w: f32, bbox_h: f32) -> u32 {
-        if self.stops.is_empty() {
-            return 0x00000000;
-        }
+    pub fn interpolate(&self, x: f32, y: f32) -> u32 {
+        let Some(device_to_gradient) = &self.device_to_gradient else { return 0x00000000 };
+        let dx = self.x2 - self.x1;
+        let dy = self.y2 - self.y1;
+        // Projecting onto the vector scaled down to at most unit length
+        // keeps huge coordinates from overflowing
+        let scale = dx.abs().max(dy.abs());
 
-        if self.stops.len() == 1 {
-            return self.stops[0].color;
+        // A zero-length vector paints the last stop
+        if scale < 1e-6 {
+            return self.ramp.last_color();
         }
 
-        let (gx1, gy1, gx2, gy2) = if self.units == GradientUnits::ObjectBoundingBox {
-            (
-                bbox_x + self.x1 * bbox_w,
-                bbox_y + self.y1 * bbox_h,
-                bbox_x + self.x2 * bbox_w,
-                bbox_y + self.y2 * bbox_h,
-            )
-        } else {
-            (self.x1, self.y1, self.x2, self.y2)
-        };
+        let (ux, uy) = (dx / scale, dy / scale);
+        let (gx, gy) = device_to_gradient.apply(x + 0.5, y + 0.5);
+        let t = ((gx - self.x1) * ux + (gy - self.y1) * uy) / (scale * (ux * ux + uy * uy));
 
-        let dx = gx2 - gx1;
-        let dy = gy2 - gy1;
-        let len_sq = dx * dx + dy * dy;
+        self.ramp.color_at(self.spread.apply(t), x, y)
+    }
+}
 
-        if len_sq < 1e-6 {
-            return self.stops[0].color;
-        }
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorInterpolation {
+    Srgb,
+    LinearRgb,
+}
 
-        let px = x - gx1;
-        let py = y - gy1;
-        let mut t = (px * dx + py * dy) / len_sq;
+/// 8x8 Bayer matrix used to dither gradients when quantizing to 8 bits.
+const BAYER_8X8: [u8; 64] = [
+     0, 32,  8, 40,  2, 34, 10, 42,
+    48, 16, 56, 24, 50, 18, 58, 26,
+    12, 44,  4, 36, 14, 46,  6, 38,
+    60, 28, 52, 20, 62, 30, 54, 22,
+     3, 35, 11, 43,  1, 33,  9, 41,
+    51, 19, 59, 27, 49, 17, 57, 25,
+    15, 47,  7, 39, 13, 45,  5, 37,
+    63, 31, 55, 23, 61, 29, 53, 21,
+];
 
-        // Basic spread method: pad (clamp)
-        // TODO: Implement repeat/reflect
-        t = t.clamp(0.0, 1.0);
+/// Gradient stops, interpolated with premultiplied alpha in either sRGB or
+/// linear-light space.
+#[derive(Debug, Clone)]
+pub struct ColorRamp {
+    pub stops: Vec<GradientStop>,
+    pub interpolation: ColorInterpolation,
+    /// Stop colors as premultiplied `[a, r, g, b]` in the interpolation space.
+    premultiplied: Vec<[f32; 4]>,
+}
+
+impl ColorRamp {
+    pub fn new(stops: Vec<GradientStop>, interpolation: ColorInterpolation) -> Self {
+        let premultiplied = stops.iter().map(|stop| {
+            let a = ((stop.color >> 24) & 0xFF) as f32 / 255.0;
+            let channel = |shift: u32| {
+                let c = ((stop.color >> shift) & 0xFF) as f32 / 255.0;
+                let c = match interpolation {
+                    ColorInterpolation::Srgb => c,
+                    ColorInterpolation::LinearRgb => srgb_to_linear(c),
+                };
+                c * a
+            };
+            [a, channel(16), channel(8), channel(0)]
+        }).collect();
 
-        if t <= self.stops[0].offset {
-            return self.stops[0].color;
+        Self { stops, interpolation, premultiplied }
+    }
+
+    /// Color of the last stop, which degenerate gradients paint.
+    pub fn last_color(&self) -> u32 {
+        self.stops.last().map(|s| s.color).unwrap_or(0x00000000)
+    }
+
+    /// Color at `t` (already within `0..=1`), dithered for device pixel (`x`, `y`).
+    pub fn color_at(&self, t: f32, x: f32, y: f32) -> u32 {
+        let stops = &self.stops;
+        match stops.len() {
+            0 => return 0x00000000,
+            1 => return stops[0].color,
+            _ => {}
         }
-        if t >= self.stops[self.stops.len() - 1].offset {
-            return self.stops[self.stops.len() - 1].color;
+
+        let last = stops.len() - 1;
+        if t.is_nan() || t <= stops[0].offset {
+            return stops[0].color;
+        }
+        if t >= stops[last].offset {
+            return stops[last].color;
         }
 
-        for i in 0..self.stops.len() - 1 {
-            let stop1 = &self.stops[i];
-            let stop2 = &self.stops[i + 1];
+        let i = stops.iter().rposition(|s| s.offset <= t).unwrap_or(0).min(last - 1);
+        let range = stops[i + 1].offset - stops[i].offset;
+        let local_t = if range > 1e-6 { (t - stops[i].offset) / range } else { 0.0 };
 
-            if t >= stop1.offset && t <= stop2.offset {
-                let range = stop2.offset - stop1.offset;
-                let local_t = if range > 1e-6 {
-                    (t - stop1.offset) / range
-                } else {
-                    0.0
-                };
+        let (c1, c2) = (self.premultiplied[i], self.premultiplied[i + 1]);
+        let mixed = [0, 1, 2, 3].map(|k| c1[k] + (c2[k] - c1[k]) * local_t);
+        self.quantize(mixed, x as i32, y as i32)
+    }
 
-                return blend_colors(stop1.color, stop2.color, local_t);
-            }
+    /// Converts a premultiplied color back to straight 8-bit sRGB with ordered dithering.
+    fn quantize(&self, color: [f32; 4], x: i32, y: i32) -> u32 {
+        let threshold = BAYER_8X8[((y & 7) * 8 + (x & 7)) as usize] as f32 / 64.0 - 0.5 + 1.0 / 128.0;
+        let to_byte = |v: f32| (v * 255.0 + threshold).round().clamp(0.0, 255.0) as u32;
+
+        let a = to_byte(color[0]);
+        if a == 0 {
+            return 0x00000000;
         }
 
-        self.stops[self.stops.len() - 1].color
+        let channel = |c: f32| {
+            let c = (c / color[0]).clamp(0.0, 1.0);
+            to_byte(match self.interpolation {
+                ColorInterpolation::Srgb => c,
+                ColorInterpolation::LinearRgb => linear_to_srgb(c),
+            })
+        };
+        (a << 24) | (channel(color[1]) << 16) | (channel(color[2]) << 8) | channel(color[3])
     }
 }
 
-pub(crate) fn blend_colors(color1: u32, color2: u32, t: f32) -> u32 {
-    let a1 = ((color1 >> 24) & 0xFF) as f32;
-    let r1 = ((color1 >> 16) & 0xFF) as f32;
-    let g1 = ((color1 >> 8) & 0xFF) as f32;
-    let b1 = (color1 & 0xFF) as f32;
-
-    let a2 = ((color2 >> 24) & 0xFF) as f32;
-    let r2 = ((color2 >> 16) & 0xFF) as f32;
-    let g2 = ((color2 >> 8) & 0xFF) as f32;
-    let b2 = (color2 & 0xFF) as f32;
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
 
-    let a = (a1 + (a2 - a1) * t) as u32;
-    let r = (r1 + (r2 - r1) * t) as u32;
-    let g = (g1 + (g2 - g1) * t) as u32;
-    let b = (b1 + (b2 - b1) * t) as u32;
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 }
+}
 
-    (a << 24) | (r << 16) | (g << 8) | b
+/// Maps device coordinates into gradient space. `None` disables painting, as
+/// for `objectBoundingBox` units on an element with an empty bounding box.
+pub(crate) fn device_to_gradient(
+    units: &GradientUnits,
+    gradient_transform: &Transform,
+    transform: &Transform,
+    bbox: Bounds,
+) -> Option<Transform> {
+    gradient_to_device(units, gradient_transform, transform, bbox)?.inverse()
 }
 
+/// Maps gradient space to device coordinates; see [`device_to_gradient`].
+pub(crate) fn gradient_to_device(
+    units: &GradientUnits,
+    gradient_transform: &Transform,
+    transform: &Transform,
+    bbox: Bounds,
+) -> Option<Transform> {
+    let to_device = match units {
+        GradientUnits::UserSpaceOnUse => transform.clone(),
+        GradientUnits::ObjectBoundingBox => {
+            if bbox.width <= 0.0 || bbox.height <= 0.0 {
+                return None;
+            }
+            transform
+                .then(&Transform::translate(bbox.x, bbox.y))
+                .then(&Transform::scale(bbox.width, bbox.height))
+        }
+    };
+    Some(to_device.then(gradient_transform))
+}
 
+/// Collects a gradient and the gradients it references through `href`,
+/// nearest first. Any gradient type can serve as a template.
+pub(crate) fn template_chain<'a>(tag: &'a Tag, defs: &'a Defs<'a>) -> Vec<&'a Tag> {
+    let mut chain = vec![tag];
+    while chain.len() < MAX_TEMPLATE_DEPTH {
+        let last = chain[chain.len() - 1];
+        let next = last.params.get("href")
+            .or_else(|| last.params.get("xlink:href"))
+            .and_then(|link| defs.get(link.trim().trim_start_matches('#')).copied())
+            .filter(|t| matches!(t.name.as_str(), "linearGradient" | "radialGradient" | "conicGradient" | "meshgradient" | "meshGradient"))
+            .filter(|t| !chain.iter().any(|seen| core::ptr::eq(*seen, *t)));
+        match next {
+            Some(next) => chain.push(next),
+            None => break,
+        }
+    }
+    chain
+}
 
-pub fn load_linear_gradient(tag: &Tag) -> LinearGradient {
-    let x1 = tag.params.get("x1").map(|s| parse_length(s, 0.0, 1.0)).unwrap_or(0.0);
-    let y1 = tag.params.get("y1").map(|s| parse_length(s, 0.0, 1.0)).unwrap_or(0.0);
-    let x2 = tag.params.get("x2").map(|s| parse_length(s, 1.0, 1.0)).unwrap_or(1.0);
-    let y2 = tag.params.get("y2").map(|s| parse_length(s, 0.0, 1.0)).unwrap_or(0.0);
+/// Reads the attributes shared by all gradient types from a template chain.
+pub(crate) fn load_common(chain: &[&Tag]) -> (GradientUnits, SpreadMethod, Transform, ColorRamp) {
+    let attr = |key: &str| chain.iter().find_map(|t| t.params.get(key)).map(|s| s.trim());
 
-    let units = tag.params.get("gradientUnits")
-        .map(|s| s.as_str())
-        .unwrap_or("objectBoundingBox");
-    
-    let units = match units {
-        "userSpaceOnUse" => GradientUnits::UserSpaceOnUse,
+    let units = match attr("gradientUnits") {
+        Some("userSpaceOnUse") => GradientUnits::UserSpaceOnUse,
         _ => GradientUnits::ObjectBoundingBox,
     };
+    let spread = match attr("spreadMethod") {
+        Some("reflect") => SpreadMethod::Reflect,
+        Some("repeat") => SpreadMethod::Repeat,
+        _ => SpreadMethod::Pad,
+    };
+    let interpolation = match attr("color-interpolation") {
+        Some("linearRGB") => ColorInterpolation::LinearRgb,
+        _ => ColorInterpolation::Srgb,
+    };
+    let transform = attr("gradientTransform")
+        .and_then(Transform::from_str)
+        .unwrap_or(Transform::identity());
 
+    // Stops come from the nearest gradient that has any
+    let stop_tags = chain.iter()
+        .find(|t| t.children.iter().any(|c| c.name == "stop"))
+        .map(|t| t.children.as_slice())
+        .unwrap_or(&[]);
+
+    (units, spread, transform, ColorRamp::new(load_stops(stop_tags), interpolation))
+}
+
+fn load_stops(children: &[Tag]) -> Vec<GradientStop> {
     let mut stops = Vec::new();
-    for child in &tag.children {
+    for child in children {
         if child.name == "stop" {
             let offset = child
                 .params
@@ -145,15 +296,11 @@ pub fn load_linear_gradient(tag: &Tag) -> LinearGradient {
                 .map(|s| parse_length(s, 0.0, 1.0))
                 .unwrap_or(0.0);
 
-            let color = child
-                .params
-                .get("stop-color")
-                .map(|c| parse_color_value(c))
+            let color = get_property(child, "stop-color")
+                .map(parse_color_value)
                 .unwrap_or(0x000000);
 
-            // stop-opacity support could be added here
-            // but we need to blend it into the color alpha
-            let opacity = child.params.get("stop-opacity")
+            let opacity = get_property(child, "stop-opacity")
                 .and_then(|s| s.parse::<f32>().ok())
                 .unwrap_or(1.0);
 
@@ -168,16 +315,143 @@ pub fn load_linear_gradient(tag: &Tag) -> LinearGradient {
             stops.push(GradientStop { offset, color: final_color });
         }
     }
-    
+
     stops.sort_by(|a, b| a.offset.partial_cmp(&b.offset).unwrap_or(Ordering::Equal));
+    stops
+}
+
+/// Loads a `<linearGradient>`, inheriting unset attributes and stops from
+/// the gradients it references through `href`.
+pub fn load_linear_gradient(tag: &Tag, defs: &Defs, lengths: &LengthContext) -> LinearGradient {
+    let chain = template_chain(tag, defs);
+    let attr = |key: &str| chain.iter()
+        .filter(|t| t.name == "linearGradient")
+        .find_map(|t| t.params.get(key));
 
+    let (units, spread, transform, ramp) = load_common(&chain);
+    let lengths = units.lengths(lengths);
+    let length = |key: &str, default: f32, axis: Axis| attr(key).and_then(|s| lengths.length(s, axis)).unwrap_or(default);
 
     LinearGradient {
-        x1,
-        y1,
-        x2,
-        y2,
-        stops,
+        x1: length("x1", 0.0, Axis::Horizontal),
+        y1: length("y1", 0.0, Axis::Vertical),
+        // Defaults to 100%
+        x2: length("x2", lengths.reference(Axis::Horizontal), Axis::Horizontal),
+        y2: length("y2", 0.0, Axis::Vertical),
+        ramp,
         units,
+        spread,
+        transform,
+        device_to_gradient: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::svg::testing::{near, render};
+    use crate::utils::compat::{format, Vec};
+
+    /// Fills a 10x2 canvas with gradient `g`.
+    fn render_gradient(gradient: &str) -> Vec<u32> {
+        render(&format!(r##"<svg width="10" height="2">{}<rect width="10" height="2" fill="url(#g)"/></svg>"##, gradient), 10, 2)
+    }
+
+    const RED_BLUE: &str = r#"<stop offset="0" stop-color="red"/><stop offset="1" stop-color="blue"/>"#;
+
+    #[test]
+    fn test_spread_methods() {
+        let pad = render_gradient(&format!(r#"<linearGradient id="g" x1="0.3" x2="0.7">{RED_BLUE}</linearGradient>"#));
+        assert_eq!(pad[1], 0xFFFF0000);
+        assert_eq!(pad[8], 0xFF0000FF);
+
+        let repeat = render_gradient(&format!(r#"<linearGradient id="g" x2="0.5" spreadMethod="repeat">{RED_BLUE}</linearGradient>"#));
+        assert!(near(repeat[1], repeat[6], 1));
+        assert!(near(repeat[4], repeat[9], 1));
+
+        let reflect = render_gradient(&format!(r#"<linearGradient id="g" x2="0.5" spreadMethod="reflect">{RED_BLUE}</linearGradient>"#));
+        assert!(near(reflect[4], reflect[5], 1));
+        assert!(near(reflect[0], reflect[9], 1));
+        assert!(!near(reflect[0], reflect[4], 16));
+    }
+
+    #[test]
+    fn test_gradient_transform_rotates_the_vector() {
+        let vertical = render_gradient(&format!(r#"<linearGradient id="g" gradientTransform="rotate(90)">{RED_BLUE}</linearGradient>"#));
+        assert!(near(vertical[0], vertical[9], 1));
+        assert!(!near(vertical[0], vertical[10], 16));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_href_templates() {
+        let inherited = render_gradient(&format!(r##"
+            <linearGradient id="t" gradientUnits="userSpaceOnUse" x1="4" x2="6">{RED_BLUE}</linearGradient>
+            <linearGradient id="g" href="#t" x2="8"/>"##));
+        assert_eq!(inherited[3], 0xFFFF0000);
+        // x1 and the units come from the template, x2 is the gradient's own
+        assert!(near(inherited[5], 0xFF9F0060, 2));
+        assert!(near(inherited[6], 0xFF60009F, 2));
+        assert_eq!(inherited[9], 0xFF0000FF);
+
+        // A gradient with its own stops ignores the template's
+        let own = render_gradient(r##"
+            <linearGradient id="t"><stop stop-color="red"/></linearGradient>
+            <linearGradient id="g" xlink:href="#t"><stop stop-color="lime"/></linearGradient>"##);
+        assert!(own.iter().all(|&pixel| pixel == 0xFF00FF00));
+    }
+
+    #[test]
+    fn test_degenerate_and_huge_vectors() {
+        // A zero-length vector paints the last stop
+        let zero = render_gradient(&format!(r#"<linearGradient id="g" x1="0.5" x2="0.5">{RED_BLUE}</linearGradient>"#));
+        assert!(zero.iter().all(|&pixel| pixel == 0xFF0000FF));
+
+        let huge = render_gradient(&format!(
+            r#"<linearGradient id="g" gradientUnits="userSpaceOnUse" x1="-1e30" x2="1e30" spreadMethod="repeat">{RED_BLUE}</linearGradient>"#
+        ));
+        assert!(huge.iter().all(|&pixel| near(pixel, 0xFF7F0080, 2)));
+    }
+
+    #[test]
+    fn test_interpolation_is_premultiplied() {
+        let pixels = render_gradient(r#"<linearGradient id="g"><stop offset="0" stop-color="red"/><stop offset="1" stop-color="blue" stop-opacity="0"/></linearGradient>"#);
+        // Half-transparent red over white, with no blue bleeding in from the transparent stop
+        assert!(near(pixels[5], 0xFFFF7F7F, 16));
+        assert_eq!(pixels[5] & 0xFF, (pixels[5] >> 8) & 0xFF);
+    }
+
+    #[test]
+    fn test_linear_rgb_interpolation() {
+        let srgb = render(r##"<svg width="2" height="1">
+            <linearGradient id="g"><stop offset="0" stop-color="red"/><stop offset="1" stop-color="lime"/></linearGradient>
+            <rect width="2" height="1" fill="url(#g)"/>
+        </svg>"##, 2, 1);
+        let linear = render(r##"<svg width="2" height="1">
+            <linearGradient id="g" color-interpolation="linearRGB"><stop offset="0" stop-color="red"/><stop offset="1" stop-color="lime"/></linearGradient>
+            <rect width="2" height="1" fill="url(#g)"/>
+        </svg>"##, 2, 1);
+        // At t = 0.25 and 0.75 the sRGB ramp is at 3/4 and 1/4 of each channel
+        assert!(near(srgb[0], 0xFFBF4000, 2));
+        assert!(near(linear[0], 0xFFE18900, 2));
+        assert!(near(linear[1], 0xFF89E100, 2));
+    }
+
+    #[test]
+    fn test_shallow_ramps_are_dithered() {
+        let pixels = render(r##"<svg width="8" height="8">
+            <linearGradient id="g" gradientUnits="userSpaceOnUse" x1="-1000" x2="1000"><stop offset="0" stop-color="#808080"/><stop offset="1" stop-color="#818181"/></linearGradient>
+            <rect width="8" height="8" fill="url(#g)"/>
+        </svg>"##, 8, 8);
+        // Halfway between two 8-bit levels, the Bayer pattern lights about half the pixels
+        let upper = pixels.iter().filter(|&&pixel| pixel == 0xFF818181).count();
+        let lower = pixels.iter().filter(|&&pixel| pixel == 0xFF808080).count();
+        assert_eq!(upper + lower, 64);
+        assert!((24..=40).contains(&upper));
+    }
+
+    #[test]
+    fn test_stop_properties_from_style() {
+        let pixels = render_gradient(r#"<linearGradient id="g"><stop offset="0.5" style="stop-color: red"/><stop offset="0.5" stop-color="red" style="stop-color: blue; stop-opacity: 0.5"/></linearGradient>"#);
+        assert_eq!(pixels[1], 0xFFFF0000);
+        assert_eq!(pixels[8], 0xFF7F7FFF);
+    }
+}
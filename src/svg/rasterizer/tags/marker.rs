@@ -0,0 +1,352 @@
+use crate::svg::parser::tags::Tag;
+use crate::svg::utils::style::get_property;
+use crate::svg::rasterizer::raster::{Bounds, Point};
+use crate::svg::rasterizer::tags::path::{arc_to_beziers, PathCommand};
+use crate::svg::utils::coords::{Axis, LengthContext};
+use crate::svg::utils::transform::{parse_view_box, view_box_transform, Transform};
+use crate::utils::compat::Vec;
+#[cfg(not(feature = "std"))]
+use crate::utils::compat::FloatExt;
+use crate::svg::tree::{Converter, Marker, Node};
+use crate::svg::utils::Defs;
+
+/// A path vertex that can carry a marker, with the directions of the
+/// segments arriving at and leaving it.
+struct Vertex {
+    point: Point,
+    incoming: Option<(f32, f32)>,
+    outgoing: Option<(f32, f32)>,
+}
+
+impl Vertex {
+    /// Direction of the path at this vertex, bisecting the two segments at corners.
+    fn angle(&self) -> f32 {
+        let pi = core::f32::consts::PI;
+        match (self.incoming, self.outgoing) {
+            (Some(i), Some(o)) => {
+                let a = i.1.atan2(i.0);
+                let mut delta = o.1.atan2(o.0) - a;
+                if delta > pi {
+                    delta -= 2.0 * pi;
+                } else if delta <= -pi {
+                    delta += 2.0 * pi;
+                }
+                a + delta / 2.0
+            }
+            (Some(d), None) | (None, Some(d)) => d.1.atan2(d.0),
+            (None, None) => 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Position {
+    Start,
+    Mid,
+    End,
+}
+
+/// Places `marker-start`, `marker-mid` and `marker-end` (or the `marker`
+/// shorthand) of a path-like element on its vertices. `commands` are in user
+/// space, and `stroke` is the element whose `stroke-width` scales the markers.
+pub(crate) fn place_markers(tag: &Tag, stroke: &Tag, commands: &[PathCommand], converter: &mut Converter) -> Vec<Marker> {
+    let defs = converter.defs;
+    let start = marker_ref(tag, "marker-start", defs);
+    let mid = marker_ref(tag, "marker-mid", defs);
+    let end = marker_ref(tag, "marker-end", defs);
+    if start.is_none() && mid.is_none() && end.is_none() {
+        return Vec::new();
+    }
+
+    let vertices = path_vertices(commands);
+    let stroke_width = get_property(stroke, "stroke-width")
+        .and_then(|s| converter.lengths.length(s, Axis::Diagonal))
+        .unwrap_or(1.0);
+
+    // Each marker's content is converted once, then copied to every vertex
+    let mut contents: Vec<(&str, Option<Node>)> = Vec::new();
+    let mut markers = Vec::new();
+    let last = vertices.len().saturating_sub(1);
+    for (i, vertex) in vertices.iter().enumerate() {
+        // A lone vertex is both the start and the end
+        let positions = [
+            (i == 0, start, Position::Start),
+            (i != 0 && i != last, mid, Position::Mid),
+            (i == last, end, Position::End),
+        ];
+        for (_, marker, position) in positions.into_iter().filter(|(at, _, _)| *at) {
+            let Some((id, marker)) = marker else { continue };
+            let Some(placed) = place_marker(marker, vertex, position, stroke_width, &converter.lengths) else { continue };
+
+            let content = match contents.iter().find(|(seen, _)| *seen == id) {
+                Some((_, content)) => content.clone(),
+                None => {
+                    // A marker never draws itself, which would recurse without end
+                    let content = converter.expand(id, |converter| {
+                        // Percentages in the content refer to the marker's own viewport
+                        let outer = converter.lengths;
+                        converter.lengths = outer.with_viewport(placed.content_size.0, placed.content_size.1);
+                        let children = converter.convert_all(&marker.children);
+                        converter.lengths = outer;
+                        children
+                    });
+                    let content = content.map(|children| Node {
+                        clip: placed.clip,
+                        ..Node::group(placed.content_transform.clone(), children)
+                    });
+                    contents.push((id, content.clone()));
+                    content
+                }
+            };
+            if let Some(content) = content {
+                markers.push(Marker { transform: placed.viewport, content });
+            }
+        }
+    }
+    markers
+}
+
+/// Resolves a marker property, falling back to the `marker` shorthand.
+fn marker_ref<'a>(tag: &'a Tag, key: &str, defs: &'a Defs<'a>) -> Option<(&'a str, &'a Tag)> {
+    let value = get_property(tag, key).or_else(|| get_property(tag, "marker"))?;
+    let id = value.strip_prefix("url(#")?.strip_suffix(')')?.trim();
+    defs.get(id).copied()
+        .filter(|marker| marker.name == "marker")
+        .map(|marker| (id, marker))
+}
+
+/// Collects the marker vertices of a path: every segment end point, with a
+/// closing vertex at the start of each closed subpath.
+fn path_vertices(commands: &[PathCommand]) -> Vec<Vertex> {
+    let mut vertices: Vec<Vertex> = Vec::new();
+    let mut current = Point { x: 0.0, y: 0.0 };
+    let mut subpath_start = 0;
+
+    for cmd in commands {
+        let (end, leave, arrive) = match cmd {
+            PathCommand::MoveTo(p) => {
+                subpath_start = vertices.len();
+                vertices.push(Vertex { point: *p, incoming: None, outgoing: None });
+                current = *p;
+                continue;
+            }
+            PathCommand::LineTo(p) => (*p, leaving(current, &[*p]), arriving(*p, &[current])),
+            PathCommand::QuadraticBezier(c, p) => {
+                (*p, leaving(current, &[*c, *p]), arriving(*p, &[*c, current]))
+            }
+            PathCommand::CubicBezier(c1, c2, p) => {
+                (*p, leaving(current, &[*c1, *c2, *p]), arriving(*p, &[*c2, *c1, current]))
+            }
+            PathCommand::Arc { rx, ry, x_axis_rotation, large_arc_flag, sweep_flag, end } => {
+                let beziers = if *rx == 0.0 || *ry == 0.0 {
+                    Vec::new()
+                } else {
+                    arc_to_beziers(current, *rx, *ry, *x_axis_rotation, *large_arc_flag, *sweep_flag, *end)
+                };
+                let leave = match beziers.first() {
+                    Some(PathCommand::CubicBezier(c1, c2, p)) => leaving(current, &[*c1, *c2, *p]),
+                    _ => None,
+                };
+                let arrive = match beziers.last() {
+                    Some(PathCommand::CubicBezier(c1, c2, _)) => arriving(*end, &[*c2, *c1]),
+                    _ => None,
+                };
+                (
+                    *end,
+                    leave.or_else(|| leaving(current, &[*end])),
+                    arrive.or_else(|| arriving(*end, &[current])),
+                )
+            }
+            PathCommand::ClosePath => {
+                let Some(first) = vertices.get(subpath_start) else { continue };
+                let start = first.point;
+                let first_out = first.outgoing;
+
+                let incoming = arriving(start, &[current])
+                    .or_else(|| vertices.last().and_then(|v| v.incoming));
+                if let Some(last) = vertices.last_mut() && last.outgoing.is_none() {
+                    last.outgoing = leaving(current, &[start]);
+                }
+                // The closing vertex and the subpath start join like any corner
+                vertices[subpath_start].incoming = incoming;
+                vertices.push(Vertex { point: start, incoming, outgoing: first_out });
+                current = start;
+                continue;
+            }
+        };
+
+        if vertices.is_empty() {
+            vertices.push(Vertex { point: current, incoming: None, outgoing: None });
+        }
+        if let Some(last) = vertices.last_mut() && last.outgoing.is_none() {
+            last.outgoing = leave;
+        }
+        vertices.push(Vertex { point: end, incoming: arrive, outgoing: None });
+        current = end;
+    }
+
+    vertices
+}
+
+/// Direction from `from` toward the first of `toward` that does not coincide with it.
+fn leaving(from: Point, toward: &[Point]) -> Option<(f32, f32)> {
+    toward.iter()
+        .map(|p| (p.x - from.x, p.y - from.y))
+        .find(|d| d.0 != 0.0 || d.1 != 0.0)
+}
+
+/// Direction into `at` from the first of `from` that does not coincide with it.
+fn arriving(at: Point, from: &[Point]) -> Option<(f32, f32)> {
+    from.iter()
+        .map(|p| (at.x - p.x, at.y - p.y))
+        .find(|d| d.0 != 0.0 || d.1 != 0.0)
+}
+
+/// Where a marker instance goes: its viewport in the path's user space
+/// and the mapping of its content into that viewport.
+struct Placement {
+    viewport: Transform,
+    content_transform: Transform,
+    /// Size of the viewport in content coordinates.
+    content_size: (f32, f32),
+    /// The viewport in its own coordinates, when its overflow is hidden.
+    clip: Option<Bounds>,
+}
+
+fn place_marker(
+    marker: &Tag,
+    vertex: &Vertex,
+    position: Position,
+    stroke_width: f32,
+    lengths: &LengthContext,
+) -> Option<Placement> {
+    if marker.children.is_empty() {
+        return None;
+    }
+
+    let attr = |key: &str| marker.params.get(key).map(|s| s.trim());
+    let width = lengths.attribute(marker, "markerWidth", Axis::Horizontal).unwrap_or(3.0);
+    let height = lengths.attribute(marker, "markerHeight", Axis::Vertical).unwrap_or(3.0);
+    if width <= 0.0 || height <= 0.0 {
+        return None;
+    }
+
+    let view_box = attr("viewBox").and_then(parse_view_box);
+    let content_transform = match view_box {
+        Some(view_box) => view_box_transform(view_box, width, height, attr("preserveAspectRatio")),
+        None => Transform::identity(),
+    };
+
+    // refX/refY are in the marker's content coordinates
+    let (box_w, box_h) = view_box.map(|vb| (vb[2], vb[3])).unwrap_or((width, height));
+    let content = lengths.with_viewport(box_w, box_h);
+    let reference = |key: &str, size: f32, axis: Axis| match attr(key) {
+        Some("left") | Some("top") => 0.0,
+        Some("center") => size / 2.0,
+        Some("right") | Some("bottom") => size,
+        Some(s) => content.length(s, axis).unwrap_or(0.0),
+        None => 0.0,
+    };
+    let (ref_x, ref_y) = content_transform.apply(
+        reference("refX", box_w, Axis::Horizontal),
+        reference("refY", box_h, Axis::Vertical),
+    );
+
+    let angle = match attr("orient") {
+        Some("auto") => vertex.angle(),
+        Some("auto-start-reverse") if position == Position::Start => vertex.angle() + core::f32::consts::PI,
+        Some("auto-start-reverse") => vertex.angle(),
+        Some(orient) => parse_angle(orient).unwrap_or(0.0),
+        None => 0.0,
+    };
+    let scale = match attr("markerUnits") {
+        Some("userSpaceOnUse") => 1.0,
+        _ => stroke_width,
+    };
+
+    // Maps the marker viewport (0, 0, markerWidth, markerHeight) to user space
+    let viewport = Transform::translate(vertex.point.x, vertex.point.y)
+        .then(&Transform::rotate(angle))
+        .then(&Transform::scale(scale, scale))
+        .then(&Transform::translate(-ref_x, -ref_y));
+
+    let visible = matches!(attr("overflow"), Some("visible") | Some("auto"));
+    Some(Placement {
+        viewport,
+        content_transform,
+        content_size: (box_w, box_h),
+        clip: (!visible).then_some(Bounds { x: 0.0, y: 0.0, width, height }),
+    })
+}
+
+/// Parses an `orient` angle into radians; bare numbers are degrees.
+fn parse_angle(s: &str) -> Option<f32> {
+    let (value, factor) = if let Some(v) = s.strip_suffix("deg") {
+        (v, core::f32::consts::PI / 180.0)
+    } else if let Some(v) = s.strip_suffix("grad") {
+        (v, core::f32::consts::PI / 200.0)
+    } else if let Some(v) = s.strip_suffix("rad") {
+        (v, 1.0)
+    } else if let Some(v) = s.strip_suffix("turn") {
+        (v, 2.0 * core::f32::consts::PI)
+    } else {
+        (s, core::f32::consts::PI / 180.0)
+    };
+    value.trim().parse::<f32>().ok().map(|v| v * factor)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::compat::{format, Vec};
+
+    /// Renders `path` with a red start marker `s` and a blue end marker `e` in scope.
+    fn render_path(path: &str) -> Vec<u32> {
+        crate::svg::testing::render(&format!(
+            r#"<svg width="20" height="10">
+                <marker id="s" markerUnits="userSpaceOnUse" overflow="visible"><rect x="-4" y="-1" width="2" height="2" fill="red"/></marker>
+                <marker id="e" markerUnits="userSpaceOnUse" overflow="visible"><rect x="2" y="-1" width="2" height="2" fill="blue"/></marker>
+                {}
+            </svg>"#,
+            path
+        ), 20, 10)
+    }
+
+    fn render(d: &str) -> Vec<u32> {
+        render_path(&format!(r##"<path d="{}" marker-start="url(#s)" marker-end="url(#e)"/>"##, d))
+    }
+
+    #[test]
+    fn test_single_vertex_gets_start_and_end_markers() {
+        let pixels = render("M10 5");
+        assert_eq!(pixels[5 * 20 + 7], 0xFFFF0000);
+        assert_eq!(pixels[5 * 20 + 12], 0xFF0000FF);
+    }
+
+    #[test]
+    fn test_degenerate_paths_with_markers() {
+        assert!(render("").iter().all(|&p| p == 0xFFFFFFFF));
+        render("M1e30 5 L-1e30 5");
+        render("M10 5 Z");
+    }
+
+    #[test]
+    fn test_marker_properties_from_style() {
+        let styled = render_path(r##"<path d="M10 5" style="marker-start: url(#s); marker-end: url(#e)"/>"##);
+        assert_eq!(styled, render("M10 5"));
+
+        // The shorthand sets every position; the longhand in style overrides it
+        let shorthand = render_path(r##"<path d="M10 5" marker="url(#s)" style="marker-end: url(#e)"/>"##);
+        assert_eq!(shorthand, render("M10 5"));
+    }
+
+    #[test]
+    fn test_stroke_width_from_style_scales_markers() {
+        // markerUnits defaults to strokeWidth
+        let svg = r##"<svg width="20" height="10">
+            <marker id="m" overflow="visible"><rect x="0" y="-1" width="2" height="2" fill="red"/></marker>
+            <path d="M4 5" marker-start="url(#m)" style="stroke-width: 3"/>
+        </svg>"##;
+        let pixels = crate::svg::testing::render(svg, 20, 10);
+        assert_eq!(crate::svg::testing::ink_bounds(&pixels, 20), Some((4, 2, 9, 7)));
+    }
+}
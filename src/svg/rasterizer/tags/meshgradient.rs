@@ -0,0 +1,490 @@
+use crate::svg::parser::tags::Tag;
+use crate::svg::utils::style::get_property;
+use crate::svg::rasterizer::raster::{Bounds, Point};
+use crate::svg::rasterizer::tags::lineargradient::{gradient_to_device, load_common, template_chain, GradientUnits};
+use crate::svg::rasterizer::tags::path::{parse_path_data, PathCommand};
+use crate::svg::utils::color::parse_color_value;
+use crate::svg::utils::coords::{Axis, LengthContext};
+use crate::svg::utils::transform::Transform;
+use crate::utils::compat::{format, Vec, vec};
+#[cfg(not(feature = "std"))]
+use crate::utils::compat::FloatExt;
+use crate::svg::utils::Defs;
+
+/// Largest edge of the rendered mesh, in device pixels.
+const MAX_MESH_SIZE: f32 = 4096.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MeshType {
+    Bilinear,
+    Bicubic,
+}
+
+/// One Coons patch: four cubic edges given as `[start, c1, c2, end]`, with
+/// premultiplied `[a, r, g, b]` corner colors. Edges run left to right and
+/// top to bottom; corners are ordered top-left, top-right, bottom-right,
+/// bottom-left.
+#[derive(Debug, Clone)]
+struct Patch {
+    top: [Point; 4],
+    bottom: [Point; 4],
+    left: [Point; 4],
+    right: [Point; 4],
+    colors: [[f32; 4]; 4],
+    /// Color derivatives along u and v at each corner, for bicubic meshes.
+    du: [[f32; 4]; 4],
+    dv: [[f32; 4]; 4],
+}
+
+/// A rendered mesh: straight-alpha pixels covering its device bounds.
+#[derive(Debug, Clone)]
+struct MeshRaster {
+    x: isize,
+    y: isize,
+    width: usize,
+    height: usize,
+    data: Vec<u32>,
+}
+
+/// A `<meshgradient>`: a grid of Coons patches with colors interpolated
+/// from their corners.
+#[derive(Debug, Clone)]
+pub struct MeshGradient {
+    patches: Vec<Patch>,
+    pub mesh_type: MeshType,
+    pub units: GradientUnits,
+    /// `gradientTransform`, mapping gradient space to the units' coordinate system.
+    pub transform: Transform,
+    raster: Option<MeshRaster>,
+}
+
+impl MeshGradient {
+    /// Renders the mesh in device space for an element with user-space
+    /// bounding box `bbox`, drawn through `transform`.
+    pub fn prepare(&mut self, transform: &Transform, bbox: Bounds) {
+        self.raster = None;
+        let Some(to_device) = gradient_to_device(&self.units, &self.transform, transform, bbox) else { return };
+
+        let patches: Vec<Patch> = self.patches.iter().map(|patch| {
+            let map = |edge: &[Point; 4]| edge.map(|p| to_device.apply_point(p));
+            Patch {
+                top: map(&patch.top),
+                bottom: map(&patch.bottom),
+                left: map(&patch.left),
+                right: map(&patch.right),
+                ..patch.clone()
+            }
+        }).collect();
+
+        // The control points bound each patch
+        let points = patches.iter().flat_map(|p| p.top.iter().chain(&p.bottom).chain(&p.left).chain(&p.right));
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY);
+        for p in points {
+            min_x = min_x.min(p.x);
+            min_y = min_y.min(p.y);
+            max_x = max_x.max(p.x);
+            max_y = max_y.max(p.y);
+        }
+        if !(max_x > min_x && max_y > min_y) {
+            return;
+        }
+
+        let (x, y) = (min_x.floor(), min_y.floor());
+        let width = (max_x.ceil() - x).clamp(1.0, MAX_MESH_SIZE) as usize;
+        let height = (max_y.ceil() - y).clamp(1.0, MAX_MESH_SIZE) as usize;
+        let mut raster = MeshRaster { x: x as isize, y: y as isize, width, height, data: vec![0; width * height] };
+
+        for patch in &patches {
+            raster.draw_patch(patch, self.mesh_type);
+        }
+        self.raster = Some(raster);
+    }
+
+    pub fn sample(&self, x: f32, y: f32) -> u32 {
+        let Some(raster) = &self.raster else { return 0x00000000 };
+        let px = x.floor() as isize - raster.x;
+        let py = y.floor() as isize - raster.y;
+        if px < 0 || py < 0 || px as usize >= raster.width || py as usize >= raster.height {
+            return 0x00000000;
+        }
+        raster.data[py as usize * raster.width + px as usize]
+    }
+}
+
+impl Patch {
+    /// Point on the Coons surface at (`u`, `v`).
+    fn point(&self, u: f32, v: f32) -> Point {
+        let (top, bottom) = (cubic(&self.top, u), cubic(&self.bottom, u));
+        let (left, right) = (cubic(&self.left, v), cubic(&self.right, v));
+        let (p00, p10, p11, p01) = (self.top[0], self.top[3], self.bottom[3], self.bottom[0]);
+
+        let blend = |f: fn(&Point) -> f32| {
+            let ruled_v = (1.0 - v) * f(&top) + v * f(&bottom);
+            let ruled_u = (1.0 - u) * f(&left) + u * f(&right);
+            let corners = (1.0 - u) * (1.0 - v) * f(&p00) + u * (1.0 - v) * f(&p10)
+                + u * v * f(&p11) + (1.0 - u) * v * f(&p01);
+            ruled_v + ruled_u - corners
+        };
+        Point { x: blend(|p| p.x), y: blend(|p| p.y) }
+    }
+
+    /// Premultiplied color at (`u`, `v`).
+    fn color(&self, u: f32, v: f32, mesh_type: MeshType) -> [f32; 4] {
+        // Corner parameters, in the same order as the colors
+        let corners = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let mut color = [0.0f32; 4];
+
+        for (i, &(cu, cv)) in corners.iter().enumerate() {
+            let (value, slope_u, slope_v) = match mesh_type {
+                MeshType::Bilinear => {
+                    let w = if cu == 0.0 { 1.0 - u } else { u } * if cv == 0.0 { 1.0 - v } else { v };
+                    (w, 0.0, 0.0)
+                }
+                MeshType::Bicubic => {
+                    let (fu, gu) = hermite(u, cu);
+                    let (fv, gv) = hermite(v, cv);
+                    (fu * fv, gu * fv, fu * gv)
+                }
+            };
+            for (k, channel) in color.iter_mut().enumerate() {
+                *channel += self.colors[i][k] * value + self.du[i][k] * slope_u + self.dv[i][k] * slope_v;
+            }
+        }
+
+        let alpha = color[0].clamp(0.0, 1.0);
+        [alpha, color[1].clamp(0.0, alpha), color[2].clamp(0.0, alpha), color[3].clamp(0.0, alpha)]
+    }
+}
+
+/// Hermite basis weights of the value and slope at `corner` (0 or 1).
+fn hermite(t: f32, corner: f32) -> (f32, f32) {
+    let (t2, t3) = (t * t, t * t * t);
+    if corner == 0.0 {
+        (2.0 * t3 - 3.0 * t2 + 1.0, t3 - 2.0 * t2 + t)
+    } else {
+        (-2.0 * t3 + 3.0 * t2, t3 - t2)
+    }
+}
+
+fn cubic(p: &[Point; 4], t: f32) -> Point {
+    let mt = 1.0 - t;
+    let (a, b, c, d) = (mt * mt * mt, 3.0 * mt * mt * t, 3.0 * mt * t * t, t * t * t);
+    Point {
+        x: a * p[0].x + b * p[1].x + c * p[2].x + d * p[3].x,
+        y: a * p[0].y + b * p[1].y + c * p[2].y + d * p[3].y,
+    }
+}
+
+impl MeshRaster {
+    /// Subdivides a device-space patch into a grid and fills its triangles.
+    fn draw_patch(&mut self, patch: &Patch, mesh_type: MeshType) {
+        // About one subdivision per 4 device pixels of the longer side
+        let points = || patch.top.iter().chain(&patch.bottom).chain(&patch.left).chain(&patch.right);
+        let span = |f: fn(&Point) -> f32| {
+            points().map(f).fold(f32::NEG_INFINITY, f32::max) - points().map(f).fold(f32::INFINITY, f32::min)
+        };
+        let steps = (span(|p| p.x).max(span(|p| p.y)) / 4.0).ceil().clamp(2.0, 64.0) as usize;
+
+        let mut grid = Vec::with_capacity((steps + 1) * (steps + 1));
+        for j in 0..=steps {
+            for i in 0..=steps {
+                let (u, v) = (i as f32 / steps as f32, j as f32 / steps as f32);
+                grid.push((patch.point(u, v), patch.color(u, v, mesh_type)));
+            }
+        }
+
+        let at = |i: usize, j: usize| &grid[j * (steps + 1) + i];
+        for j in 0..steps {
+            for i in 0..steps {
+                let (a, b, c, d) = (at(i, j), at(i + 1, j), at(i + 1, j + 1), at(i, j + 1));
+                self.fill_triangle(a, b, c);
+                self.fill_triangle(a, c, d);
+            }
+        }
+    }
+
+    /// Fills the pixels whose centers fall in a triangle, interpolating
+    /// premultiplied vertex colors.
+    fn fill_triangle(&mut self, a: &(Point, [f32; 4]), b: &(Point, [f32; 4]), c: &(Point, [f32; 4])) {
+        let (p0, p1, p2) = (a.0, b.0, c.0);
+        let area = (p1.x - p0.x) * (p2.y - p0.y) - (p2.x - p0.x) * (p1.y - p0.y);
+        if area.abs() < 1e-6 {
+            return;
+        }
+
+        let (ox, oy) = (self.x as f32, self.y as f32);
+        let min_x = (p0.x.min(p1.x).min(p2.x) - ox).floor().max(0.0) as usize;
+        let min_y = (p0.y.min(p1.y).min(p2.y) - oy).floor().max(0.0) as usize;
+        let max_x = ((p0.x.max(p1.x).max(p2.x) - ox).ceil().max(0.0) as usize).min(self.width);
+        let max_y = ((p0.y.max(p1.y).max(p2.y) - oy).ceil().max(0.0) as usize).min(self.height);
+
+        // A little slack keeps shared triangle edges from leaving seams
+        let epsilon = -1e-4;
+        for py in min_y..max_y {
+            for px in min_x..max_x {
+                let (x, y) = (ox + px as f32 + 0.5, oy + py as f32 + 0.5);
+                let w0 = ((p1.x - x) * (p2.y - y) - (p2.x - x) * (p1.y - y)) / area;
+                let w1 = ((p2.x - x) * (p0.y - y) - (p0.x - x) * (p2.y - y)) / area;
+                let w2 = 1.0 - w0 - w1;
+                if w0 < epsilon || w1 < epsilon || w2 < epsilon {
+                    continue;
+                }
+
+                let color = [0, 1, 2, 3].map(|k| a.1[k] * w0 + b.1[k] * w1 + c.1[k] * w2);
+                self.data[py * self.width + px] = to_straight(color);
+            }
+        }
+    }
+}
+
+fn to_straight(color: [f32; 4]) -> u32 {
+    let a = (color[0] * 255.0).round().clamp(0.0, 255.0) as u32;
+    if a == 0 {
+        return 0x00000000;
+    }
+    let channel = |c: f32| ((c / color[0]) * 255.0).round().clamp(0.0, 255.0) as u32;
+    (a << 24) | (channel(color[1]) << 16) | (channel(color[2]) << 8) | channel(color[3])
+}
+
+fn premultiply(color: u32) -> [f32; 4] {
+    let a = (color >> 24) as f32 / 255.0;
+    let channel = |shift: u32| ((color >> shift) & 0xFF) as f32 / 255.0 * a;
+    [a, channel(16), channel(8), channel(0)]
+}
+
+/// Parses one mesh edge, a single `l`/`L`/`c`/`C` segment starting at
+/// `start`, into cubic control points.
+fn parse_edge(start: Point, path: &str) -> Option<[Point; 4]> {
+    let commands = parse_path_data(&format!("M {} {} {}", start.x, start.y, path));
+    match commands.get(1)? {
+        PathCommand::LineTo(end) => {
+            let lerp = |t: f32| Point { x: start.x + (end.x - start.x) * t, y: start.y + (end.y - start.y) * t };
+            Some([start, lerp(1.0 / 3.0), lerp(2.0 / 3.0), *end])
+        }
+        PathCommand::CubicBezier(c1, c2, end) => Some([start, *c1, *c2, *end]),
+        _ => None,
+    }
+}
+
+/// Node positions, colors and edges of a mesh grid, filled in as stops are read.
+struct MeshGrid {
+    cols: usize,
+    nodes: Vec<Option<Point>>,
+    colors: Vec<Option<u32>>,
+    /// Horizontal edges, `(rows + 1) * cols`, left to right.
+    horizontal: Vec<Option<[Point; 4]>>,
+    /// Vertical edges, `rows * (cols + 1)`, top to bottom.
+    vertical: Vec<Option<[Point; 4]>>,
+}
+
+impl MeshGrid {
+    fn node(&self, r: usize, c: usize) -> usize {
+        r * (self.cols + 1) + c
+    }
+
+    /// Reads the stop for edge `edge` (0 top, 1 right, 2 bottom, 3 left) of patch (`r`, `c`).
+    fn read_stop(&mut self, r: usize, c: usize, edge: usize, stop: &Tag) {
+        let (from, to) = match edge {
+            0 => (self.node(r, c), self.node(r, c + 1)),
+            1 => (self.node(r, c + 1), self.node(r + 1, c + 1)),
+            2 => (self.node(r + 1, c + 1), self.node(r + 1, c)),
+            _ => (self.node(r + 1, c), self.node(r, c)),
+        };
+        let Some(start) = self.nodes[from] else { return };
+
+        if self.colors[from].is_none() {
+            let color = get_property(stop, "stop-color").map(parse_color_value).unwrap_or(0xFF000000);
+            let opacity = get_property(stop, "stop-opacity")
+                .and_then(|s| s.trim().parse::<f32>().ok())
+                .unwrap_or(1.0)
+                .clamp(0.0, 1.0);
+            let alpha = ((color >> 24) as f32 * opacity).round() as u32;
+            self.colors[from] = Some((color & 0x00FFFFFF) | (alpha << 24));
+        }
+
+        let Some(mut curve) = stop.params.get("path").and_then(|p| parse_edge(start, p)) else { return };
+        // An edge ending at a known node (always the closing edge) snaps to it
+        match self.nodes[to] {
+            Some(end) => curve[3] = end,
+            None => self.nodes[to] = Some(curve[3]),
+        }
+
+        let reversed = [curve[3], curve[2], curve[1], curve[0]];
+        match edge {
+            0 => self.horizontal[r * self.cols + c] = Some(curve),
+            1 => self.vertical[r * (self.cols + 1) + c + 1] = Some(curve),
+            2 => self.horizontal[(r + 1) * self.cols + c] = Some(reversed),
+            _ => self.vertical[r * (self.cols + 1) + c] = Some(reversed),
+        }
+    }
+}
+
+/// Loads a `<meshgradient>` from its `meshrow`/`meshpatch` children,
+/// inheriting unset attributes and rows from the gradients it references
+/// through `href`.
+pub fn load_mesh_gradient(tag: &Tag, defs: &Defs, lengths: &LengthContext) -> MeshGradient {
+    let chain = template_chain(tag, defs);
+    let attr = |key: &str| chain.iter()
+        .filter(|t| is_mesh(t))
+        .find_map(|t| t.params.get(key))
+        .map(|s| s.trim());
+
+    let (units, _, transform, _) = load_common(&chain);
+    let lengths = units.lengths(lengths);
+    let mesh_type = match attr("type") {
+        Some("bicubic") => MeshType::Bicubic,
+        _ => MeshType::Bilinear,
+    };
+    let origin = Point {
+        x: attr("x").and_then(|s| lengths.length(s, Axis::Horizontal)).unwrap_or(0.0),
+        y: attr("y").and_then(|s| lengths.length(s, Axis::Vertical)).unwrap_or(0.0),
+    };
+
+    // Rows come from the nearest mesh that has any
+    let rows: Vec<&Tag> = chain.iter()
+        .filter(|t| is_mesh(t))
+        .map(|t| t.children.iter().filter(|c| c.name == "meshrow").collect::<Vec<_>>())
+        .find(|rows| !rows.is_empty())
+        .unwrap_or_default();
+    let cols = rows.iter()
+        .map(|row| row.children.iter().filter(|c| c.name == "meshpatch").count())
+        .max()
+        .unwrap_or(0);
+
+    let mut grid = MeshGrid {
+        cols,
+        nodes: vec![None; (rows.len() + 1) * (cols + 1)],
+        colors: vec![None; (rows.len() + 1) * (cols + 1)],
+        horizontal: vec![None; (rows.len() + 1) * cols],
+        vertical: vec![None; rows.len() * (cols + 1)],
+    };
+    if cols > 0 {
+        grid.nodes[0] = Some(origin);
+    }
+
+    // Patches share edges with the ones above and to the left, so later
+    // patches omit the stops for those edges
+    for (r, row) in rows.iter().enumerate() {
+        for (c, patch) in row.children.iter().filter(|c| c.name == "meshpatch").enumerate() {
+            let first_edge = if r > 0 { 1 } else { 0 };
+            let last_edge = if c > 0 { 2 } else { 3 };
+            let stops = patch.children.iter().filter(|s| s.name == "stop");
+            for (edge, stop) in (first_edge..=last_edge).zip(stops) {
+                grid.read_stop(r, c, edge, stop);
+            }
+        }
+    }
+
+    MeshGradient {
+        patches: build_patches(&grid, rows.len(), mesh_type),
+        mesh_type,
+        units,
+        transform,
+        raster: None,
+    }
+}
+
+fn is_mesh(tag: &Tag) -> bool {
+    matches!(tag.name.as_str(), "meshgradient" | "meshGradient")
+}
+
+/// Builds the complete patches of a grid, with straight edges where a
+/// stop gave no path.
+fn build_patches(grid: &MeshGrid, rows: usize, mesh_type: MeshType) -> Vec<Patch> {
+    let cols = grid.cols;
+    let color = |r: usize, c: usize| grid.colors[grid.node(r, c)].map(premultiply);
+
+    // Bicubic slopes from central differences over neighbouring nodes
+    let slope = |r: usize, c: usize, along_u: bool| -> [f32; 4] {
+        let (prev, next) = if along_u {
+            (c.checked_sub(1).and_then(|c| color(r, c)), if c < cols { color(r, c + 1) } else { None })
+        } else {
+            (r.checked_sub(1).and_then(|r| color(r, c)), if r < rows { color(r + 1, c) } else { None })
+        };
+        let Some(here) = color(r, c) else { return [0.0; 4] };
+        match (prev, next) {
+            (Some(p), Some(n)) => [0, 1, 2, 3].map(|k| (n[k] - p[k]) / 2.0),
+            (Some(p), None) => [0, 1, 2, 3].map(|k| here[k] - p[k]),
+            (None, Some(n)) => [0, 1, 2, 3].map(|k| n[k] - here[k]),
+            (None, None) => [0.0; 4],
+        }
+    };
+
+    let mut patches = Vec::new();
+    for r in 0..rows {
+        for c in 0..cols {
+            let corners = [(r, c), (r, c + 1), (r + 1, c + 1), (r + 1, c)];
+            let Some(points) = corners.iter().map(|&(r, c)| grid.nodes[grid.node(r, c)]).collect::<Option<Vec<_>>>() else { continue };
+            let Some(colors) = corners.iter().map(|&(r, c)| color(r, c)).collect::<Option<Vec<_>>>() else { continue };
+
+            let line = |a: Point, b: Point| {
+                let lerp = |t: f32| Point { x: a.x + (b.x - a.x) * t, y: a.y + (b.y - a.y) * t };
+                [a, lerp(1.0 / 3.0), lerp(2.0 / 3.0), b]
+            };
+            let (bicubic, linear) = (mesh_type == MeshType::Bicubic, [0.0; 4]);
+            let du = corners.map(|(r, c)| if bicubic { slope(r, c, true) } else { linear });
+            let dv = corners.map(|(r, c)| if bicubic { slope(r, c, false) } else { linear });
+
+            patches.push(Patch {
+                top: grid.horizontal[r * cols + c].unwrap_or_else(|| line(points[0], points[1])),
+                bottom: grid.horizontal[(r + 1) * cols + c].unwrap_or_else(|| line(points[3], points[2])),
+                left: grid.vertical[r * (cols + 1) + c].unwrap_or_else(|| line(points[0], points[3])),
+                right: grid.vertical[r * (cols + 1) + c + 1].unwrap_or_else(|| line(points[1], points[2])),
+                colors: [colors[0], colors[1], colors[2], colors[3]],
+                du,
+                dv,
+            });
+        }
+    }
+    patches
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::svg::testing::render;
+    use crate::utils::compat::{format, Vec};
+
+    /// Fills a 10x10 canvas with one square patch of edge `size` whose corner
+    /// stops, clockwise from the top left, carry `stops`.
+    fn render_patch(size: &str, stops: [&str; 4]) -> Vec<u32> {
+        render(&format!(
+            r##"<svg width="10" height="10"><meshgradient id="m" gradientUnits="userSpaceOnUse"><meshrow><meshpatch>
+                <stop path="l {size} 0" {}/><stop path="l 0 {size}" {}/><stop path="l -{size} 0" {}/><stop path="l 0 -{size}" {}/>
+            </meshpatch></meshrow></meshgradient><rect width="10" height="10" fill="url(#m)"/></svg>"##,
+            stops[0], stops[1], stops[2], stops[3]
+        ), 10, 10)
+    }
+
+    #[test]
+    fn test_corner_colors_blend_across_the_patch() {
+        let pixels = render_patch("10", [r#"stop-color="red""#, r#"stop-color="red""#, r#"stop-color="blue""#, r#"stop-color="blue""#]);
+        let (top, middle, bottom) = (pixels[4], pixels[5 * 10 + 4], pixels[9 * 10 + 4]);
+        assert!(top >> 16 & 0xFF > 0xE0 && top & 0xFF < 0x20);
+        assert!(bottom & 0xFF > 0xE0 && bottom >> 16 & 0xFF < 0x20);
+        assert!((0x60..0xA0).contains(&(middle & 0xFF)));
+        // Rows of a patch whose colors only change vertically are uniform
+        assert_eq!(pixels[5 * 10 + 1] >> 8, middle >> 8);
+    }
+
+    #[test]
+    fn test_corner_colors_from_style() {
+        let styled = render_patch("10", [
+            r#"style="stop-color: red""#,
+            r#"stop-color="blue" style="stop-color: red""#,
+            r#"style="stop-color: blue""#,
+            r#"stop-color="blue""#,
+        ]);
+        let plain = render_patch("10", [r#"stop-color="red""#, r#"stop-color="red""#, r#"stop-color="blue""#, r#"stop-color="blue""#]);
+        assert_eq!(styled, plain);
+    }
+
+    #[test]
+    fn test_degenerate_and_huge_patches() {
+        let red = [r#"stop-color="red""#; 4];
+        assert!(render_patch("0", red).iter().all(|&pixel| pixel == 0xFFFFFFFF));
+        assert!(render_patch("1000000", red).iter().all(|&pixel| pixel == 0xFFFF0000));
+
+        let empty = r##"<svg width="4" height="4"><meshgradient id="m"/><rect width="4" height="4" fill="url(#m)"/></svg>"##;
+        assert!(render(empty, 4, 4).iter().all(|&pixel| pixel == 0xFFFFFFFF));
+    }
+}
@@ -1,12 +1,11 @@
 pub(crate) mod clippath;
+#[cfg(feature = "conic-gradient")]
+pub(crate) mod conicgradient;
 pub(crate) mod lineargradient;
+pub(crate) mod marker;
+pub(crate) mod meshgradient;
 pub(crate) mod path;
-pub(crate) mod rect;
-pub(crate) mod polygon;
-pub(crate) mod ellipse;
-pub(crate) mod circle;
-pub(crate) mod line;
-pub(crate) mod polyline;
+pub(crate) mod pattern;
+pub(crate) mod image;
 pub(crate) mod radialgradient;
-pub(crate) mod r#use;
 pub(crate) mod text;
@@ -1,14 +1,14 @@
-use crate::svg::parser::tags::Tag;
 use crate::svg::rasterizer::canva::Canvas;
 use crate::svg::rasterizer::dda::Rasterizer;
-use crate::svg::rasterizer::raster::{Line, PathRasterizer, Point};
-use crate::svg::utils::color::{Paint, get_fill, get_stroke};
+use crate::svg::rasterizer::raster::{clip_lines, visible_buffer, Bounds, PathRasterizer, Point};
+use crate::svg::tree::Path;
+use crate::svg::utils::color::Paint;
 use crate::svg::utils::transform::Transform;
-use crate::utils::compat::HashMap;
 use crate::svg::rasterizer::stroke::draw_stroke;
-use crate::svg::utils::effects::get_stroke_width;
+#[cfg(not(feature = "std"))]
 use crate::utils::compat::FloatExt;
 use crate::utils::compat::{Vec, String};
+use crate::svg::utils::Defs;
 
 #[derive(Debug, Clone)]
 pub enum PathCommand {
@@ -27,114 +27,103 @@ pub enum PathCommand {
     ClosePath,
 }
 
-fn translate_lines(lines: &[Line], dx: f32, dy: f32) -> Vec<Line> {
-    lines.iter().map(|line| {
-        let mut new_line = line.clone();
-        new_line.x0 -= dx;
-        new_line.y0 -= dy;
-        new_line.x1 -= dx;
-        new_line.y1 -= dy;
-        new_line
-    }).collect()
-}
-
-
-pub fn draw_path(
-    tag: &mut Tag,
-    defs: &HashMap<String, Tag>,
+pub(crate) fn draw_path(
+    path: &Path,
+    defs: &Defs,
     map: &mut Canvas,
     transform: &Transform
 ) {
-    let d = match tag.params.get("d") {
-        Some(d) => d,
-        None => return,
-    };
+    let transformed_path = apply_transform_to_path(&path.commands, transform);
 
-    let d_path = parse_path_data(d);
-    let transformed_path = apply_transform_to_path(&d_path, transform);
+    let mut fill = path.fill.clone();
+    let mut stroke = path.stroke.clone();
 
-    let mut fill = get_fill(tag).resolve(defs);
-    let mut stroke = get_stroke(tag).resolve(defs);
-    let stroke_width = get_stroke_width(tag);
+    if let Some(bbox) = path.bbox {
+        fill.prepare(defs, transform, bbox);
+        if let Some(stroke) = &mut stroke {
+            stroke.paint.prepare(defs, transform, bbox);
+        }
+    }
 
-    let (sx, sy) = transform.get_scale();
-    let scale = sx.min(sy);
+    if !fill.is_none() {
+        fill_path(map, &transformed_path, &fill);
+    }
 
-    fill.scale(scale);
-    stroke.scale(scale);
+    if let Some(stroke) = stroke {
+        stroke_path(map, &transformed_path, &stroke.paint, stroke.width);
+    }
 
+    for marker in &path.markers {
+        map.draw_node(&marker.content, defs, &transform.then(&marker.transform));
+    }
+}
 
-    if !fill.is_none() {
-    
-        let mut fill_commands = Vec::new();
-        let mut subpath_open = false;
-        for cmd in &transformed_path {
-            match cmd {
-                PathCommand::MoveTo(_) => {
-                    if subpath_open {
-                        fill_commands.push(PathCommand::ClosePath);
-                    }
-                    fill_commands.push(cmd.clone());
-                    subpath_open = true;
-                }
-                PathCommand::ClosePath => {
-                    fill_commands.push(cmd.clone());
-                    subpath_open = false;
-                }
-                _ => {
-                    fill_commands.push(cmd.clone());
+/// Fills device-space path commands, closing open subpaths.
+pub(crate) fn fill_path(map: &mut Canvas, transformed_path: &[PathCommand], fill: &Paint) {
+    let mut fill_commands = Vec::new();
+    let mut subpath_open = false;
+    for cmd in transformed_path {
+        match cmd {
+            PathCommand::MoveTo(_) => {
+                if subpath_open {
+                    fill_commands.push(PathCommand::ClosePath);
                 }
+                fill_commands.push(cmd.clone());
+                subpath_open = true;
+            }
+            PathCommand::ClosePath => {
+                fill_commands.push(cmd.clone());
+                subpath_open = false;
+            }
+            _ => {
+                fill_commands.push(cmd.clone());
             }
         }
-        if subpath_open {
-            fill_commands.push(PathCommand::ClosePath);
-        }
-
-        let mut fill_rasterizer = PathRasterizer::new();
-
-        fill_rasterizer.build_lines_from_path(&fill_commands, 1.0, 1.0, 0.0);
+    }
+    if subpath_open {
+        fill_commands.push(PathCommand::ClosePath);
+    }
 
-        let bounds = fill_rasterizer.bounds;
+    let mut fill_rasterizer = PathRasterizer::new();
 
-        let r_w = bounds.width.ceil() as usize + 1;
-        let r_h = bounds.height.ceil() as usize;
+    fill_rasterizer.build_lines_from_path(&fill_commands, 1.0, 1.0, 0.0);
 
-        if r_w > 0 && r_h > 0 {
-            let draw_x = bounds.x.round() as usize;
-            let draw_y = bounds.y.round() as usize;
-            let local_v = translate_lines(&fill_rasterizer.v_lines, bounds.x, bounds.y);
-            let local_m = translate_lines(&fill_rasterizer.m_lines, bounds.x, bounds.y);
-            let renderer = Rasterizer::new(r_w, r_h);
-            let bitmap = renderer.draw(&local_v, &local_m).to_bitmap();
-            let color_map = generate_color_map(&bitmap, &fill, r_w, r_h, bounds.x, bounds.y, bounds.x, bounds.y, bounds.width, bounds.height);
-            map.add_buffer(&color_map, draw_x as isize, draw_y as isize, r_w, r_h);
-        } else {
-            #[cfg(feature = "std")]
-            std::println!("Skipping draw: invalid bounds dimensions {}x{}", r_w, r_h);
-        }
-    }
+    // Only the part of the buffer over the canvas is rasterized
+    let bounds = fill_rasterizer.bounds;
+    let (draw_x, draw_y) = (bounds.x.round(), bounds.y.round());
+    let Some((skip_x, skip_y, r_w, r_h)) = visible_buffer(
+        draw_x, draw_y, bounds.width.ceil() + 1.0, bounds.height.ceil(), map.width, map.height,
+    ) else {
+        return;
+    };
 
+    let (origin_x, origin_y) = (bounds.x + skip_x, bounds.y + skip_y);
+    let lines = fill_rasterizer.v_lines.iter().chain(&fill_rasterizer.m_lines);
+    let (local_v, local_m) = clip_lines(lines, origin_x, origin_y, (r_w - 1) as f32, r_h as f32);
+    let renderer = Rasterizer::new(r_w, r_h);
+    let bitmap = renderer.draw(&local_v, &local_m).to_bitmap();
+    let color_map = generate_color_map(&bitmap, fill, r_w, r_h, origin_x, origin_y);
+    map.add_buffer(&color_map, (draw_x + skip_x) as isize, (draw_y + skip_y) as isize, r_w, r_h);
+}
 
-    if !stroke.is_none() && stroke_width > 0.0 {
-        let mut subpath_start_index = 0;
-        for (i, cmd) in transformed_path.iter().enumerate() {
-            if i > 0 && matches!(cmd, PathCommand::MoveTo(_)) {
-                let subpath = &transformed_path[subpath_start_index..i];
-                if !subpath.is_empty() {
-                    let mut stroke_rasterizer = PathRasterizer::new();
-                    stroke_rasterizer.build_lines_from_path(subpath, 1.0, 1.0, stroke_width);
-                    draw_stroke(map, &stroke_rasterizer, stroke.clone(), stroke_width, 0.0, 0.0, 0.0, 0.0);
-                }
-                subpath_start_index = i;
-            }
-        }
-        let subpath = &transformed_path[subpath_start_index..];
+/// Strokes device-space path commands one subpath at a time.
+pub(crate) fn stroke_path(map: &mut Canvas, transformed_path: &[PathCommand], stroke: &Paint, stroke_width: f32) {
+    let mut draw_subpath = |subpath: &[PathCommand]| {
         if !subpath.is_empty() {
             let mut stroke_rasterizer = PathRasterizer::new();
             stroke_rasterizer.build_lines_from_path(subpath, 1.0, 1.0, stroke_width);
-            draw_stroke(map, &stroke_rasterizer, stroke, stroke_width, 0.0, 0.0, 0.0, 0.0);
+            draw_stroke(map, &stroke_rasterizer, stroke.clone(), stroke_width);
+        }
+    };
+
+    let mut subpath_start_index = 0;
+    for (i, cmd) in transformed_path.iter().enumerate() {
+        if i > 0 && matches!(cmd, PathCommand::MoveTo(_)) {
+            draw_subpath(&transformed_path[subpath_start_index..i]);
+            subpath_start_index = i;
         }
     }
+    draw_subpath(&transformed_path[subpath_start_index..]);
 }
 
 pub fn parse_path_data(d: &str) -> Vec<PathCommand> {
@@ -353,7 +342,7 @@ fn process_command(
     args.clear();
 }
 
-fn arc_to_beziers(start: Point, rx: f32, ry: f32, x_axis_rotation: f32, large_arc: bool, sweep: bool, end: Point) -> Vec<PathCommand> {
+pub(crate) fn arc_to_beziers(start: Point, rx: f32, ry: f32, x_axis_rotation: f32, large_arc: bool, sweep: bool, end: Point) -> Vec<PathCommand> {
     let pi = core::f32::consts::PI;
     let cos_phi = (x_axis_rotation * pi / 180.0).cos();
     let sin_phi = (x_axis_rotation * pi / 180.0).sin();
@@ -495,6 +484,51 @@ pub(crate) fn apply_transform_to_path(commands: &[PathCommand], transform: &Tran
     transformed_cmds
 }
 
+/// Bounds of all points of the commands (control points included).
+pub(crate) fn commands_bounds<'a>(commands: impl Iterator<Item = &'a PathCommand>) -> Option<Bounds> {
+    let mut min_x = f32::MAX;
+    let mut min_y = f32::MAX;
+    let mut max_x = f32::MIN;
+    let mut max_y = f32::MIN;
+
+    let mut include = |p: &Point| {
+        min_x = min_x.min(p.x);
+        min_y = min_y.min(p.y);
+        max_x = max_x.max(p.x);
+        max_y = max_y.max(p.y);
+    };
+
+    // Arcs are bounded by their Bézier approximation, which needs the current point
+    let mut current = Point { x: 0.0, y: 0.0 };
+    let mut subpath_start = current;
+    for cmd in commands {
+        match cmd {
+            PathCommand::MoveTo(p) => { include(p); subpath_start = *p; current = *p; }
+            PathCommand::LineTo(p) => { include(p); current = *p; }
+            PathCommand::QuadraticBezier(cp, p) => { include(cp); include(p); current = *p; }
+            PathCommand::CubicBezier(cp1, cp2, p) => { include(cp1); include(cp2); include(p); current = *p; }
+            PathCommand::Arc { rx, ry, x_axis_rotation, large_arc_flag, sweep_flag, end } => {
+                if *rx != 0.0 && *ry != 0.0 {
+                    for bezier in arc_to_beziers(current, *rx, *ry, *x_axis_rotation, *large_arc_flag, *sweep_flag, *end) {
+                        if let PathCommand::CubicBezier(cp1, cp2, _) = bezier {
+                            include(&cp1);
+                            include(&cp2);
+                        }
+                    }
+                }
+                include(end);
+                current = *end;
+            }
+            PathCommand::ClosePath => current = subpath_start,
+        }
+    }
+
+    if min_x > max_x {
+        return None;
+    }
+    Some(Bounds { x: min_x, y: min_y, width: max_x - min_x, height: max_y - min_y })
+}
+
 pub(crate) fn generate_color_map(
     bitmap: &[u8],
     paint: &Paint,
@@ -502,10 +536,6 @@ pub(crate) fn generate_color_map(
     height: usize,
     draw_x: f32,
     draw_y: f32,
-    bbox_x: f32,
-    bbox_y: f32,
-    bbox_w: f32,
-    bbox_h: f32,
 ) -> Vec<u32> {
     let mut color_map = Vec::with_capacity(bitmap.len());
 
@@ -519,27 +549,93 @@ pub(crate) fn generate_color_map(
             }
         }
 
-        Paint::LinearGradient(_) | Paint::RadialGradient(_) => {
+        Paint::None | Paint::Reference(_) => {
+            for _ in 0..bitmap.len() {
+                color_map.push(0x00000000);
+            }
+        }
+
+        // Gradients and patterns vary from pixel to pixel
+        _ => {
             for y in 0..height {
                 for x in 0..width {
                     let idx = y * width + x;
                     let coverage = bitmap[idx];
                     let svg_x = draw_x + x as f32;
                     let svg_y = draw_y + y as f32;
-                    let color = paint.get_color_at(svg_x, svg_y, bbox_x, bbox_y, bbox_w, bbox_h);
+                    let color = paint.get_color_at(svg_x, svg_y);
                     let src_a = ((color >> 24) & 0xFF) as u32;
                     let final_a = (src_a * (coverage as u32)) / 255;
                     color_map.push((final_a << 24) | (color & 0xFFFFFF));
                 }
             }
         }
+    }
 
-        Paint::None | Paint::Reference(_) => {
-            for _ in 0..bitmap.len() {
-                color_map.push(0x00000000);
+    color_map
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f32, y: f32, w: f32, h: f32) -> Vec<PathCommand> {
+        vec![
+            PathCommand::MoveTo(Point { x, y }),
+            PathCommand::LineTo(Point { x: x + w, y }),
+            PathCommand::LineTo(Point { x: x + w, y: y + h }),
+            PathCommand::LineTo(Point { x, y: y + h }),
+            PathCommand::ClosePath,
+        ]
+    }
+
+    #[test]
+    fn test_fill_larger_than_canvas_covers_it() {
+        let mut canvas = Canvas::new_transparent(8, 8);
+        fill_path(&mut canvas, &rect(-1e9, -1e9, 2e9, 2e9), &Paint::Solid(0xFF00FF00));
+        assert!(canvas.data.iter().all(|&pixel| pixel == 0xFF00FF00), "{:x?}", canvas.data);
+    }
+
+    #[test]
+    fn test_fill_clipped_on_the_left_keeps_its_winding() {
+        let mut canvas = Canvas::new_transparent(8, 8);
+        fill_path(&mut canvas, &rect(-100.0, 2.0, 104.0, 4.0), &Paint::Solid(0xFF00FF00));
+        for y in 0..8 {
+            for x in 0..8 {
+                let expected = if (2..6).contains(&y) && x < 4 { 0xFF00FF00 } else { 0 };
+                assert_eq!(canvas.data[y * 8 + x], expected, "pixel {}, {}", x, y);
             }
         }
     }
 
-    color_map
-}
\ No newline at end of file
+    #[test]
+    fn test_fill_off_canvas_draws_nothing() {
+        let mut canvas = Canvas::new_transparent(8, 8);
+        fill_path(&mut canvas, &rect(20.0, 20.0, 1e9, 1e9), &Paint::Solid(0xFF00FF00));
+        assert!(canvas.data.iter().all(|&pixel| pixel == 0));
+    }
+
+    #[test]
+    fn test_huge_font_size_renders() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="20" height="20">
+            <text x="-1000" y="1000" font-size="1e6">M</text>
+        </svg>"#;
+        assert_eq!(crate::svg::render(svg, 20, 20).unwrap().len(), 400);
+    }
+
+    #[test]
+    fn test_fill_and_stroke_from_style() {
+        let pixels = crate::svg::testing::render(r#"<svg width="4" height="4">
+            <rect width="4" height="4" fill="lime" style="fill: red; stroke: blue" stroke-width="2"/>
+        </svg>"#, 4, 4);
+        assert_eq!(pixels[0], 0xFF0000FF);
+        assert_eq!(pixels[5], 0xFFFF0000);
+    }
+
+    #[test]
+    fn test_stroke_width_from_style() {
+        let pixels = crate::svg::testing::render(r#"<svg width="8" height="8">
+            <rect x="2" y="2" width="4" height="4" fill="none" stroke="blue" stroke-width="0" style="stroke-width: 2"/>
+        </svg>"#, 8, 8);
+        assert_eq!(crate::svg::testing::ink_bounds(&pixels, 8), Some((1, 1, 6, 6)));
+    }
+}
@@ -0,0 +1,290 @@
+use crate::svg::parser::tags::Tag;
+use crate::svg::rasterizer::canva::Canvas;
+use crate::svg::rasterizer::raster::Bounds;
+use crate::svg::rasterizer::tags::lineargradient::GradientUnits;
+use crate::svg::rasterizer::tags::text::Font;
+use crate::svg::tree::Node;
+use crate::svg::utils::coords::{Axis, LengthContext};
+use crate::svg::utils::transform::{parse_view_box, view_box_transform, Transform};
+use crate::utils::compat::{String, ToString, Vec, vec};
+#[cfg(not(feature = "std"))]
+use crate::utils::compat::FloatExt;
+use crate::svg::utils::Defs;
+
+/// Longest `href` chain followed when collecting template attributes.
+const MAX_TEMPLATE_DEPTH: usize = 16;
+
+/// Largest tile edge rendered offscreen, in device pixels.
+const MAX_TILE_SIZE: f32 = 2048.0;
+
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub units: GradientUnits,
+    pub content_units: GradientUnits,
+    pub view_box: Option<[f32; 4]>,
+    pub preserve_aspect_ratio: Option<String>,
+    /// `patternTransform`, mapping pattern space to the user space of the painted element.
+    pub transform: Transform,
+    id: String,
+    /// The children, converted in the context of the tile content.
+    content: Vec<Node>,
+    tile: Option<PatternTile>,
+}
+
+/// A pattern tile rendered for one element.
+#[derive(Debug, Clone)]
+struct PatternTile {
+    pixels: Vec<u32>,
+    width: usize,
+    height: usize,
+    /// Maps device coordinates to pattern space.
+    device_to_pattern: Transform,
+    /// Tile rectangle in pattern space.
+    x: f32,
+    y: f32,
+    tile_w: f32,
+    tile_h: f32,
+}
+
+impl Pattern {
+    /// Renders the tile for an element with user-space bounding box `bbox`,
+    /// drawn through `transform`. Without a usable tile the pattern paints nothing.
+    pub fn prepare(&mut self, defs: &Defs, transform: &Transform, bbox: Bounds) {
+        self.tile = None;
+
+        let bbox_units = self.units == GradientUnits::ObjectBoundingBox;
+        let bbox_content = self.content_units == GradientUnits::ObjectBoundingBox;
+        if (bbox_units || bbox_content) && (bbox.width <= 0.0 || bbox.height <= 0.0) {
+            return;
+        }
+
+        let (x, y, tile_w, tile_h) = if bbox_units {
+            (
+                bbox.x + self.x * bbox.width,
+                bbox.y + self.y * bbox.height,
+                self.width * bbox.width,
+                self.height * bbox.height,
+            )
+        } else {
+            (self.x, self.y, self.width, self.height)
+        };
+        if tile_w <= 0.0 || tile_h <= 0.0 || self.content.is_empty() {
+            return;
+        }
+
+        let pattern_to_device = transform.then(&self.transform);
+        let Some(device_to_pattern) = pattern_to_device.inverse() else { return };
+
+        // Render at device resolution so the tile is sampled about 1:1, never
+        // enlarging the content past the device scale. A tile under a device
+        // pixel cannot be rendered that way, so it paints nothing.
+        let (sx, sy) = pattern_to_device.get_scale();
+        let (device_w, device_h) = (tile_w * sx, tile_h * sy);
+        if !(device_w >= 1.0 && device_h >= 1.0) {
+            return;
+        }
+        let width = device_w.floor().min(MAX_TILE_SIZE) as usize;
+        let height = device_h.floor().min(MAX_TILE_SIZE) as usize;
+
+        // Content coordinates start at the tile's top-left corner
+        let content_transform = match self.view_box {
+            Some(view_box) => view_box_transform(view_box, tile_w, tile_h, self.preserve_aspect_ratio.as_deref()),
+            None if bbox_content => Transform::scale(bbox.width, bbox.height),
+            None => Transform::identity(),
+        };
+        let tile_transform = Transform::scale(width as f32 / tile_w, height as f32 / tile_h)
+            .then(&content_transform);
+
+        // A pattern never paints itself: references back to it resolve to nothing
+        let mut tile_defs = defs.clone();
+        tile_defs.remove(self.id.as_str());
+
+        let mut canvas = Canvas::new_transparent(width, height);
+        for node in &self.content {
+            canvas.draw_node(node, &tile_defs, &tile_transform);
+        }
+
+        self.tile = Some(PatternTile {
+            pixels: canvas.data,
+            width,
+            height,
+            device_to_pattern,
+            x,
+            y,
+            tile_w,
+            tile_h,
+        });
+    }
+
+    /// Color of the pattern at a device position, repeating the tile.
+    pub fn sample(&self, x: f32, y: f32) -> u32 {
+        let Some(tile) = &self.tile else { return 0x00000000 };
+
+        let (u, v) = tile.device_to_pattern.apply(x + 0.5, y + 0.5);
+        let fu = (u - tile.x) / tile.tile_w;
+        let fv = (v - tile.y) / tile.tile_h;
+        let px = (fu - fu.floor()) * tile.width as f32 - 0.5;
+        let py = (fv - fv.floor()) * tile.height as f32 - 0.5;
+
+        tile.bilinear(px, py)
+    }
+}
+
+impl PatternTile {
+    /// Bilinear sample in premultiplied space, wrapping at the tile edges.
+    fn bilinear(&self, px: f32, py: f32) -> u32 {
+        let x0 = px.floor();
+        let y0 = py.floor();
+        let tx = px - x0;
+        let ty = py - y0;
+
+        let wrap = |v: f32, size: usize| (v as isize).rem_euclid(size as isize) as usize;
+        let (x0, x1) = (wrap(x0, self.width), wrap(x0 + 1.0, self.width));
+        let (y0, y1) = (wrap(y0, self.height), wrap(y0 + 1.0, self.height));
+
+        let mut sum = [0.0f32; 4];
+        for (x, y, weight) in [
+            (x0, y0, (1.0 - tx) * (1.0 - ty)),
+            (x1, y0, tx * (1.0 - ty)),
+            (x0, y1, (1.0 - tx) * ty),
+            (x1, y1, tx * ty),
+        ] {
+            let c = self.pixels[y * self.width + x];
+            let a = ((c >> 24) & 0xFF) as f32 * weight;
+            sum[0] += a;
+            sum[1] += ((c >> 16) & 0xFF) as f32 * a;
+            sum[2] += ((c >> 8) & 0xFF) as f32 * a;
+            sum[3] += (c & 0xFF) as f32 * a;
+        }
+
+        if sum[0] <= 0.0 {
+            return 0x00000000;
+        }
+        let a = sum[0].round().min(255.0) as u32;
+        let r = (sum[1] / sum[0]).round().min(255.0) as u32;
+        let g = (sum[2] / sum[0]).round().min(255.0) as u32;
+        let b = (sum[3] / sum[0]).round().min(255.0) as u32;
+        (a << 24) | (r << 16) | (g << 8) | b
+    }
+}
+
+/// Loads a `<pattern>`, inheriting unset attributes and children from the
+/// patterns it references through `href`.
+pub fn load_pattern(id: &str, tag: &Tag, defs: &Defs, fonts: &[Font], lengths: &LengthContext) -> Pattern {
+    let mut chain = vec![tag];
+    while chain.len() < MAX_TEMPLATE_DEPTH {
+        let last = chain[chain.len() - 1];
+        let next = last.params.get("href")
+            .or_else(|| last.params.get("xlink:href"))
+            .and_then(|link| defs.get(link.trim().trim_start_matches('#')).copied())
+            .filter(|t| t.name == "pattern" && !chain.iter().any(|seen| core::ptr::eq(*seen, *t)));
+        match next {
+            Some(next) => chain.push(next),
+            None => break,
+        }
+    }
+
+    let attr = |key: &str| chain.iter().find_map(|t| t.params.get(key)).map(|s| s.trim());
+    let units = |key: &str, default: GradientUnits| match attr(key) {
+        Some("userSpaceOnUse") => GradientUnits::UserSpaceOnUse,
+        Some("objectBoundingBox") => GradientUnits::ObjectBoundingBox,
+        _ => default,
+    };
+    let (tile_units, content_units) = (
+        units("patternUnits", GradientUnits::ObjectBoundingBox),
+        units("patternContentUnits", GradientUnits::UserSpaceOnUse),
+    );
+    let tile_lengths = tile_units.lengths(lengths);
+    let length = |key: &str, axis: Axis| attr(key).and_then(|s| tile_lengths.length(s, axis)).unwrap_or(0.0);
+    let view_box = attr("viewBox").and_then(parse_view_box);
+
+    // A pattern never paints itself: references back to it resolve to nothing
+    let mut content_defs = defs.clone();
+    content_defs.remove(id);
+    let content_lengths = match view_box {
+        Some(view_box) => lengths.with_viewport(view_box[2], view_box[3]),
+        None => content_units.lengths(lengths),
+    };
+    let content = chain.iter()
+        .find(|t| !t.children.is_empty())
+        .map(|t| t.children.iter().filter_map(|child| Node::from_tag(child, &content_defs, fonts, &content_lengths)).collect())
+        .unwrap_or_default();
+
+    Pattern {
+        x: length("x", Axis::Horizontal),
+        y: length("y", Axis::Vertical),
+        width: length("width", Axis::Horizontal),
+        height: length("height", Axis::Vertical),
+        units: tile_units,
+        content_units,
+        view_box,
+        preserve_aspect_ratio: attr("preserveAspectRatio").map(|s| s.to_string()),
+        transform: attr("patternTransform")
+            .and_then(Transform::from_str)
+            .unwrap_or(Transform::identity()),
+        id: id.to_string(),
+        content,
+        tile: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svg::parser::parse::parse_xml;
+    use crate::svg::tree::NodeKind;
+    use crate::svg::utils::collect_defs;
+    use crate::svg::utils::color::Paint;
+
+    fn load(svg: &str) -> Pattern {
+        let root = parse_xml(svg.as_bytes()).unwrap().remove(0);
+        let defs = collect_defs(&root);
+        load_pattern("p", defs["p"], &defs, &[], &LengthContext::default())
+    }
+
+    #[test]
+    fn test_content_is_converted_when_loaded() {
+        let pattern = load(r#"<svg><pattern id="p" width="4" height="4"><rect width="50%" height="2" fill="red"/><defs/></pattern></svg>"#);
+        assert_eq!(pattern.content.len(), 1);
+        let NodeKind::Path(path) = &pattern.content[0].kind else { panic!("expected a path") };
+        assert!(matches!(path.fill, Paint::Solid(0xFFFF0000)));
+        // Without a viewBox, content percentages are of the painted element's viewport
+        assert_eq!(path.bbox.map(|b| b.width), Some(LengthContext::default().viewport.0 / 2.0));
+    }
+
+    #[test]
+    fn test_content_cannot_paint_with_its_own_pattern() {
+        let pattern = load(r##"<svg><pattern id="p" width="4" height="4"><rect width="2" height="2" fill="url(#p)"/></pattern></svg>"##);
+        let NodeKind::Path(path) = &pattern.content[0].kind else { panic!("expected a path") };
+        assert!(path.fill.is_none());
+    }
+
+    #[test]
+    fn test_pattern_repeats_its_tile() {
+        let svg = br##"<svg xmlns="http://www.w3.org/2000/svg" width="8" height="8">
+            <pattern id="p" width="4" height="4" patternUnits="userSpaceOnUse">
+                <rect width="2" height="2" fill="red"/>
+            </pattern>
+            <rect width="8" height="8" fill="url(#p)"/>
+        </svg>"##;
+        let pixels = crate::svg::render(svg, 8, 8).unwrap();
+        assert_eq!(pixels[4 * 8 + 4], 0xFFFF0000);
+        assert_eq!(pixels[3 * 8 + 3], 0xFFFFFFFF);
+    }
+
+    #[test]
+    fn test_sub_pixel_tile_paints_nothing() {
+        let svg = br##"<svg xmlns="http://www.w3.org/2000/svg" width="8" height="8">
+            <pattern id="p" width="0.001" height="0.001" patternUnits="userSpaceOnUse">
+                <rect width="1e9" height="1e9" fill="red"/>
+            </pattern>
+            <rect width="8" height="8" fill="url(#p)"/>
+        </svg>"##;
+        let pixels = crate::svg::render(svg, 8, 8).unwrap();
+        assert!(pixels.iter().all(|&pixel| pixel == 0xFFFFFFFF));
+    }
+}
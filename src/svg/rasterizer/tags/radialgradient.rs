@@ -1,10 +1,17 @@
 use crate::svg::parser::tags::Tag;
-use crate::svg::rasterizer::tags::lineargradient::{GradientStop, GradientUnits, blend_colors};
-use crate::svg::utils::color::parse_color_value;
-use crate::svg::utils::coords::parse_length;
+use crate::svg::rasterizer::raster::Bounds;
+use crate::svg::rasterizer::tags::lineargradient::{
+    device_to_gradient, load_common, template_chain, ColorRamp, GradientUnits, SpreadMethod,
+};
+use crate::svg::utils::coords::{Axis, LengthContext};
+use crate::svg::utils::transform::Transform;
+#[cfg(not(feature = "std"))]
 use crate::utils::compat::FloatExt;
-use crate::utils::compat::Vec;
-use core::cmp::Ordering;
+use crate::svg::utils::Defs;
+
+/// Fraction of the radius a focal point may lie from the centre, keeping it
+/// just inside the end circle.
+const FOCAL_LIMIT: f32 = 0.999;
 
 #[derive(Debug, Clone)]
 pub struct RadialGradient {
@@ -13,178 +20,176 @@ pub struct RadialGradient {
     pub r: f32,
     pub fx: f32,
     pub fy: f32,
-    pub stops: Vec<GradientStop>,
+    pub fr: f32,
+    pub ramp: ColorRamp,
     pub units: GradientUnits,
+    pub spread: SpreadMethod,
+    /// `gradientTransform`, mapping gradient space to the units' coordinate system.
+    pub transform: Transform,
+    device_to_gradient: Option<Transform>,
 }
 
 impl RadialGradient {
-    pub fn interpolate(&self, x: f32, y: f32, bbox_x: f32, bbox_y: f32, bbox_w: f32, bbox_h: f32) -> u32 {
-        if self.stops.is_empty() {
-            return 0x00000000;
-        }
+    /// Resolves the gradient for an element with user-space bounding box
+    /// `bbox`, drawn through `transform`.
+    pub fn prepare(&mut self, transform: &Transform, bbox: Bounds) {
+        self.device_to_gradient = device_to_gradient(&self.units, &self.transform, transform, bbox);
+    }
 
-        if self.stops.len() == 1 {
-            return self.stops[0].color;
+    pub fn interpolate(&self, x: f32, y: f32) -> u32 {
+        let Some(device_to_gradient) = &self.device_to_gradient else { return 0x00000000 };
+        // A zero radius paints the last stop
+        if self.r <= 0.0 {
+            return self.ramp.last_color();
         }
 
-        // Resolve coordinates based on units
-        let (cx, cy, r, fx, fy) = if self.units == GradientUnits::ObjectBoundingBox {
-            (
-                bbox_x + self.cx * bbox_w,
-                bbox_y + self.cy * bbox_h,
-                self.r * (bbox_w.powi(2) + bbox_h.powi(2)).sqrt() / 2.0f32.sqrt(),
-                bbox_x + self.fx * bbox_w,
-                bbox_y + self.fy * bbox_h
-            )
-        } else {
-            (self.cx, self.cy, self.r, self.fx, self.fy)
-        };
-
-        let t = if self.units == GradientUnits::ObjectBoundingBox {
-            // Transform P to unit space
-            let nx = if bbox_w > 1e-6 { (x - bbox_x) / bbox_w } else { 0.0 };
-            let ny = if bbox_h > 1e-6 { (y - bbox_y) / bbox_h } else { 0.0 };
-            
-            calculate_t(nx, ny, self.cx, self.cy, self.r, self.fx, self.fy)
-        } else {
-            calculate_t(x, y, cx, cy, r, fx, fy)
-        };
-
-        let t = t.clamp(0.0, 1.0);
-
-        if t <= self.stops[0].offset {
-            return self.stops[0].color;
+        let (gx, gy) = device_to_gradient.apply(x + 0.5, y + 0.5);
+        match self.conical_t(gx, gy) {
+            Some(t) => self.ramp.color_at(self.spread.apply(t), x, y),
+            None => 0x00000000,
         }
-        if t >= self.stops[self.stops.len() - 1].offset {
-            return self.stops[self.stops.len() - 1].color;
+    }
+
+    /// Solves the two-point conical gradient at (`x`, `y`): the largest `t` for
+    /// which the point lies on the circle interpolated between the focal circle
+    /// (`t = 0`) and the end circle (`t = 1`) with a non-negative radius. Points
+    /// outside the cone swept by the circles have no `t`.
+    fn conical_t(&self, x: f32, y: f32) -> Option<f32> {
+        // `t` does not change with scale, so solve in units of the end radius
+        // to keep huge gradients from overflowing
+        let unit = |v: f32| v / self.r;
+        let cdx = unit(self.cx - self.fx);
+        let cdy = unit(self.cy - self.fy);
+        let fr = unit(self.fr);
+        let dr = 1.0 - fr;
+        let px = unit(x - self.fx);
+        let py = unit(y - self.fy);
+
+        // |p - f - t(c - f)| = fr + t(r - fr), squared: a t^2 - 2b t + c = 0
+        let a = cdx * cdx + cdy * cdy - dr * dr;
+        let b = px * cdx + py * cdy + fr * dr;
+        let c = px * px + py * py - fr * fr;
+        let valid = |t: f32| fr + t * dr >= 0.0;
+
+        if a.abs() < 1e-6 {
+            if b.abs() < 1e-6 {
+                return None;
+            }
+            let t = c / (2.0 * b);
+            return valid(t).then_some(t);
         }
 
-        for i in 0..self.stops.len() - 1 {
-            let stop1 = &self.stops[i];
-            let stop2 = &self.stops[i + 1];
+        let discriminant = b * b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
 
-            if t >= stop1.offset && t <= stop2.offset {
-                let range = stop2.offset - stop1.offset;
-                let local_t = if range > 1e-6 {
-                    (t - stop1.offset) / range
-                } else {
-                    0.0
-                };
+        let sqrt_disc = discriminant.sqrt();
+        let t1 = (b + sqrt_disc) / a;
+        let t2 = (b - sqrt_disc) / a;
+        let (hi, lo) = if t1 > t2 { (t1, t2) } else { (t2, t1) };
 
-                return blend_colors(stop1.color, stop2.color, local_t);
-            }
+        if valid(hi) {
+            Some(hi)
+        } else if valid(lo) {
+            Some(lo)
+        } else {
+            None
         }
+    }
+}
 
-        self.stops[self.stops.len() - 1].color
+/// Loads a `<radialGradient>`, inheriting unset attributes and stops from
+/// the gradients it references through `href`.
+pub fn load_radial_gradient(tag: &Tag, defs: &Defs, lengths: &LengthContext) -> RadialGradient {
+    let chain = template_chain(tag, defs);
+    let attr = |key: &str| chain.iter()
+        .filter(|t| t.name == "radialGradient")
+        .find_map(|t| t.params.get(key));
+
+    let (units, spread, transform, ramp) = load_common(&chain);
+    let lengths = units.lengths(lengths);
+    let length = |key: &str, default: f32, axis: Axis| attr(key).and_then(|s| lengths.length(s, axis)).unwrap_or(default);
+
+    // Defaults: cx=50%, cy=50%, r=50%, focus at the centre
+    let half = |axis: Axis| lengths.reference(axis) / 2.0;
+    let cx = length("cx", half(Axis::Horizontal), Axis::Horizontal);
+    let cy = length("cy", half(Axis::Vertical), Axis::Vertical);
+    let r = length("r", half(Axis::Diagonal), Axis::Diagonal);
+    let mut fx = length("fx", cx, Axis::Horizontal);
+    let mut fy = length("fy", cy, Axis::Vertical);
+
+    // A focal point outside the end circle moves onto its edge, as browsers do
+    let (dx, dy) = (fx - cx, fy - cy);
+    let distance = (dx * dx + dy * dy).sqrt();
+    if r > 0.0 && distance > r * FOCAL_LIMIT {
+        fx = cx + dx * r * FOCAL_LIMIT / distance;
+        fy = cy + dy * r * FOCAL_LIMIT / distance;
+    }
+
+    RadialGradient {
+        cx,
+        cy,
+        r,
+        fx,
+        fy,
+        fr: length("fr", 0.0, Axis::Diagonal).max(0.0),
+        ramp,
+        units,
+        spread,
+        transform,
+        device_to_gradient: None,
     }
 }
 
-fn calculate_t(x: f32, y: f32, cx: f32, cy: f32, r: f32, fx: f32, fy: f32) -> f32 {
-    // V = C - F
-    let vx = cx - fx;
-    let vy = cy - fy;
-    
-    // D = P - F
-    let dx = x - fx;
-    let dy = y - fy;
-
-    // A = V.V - R^2
-    // B = -2 (D.V)
-    // C = D.D
-
-    let r2 = r * r;
-    let v_dot_v = vx * vx + vy * vy;
-    let d_dot_v = dx * vx + dy * vy;
-    let d_dot_d = dx * dx + dy * dy;
-
-    let a = v_dot_v - r2;
-    let b = -2.0 * d_dot_v;
-    let c = d_dot_d;
-
-    if a.abs() < 1e-6 {
-        // Linear equation B t + C = 0 => t = -C / B
-        if b.abs() < 1e-6 {
-            return 0.0; // Degenerate
-        }
-        return -c / b;
+#[cfg(test)]
+mod tests {
+    use crate::svg::testing::{near, render};
+    use crate::utils::compat::{format, Vec};
+
+    /// Fills a 10x10 canvas with a red to blue radial gradient with `attributes`.
+    fn render_gradient(attributes: &str) -> Vec<u32> {
+        render(&format!(
+            r##"<svg width="10" height="10">
+                <radialGradient id="g" {}><stop offset="0" stop-color="red"/><stop offset="1" stop-color="blue"/></radialGradient>
+                <rect width="10" height="10" fill="url(#g)"/>
+            </svg>"##,
+            attributes
+        ), 10, 10)
     }
 
-    let discriminant = b * b - 4.0 * a * c;
-    if discriminant < 0.0 {
-        return 0.0; // Should not happen if P is inside? Or maybe outside. 
-        // If outside cone, t is undefined or we clamp.
-        // We treat it as 1.0 (outside) or 0.0?
-        // Usually we clamp t to 1.0 if outside.
-        // But let's return 0.0 for now.
+    #[test]
+    fn test_focal_radius_paints_the_first_stop() {
+        let pixels = render_gradient(r#"fr="0.25""#);
+        assert_eq!(pixels[5 * 10 + 5], 0xFFFF0000);
+        assert_eq!(pixels[5 * 10 + 3], 0xFFFF0000);
+        assert!(!near(pixels[5 * 10 + 1], 0xFFFF0000, 32));
+        assert_eq!(pixels[0], 0xFF0000FF);
     }
 
-    let sqrt_disc = discriminant.sqrt();
-    let t1 = (-b + sqrt_disc) / (2.0 * a);
-    let t2 = (-b - sqrt_disc) / (2.0 * a);
-
-    // We want the positive t > 0.
-    // Actually, t represents the scaling factor of the radius.
-    // We usually want the smallest positive solution that makes sense?
-    // No, for radial gradient, t maps 0 (at F) to 1 (at Circle).
-    // So we expect 0 <= t <= 1 inside the gradient area.
-    // If point is outside, t > 1.
-    
-    t1.max(t2)
-}
+    #[test]
+    fn test_off_centre_focal_point() {
+        let pixels = render_gradient(r#"fx="0.15""#);
+        let red = |x: usize| (pixels[5 * 10 + x] >> 16) & 0xFF;
+        assert!(red(1) > 0xD0);
+        // The ramp is compressed on the focal side and stretched on the other
+        assert!(red(1) - red(0) > 2 * (red(8) - red(9)));
+    }
 
-pub fn load_radial_gradient(tag: &Tag) -> RadialGradient {
-    // Defaults: cx=50%, cy=50%, r=50%
-    let cx = tag.params.get("cx").map(|s| parse_length(s, 0.5, 1.0)).unwrap_or(0.5);
-    let cy = tag.params.get("cy").map(|s| parse_length(s, 0.5, 1.0)).unwrap_or(0.5);
-    let r = tag.params.get("r").map(|s| parse_length(s, 0.5, 1.0)).unwrap_or(0.5);
-    
-    let fx = tag.params.get("fx").map(|s| parse_length(s, cx, 1.0)).unwrap_or(cx);
-    let fy = tag.params.get("fy").map(|s| parse_length(s, cy, 1.0)).unwrap_or(cy);
-
-    let units = tag.params.get("gradientUnits")
-        .map(|s| s.as_str())
-        .unwrap_or("objectBoundingBox");
-    
-    let units = match units {
-        "userSpaceOnUse" => GradientUnits::UserSpaceOnUse,
-        _ => GradientUnits::ObjectBoundingBox,
-    };
-
-    let mut stops = Vec::new();
-    for child in &tag.children {
-        if child.name == "stop" {
-            let offset = child
-                .params
-                .get("offset")
-                .map(|s| parse_length(s, 0.0, 1.0))
-                .unwrap_or(0.0);
-
-            let color = child
-                .params
-                .get("stop-color")
-                .map(|c| parse_color_value(c))
-                .unwrap_or(0x000000);
-
-            // stop-opacity support
-            let opacity = child.params.get("stop-opacity")
-                .and_then(|s| s.parse::<f32>().ok())
-                .unwrap_or(1.0);
-
-            let final_color = if opacity < 1.0 {
-                let a = ((color >> 24) & 0xFF) as f32;
-                let new_a = (a * opacity) as u32;
-                (color & 0x00FFFFFF) | (new_a << 24)
-            } else {
-                color
-            };
-
-            stops.push(GradientStop { offset, color: final_color });
-        }
+    #[test]
+    fn test_focal_point_outside_is_moved_onto_the_edge() {
+        let outside = render_gradient(r#"fx="5" fy="0.5""#);
+        let edge = render_gradient(r#"fx="0.9995" fy="0.5""#);
+        assert!(outside.iter().zip(&edge).all(|(&a, &b)| near(a, b, 1)));
+        assert!(outside.iter().all(|&pixel| pixel >> 24 == 0xFF));
+        assert!(near(outside[5 * 10 + 9], 0xFFFF0000, 48));
     }
-    
-    stops.sort_by(|a, b| a.offset.partial_cmp(&b.offset).unwrap_or(Ordering::Equal));
 
-    RadialGradient {
-        cx, cy, r, fx, fy, stops, units
+    #[test]
+    fn test_degenerate_and_huge_radii() {
+        assert!(render_gradient(r#"r="0""#).iter().all(|&pixel| pixel == 0xFF0000FF));
+
+        let huge = render_gradient(r#"gradientUnits="userSpaceOnUse" r="1e30""#);
+        assert!(huge.iter().all(|&pixel| pixel == 0xFFFF0000));
     }
 }
@@ -0,0 +1,654 @@
+//! Unicode Bidirectional Algorithm (UAX #9): resolution of embedding levels
+//! for one paragraph and reordering of level runs into visual order.
+
+use crate::utils::compat::{Vec, vec};
+
+/// Deepest valid embedding level (BD2).
+const MAX_DEPTH: u8 = 125;
+/// Size of the bracket pair stack (BD16).
+const MAX_BRACKET_STACK: usize = 63;
+
+// Class names follow UAX #9
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BidiClass {
+    L, R, AL,
+    EN, ES, ET, AN, CS, NSM, BN,
+    B, S, WS, ON,
+    LRE, LRO, RLE, RLO, PDF,
+    LRI, RLI, FSI, PDI,
+}
+
+use BidiClass::*;
+
+// Explicit formatting characters, used to express `unicode-bidi` on elements
+pub(crate) const LRE_CHAR: char = '\u{202A}';
+pub(crate) const RLE_CHAR: char = '\u{202B}';
+pub(crate) const PDF_CHAR: char = '\u{202C}';
+pub(crate) const LRO_CHAR: char = '\u{202D}';
+pub(crate) const RLO_CHAR: char = '\u{202E}';
+pub(crate) const LRI_CHAR: char = '\u{2066}';
+pub(crate) const RLI_CHAR: char = '\u{2067}';
+pub(crate) const FSI_CHAR: char = '\u{2068}';
+pub(crate) const PDI_CHAR: char = '\u{2069}';
+
+/// Bidi class of a character. Covers the scripts and punctuation the
+/// renderer meets in practice; unlisted characters are strong left-to-right.
+pub(crate) fn bidi_class(c: char) -> BidiClass {
+    let cp = c as u32;
+    match cp {
+        0x0000..=0x0008 | 0x000E..=0x001B | 0x007F..=0x0084 | 0x0086..=0x009F => BN,
+        0x0009 | 0x000B | 0x001F => S,
+        0x000A | 0x000D | 0x001C..=0x001E | 0x0085 | 0x2029 => B,
+        0x000C | 0x0020 | 0x2000..=0x200A | 0x2028 | 0x205F | 0x3000 => WS,
+        0x0023..=0x0025 | 0x00A2..=0x00A5 | 0x00B0 | 0x00B1 | 0x2030..=0x2034 | 0x20A0..=0x20CF => ET,
+        0x002B | 0x002D | 0x207A | 0x207B | 0x208A | 0x208B | 0x2212 => ES,
+        0x002C | 0x002E | 0x002F | 0x003A | 0x00A0 | 0x2044 => CS,
+        0x0030..=0x0039 | 0x00B2 | 0x00B3 | 0x00B9 | 0x2070 | 0x2074..=0x2079 | 0x2080..=0x2089 => EN,
+        0x0021 | 0x0022 | 0x0026..=0x002A | 0x003B..=0x0040 | 0x005B..=0x0060 | 0x007B..=0x007E
+        | 0x00A1 | 0x00A6..=0x00A9 | 0x00AB | 0x00AC | 0x00AE | 0x00AF | 0x00B4 | 0x00B6..=0x00B8
+        | 0x00BB..=0x00BF | 0x00D7 | 0x00F7 => ON,
+        0x00AD | 0x200B..=0x200D | 0x2060..=0x2064 | 0xFEFF => BN,
+        0x200E => L,
+        0x200F => R,
+        0x202A => LRE,
+        0x202B => RLE,
+        0x202C => PDF,
+        0x202D => LRO,
+        0x202E => RLO,
+        0x2066 => LRI,
+        0x2067 => RLI,
+        0x2068 => FSI,
+        0x2069 => PDI,
+        0x2010..=0x2027 | 0x2035..=0x2043 | 0x2045..=0x205E | 0x207C..=0x207E | 0x208C..=0x208E
+        | 0x2190..=0x23FF | 0x2400..=0x24FF | 0x2500..=0x27FF | 0x2900..=0x2BFF
+        | 0x3001..=0x3003 | 0x3008..=0x3011 | 0x3014..=0x301F | 0xFE50..=0xFE6F | 0xFF01..=0xFF0F => ON,
+
+        // Combining marks
+        0x0300..=0x036F | 0x0483..=0x0489 | 0x1AB0..=0x1AFF | 0x1DC0..=0x1DFF | 0x20D0..=0x20FF
+        | 0xFE00..=0xFE0F | 0xFE20..=0xFE2F => NSM,
+
+        // Hebrew
+        0x0591..=0x05BD | 0x05BF | 0x05C1 | 0x05C2 | 0x05C4 | 0x05C5 | 0x05C7 => NSM,
+        0x0590..=0x05FF => R,
+
+        // Arabic, Syriac, Thaana
+        0x0600..=0x0605 | 0x0660..=0x0669 | 0x066B | 0x066C | 0x06DD | 0x0890 | 0x0891 | 0x08E2 => AN,
+        0x060C => CS,
+        0x066A => ET,
+        0x06F0..=0x06F9 => EN,
+        0x06DE | 0x06E9 | 0x060E | 0x060F => ON,
+        0x0610..=0x061A | 0x064B..=0x065F | 0x0670 | 0x06D6..=0x06DC | 0x06DF..=0x06E4
+        | 0x06E7 | 0x06E8 | 0x06EA..=0x06ED | 0x0711 | 0x0730..=0x074A | 0x07A6..=0x07B0
+        | 0x0898..=0x089F | 0x08CA..=0x08E1 | 0x08E3..=0x08FF => NSM,
+        0x0600..=0x07BF | 0x0860..=0x08FF => AL,
+
+        // NKo, Samaritan, Mandaic
+        0x07EB..=0x07F3 | 0x07FD | 0x0816..=0x0819 | 0x081B..=0x0823 | 0x0825..=0x0827
+        | 0x0829..=0x082D | 0x0859..=0x085B => NSM,
+        0x07C0..=0x085F => R,
+
+        // Devanagari and Bengali dependent signs that are non-spacing
+        0x0900..=0x0902 | 0x093A | 0x093C | 0x0941..=0x0948 | 0x094D | 0x0951..=0x0957
+        | 0x0962 | 0x0963 | 0x0981 | 0x09BC | 0x09C1..=0x09C4 | 0x09CD | 0x09E2 | 0x09E3 => NSM,
+
+        // Presentation forms
+        0xFB1E => NSM,
+        0xFB29 => ES,
+        0xFB1D..=0xFB4F => R,
+        0xFD3E | 0xFD3F => ON,
+        0xFB50..=0xFDFF | 0xFE70..=0xFEFE => AL,
+
+        0x10800..=0x10FFF | 0x1E800..=0x1EFFF => R,
+        _ => L,
+    }
+}
+
+/// Mirrored glyph for characters with the Bidi_Mirrored property (L4).
+pub(crate) fn mirror(c: char) -> char {
+    match c {
+        '(' => ')',
+        ')' => '(',
+        '<' => '>',
+        '>' => '<',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        '«' => '»',
+        '»' => '«',
+        '‹' => '›',
+        '›' => '‹',
+        '⁅' => '⁆',
+        '⁆' => '⁅',
+        '≤' => '≥',
+        '≥' => '≤',
+        '⟨' => '⟩',
+        '⟩' => '⟨',
+        '〈' => '〉',
+        '〉' => '〈',
+        '《' => '》',
+        '》' => '《',
+        '「' => '」',
+        '」' => '「',
+        '『' => '』',
+        '』' => '『',
+        '【' => '】',
+        '】' => '【',
+        _ => c,
+    }
+}
+
+/// Opening bracket for a closing one, or `None` (Bidi_Paired_Bracket).
+fn opening_bracket(c: char) -> Option<char> {
+    match c {
+        ')' => Some('('),
+        ']' => Some('['),
+        '}' => Some('{'),
+        '⁆' => Some('⁅'),
+        '⟩' | '〉' => Some('〈'),
+        '》' => Some('《'),
+        '」' => Some('「'),
+        '』' => Some('『'),
+        '】' => Some('【'),
+        _ => None,
+    }
+}
+
+fn is_opening_bracket(c: char) -> bool {
+    matches!(c, '(' | '[' | '{' | '⁅' | '〈' | '⟨' | '《' | '「' | '『' | '【')
+}
+
+/// Canonical form of a bracket, so U+2329/U+232A pair like U+3008/U+3009.
+fn canonical_bracket(c: char) -> char {
+    match c {
+        '⟨' | '\u{2329}' => '〈',
+        '⟩' | '\u{232A}' => '〉',
+        _ => c,
+    }
+}
+
+#[inline]
+fn is_isolate_initiator(class: BidiClass) -> bool {
+    matches!(class, LRI | RLI | FSI)
+}
+
+#[inline]
+fn is_removed(class: BidiClass) -> bool {
+    matches!(class, LRE | RLE | LRO | RLO | PDF | BN)
+}
+
+#[inline]
+fn is_neutral_or_isolate(class: BidiClass) -> bool {
+    matches!(class, B | S | WS | ON | LRI | RLI | FSI | PDI)
+}
+
+/// Direction of a level as a strong class.
+#[inline]
+fn direction(level: u8) -> BidiClass {
+    if level % 2 == 1 { R } else { L }
+}
+
+/// Paragraph embedding level from the first strong character (P2, P3),
+/// skipping isolated content.
+pub(crate) fn paragraph_level(classes: &[BidiClass]) -> u8 {
+    let mut isolate_depth = 0usize;
+    for &class in classes {
+        match class {
+            LRI | RLI | FSI => isolate_depth += 1,
+            PDI if isolate_depth > 0 => isolate_depth -= 1,
+            L if isolate_depth == 0 => return 0,
+            R | AL if isolate_depth == 0 => return 1,
+            B => break,
+            _ => {}
+        }
+    }
+    0
+}
+
+/// Index of the PDI closing each isolate initiator (BD9).
+fn matching_pdis(classes: &[BidiClass]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; classes.len()];
+    let mut open: Vec<usize> = Vec::new();
+
+    for (i, &class) in classes.iter().enumerate() {
+        match class {
+            LRI | RLI | FSI => open.push(i),
+            PDI => {
+                if let Some(start) = open.pop() {
+                    matches[start] = Some(i);
+                }
+            }
+            B => open.clear(),
+            _ => {}
+        }
+    }
+
+    matches
+}
+
+#[derive(Clone, Copy)]
+struct Status {
+    level: u8,
+    override_class: Option<BidiClass>,
+    isolate: bool,
+}
+
+/// Resolves the embedding level of every character of one paragraph.
+/// `base_level` is the paragraph level, or `None` to derive it from the text.
+pub(crate) fn resolve_levels(text: &[char], base_level: Option<u8>) -> Vec<u8> {
+    let original: Vec<BidiClass> = text.iter().map(|&c| bidi_class(c)).collect();
+    let para = base_level.unwrap_or_else(|| paragraph_level(&original));
+    let n = text.len();
+
+    let pdis = matching_pdis(&original);
+    let mut classes = original.clone();
+    let mut levels = vec![para; n];
+
+    // X1-X8: explicit embeddings, overrides and isolates
+    let mut stack = vec![Status { level: para, override_class: None, isolate: false }];
+    let mut overflow_isolates = 0usize;
+    let mut overflow_embeddings = 0usize;
+    let mut valid_isolates = 0usize;
+
+    for i in 0..n {
+        let top = *stack.last().unwrap_or(&Status { level: para, override_class: None, isolate: false });
+        let next_level = |rtl: bool| if rtl { (top.level + 1) | 1 } else { (top.level + 2) & !1 };
+
+        match original[i] {
+            class @ (RLE | LRE | RLO | LRO) => {
+                levels[i] = top.level;
+                let level = next_level(matches!(class, RLE | RLO));
+                if level <= MAX_DEPTH && overflow_isolates == 0 && overflow_embeddings == 0 {
+                    let override_class = match class {
+                        RLO => Some(R),
+                        LRO => Some(L),
+                        _ => None,
+                    };
+                    stack.push(Status { level, override_class, isolate: false });
+                } else if overflow_isolates == 0 {
+                    overflow_embeddings += 1;
+                }
+            }
+            class @ (RLI | LRI | FSI) => {
+                levels[i] = top.level;
+                if let Some(o) = top.override_class {
+                    classes[i] = o;
+                }
+                let rtl = match class {
+                    RLI => true,
+                    LRI => false,
+                    _ => {
+                        let end = pdis[i].unwrap_or(n);
+                        paragraph_level(&original[i + 1..end]) == 1
+                    }
+                };
+                let level = next_level(rtl);
+                if level <= MAX_DEPTH && overflow_isolates == 0 && overflow_embeddings == 0 {
+                    valid_isolates += 1;
+                    stack.push(Status { level, override_class: None, isolate: true });
+                } else {
+                    overflow_isolates += 1;
+                }
+            }
+            PDI => {
+                if overflow_isolates > 0 {
+                    overflow_isolates -= 1;
+                } else if valid_isolates > 0 {
+                    overflow_embeddings = 0;
+                    while stack.last().map(|s| !s.isolate).unwrap_or(false) {
+                        stack.pop();
+                    }
+                    stack.pop();
+                    valid_isolates -= 1;
+                }
+                let top = *stack.last().unwrap_or(&top);
+                levels[i] = top.level;
+                if let Some(o) = top.override_class {
+                    classes[i] = o;
+                }
+            }
+            PDF => {
+                if overflow_isolates > 0 {
+                    // Terminated by an overflowing isolate: ignored
+                } else if overflow_embeddings > 0 {
+                    overflow_embeddings -= 1;
+                } else if !top.isolate && stack.len() >= 2 {
+                    stack.pop();
+                }
+                levels[i] = top.level;
+            }
+            B => levels[i] = para,
+            BN => levels[i] = top.level,
+            _ => {
+                levels[i] = top.level;
+                if let Some(o) = top.override_class {
+                    classes[i] = o;
+                }
+            }
+        }
+    }
+
+    // X9-X10: resolve each isolating run sequence
+    for sequence in isolating_run_sequences(&original, &levels, &pdis) {
+        resolve_sequence(&sequence, text, &original, &mut classes, &mut levels, para);
+    }
+
+    // L1: separators and trailing white space go back to the paragraph level
+    let mut trailing = true;
+    for i in (0..n).rev() {
+        match original[i] {
+            B | S => {
+                levels[i] = para;
+                trailing = true;
+            }
+            WS | LRI | RLI | FSI | PDI => {
+                if trailing {
+                    levels[i] = para;
+                }
+            }
+            class if is_removed(class) => {
+                if trailing {
+                    levels[i] = para;
+                }
+            }
+            _ => trailing = false,
+        }
+    }
+
+    // Removed characters take the level of the preceding one, so they never split runs
+    for i in 0..n {
+        if is_removed(original[i]) {
+            levels[i] = if i > 0 { levels[i - 1] } else { para };
+        }
+    }
+
+    levels
+}
+
+/// Groups the characters kept after X9 into isolating run sequences (BD13).
+fn isolating_run_sequences(original: &[BidiClass], levels: &[u8], pdis: &[Option<usize>]) -> Vec<Vec<usize>> {
+    // Level runs over the characters that survive X9
+    let mut runs: Vec<Vec<usize>> = Vec::new();
+    let mut run_of = vec![usize::MAX; original.len()];
+    for (i, &class) in original.iter().enumerate() {
+        if is_removed(class) {
+            continue;
+        }
+        let same_run = runs.last()
+            .and_then(|r| r.last())
+            .map(|&last| levels[last] == levels[i])
+            .unwrap_or(false);
+        if !same_run {
+            runs.push(Vec::new());
+        }
+        let idx = runs.len() - 1;
+        runs[idx].push(i);
+        run_of[i] = idx;
+    }
+
+    let mut is_matched_pdi = vec![false; original.len()];
+    for pdi in pdis.iter().flatten() {
+        is_matched_pdi[*pdi] = true;
+    }
+
+    let mut sequences = Vec::new();
+    for run in &runs {
+        if is_matched_pdi[run[0]] {
+            // Continues the sequence of its isolate initiator
+            continue;
+        }
+
+        let mut sequence = run.clone();
+        loop {
+            let last = *sequence.last().unwrap_or(&0);
+            if !is_isolate_initiator(original[last]) {
+                break;
+            }
+            let Some(pdi) = pdis[last] else { break };
+            let next_run = run_of[pdi];
+            if next_run == usize::MAX {
+                break;
+            }
+            sequence.extend_from_slice(&runs[next_run]);
+        }
+        sequences.push(sequence);
+    }
+
+    sequences
+}
+
+fn resolve_sequence(
+    sequence: &[usize],
+    text: &[char],
+    original: &[BidiClass],
+    classes: &mut [BidiClass],
+    levels: &mut [u8],
+    para: u8,
+) {
+    let Some(&first) = sequence.first() else { return };
+    let last = sequence[sequence.len() - 1];
+    let level = levels[first];
+
+    // Levels of the neighbouring non-removed characters decide sos and eos
+    let before = (0..first).rev().find(|&i| !is_removed(original[i])).map(|i| levels[i]).unwrap_or(para);
+    let after = if is_isolate_initiator(original[last]) {
+        para
+    } else {
+        (last + 1..original.len()).find(|&i| !is_removed(original[i])).map(|i| levels[i]).unwrap_or(para)
+    };
+    let sos = direction(level.max(before));
+    let eos = direction(level.max(after));
+
+    let mut types: Vec<BidiClass> = sequence.iter().map(|&i| classes[i]).collect();
+    let len = types.len();
+
+    // W1: non-spacing marks take the type of what precedes them
+    for k in 0..len {
+        if types[k] == NSM {
+            types[k] = match k.checked_sub(1).map(|p| types[p]) {
+                None => sos,
+                Some(prev) if is_isolate_initiator(prev) || prev == PDI => ON,
+                Some(prev) => prev,
+            };
+        }
+    }
+
+    // W2, W3: European numbers after Arabic letters are Arabic numbers
+    let mut last_strong = sos;
+    for t in types.iter_mut() {
+        match *t {
+            L | R => last_strong = *t,
+            AL => {
+                last_strong = AL;
+                *t = R;
+            }
+            EN if last_strong == AL => *t = AN,
+            _ => {}
+        }
+    }
+
+    // W4: single separators between numbers
+    for k in 1..len.saturating_sub(1) {
+        let (prev, next) = (types[k - 1], types[k + 1]);
+        match types[k] {
+            ES if prev == EN && next == EN => types[k] = EN,
+            CS if prev == next && matches!(prev, EN | AN) => types[k] = prev,
+            _ => {}
+        }
+    }
+
+    // W5: terminators adjacent to European numbers
+    let mut k = 0;
+    while k < len {
+        if types[k] != ET {
+            k += 1;
+            continue;
+        }
+        let start = k;
+        while k < len && types[k] == ET {
+            k += 1;
+        }
+        let touches_en = (start > 0 && types[start - 1] == EN) || (k < len && types[k] == EN);
+        if touches_en {
+            for t in &mut types[start..k] {
+                *t = EN;
+            }
+        }
+    }
+
+    // W6: remaining separators and terminators are neutral
+    for t in types.iter_mut() {
+        if matches!(*t, ES | ET | CS) {
+            *t = ON;
+        }
+    }
+
+    // W7: European numbers in a left-to-right context
+    let mut last_strong = sos;
+    for t in types.iter_mut() {
+        match *t {
+            L | R => last_strong = *t,
+            EN if last_strong == L => *t = L,
+            _ => {}
+        }
+    }
+
+    let embedding = direction(level);
+
+    // N0: paired brackets
+    resolve_brackets(sequence, text, original, &mut types, sos, embedding);
+
+    // N1, N2: neutrals take the surrounding direction, else the embedding one
+    let strong = |t: BidiClass| match t {
+        L => Some(L),
+        R | EN | AN => Some(R),
+        _ => None,
+    };
+    let mut k = 0;
+    while k < len {
+        if !is_neutral_or_isolate(types[k]) {
+            k += 1;
+            continue;
+        }
+        let start = k;
+        while k < len && is_neutral_or_isolate(types[k]) {
+            k += 1;
+        }
+        let prev = if start == 0 { sos } else { strong(types[start - 1]).unwrap_or(embedding) };
+        let next = if k == len { eos } else { strong(types[k]).unwrap_or(embedding) };
+        let resolved = if prev == next { prev } else { embedding };
+        for t in &mut types[start..k] {
+            *t = resolved;
+        }
+    }
+
+    // I1, I2: implicit levels
+    for (k, &i) in sequence.iter().enumerate() {
+        let bump = match (level & 1 == 0, types[k]) {
+            (true, R) => 1,
+            (true, AN | EN) => 2,
+            (false, L | EN | AN) => 1,
+            _ => 0,
+        };
+        levels[i] = level + bump;
+        classes[i] = types[k];
+    }
+}
+
+/// Resolves bracket pairs within an isolating run sequence (N0).
+fn resolve_brackets(
+    sequence: &[usize],
+    text: &[char],
+    original: &[BidiClass],
+    types: &mut [BidiClass],
+    sos: BidiClass,
+    embedding: BidiClass,
+) {
+    // BD16: identify bracket pairs
+    let mut stack: Vec<(char, usize)> = Vec::new();
+    let mut pairs: Vec<(usize, usize)> = Vec::new();
+    for (k, &i) in sequence.iter().enumerate() {
+        if types[k] != ON {
+            continue;
+        }
+        let c = canonical_bracket(text[i]);
+        if is_opening_bracket(c) {
+            if stack.len() == MAX_BRACKET_STACK {
+                break;
+            }
+            stack.push((c, k));
+        } else if let Some(open) = opening_bracket(c).map(canonical_bracket)
+            && let Some(depth) = stack.iter().rposition(|&(o, _)| o == open)
+        {
+            pairs.push((stack[depth].1, k));
+            stack.truncate(depth);
+        }
+    }
+    pairs.sort_unstable();
+
+    let strong = |t: BidiClass| match t {
+        L => Some(L),
+        R | EN | AN => Some(R),
+        _ => None,
+    };
+
+    for (open, close) in pairs {
+        let mut found_embedding = false;
+        let mut found_opposite = false;
+        for t in &types[open + 1..close] {
+            match strong(*t) {
+                Some(d) if d == embedding => found_embedding = true,
+                Some(_) => found_opposite = true,
+                None => {}
+            }
+        }
+
+        let resolved = if found_embedding {
+            embedding
+        } else if found_opposite {
+            let context = types[..open].iter().rev().find_map(|t| strong(*t)).unwrap_or(sos);
+            if context != embedding { context } else { embedding }
+        } else {
+            continue;
+        };
+
+        for k in [open, close] {
+            types[k] = resolved;
+            // Marks following a resolved bracket take its direction
+            let mut m = k + 1;
+            while m < types.len() && original[sequence[m]] == NSM {
+                types[m] = resolved;
+                m += 1;
+            }
+        }
+    }
+}
+
+/// Visual order of runs with the given embedding levels (L2): every maximal
+/// sequence at or above each level, from the highest down to the lowest odd
+/// one, is reversed.
+pub(crate) fn reorder_runs(levels: &[u8]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..levels.len()).collect();
+    let Some(&max) = levels.iter().max() else { return order };
+    let min_odd = levels.iter().copied().filter(|l| l % 2 == 1).min().unwrap_or(max + 1).max(1);
+
+    let mut level = max;
+    while level >= min_odd && level > 0 {
+        let mut k = 0;
+        while k < order.len() {
+            if levels[order[k]] < level {
+                k += 1;
+                continue;
+            }
+            let start = k;
+            while k < order.len() && levels[order[k]] >= level {
+                k += 1;
+            }
+            order[start..k].reverse();
+        }
+        level -= 1;
+    }
+
+    order
+}
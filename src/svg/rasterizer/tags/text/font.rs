@@ -0,0 +1,360 @@
+use crate::svg::rasterizer::tags::path::PathCommand;
+use crate::svg::rasterizer::tags::text::opentype::{Gdef, LayoutTable};
+use crate::svg::rasterizer::tags::text::outline::GlyphOutlines;
+use crate::utils::compat::{String, Vec};
+
+const DEFAULT_FONT: &[u8] = include_bytes!("../../../../../fonts/CaskaydiaMonoNerdFontMono-Regular.ttf");
+
+/// A loaded font: views over the raw sfnt tables it is laid out and drawn
+/// with (character mapping, horizontal metrics, vertical font metrics, pair
+/// kerning, OpenType layout tables and unflattened glyph outlines).
+#[derive(Clone)]
+pub(crate) struct FontFace {
+    pub(crate) units_per_em: f32,
+    pub(crate) ascender: f32,
+    pub(crate) descender: f32,
+    pub(crate) line_gap: f32,
+    pub(crate) x_height: f32,
+    cmap: &'static [u8],
+    cmap_format: u16,
+    hmtx: &'static [u8],
+    num_h_metrics: u16,
+    /// Pairs of the legacy `kern` table, sorted by left and right glyph.
+    kern_pairs: &'static [u8],
+    pub(crate) gsub: Option<LayoutTable<'static>>,
+    pub(crate) gpos: Option<LayoutTable<'static>>,
+    pub(crate) gdef: Option<Gdef<'static>>,
+    outlines: Option<GlyphOutlines>,
+}
+
+impl FontFace {
+    pub(crate) fn load(data: &'static [u8]) -> Option<Self> {
+        let head = find_table(data, b"head")?;
+        let hhea = find_table(data, b"hhea")?;
+        let hmtx = find_table(data, b"hmtx")?;
+
+        let units_per_em = match read_u16(head, 18) {
+            0 => 1000.0,
+            u => u as f32,
+        };
+        let ascender = read_i16(hhea, 4) as f32;
+        let descender = read_i16(hhea, 6) as f32;
+        let line_gap = read_i16(hhea, 8) as f32;
+        let num_h_metrics = read_u16(hhea, 34);
+
+        // sxHeight only exists from OS/2 version 2 onwards
+        let x_height = find_table(data, b"OS/2")
+            .filter(|os2| read_u16(os2, 0) >= 2 && os2.len() >= 88)
+            .map(|os2| read_i16(os2, 86) as f32)
+            .filter(|h| *h > 0.0)
+            .unwrap_or(ascender * 0.5);
+
+        let (cmap, cmap_format) = find_table(data, b"cmap")
+            .and_then(select_cmap_subtable)
+            .unwrap_or((&[], 0));
+
+        let kern_pairs = find_table(data, b"kern").map(kern_pairs).unwrap_or(&[]);
+        let gsub = find_table(data, b"GSUB").and_then(LayoutTable::new);
+        let gpos = find_table(data, b"GPOS").and_then(LayoutTable::new);
+        let gdef = find_table(data, b"GDEF").and_then(Gdef::new);
+
+        let long_loca = read_i16(head, 50) == 1;
+        let outlines = find_table(data, b"glyf")
+            .zip(find_table(data, b"loca"))
+            .map(|(glyf, loca)| GlyphOutlines::new(glyf, loca, long_loca));
+
+        Some(Self {
+            units_per_em,
+            ascender,
+            descender,
+            line_gap,
+            x_height,
+            cmap,
+            cmap_format,
+            hmtx,
+            num_h_metrics,
+            kern_pairs,
+            gsub,
+            gpos,
+            gdef,
+            outlines,
+        })
+    }
+
+    /// Factor converting font units to user units at `font_size`.
+    #[inline]
+    pub(crate) fn scale(&self, font_size: f32) -> f32 {
+        font_size / self.units_per_em
+    }
+
+    /// Whether the font maps `c` to a glyph other than `.notdef`.
+    pub(crate) fn has_glyph(&self, c: char) -> bool {
+        self.glyph_id(c) != 0
+    }
+
+    pub(crate) fn glyph_id(&self, c: char) -> u16 {
+        let code = c as u32;
+        match self.cmap_format {
+            4 => cmap_format4_lookup(self.cmap, code),
+            12 => cmap_format12_lookup(self.cmap, code),
+            _ => 0,
+        }
+    }
+
+    /// Horizontal advance of a glyph in font units.
+    pub(crate) fn glyph_advance(&self, glyph_id: u16) -> f32 {
+        if self.num_h_metrics == 0 {
+            return 0.0;
+        }
+        let idx = (glyph_id as usize).min(self.num_h_metrics as usize - 1);
+        read_u16(self.hmtx, idx * 4) as f32
+    }
+
+    /// Pair adjustment from the legacy `kern` table, in font units.
+    pub(crate) fn legacy_kerning(&self, left: u16, right: u16) -> f32 {
+        let key = (left as u32) << 16 | right as u32;
+        let (mut lo, mut hi) = (0, self.kern_pairs.len() / 6);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            let pair = read_u32(self.kern_pairs, mid * 6);
+            if key < pair {
+                hi = mid;
+            } else if key > pair {
+                lo = mid + 1;
+            } else {
+                return read_i16(self.kern_pairs, mid * 6 + 4) as f32;
+            }
+        }
+        0.0
+    }
+
+    /// Outline of a glyph in font units (y up); empty for fonts without `glyf` outlines.
+    pub(crate) fn glyph_outline(&self, glyph_id: u16) -> Vec<PathCommand> {
+        self.outlines.as_ref().map(|o| o.outline(glyph_id)).unwrap_or_default()
+    }
+
+}
+
+/// A TrueType font text can be set in, known by the family names of its
+/// `name` table.
+///
+/// Fonts are added to a document with
+/// [`SvgDocument::add_font`](crate::svg::SvgDocument::add_font); a font
+/// that is always available is bundled.
+#[derive(Clone)]
+pub struct Font {
+    pub(crate) face: FontFace,
+    families: Vec<String>,
+}
+
+impl Font {
+    /// Loads a TrueType font, or `None` when `data` lacks the tables text
+    /// needs. The data is borrowed for good, as from `include_bytes!`.
+    pub fn from_bytes(data: &'static [u8]) -> Option<Self> {
+        let face = FontFace::load(data)?;
+        let families = find_table(data, b"name").map(family_names).unwrap_or_default();
+        Some(Self { face, families })
+    }
+
+    /// The font used for characters no other font has.
+    pub(crate) fn bundled() -> Option<Self> {
+        Self::from_bytes(DEFAULT_FONT)
+    }
+
+    /// Family names `font-family` can select the font by.
+    pub fn families(&self) -> &[String] {
+        &self.families
+    }
+
+    pub(crate) fn is_family(&self, family: &str) -> bool {
+        self.families.iter().any(|f| f.eq_ignore_ascii_case(family))
+    }
+}
+
+impl core::fmt::Debug for Font {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Font").field("families", &self.families).finish()
+    }
+}
+
+/// Family names of a `name` table: typographic family names, then legacy
+/// ones, in Unicode or Mac Roman (read as Latin-1) encodings.
+fn family_names(name: &[u8]) -> Vec<String> {
+    let count = read_u16(name, 2) as usize;
+    let strings = read_u16(name, 4) as usize;
+
+    let mut families: Vec<String> = Vec::new();
+    for name_id in [16, 1] {
+        for i in 0..count {
+            let record = 6 + i * 12;
+            if read_u16(name, record + 6) != name_id {
+                continue;
+            }
+            let length = read_u16(name, record + 8) as usize;
+            let offset = strings + read_u16(name, record + 10) as usize;
+            let Some(bytes) = name.get(offset..offset + length) else { continue };
+
+            let family: String = match (read_u16(name, record), read_u16(name, record + 2)) {
+                (0, _) | (3, 0 | 1 | 10) => char::decode_utf16(bytes.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]])))
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect(),
+                (1, 0) => bytes.iter().map(|&b| b as char).collect(),
+                _ => continue,
+            };
+            if !family.is_empty() && !families.iter().any(|f| f.eq_ignore_ascii_case(&family)) {
+                families.push(family);
+            }
+        }
+    }
+    families
+}
+
+/// Locates a table in the sfnt table directory.
+pub(crate) fn find_table<'a>(data: &'a [u8], tag: &[u8; 4]) -> Option<&'a [u8]> {
+    let num_tables = read_u16(data, 4) as usize;
+
+    for i in 0..num_tables {
+        let record = 12 + i * 16;
+        if record + 16 > data.len() {
+            return None;
+        }
+        if &data[record..record + 4] == tag {
+            let offset = read_u32(data, record + 8) as usize;
+            let length = read_u32(data, record + 12) as usize;
+            return data.get(offset..offset.checked_add(length)?);
+        }
+    }
+
+    None
+}
+
+/// The pair records of the first horizontal format 0 subtable of a `kern`
+/// table. Apple's version 1 tables and other formats are not supported.
+fn kern_pairs(kern: &[u8]) -> &[u8] {
+    if read_u16(kern, 0) != 0 {
+        return &[];
+    }
+
+    let mut subtable = 4;
+    for _ in 0..read_u16(kern, 2) {
+        let length = read_u16(kern, subtable + 2) as usize;
+        let coverage = read_u16(kern, subtable + 4);
+        // Horizontal kerning values, rather than minimums or cross-stream shifts
+        if coverage >> 8 == 0 && coverage & 0x0F == 0x01 {
+            let count = read_u16(kern, subtable + 6) as usize;
+            return kern.get(subtable + 14..subtable + 14 + count * 6).unwrap_or(&[]);
+        }
+        if length == 0 {
+            break;
+        }
+        subtable += length;
+    }
+    &[]
+}
+
+fn select_cmap_subtable(cmap: &[u8]) -> Option<(&[u8], u16)> {
+    let num_tables = read_u16(cmap, 2) as usize;
+    let mut best: Option<(&[u8], u16, u8)> = None;
+
+    for i in 0..num_tables {
+        let record = 4 + i * 8;
+        let platform = read_u16(cmap, record);
+        let encoding = read_u16(cmap, record + 2);
+        let offset = read_u32(cmap, record + 4) as usize;
+        let Some(subtable) = cmap.get(offset..) else { continue };
+        let format = read_u16(subtable, 0);
+
+        // Prefer full Unicode repertoires over BMP-only ones
+        let rank = match (platform, encoding, format) {
+            (3, 10, 12) | (0, 4, 12) | (0, 6, 12) => 3,
+            (3, 1, 4) | (0, 3, 4) => 2,
+            (0, _, 4) => 1,
+            _ => continue,
+        };
+
+        if best.map(|(_, _, r)| rank > r).unwrap_or(true) {
+            best = Some((subtable, format, rank));
+        }
+    }
+
+    best.map(|(subtable, format, _)| (subtable, format))
+}
+
+fn cmap_format4_lookup(subtable: &[u8], code: u32) -> u16 {
+    if code > 0xFFFF {
+        return 0;
+    }
+    let code = code as u16;
+    let seg_x2 = read_u16(subtable, 6) as usize;
+    let end_codes = 14;
+    let start_codes = end_codes + seg_x2 + 2;
+    let id_deltas = start_codes + seg_x2;
+    let id_range_offsets = id_deltas + seg_x2;
+
+    for seg in (0..seg_x2).step_by(2) {
+        let end = read_u16(subtable, end_codes + seg);
+        if code > end {
+            continue;
+        }
+        let start = read_u16(subtable, start_codes + seg);
+        if code < start {
+            return 0;
+        }
+
+        let delta = read_u16(subtable, id_deltas + seg);
+        let range_offset = read_u16(subtable, id_range_offsets + seg) as usize;
+
+        if range_offset == 0 {
+            return code.wrapping_add(delta);
+        }
+
+        let glyph_addr = id_range_offsets + seg + range_offset + 2 * (code - start) as usize;
+        let glyph = read_u16(subtable, glyph_addr);
+        return if glyph == 0 { 0 } else { glyph.wrapping_add(delta) };
+    }
+
+    0
+}
+
+fn cmap_format12_lookup(subtable: &[u8], code: u32) -> u16 {
+    let num_groups = read_u32(subtable, 12) as usize;
+
+    let (mut lo, mut hi) = (0, num_groups);
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        let group = 16 + mid * 12;
+        let start = read_u32(subtable, group);
+        let end = read_u32(subtable, group + 4);
+
+        if code < start {
+            hi = mid;
+        } else if code > end {
+            lo = mid + 1;
+        } else {
+            return (read_u32(subtable, group + 8) + (code - start)) as u16;
+        }
+    }
+
+    0
+}
+
+#[inline]
+pub(crate) fn read_u16(data: &[u8], offset: usize) -> u16 {
+    match data.get(offset..offset + 2) {
+        Some(b) => u16::from_be_bytes([b[0], b[1]]),
+        None => 0,
+    }
+}
+
+#[inline]
+pub(crate) fn read_i16(data: &[u8], offset: usize) -> i16 {
+    read_u16(data, offset) as i16
+}
+
+#[inline]
+pub(crate) fn read_u32(data: &[u8], offset: usize) -> u32 {
+    match data.get(offset..offset + 4) {
+        Some(b) => u32::from_be_bytes([b[0], b[1], b[2], b[3]]),
+        None => 0,
+    }
+}
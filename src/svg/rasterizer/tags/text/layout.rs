@@ -0,0 +1,994 @@
+use core::ops::Range;
+use crate::svg::parser::tags::Tag;
+use crate::svg::rasterizer::tags::text::bidi::{
+    bidi_class, reorder_runs, resolve_levels, BidiClass, FSI_CHAR, LRE_CHAR, LRI_CHAR, LRO_CHAR, PDF_CHAR,
+    PDI_CHAR, RLE_CHAR, RLI_CHAR, RLO_CHAR,
+};
+use crate::svg::rasterizer::tags::text::font::{Font, FontFace};
+use crate::svg::rasterizer::tags::text::shaping::{char_script, is_default_ignorable, shape, Script};
+use crate::svg::rasterizer::tags::text::text_path::{load_text_path, TextPath};
+use crate::svg::rasterizer::tags::text::wrap::{break_lines, load_text_area, TextArea};
+use crate::svg::utils::coords::{Axis, LengthContext};
+use crate::svg::utils::style::get_property;
+use crate::utils::compat::{ToString, Vec, vec};
+#[cfg(not(feature = "std"))]
+use crate::utils::compat::FloatExt;
+use crate::svg::utils::Defs;
+
+/// Presentation attributes that `<tspan>` children inherit from their ancestors.
+const INHERITED: &[&str] = &[
+    "fill",
+    "stroke",
+    "stroke-width",
+    "font-size",
+    "font-family",
+    "text-anchor",
+    "dominant-baseline",
+    "letter-spacing",
+    "word-spacing",
+    "font-kerning",
+    "direction",
+    "line-height",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum TextAnchor {
+    Start,
+    Middle,
+    End,
+}
+
+/// Resolved style of one text content element (`<text>` or `<tspan>`).
+pub(crate) struct TextSpan {
+    /// Carries the inherited presentation attributes, so the usual
+    /// `get_fill`/`get_stroke` helpers work on it.
+    pub(crate) style: Tag,
+    pub(crate) font_size: f32,
+    pub(crate) anchor: TextAnchor,
+    /// Vertical offset from the alphabetic baseline (positive is down).
+    pub(crate) baseline_offset: f32,
+    /// Extra advance after every character, in user units.
+    pub(crate) letter_spacing: f32,
+    /// Extra advance after every space character, in user units.
+    pub(crate) word_spacing: f32,
+    /// Whether pair kerning from the font is applied (`font-kerning`).
+    pub(crate) kerning: bool,
+    /// Whether the element's inline direction is right-to-left (`direction`).
+    pub(crate) rtl: bool,
+    /// Distance between the baselines of wrapped lines, in user units.
+    pub(crate) line_height: f32,
+    /// Fonts the characters are looked up in, in order of preference
+    /// (`font-family`); the first one sets the span's metrics.
+    pub(crate) fonts: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct PositionedGlyph {
+    pub(crate) glyph_id: u16,
+    /// Index of the first character the glyph renders.
+    pub(crate) cluster: usize,
+    /// Pen position on the alphabetic baseline, in user units.
+    pub(crate) x: f32,
+    pub(crate) y: f32,
+    pub(crate) advance: f32,
+    pub(crate) span: usize,
+    /// Index of the font the glyph comes from.
+    pub(crate) font: usize,
+    /// Rotation of the glyph about its origin, in radians (glyphs on a `<textPath>`).
+    pub(crate) rotate: f32,
+    /// Index into `TextLayout::paths` for glyphs warped along a path with
+    /// `method="stretch"`; `x` is then the distance along the path and `y`
+    /// the offset along its normal.
+    pub(crate) warp: Option<usize>,
+}
+
+pub(crate) struct TextLayout {
+    pub(crate) spans: Vec<TextSpan>,
+    pub(crate) glyphs: Vec<PositionedGlyph>,
+    pub(crate) paths: Vec<TextPath>,
+}
+
+/// An addressable character with the element that styles it.
+#[derive(Clone, Copy)]
+struct TextChar {
+    c: char,
+    span: usize,
+    /// Index of the font that renders the character.
+    font: usize,
+    /// Index of the enclosing `<textPath>`.
+    path: Option<usize>,
+}
+
+/// Per-character positioning lists of one element, applied to the
+/// characters it contains (`x`, `y`, `dx`, `dy`).
+struct PositionLists {
+    start: usize,
+    end: usize,
+    x: Vec<f32>,
+    y: Vec<f32>,
+    dx: Vec<f32>,
+    dy: Vec<f32>,
+}
+
+/// Characters of an element with a `unicode-bidi` value other than `normal`,
+/// and the explicit bidi controls that enclose them.
+struct Embedding {
+    start: usize,
+    end: usize,
+    open: &'static [char],
+    close: &'static [char],
+}
+
+struct Collector<'a> {
+    fonts: &'a [&'a Font],
+    defs: &'a Defs<'a>,
+    /// Context of the text element.
+    lengths: &'a LengthContext,
+    chars: Vec<TextChar>,
+    spans: Vec<TextSpan>,
+    lists: Vec<PositionLists>,
+    paths: Vec<TextPath>,
+    embeddings: Vec<Embedding>,
+    current_path: Option<usize>,
+    pending_space: Option<(usize, Option<usize>)>,
+}
+
+/// Lays out a `<text>` element: collapses white space, resolves `<tspan>`
+/// styles and positioning lists, splits the result into text chunks and
+/// applies `text-anchor` and baseline alignment to each of them. Characters
+/// inside a `<textPath>` are finally placed along its path. Lengths resolve
+/// in `lengths`, the context of the text element itself. Every character is
+/// set in the first font of its `font-family` that has it, falling back to
+/// the other `fonts` in order.
+pub(crate) fn layout_text(
+    tag: &Tag,
+    fonts: &[&Font],
+    defs: &Defs,
+    lengths: &LengthContext,
+) -> TextLayout {
+    let mut collector = Collector {
+        fonts,
+        defs,
+        lengths,
+        chars: Vec::new(),
+        spans: Vec::new(),
+        lists: Vec::new(),
+        paths: Vec::new(),
+        embeddings: Vec::new(),
+        current_path: None,
+        pending_space: None,
+    };
+
+    collector.collect(tag, None);
+
+    let Collector { chars, spans, lists, paths, embeddings, .. } = collector;
+    let count = chars.len();
+
+    let mut abs_x: Vec<Option<f32>> = vec![None; count];
+    let mut abs_y: Vec<Option<f32>> = vec![None; count];
+    let mut rel_x = vec![0.0f32; count];
+    let mut rel_y = vec![0.0f32; count];
+
+    // Lists were recorded in pre-order, so inner elements override outer ones
+    for list in &lists {
+        let end = list.end.min(count);
+        for (k, i) in (list.start..end).enumerate() {
+            if let Some(v) = list.x.get(k) { abs_x[i] = Some(*v); }
+            if let Some(v) = list.y.get(k) { abs_y[i] = Some(*v); }
+            if let Some(v) = list.dx.get(k) { rel_x[i] = *v; }
+            if let Some(v) = list.dy.get(k) { rel_y[i] = *v; }
+        }
+    }
+
+    // `unicode-bidi: plaintext` on the text element derives the direction from its content
+    let rtl = spans.first().map(|s| s.rtl).unwrap_or(false);
+    let base_level = match get_property(tag, "unicode-bidi") {
+        Some("plaintext") => None,
+        _ => Some(rtl as u8),
+    };
+
+    // Text areas wrap the whole text as one bidi paragraph; text paths are never wrapped
+    let area = load_text_area(tag, defs, lengths)
+        .filter(|_| chars.iter().all(|ch| ch.path.is_none()));
+    let paragraph_levels = area.as_ref().map(|_| chunk_levels(&chars, &embeddings, 0, count, base_level));
+
+    let chunks: Vec<Range<usize>> = match (&area, &paragraph_levels) {
+        (Some(area), Some(levels)) => {
+            let origin = (abs_x.first().copied().flatten().unwrap_or(0.0), abs_y.first().copied().flatten().unwrap_or(0.0));
+            let lines = wrap_lines(fonts, &chars, &spans, levels, area, origin, rtl);
+
+            // Only the first x and y position text in an area; lines start at their own origins
+            abs_x.fill(None);
+            abs_y.fill(None);
+            rel_x.fill(0.0);
+            rel_y.fill(0.0);
+            for (line, x, y) in &lines {
+                abs_x[line.start] = Some(*x);
+                abs_y[line.start] = Some(*y);
+            }
+            lines.into_iter().map(|(line, ..)| line).collect()
+        }
+        _ => {
+            let starts: Vec<usize> = (0..count)
+                .filter(|&i| i == 0 || chars[i].path != chars[i - 1].path || abs_x[i].is_some() || abs_y[i].is_some())
+                .collect();
+            starts.iter().enumerate()
+                .map(|(n, &start)| start..starts.get(n + 1).copied().unwrap_or(count))
+                .collect()
+        }
+    };
+
+    let mut glyphs = Vec::with_capacity(count);
+    // First glyph of every chunk
+    let mut glyph_chunks = Vec::with_capacity(chunks.len());
+    // Chunks that continue after a text path: (chunk, path, normal offset)
+    let mut resumes = Vec::new();
+    let mut pen_x = 0.0;
+    let mut pen_y = 0.0;
+
+    for (n, chunk) in chunks.iter().enumerate() {
+        let (start, end) = (chunk.start, chunk.end);
+        let path = chars[start].path;
+        let prev_path = if start == 0 { None } else { chars[start - 1].path };
+
+        if path != prev_path {
+            if let Some(p) = path {
+                // Inside a text path the pen measures distance along the path
+                pen_x = paths[p].start_offset;
+                pen_y = 0.0;
+            } else if let Some(p) = prev_path {
+                // Resolved once the path text has been anchored
+                resumes.push((n, p, pen_y));
+                pen_x = 0.0;
+                pen_y = 0.0;
+            }
+        }
+        if let Some(x) = abs_x[start] { pen_x = x; }
+        if let Some(y) = abs_y[start] { pen_y = y; }
+
+        glyph_chunks.push(glyphs.len());
+
+        let levels = match &paragraph_levels {
+            Some(levels) => levels[start..end].to_vec(),
+            None => chunk_levels(&chars, &embeddings, start, end, base_level),
+        };
+        let runs = shape_chunk(fonts, &chars, &spans, &levels, start);
+        let run_levels: Vec<u8> = runs.iter().map(|r| r.level).collect();
+
+        for r in reorder_runs(&run_levels) {
+            let run = &runs[r];
+            let base = glyphs.len();
+
+            for (k, g) in run.glyphs.iter().enumerate() {
+                if k == 0 || run.glyphs[k - 1].cluster != g.cluster {
+                    pen_x += rel_x[g.cluster];
+                    pen_y += rel_y[g.cluster];
+                }
+
+                let span_idx = chars[g.cluster].span;
+                glyphs.push(PositionedGlyph {
+                    glyph_id: g.id,
+                    cluster: g.cluster,
+                    x: pen_x + g.x_offset,
+                    y: pen_y + spans[span_idx].baseline_offset + g.y_offset,
+                    advance: g.advance,
+                    span: span_idx,
+                    font: chars[g.cluster].font,
+                    rotate: 0.0,
+                    warp: None,
+                });
+
+                pen_x += g.advance + g.kern;
+            }
+
+            // Attached marks are offset from their base glyph rather than the pen
+            for (k, g) in run.glyphs.iter().enumerate() {
+                if let Some(a) = g.attach {
+                    let (bx, by) = (glyphs[base + a].x, glyphs[base + a].y);
+                    glyphs[base + k].x = bx + g.x_offset;
+                    glyphs[base + k].y = by + g.y_offset;
+                }
+            }
+        }
+    }
+
+    for (n, &start) in glyph_chunks.iter().enumerate() {
+        let end = glyph_chunks.get(n + 1).copied().unwrap_or(glyphs.len());
+        apply_anchor(&mut glyphs[start..end], &spans, rtl);
+    }
+
+    // Text after a path continues where the path text ended
+    for &(n, p, offset) in &resumes {
+        let start = glyph_chunks[n];
+        let Some(last) = start.checked_sub(1).map(|i| &glyphs[i]) else { continue };
+        let (ex, ey) = paths[p].path.map(last.x + last.advance, offset);
+        let end = glyph_chunks.get(n + 1).copied().unwrap_or(glyphs.len());
+        let first_char = chunks[n].start;
+        for g in &mut glyphs[start..end] {
+            if abs_x[first_char].is_none() { g.x += ex; }
+            if abs_y[first_char].is_none() { g.y += ey; }
+        }
+    }
+
+    let glyphs = glyphs.into_iter()
+        .filter_map(|glyph| match chars[glyph.cluster].path {
+            Some(p) => place_on_path(glyph, p, &paths[p]),
+            None => Some(glyph),
+        })
+        .collect();
+
+    TextLayout { spans, glyphs, paths }
+}
+
+/// Breaks the text into lines that fit `area` and places them: the range of
+/// every line with its anchor point and baseline. Lines are reordered and
+/// shaped again afterwards, as bidi reordering happens line by line.
+fn wrap_lines(
+    fonts: &[&Font],
+    chars: &[TextChar],
+    spans: &[TextSpan],
+    levels: &[u8],
+    area: &TextArea,
+    origin: (f32, f32),
+    rtl: bool,
+) -> Vec<(Range<usize>, f32, f32)> {
+    let mut widths = vec![0.0; chars.len()];
+    for run in shape_chunk(fonts, chars, spans, levels, 0) {
+        for g in &run.glyphs {
+            widths[g.cluster] += g.advance + g.kern;
+        }
+    }
+
+    let text: Vec<char> = chars.iter().map(|ch| ch.c).collect();
+    let anchor = spans.first().map(|s| resolve_anchor(s.anchor, rtl)).unwrap_or(TextAnchor::Start);
+    let (max_width, x) = match *area {
+        TextArea::Inline(size) => (size, origin.0),
+        TextArea::Rect { x, width, .. } => (width, match anchor {
+            TextAnchor::Start => x,
+            TextAnchor::Middle => x + width / 2.0,
+            TextAnchor::End => x + width,
+        }),
+    };
+
+    let mut placed = Vec::new();
+    let mut baseline = origin.1;
+    let mut top = match *area {
+        TextArea::Rect { y, .. } => y,
+        TextArea::Inline(_) => 0.0,
+    };
+
+    for (k, line) in break_lines(&text, &widths, max_width).into_iter().enumerate() {
+        let line_spans = || chars[line.clone()].iter().map(|ch| &spans[ch.span]);
+        let fallback = spans.first().map(|s| s.line_height).unwrap_or(0.0);
+        let line_height = line_spans().map(|s| s.line_height).fold(fallback, f32::max);
+
+        match *area {
+            TextArea::Inline(_) => {
+                if k > 0 {
+                    baseline += line_height;
+                }
+            }
+            TextArea::Rect { y, height, .. } => {
+                if top + line_height > y + height {
+                    break;
+                }
+                // Half-leading above the tallest glyphs of the line
+                let face = |s: &TextSpan| &fonts[s.fonts[0]].face;
+                let ascent = line_spans().map(|s| face(s).ascender * face(s).scale(s.font_size)).fold(0.0, f32::max);
+                let descent = line_spans().map(|s| face(s).descender * face(s).scale(s.font_size)).fold(0.0, f32::min);
+                baseline = top + (line_height - (ascent - descent)) / 2.0 + ascent;
+                top += line_height;
+            }
+        }
+
+        if !line.is_empty() {
+            placed.push((line, x, baseline));
+        }
+    }
+
+    placed
+}
+
+/// A shaped run in visual order, in user units with y pointing down.
+struct ShapedRun {
+    level: u8,
+    glyphs: Vec<RunGlyph>,
+}
+
+struct RunGlyph {
+    id: u16,
+    cluster: usize,
+    /// Advance including letter and word spacing.
+    advance: f32,
+    /// Pair adjustment before the next glyph in visual order.
+    kern: f32,
+    x_offset: f32,
+    y_offset: f32,
+    /// Index within the run of the base glyph a mark is attached to.
+    attach: Option<usize>,
+}
+
+/// Embedding levels of the characters of one chunk, with the explicit bidi
+/// controls of `unicode-bidi` elements wrapped around their content.
+fn chunk_levels(chars: &[TextChar], embeddings: &[Embedding], start: usize, end: usize, base_level: Option<u8>) -> Vec<u8> {
+    let active: Vec<&Embedding> = embeddings.iter()
+        .filter(|e| e.start < e.end && e.start < end && e.end > start)
+        .collect();
+
+    let mut text = Vec::with_capacity(end - start);
+    let mut index = Vec::with_capacity(end - start);
+    for (i, ch) in chars.iter().enumerate().take(end).skip(start) {
+        // Inner elements close first
+        for e in active.iter().rev().filter(|e| e.end == i) {
+            text.extend_from_slice(e.close);
+        }
+        for e in active.iter().filter(|e| e.start.max(start) == i) {
+            text.extend_from_slice(e.open);
+        }
+        index.push(text.len());
+        text.push(ch.c);
+    }
+
+    let levels = resolve_levels(&text, base_level);
+    index.into_iter().map(|k| levels[k]).collect()
+}
+
+/// Splits a chunk into runs of one level, script, font and font size and
+/// shapes them. Runs come back in logical order.
+fn shape_chunk(fonts: &[&Font], chars: &[TextChar], spans: &[TextSpan], levels: &[u8], start: usize) -> Vec<ShapedRun> {
+    let mut scripts: Vec<Script> = levels.iter().enumerate()
+        .map(|(k, _)| char_script(chars[start + k].c))
+        .collect();
+
+    // Common characters (spaces, digits, punctuation) join the script around them
+    if let Some(first) = scripts.iter().copied().find(|s| *s != Script::Common) {
+        let mut current = first;
+        for s in scripts.iter_mut() {
+            if *s == Script::Common {
+                *s = current;
+            } else {
+                current = *s;
+            }
+        }
+    }
+
+    let mut runs = Vec::new();
+    let mut run_start = 0;
+    for k in 1..=levels.len() {
+        let breaks = k == levels.len()
+            || levels[k] != levels[run_start]
+            || scripts[k] != scripts[run_start]
+            || chars[start + k].font != chars[start + run_start].font
+            || {
+            let (a, b) = (&spans[chars[start + k].span], &spans[chars[start + run_start].span]);
+            a.font_size != b.font_size || a.kerning != b.kerning
+        };
+        if breaks {
+            runs.push(shape_run(fonts, chars, spans, start + run_start..start + k, scripts[run_start], levels[run_start]));
+            run_start = k;
+        }
+    }
+    runs
+}
+
+fn shape_run(fonts: &[&Font], chars: &[TextChar], spans: &[TextSpan], range: Range<usize>, script: Script, level: u8) -> ShapedRun {
+    let span = &spans[chars[range.start].span];
+    let face = &fonts[chars[range.start].font].face;
+    let scale = face.scale(span.font_size);
+    let rtl = level % 2 == 1;
+
+    let input: Vec<(char, usize)> = range.map(|i| (chars[i].c, i)).collect();
+    let mut glyphs: Vec<RunGlyph> = shape(face, &input, script, rtl, span.kerning).iter()
+        .map(|g| {
+            let span = &spans[chars[g.cluster].span];
+            let mut advance = g.advance * scale;
+            if g.attach.is_none() && g.advance > 0.0 {
+                advance += span.letter_spacing;
+            }
+            if matches!(chars[g.cluster].c, ' ' | '\u{00A0}') {
+                advance += span.word_spacing;
+            }
+            RunGlyph {
+                id: g.id,
+                cluster: g.cluster,
+                advance,
+                kern: g.kern * scale,
+                x_offset: g.x_offset * scale,
+                y_offset: -g.y_offset * scale,
+                attach: g.attach,
+            }
+        })
+        .collect();
+
+    if rtl {
+        // A logical pair adjustment now sits before the glyph that precedes it visually
+        let count = glyphs.len();
+        glyphs.reverse();
+        for k in 0..count {
+            glyphs[k].kern = glyphs.get(k + 1).map(|g| g.kern).unwrap_or(0.0);
+            glyphs[k].attach = glyphs[k].attach.map(|a| count - 1 - a);
+        }
+    }
+
+    ShapedRun { level, glyphs }
+}
+
+/// Moves a glyph laid out in path space (`x` along the path, `y` across it)
+/// onto the path. Glyphs whose midpoint falls off either end are dropped.
+fn place_on_path(mut glyph: PositionedGlyph, path_idx: usize, text_path: &TextPath) -> Option<PositionedGlyph> {
+    if text_path.stretch {
+        glyph.warp = Some(path_idx);
+        return Some(glyph);
+    }
+
+    let half = glyph.advance / 2.0;
+    let (px, py, tx, ty) = text_path.path.sample(glyph.x + half)?;
+
+    // The glyph is rotated about its midpoint on the path
+    glyph.x = px - tx * half - ty * glyph.y;
+    glyph.y = py - ty * half + tx * glyph.y;
+    glyph.rotate = ty.atan2(tx);
+    Some(glyph)
+}
+
+/// Physical alignment of `anchor`: start and end swap in right-to-left text.
+fn resolve_anchor(anchor: TextAnchor, rtl: bool) -> TextAnchor {
+    match (anchor, rtl) {
+        (TextAnchor::Start, true) => TextAnchor::End,
+        (TextAnchor::End, true) => TextAnchor::Start,
+        (anchor, _) => anchor,
+    }
+}
+
+/// Aligns a chunk on its start position. In right-to-left text the start is
+/// the right edge.
+fn apply_anchor(chunk: &mut [PositionedGlyph], spans: &[TextSpan], rtl: bool) {
+    let Some(first) = chunk.first() else { return };
+    let anchor = resolve_anchor(spans[first.span].anchor, rtl);
+    if anchor == TextAnchor::Start {
+        return;
+    }
+
+    let mut min_x = f32::MAX;
+    let mut max_x = f32::MIN;
+    for g in chunk.iter() {
+        min_x = min_x.min(g.x).min(g.x + g.advance);
+        max_x = max_x.max(g.x).max(g.x + g.advance);
+    }
+
+    let shift = match anchor {
+        TextAnchor::Middle => -(max_x - min_x) / 2.0,
+        TextAnchor::End => -(max_x - min_x),
+        TextAnchor::Start => 0.0,
+    };
+
+    for g in chunk.iter_mut() {
+        g.x += shift;
+    }
+}
+
+impl Collector<'_> {
+    fn collect(&mut self, tag: &Tag, parent: Option<usize>) {
+        // A collapsed space before an element belongs to the text preceding it
+        self.flush_pending_space();
+
+        let span_idx = self.spans.len();
+        let span = self.resolve_span(tag, parent);
+        let lengths = self.lengths.with_font_size(span.font_size);
+        self.spans.push(span);
+
+        let list_idx = self.lists.len();
+        self.lists.push(PositionLists {
+            start: self.chars.len(),
+            end: usize::MAX,
+            x: parse_list(tag, "x", &lengths, Axis::Horizontal),
+            y: parse_list(tag, "y", &lengths, Axis::Vertical),
+            dx: parse_list(tag, "dx", &lengths, Axis::Horizontal),
+            dy: parse_list(tag, "dy", &lengths, Axis::Vertical),
+        });
+
+        // On the text element itself `plaintext` only selects the paragraph direction
+        let embedding = get_property(tag, "unicode-bidi")
+            .filter(|s| parent.is_some() || *s != "plaintext")
+            .and_then(|s| bidi_controls(s, self.spans[span_idx].rtl))
+            .map(|(open, close)| {
+                self.embeddings.push(Embedding { start: self.chars.len(), end: usize::MAX, open, close });
+                self.embeddings.len() - 1
+            });
+
+        let has_text_nodes = tag.children.iter().any(|c| c.is_text_node());
+        if !has_text_nodes && tag.children.is_empty() {
+            self.push_text(&tag.text_content, span_idx);
+        }
+
+        for child in &tag.children {
+            match child.name.as_str() {
+                "tspan" | "a" => self.collect(child, Some(span_idx)),
+                // Text paths do not nest, and one with no usable path is not rendered
+                "textPath" if self.current_path.is_none() => {
+                    if let Some(text_path) = load_text_path(child, self.defs, &lengths) {
+                        self.flush_pending_space();
+                        self.paths.push(text_path);
+                        self.current_path = Some(self.paths.len() - 1);
+                        self.collect(child, Some(span_idx));
+                        self.current_path = None;
+                    }
+                }
+                _ if child.is_text_node() => self.push_text(&child.text_content, span_idx),
+                _ => {}
+            }
+        }
+
+        self.lists[list_idx].end = self.chars.len();
+        if let Some(e) = embedding {
+            self.embeddings[e].end = self.chars.len();
+        }
+    }
+
+    fn push_text(&mut self, text: &str, span: usize) {
+        for c in text.chars() {
+            if matches!(c, ' ' | '\t' | '\n' | '\r') {
+                if !self.chars.is_empty() && self.pending_space.is_none() {
+                    self.pending_space = Some((span, self.current_path));
+                }
+                continue;
+            }
+            self.flush_pending_space();
+            self.push_char(c, span, self.current_path);
+        }
+    }
+
+    fn push_char(&mut self, c: char, span: usize, path: Option<usize>) {
+        let fonts = &self.spans[span].fonts;
+        let font = match self.chars.last() {
+            // Joiners, and marks the preceding character's font has, stay in that font
+            Some(prev) if is_default_ignorable(c)
+                || (bidi_class(c) == BidiClass::NSM && self.fonts[prev.font].face.has_glyph(c)) => prev.font,
+            _ => fonts.iter().copied()
+                .find(|&f| self.fonts[f].face.has_glyph(c))
+                .unwrap_or(fonts[0]),
+        };
+        self.chars.push(TextChar { c, span, font, path });
+    }
+
+    fn flush_pending_space(&mut self) {
+        let pending = self.pending_space.take()
+            .filter(|_| self.chars.last().map(|ch| ch.c != ' ').unwrap_or(false));
+        if let Some((span, path)) = pending {
+            self.push_char(' ', span, path);
+        }
+    }
+
+    fn resolve_span(&self, tag: &Tag, parent: Option<usize>) -> TextSpan {
+        let mut style = Tag::new();
+        style.name = tag.name.clone();
+
+        for &key in INHERITED {
+            let value = get_property(tag, key)
+                .or_else(|| parent.and_then(|p| self.spans[p].style.params.get(key).map(|s| s.as_str())));
+            if let Some(value) = value {
+                style.params.insert(key.to_string(), value.to_string());
+            }
+        }
+
+        // The text element's own font size is already part of the context
+        let font_size = match parent {
+            Some(p) => {
+                let parent_size = self.spans[p].font_size;
+                get_property(tag, "font-size")
+                    .and_then(|s| self.lengths.with_font_size(parent_size).font_size_of(s))
+                    .unwrap_or(parent_size)
+            }
+            None => self.lengths.font_size,
+        };
+        let lengths = self.lengths.with_font_size(font_size);
+
+        let anchor = match style.params.get("text-anchor").map(|s| s.trim()) {
+            Some("middle") => TextAnchor::Middle,
+            Some("end") => TextAnchor::End,
+            _ => TextAnchor::Start,
+        };
+
+        // alignment-baseline is not inherited and takes precedence on the element itself
+        let baseline = get_property(tag, "alignment-baseline")
+            .filter(|s| !matches!(*s, "auto" | "baseline"))
+            .or_else(|| style.params.get("dominant-baseline").map(|s| s.trim()))
+            .unwrap_or("auto");
+
+        let spacing = |key: &str| style.params.get(key)
+            .map(|s| parse_spacing(s, &lengths))
+            .unwrap_or(0.0);
+        let letter_spacing = spacing("letter-spacing");
+        let word_spacing = spacing("word-spacing");
+
+        let kerning = style.params.get("font-kerning")
+            .map(|s| s.trim() != "none")
+            .unwrap_or(true);
+
+        let rtl = style.params.get("direction")
+            .map(|s| s.trim() == "rtl")
+            .unwrap_or(false);
+
+        let fonts = font_order(self.fonts, style.params.get("font-family").map(|s| s.as_str()));
+        let face = &self.fonts[fonts[0]].face;
+
+        let normal_line_height = (face.ascender - face.descender + face.line_gap) * face.scale(font_size);
+        let line_height = style.params.get("line-height")
+            .map(|s| parse_line_height(s, &lengths, normal_line_height))
+            .unwrap_or(normal_line_height);
+
+        TextSpan {
+            baseline_offset: baseline_offset(face, baseline, font_size),
+            letter_spacing,
+            word_spacing,
+            kerning,
+            rtl,
+            line_height,
+            fonts,
+            style,
+            font_size,
+            anchor,
+        }
+    }
+}
+
+fn baseline_offset(face: &FontFace, baseline: &str, font_size: f32) -> f32 {
+    let scale = face.scale(font_size);
+    let ascent = face.ascender * scale;
+    let descent = face.descender * scale;
+
+    match baseline {
+        "middle" => face.x_height * scale / 2.0,
+        "central" => (ascent + descent) / 2.0,
+        "hanging" => ascent * 0.8,
+        "mathematical" => ascent * 0.5,
+        "text-before-edge" | "text-top" | "before-edge" => ascent,
+        "text-after-edge" | "text-bottom" | "after-edge" | "ideographic" => descent,
+        _ => 0.0,
+    }
+}
+
+/// Indices of `fonts` in order of preference for a `font-family` list: the
+/// fonts of the listed families, then the others in their own order.
+/// Generic families such as `serif` select no font of their own.
+fn font_order(fonts: &[&Font], families: Option<&str>) -> Vec<usize> {
+    let mut order: Vec<usize> = Vec::with_capacity(fonts.len());
+    for family in families.unwrap_or("").split(',') {
+        let family = family.trim().trim_matches(|c| c == '"' || c == '\'');
+        for (i, font) in fonts.iter().enumerate() {
+            if !order.contains(&i) && font.is_family(family) {
+                order.push(i);
+            }
+        }
+    }
+    let others: Vec<usize> = (0..fonts.len()).filter(|i| !order.contains(i)).collect();
+    order.extend(others);
+    order
+}
+
+/// Parses a list of lengths such as `x` or `dx`; entries that are not
+/// lengths count as 0.
+fn parse_list(tag: &Tag, key: &str, lengths: &LengthContext, axis: Axis) -> Vec<f32> {
+    match tag.params.get(key) {
+        Some(s) => s
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|v| !v.is_empty())
+            .map(|v| lengths.length(v, axis).unwrap_or(0.0))
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Explicit bidi controls (opening, closing) equivalent to a `unicode-bidi` value.
+fn bidi_controls(value: &str, rtl: bool) -> Option<(&'static [char], &'static [char])> {
+    let (open, close): (&'static [char], &'static [char]) = match (value.trim(), rtl) {
+        ("embed", false) => (&[LRE_CHAR], &[PDF_CHAR]),
+        ("embed", true) => (&[RLE_CHAR], &[PDF_CHAR]),
+        ("bidi-override", false) => (&[LRO_CHAR], &[PDF_CHAR]),
+        ("bidi-override", true) => (&[RLO_CHAR], &[PDF_CHAR]),
+        ("isolate", false) => (&[LRI_CHAR], &[PDI_CHAR]),
+        ("isolate", true) => (&[RLI_CHAR], &[PDI_CHAR]),
+        ("isolate-override", false) => (&[LRI_CHAR, LRO_CHAR], &[PDF_CHAR, PDI_CHAR]),
+        ("isolate-override", true) => (&[RLI_CHAR, RLO_CHAR], &[PDF_CHAR, PDI_CHAR]),
+        ("plaintext", _) => (&[FSI_CHAR], &[PDI_CHAR]),
+        _ => return None,
+    };
+    Some((open, close))
+}
+
+/// Parses a `letter-spacing`/`word-spacing` value; `normal` means no extra
+/// spacing and percentages are of the font size.
+fn parse_spacing(s: &str, lengths: &LengthContext) -> f32 {
+    if s.trim() == "normal" {
+        return 0.0;
+    }
+    font_relative(s, lengths).unwrap_or(0.0)
+}
+
+/// Parses `line-height`: `normal`, a multiple of the font size, or a length.
+fn parse_line_height(s: &str, lengths: &LengthContext, normal: f32) -> f32 {
+    let s = s.trim();
+    if s == "normal" {
+        return normal;
+    }
+    match s.parse::<f32>() {
+        Ok(multiple) => multiple * lengths.font_size,
+        Err(_) => font_relative(s, lengths).unwrap_or(normal),
+    }
+}
+
+/// A length whose percentages are of the font size rather than the viewport.
+fn font_relative(s: &str, lengths: &LengthContext) -> Option<f32> {
+    let s = s.trim();
+    match s.strip_suffix('%') {
+        Some(percent) => percent.parse::<f32>().ok().map(|p| p / 100.0 * lengths.font_size),
+        None => lengths.length(s, Axis::Horizontal),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::svg::parser::parse::parse_xml;
+    use crate::svg::rasterizer::tags::text::font::Font;
+    use crate::svg::rasterizer::tags::text::layout::{layout_text, PositionedGlyph};
+    use crate::svg::rasterizer::tags::text::test_font::{kern_table, FontBuilder};
+    use crate::svg::testing::ink_bounds;
+    use crate::svg::utils::collect_defs;
+    use crate::svg::utils::coords::LengthContext;
+    use crate::utils::compat::{format, Vec};
+
+    fn render(text: &str) -> Vec<u32> {
+        crate::svg::testing::render(&format!(r#"<svg width="40" height="20">{}</svg>"#, text), 40, 20)
+    }
+
+    /// Horizontal ink extent of a rendering.
+    fn ink_x(text: &str) -> (usize, usize) {
+        let (left, _, right, _) = ink_bounds(&render(text), 40).unwrap();
+        (left, right)
+    }
+
+    /// Vertical ink extent of a rendering.
+    fn ink_y(text: &str) -> (usize, usize) {
+        let (_, top, _, bottom) = ink_bounds(&render(text), 40).unwrap();
+        (top, bottom)
+    }
+
+    #[test]
+    fn test_text_anchor_positions_the_chunk() {
+        let (start, _) = ink_x(r#"<text x="20" y="15" font-size="10">MM</text>"#);
+        let (_, end) = ink_x(r#"<text x="20" y="15" font-size="10" text-anchor="end">MM</text>"#);
+        let (left, right) = ink_x(r#"<text x="20" y="15" font-size="10" text-anchor="middle">MM</text>"#);
+        assert!((19..=21).contains(&start));
+        assert!((18..=20).contains(&end));
+        assert!((left + right).abs_diff(40) <= 2);
+    }
+
+    #[test]
+    fn test_absolute_tspan_starts_an_anchored_chunk() {
+        // The second chunk ends at x=38 however wide the first one is
+        let (left, right) = ink_x(r#"<text y="15" font-size="8" text-anchor="end"><tspan x="4">M</tspan><tspan x="38">MW</tspan></text>"#);
+        assert!(left < 4);
+        assert!((36..=38).contains(&right));
+    }
+
+    #[test]
+    fn test_tspan_offsets_and_per_glyph_positions() {
+        let plain = ink_y(r#"<text x="2" y="10" font-size="8"><tspan>M</tspan></text>"#);
+        let shifted = ink_y(r#"<text x="2" y="10" font-size="8"><tspan dy="5">M</tspan></text>"#);
+        assert_eq!((plain.0 + 5, plain.1 + 5), shifted);
+
+        let (_, spread) = ink_x(r#"<text y="10" font-size="8" x="2 30">MM</text>"#);
+        assert!(spread > 30);
+    }
+
+    #[test]
+    fn test_dominant_baseline_moves_the_glyphs_down() {
+        let alphabetic = ink_y(r#"<text x="2" y="10" font-size="8">M</text>"#);
+        let hanging = ink_y(r#"<text x="2" y="10" font-size="8" dominant-baseline="hanging">M</text>"#);
+        let middle = ink_y(r#"<text x="2" y="10" font-size="8" dominant-baseline="middle">M</text>"#);
+        assert!(alphabetic.1 <= 10 && hanging.0 >= 9);
+        assert!(alphabetic.0 < middle.0 && middle.0 < hanging.0);
+    }
+
+    #[test]
+    fn test_tspan_inherits_from_text() {
+        let inherited = render(r#"<text x="2" y="15" font-size="10" fill="red"><tspan>M</tspan></text>"#);
+        assert_eq!(inherited, render(r#"<text x="2" y="15"><tspan font-size="10" fill="red">M</tspan></text>"#));
+        assert!(inherited.contains(&0xFFFF0000));
+    }
+
+    #[test]
+    fn test_style_declarations_are_inherited_like_attributes() {
+        let declared = render(r#"<text x="20" y="15" style="fill: red; text-anchor: end; letter-spacing: 2px"><tspan>MW</tspan></text>"#);
+        let attributes = render(r#"<text x="20" y="15" fill="red" text-anchor="end" letter-spacing="2px"><tspan>MW</tspan></text>"#);
+        assert_eq!(declared, attributes);
+        assert_ne!(declared, render(r#"<text x="20" y="15" fill="red" letter-spacing="2px"><tspan>MW</tspan></text>"#));
+    }
+
+    #[test]
+    fn test_style_overrides_the_presentation_attribute() {
+        let overridden = render(r#"<text x="2" y="15" fill="blue" style="fill: red">M</text>"#);
+        assert_eq!(overridden, render(r#"<text x="2" y="15" fill="red">M</text>"#));
+    }
+
+    /// Glyphs of a 10px `text` with `attributes`, set in `fonts`.
+    fn layout(fonts: &[&Font], text: &str, attributes: &str) -> Vec<PositionedGlyph> {
+        let svg = format!(r#"<svg><text font-size="10" {}>{}</text></svg>"#, attributes, text);
+        let root = parse_xml(svg.as_bytes()).unwrap().remove(0);
+        let (text, defs) = (&root.children[0], collect_defs(&root));
+        let lengths = LengthContext::default().for_element(text);
+        layout_text(text, fonts, &defs, &lengths).glyphs
+    }
+
+    /// Pen positions of the glyphs of a 10px `text` with `attributes`, set in
+    /// a font whose `A` and `V` are 5px wide and kern by -0.8px and -0.4px.
+    fn pen_x(text: &str, attributes: &str) -> Vec<f32> {
+        let font = FontBuilder::new(4, 500)
+            .map(&[('A', 1), ('V', 2), (' ', 3)])
+            .advance(3, 250)
+            .table(b"kern", kern_table(&[(1, 2, -80), (2, 1, -40)]))
+            .font();
+        layout(&[&font], text, attributes).iter().map(|g| g.x).collect()
+    }
+
+    /// Glyph ids and the fonts they come from, for text set in "Alpha",
+    /// which has `A` (1) and an acute accent (2), and "Beta", which has `A`
+    /// (1), `B` (2) and the accent (3).
+    fn fonts_used(text: &str, attributes: &str) -> Vec<(u16, usize)> {
+        let alpha = FontBuilder::new(3, 500).family("Alpha").map(&[('A', 1), ('\u{0301}', 2)]).font();
+        let beta = FontBuilder::new(4, 500).family("Beta").map(&[('A', 1), ('B', 2), ('\u{0301}', 3)]).font();
+        layout(&[&alpha, &beta], text, attributes).iter().map(|g| (g.glyph_id, g.font)).collect()
+    }
+
+    fn assert_positions(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len(), "{:?} != {:?}", actual, expected);
+        assert!(actual.iter().zip(expected).all(|(a, e)| (a - e).abs() < 1e-3), "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn test_pairs_are_kerned_unless_font_kerning_is_none() {
+        assert_positions(&pen_x("AVA", ""), &[0.0, 4.2, 8.8]);
+        assert_positions(&pen_x("AVA", r#"font-kerning="normal""#), &[0.0, 4.2, 8.8]);
+        assert_positions(&pen_x("AVA", r#"font-kerning="none""#), &[0.0, 5.0, 10.0]);
+        assert_positions(&pen_x("AVA", r#"style="font-kerning: none""#), &[0.0, 5.0, 10.0]);
+    }
+
+    #[test]
+    fn test_letter_spacing_is_added_to_kerned_advances() {
+        assert_positions(&pen_x("AVA", r#"letter-spacing="1""#), &[0.0, 5.2, 10.8]);
+        assert_positions(&pen_x("AVA", r#"letter-spacing="1" font-kerning="none""#), &[0.0, 6.0, 12.0]);
+    }
+
+    #[test]
+    fn test_word_spacing_widens_only_spaces() {
+        assert_positions(&pen_x("A A", r#"word-spacing="2""#), &[0.0, 5.0, 9.5]);
+    }
+
+    #[test]
+    fn test_font_family_selects_the_font() {
+        assert_eq!(fonts_used("A", ""), [(1, 0)]);
+        assert_eq!(fonts_used("A", r#"font-family="Beta, Alpha""#), [(1, 1)]);
+        assert_eq!(fonts_used("A", r#"font-family="Missing, 'beta', serif""#), [(1, 1)]);
+        assert_eq!(fonts_used("A", r#"style="font-family: &quot;Beta&quot;""#), [(1, 1)]);
+        assert_eq!(fonts_used("<tspan font-family=\"Beta\">A</tspan>A", ""), [(1, 1), (1, 0)]);
+    }
+
+    #[test]
+    fn test_characters_fall_back_to_fonts_that_have_them() {
+        assert_eq!(fonts_used("AB", r#"font-family="Alpha""#), [(1, 0), (2, 1)]);
+        // No font has it: the preferred font shows its missing glyph
+        assert_eq!(fonts_used("AC", r#"font-family="Beta""#), [(1, 1), (0, 1)]);
+    }
+
+    #[test]
+    fn test_marks_stay_in_the_font_of_their_base() {
+        assert_eq!(fonts_used("A\u{0301}B\u{0301}", r#"font-family="Alpha""#), [(1, 0), (2, 0), (2, 1), (3, 1)]);
+    }
+
+    #[test]
+    fn test_right_to_left_runs_are_laid_out_in_visual_order() {
+        let font = FontBuilder::new(4, 500).map(&[('A', 1), ('\u{0628}', 2), ('\u{0627}', 3)]).font();
+        let glyphs: Vec<(u16, f32)> = layout(&[&font], "A\u{0628}\u{0627}", "").iter().map(|g| (g.glyph_id, g.x)).collect();
+        assert_eq!(glyphs, [(1, 0.0), (3, 5.0), (2, 10.0)]);
+    }
+}
@@ -0,0 +1,226 @@
+//! Unicode Line Breaking Algorithm (UAX #14): break opportunities between
+//! the characters of a paragraph.
+
+use crate::utils::compat::Vec;
+
+use LineBreakClass::*;
+
+// Class names follow UAX #14
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineBreakClass {
+    BK, CR, LF, NL, SP, ZW, WJ, GL, CM, ZWJ,
+    BA, BB, B2, HY, CB, CL, CP, EX, IN, NS, OP, QU, IS, NU, PO, PR, SY,
+    AL, HL, ID, EB, EM, JL, JV, JT, H2, H3, RI,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Break {
+    Never,
+    Allowed,
+    Mandatory,
+}
+
+/// Line break class of a character after LB1: ambiguous, unknown and
+/// complex-context characters are alphabetic, conditional Japanese starters
+/// are non-starters.
+fn line_break_class(c: char) -> LineBreakClass {
+    let cp = c as u32;
+    match cp {
+        0x000A => LF,
+        0x000D => CR,
+        0x0085 => NL,
+        0x000B | 0x000C | 0x2028 | 0x2029 => BK,
+        0x0020 => SP,
+        0x0009 | 0x007C | 0x00AD | 0x058A | 0x05BE | 0x1680 | 0x2000..=0x2006 | 0x2008..=0x200A
+        | 0x2010 | 0x2012 | 0x2013 | 0x2027 | 0x205F | 0x3000 => BA,
+        0x200B => ZW,
+        0x200D => ZWJ,
+        0x2060 | 0xFEFF => WJ,
+        0x00A0 | 0x034F | 0x2007 | 0x2011 | 0x202F | 0x0F0C => GL,
+        0x0000..=0x0008 | 0x000E..=0x001F | 0x007F..=0x0084 | 0x0086..=0x009F | 0x200C => CM,
+
+        // Combining marks
+        0x0300..=0x034E | 0x0350..=0x036F | 0x0483..=0x0489 | 0x0591..=0x05BD | 0x05BF
+        | 0x05C1 | 0x05C2 | 0x05C4 | 0x05C5 | 0x05C7 | 0x0610..=0x061A | 0x064B..=0x065F
+        | 0x0670 | 0x06D6..=0x06DC | 0x06DF..=0x06E4 | 0x06E7 | 0x06E8 | 0x06EA..=0x06ED
+        | 0x0900..=0x0903 | 0x093A..=0x093C | 0x093E..=0x094F | 0x0951..=0x0957 | 0x0962 | 0x0963
+        | 0x0981..=0x0983 | 0x09BC | 0x09BE..=0x09CD | 0x09D7 | 0x09E2 | 0x09E3
+        | 0x1AB0..=0x1AFF | 0x1DC0..=0x1DFF | 0x20D0..=0x20FF | 0x3099 | 0x309A
+        | 0xFE00..=0xFE0F | 0xFE20..=0xFE2F | 0xE0100..=0xE01EF => CM,
+
+        0x002D => HY,
+        0x2014 => B2,
+        0x00B4 | 0x02C8 | 0x02CC | 0x02DF | 0x1FFD => BB,
+        0xFFFC => CB,
+        0x0029 | 0x005D => CP,
+        0x007D | 0x0F3B | 0x0F3D | 0x2046 | 0x207E | 0x208E | 0x2309 | 0x230B | 0x232A
+        | 0x3001 | 0x3002 | 0x3009 | 0x300B | 0x300D | 0x300F | 0x3011 | 0x3015 | 0x3017
+        | 0x3019 | 0x301B | 0x301E | 0x301F | 0xFE11 | 0xFE12 | 0xFE50 | 0xFE52 | 0xFF09
+        | 0xFF0C | 0xFF0E | 0xFF3D | 0xFF5D | 0xFF60 | 0xFF61 | 0xFF63 | 0xFF64 => CL,
+        0x0021 | 0x003F | 0x05C6 | 0x061B | 0x061D..=0x061F | 0x06D4 | 0x07F9 | 0x0F0D..=0x0F11
+        | 0x0F14 | 0x1802 | 0x1803 | 0x1808 | 0x1809 | 0x1944 | 0x1945 | 0x2762 | 0x2763
+        | 0x2CF9 | 0x2CFE | 0x2E2E | 0xA60E | 0xA876 | 0xA877 | 0xFE15 | 0xFE16 | 0xFE56
+        | 0xFE57 | 0xFF01 | 0xFF1F => EX,
+        0x2024..=0x2026 | 0x22EF | 0xFE19 => IN,
+        0x17D6 | 0x203C | 0x203D | 0x2047..=0x2049 | 0x3005 | 0x301C | 0x303B | 0x303C
+        | 0x309B..=0x309E | 0x30A0 | 0x30FB | 0x30FD | 0x30FE | 0xA015 | 0xFE54 | 0xFE55
+        | 0xFF1A | 0xFF1B | 0xFF65 | 0xFF9E | 0xFF9F => NS,
+        // Small kana and the prolonged sound mark (CJ)
+        0x3041 | 0x3043 | 0x3045 | 0x3047 | 0x3049 | 0x3063 | 0x3083 | 0x3085 | 0x3087
+        | 0x308E | 0x3095 | 0x3096 | 0x30A1 | 0x30A3 | 0x30A5 | 0x30A7 | 0x30A9 | 0x30C3
+        | 0x30E3 | 0x30E5 | 0x30E7 | 0x30EE | 0x30F5 | 0x30F6 | 0x30FC | 0x31F0..=0x31FF
+        | 0xFF67..=0xFF70 => NS,
+        0x0028 | 0x005B | 0x007B | 0x00A1 | 0x00BF | 0x0F3A | 0x0F3C | 0x201A | 0x201E | 0x2045
+        | 0x207D | 0x208D | 0x2308 | 0x230A | 0x2329 | 0x3008 | 0x300A | 0x300C | 0x300E
+        | 0x3010 | 0x3014 | 0x3016 | 0x3018 | 0x301A | 0x301D | 0xFE59 | 0xFE5B | 0xFE5D
+        | 0xFF08 | 0xFF3B | 0xFF5B | 0xFF5F | 0xFF62 => OP,
+        0x0022 | 0x0027 | 0x00AB | 0x00BB | 0x2018 | 0x2019 | 0x201B..=0x201D | 0x201F
+        | 0x2039 | 0x203A | 0x275B..=0x2760 | 0x2E00..=0x2E0D | 0x2E1C | 0x2E1D | 0x2E20 | 0x2E21 => QU,
+        0x002C | 0x002E | 0x003A | 0x003B | 0x037E | 0x0589 | 0x060C | 0x060D | 0x07F8 | 0x2044
+        | 0xFE10 | 0xFE13 | 0xFE14 => IS,
+        0x0030..=0x0039 | 0x0660..=0x0669 | 0x066B | 0x066C | 0x06F0..=0x06F9 | 0x0966..=0x096F
+        | 0x09E6..=0x09EF => NU,
+        0x0025 | 0x00A2 | 0x00B0 | 0x060B | 0x066A | 0x2030..=0x2037 | 0x20A7 | 0x20B6 | 0x20BB
+        | 0x20BE | 0x2103 | 0x2109 | 0xFE6A | 0xFF05 | 0xFFE0 => PO,
+        0x0024 | 0x002B | 0x005C | 0x00A3..=0x00A5 | 0x00B1 | 0x058F | 0x09F2 | 0x09F3
+        | 0x20A0..=0x20CF | 0x2116 | 0x2212 | 0x2213 | 0xFE69 | 0xFF04 | 0xFFE1 | 0xFFE5 | 0xFFE6 => PR,
+        0x002F => SY,
+
+        // Hebrew letters
+        0x05D0..=0x05EA | 0x05EF..=0x05F2 | 0xFB1D | 0xFB1F..=0xFB28 | 0xFB2A..=0xFB4F => HL,
+
+        // Hangul
+        0x1100..=0x115F | 0xA960..=0xA97C => JL,
+        0x1160..=0x11A7 | 0xD7B0..=0xD7C6 => JV,
+        0x11A8..=0x11FF | 0xD7CB..=0xD7FB => JT,
+        0xAC00..=0xD7A3 if (cp - 0xAC00).is_multiple_of(28) => H2,
+        0xAC00..=0xD7A3 => H3,
+
+        // Emoji
+        0x1F1E6..=0x1F1FF => RI,
+        0x1F3FB..=0x1F3FF => EM,
+        0x261D | 0x26F9 | 0x270A..=0x270D | 0x1F385 | 0x1F3C2..=0x1F3C4 | 0x1F3C7
+        | 0x1F3CA..=0x1F3CC | 0x1F442 | 0x1F443 | 0x1F446..=0x1F450 | 0x1F466..=0x1F478
+        | 0x1F47C | 0x1F481..=0x1F483 | 0x1F485..=0x1F487 | 0x1F4AA | 0x1F574 | 0x1F575
+        | 0x1F57A | 0x1F590 | 0x1F595 | 0x1F596 | 0x1F645..=0x1F647 | 0x1F64B..=0x1F64F
+        | 0x1F6A3 | 0x1F6B4..=0x1F6B6 | 0x1F6C0 | 0x1F6CC | 0x1F90C | 0x1F90F
+        | 0x1F918..=0x1F91F | 0x1F926 | 0x1F930..=0x1F939 | 0x1F93C..=0x1F93E | 0x1F977
+        | 0x1F9B5 | 0x1F9B6 | 0x1F9B8 | 0x1F9B9 | 0x1F9BB | 0x1F9CD..=0x1F9CF
+        | 0x1F9D1..=0x1F9DD => EB,
+
+        // Ideographs, kana and other CJK characters
+        0x2E80..=0x2FFF | 0x3003 | 0x3004 | 0x3006 | 0x3007 | 0x3012 | 0x3013 | 0x3020..=0x3029
+        | 0x3030..=0x303A | 0x303D..=0x303F | 0x3040..=0x30FF | 0x3100..=0x31EF | 0x3200..=0x4DBF
+        | 0x4E00..=0x9FFF | 0xA000..=0xA4CF | 0xF900..=0xFAFF | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF5F | 0xFFE2..=0xFFE4 | 0x1F000..=0x1FAFF | 0x20000..=0x3FFFD => ID,
+
+        _ => AL,
+    }
+}
+
+/// Break opportunities of a paragraph: entry `i` says whether a line may
+/// (or must) end before character `i`. Never before the first character.
+pub(crate) fn line_breaks(text: &[char]) -> Vec<Break> {
+    let classes: Vec<LineBreakClass> = text.iter().map(|&c| line_break_class(c)).collect();
+
+    // LB9, LB10: combining marks take the class of the character they follow
+    let mut resolved = classes.clone();
+    for i in 0..resolved.len() {
+        if matches!(classes[i], CM | ZWJ) {
+            resolved[i] = match i.checked_sub(1).map(|p| resolved[p]) {
+                Some(BK | CR | LF | NL | SP | ZW) | None => AL,
+                Some(prev) => prev,
+            };
+        }
+    }
+
+    let mut breaks = Vec::with_capacity(text.len());
+    let mut ri_count = 0;
+    for i in 0..text.len() {
+        if i == 0 {
+            breaks.push(Break::Never);
+            ri_count = (resolved[0] == RI) as usize;
+            continue;
+        }
+        breaks.push(pair_break(&classes, &resolved, i, ri_count));
+        ri_count = if resolved[i] == RI { ri_count + 1 } else { 0 };
+    }
+    breaks
+}
+
+/// Applies LB4–LB31 to the position before `i`. `ri_count` is the number of
+/// regional indicators directly before it.
+fn pair_break(classes: &[LineBreakClass], resolved: &[LineBreakClass], i: usize, ri_count: usize) -> Break {
+    let (before, after) = (resolved[i - 1], resolved[i]);
+    // The last class before `i` other than spaces
+    let before_spaces = resolved[..i].iter().rev().copied().find(|&c| c != SP);
+
+    match (classes[i - 1], classes[i]) {
+        (BK, _) => return Break::Mandatory,
+        (CR, LF) => return Break::Never,
+        (CR | LF | NL, _) => return Break::Mandatory,
+        (_, BK | CR | LF | NL | SP | ZW) => return Break::Never,
+        _ => {}
+    }
+    if before_spaces == Some(ZW) {
+        return Break::Allowed;
+    }
+    if classes[i - 1] == ZWJ {
+        return Break::Never;
+    }
+    if matches!(classes[i], CM | ZWJ) && !matches!(classes[i - 1], BK | CR | LF | NL | SP | ZW) {
+        return Break::Never;
+    }
+
+    let never = match (before, after) {
+        // LB11, LB12, LB12a
+        (WJ, _) | (_, WJ) | (GL, _) => true,
+        (b, GL) if !matches!(b, SP | BA | HY) => true,
+        // LB13
+        (_, CL | CP | EX | IS | SY) => true,
+        _ => false,
+    };
+    if never {
+        return Break::Never;
+    }
+
+    // LB14–LB17: sequences that stay together across spaces
+    match (before_spaces, after) {
+        (Some(OP), _) | (Some(QU), OP) | (Some(CL | CP), NS) | (Some(B2), B2) => return Break::Never,
+        _ => {}
+    }
+
+    // LB18
+    if before == SP {
+        return Break::Allowed;
+    }
+
+    let never = match (before, after) {
+        // LB19, LB20
+        (QU, _) | (_, QU) => true,
+        (CB, _) | (_, CB) => return Break::Allowed,
+        // LB21, LB21a, LB21b, LB22
+        (_, BA | HY | NS) | (BB, _) => true,
+        (HY | BA, _) if i >= 2 && resolved[i - 2] == HL => true,
+        (SY, HL) | (_, IN) => true,
+        // LB23, LB23a, LB24
+        (AL | HL, NU) | (NU, AL | HL) => true,
+        (PR, ID | EB | EM) | (ID | EB | EM, PO) => true,
+        (PR | PO, AL | HL) | (AL | HL, PR | PO) => true,
+        // LB25: numeric expressions
+        (CL | CP | NU, PO | PR) | (PO | PR, OP) | (PO | PR | HY | IS | NU | SY, NU) => true,
+        // LB26, LB27: Korean syllable blocks
+        (JL, JL | JV | H2 | H3) | (JV | H2, JV | JT) | (JT | H3, JT) => true,
+        (JL | JV | JT | H2 | H3, PO) | (PR, JL | JV | JT | H2 | H3) => true,
+        // LB28, LB29, LB30
+        (AL | HL, AL | HL) | (IS, AL | HL) => true,
+        (AL | HL | NU, OP) | (CP, AL | HL | NU) => true,
+        // LB30a, LB30b
+        (RI, RI) => ri_count % 2 == 1,
+        (EB, EM) => true,
+        _ => false,
+    };
+
+    if never { Break::Never } else { Break::Allowed }
+}
@@ -0,0 +1,274 @@
+use crate::utils::compat::Vec;
+use crate::svg::parser::tags::Tag;
+use crate::svg::rasterizer::canva::Canvas;
+use crate::svg::utils::transform::{parse_transform, Transform};
+use crate::svg::utils::color::{get_fill, get_stroke, Paint};
+use crate::svg::utils::coords::LengthContext;
+use crate::svg::tree::{Path, Stroke};
+use crate::svg::utils::effects::get_stroke_width;
+use crate::svg::rasterizer::raster::Point;
+use crate::svg::rasterizer::tags::path::{apply_transform_to_path, commands_bounds, fill_path, stroke_path, PathCommand};
+use crate::utils::compat::FloatExt;
+
+mod bidi;
+mod font;
+mod layout;
+mod linebreak;
+mod opentype;
+mod outline;
+mod shaping;
+#[cfg(test)]
+pub(crate) mod test_font;
+mod text_path;
+mod wrap;
+
+pub use font::Font;
+use layout::{layout_text, PositionedGlyph, TextLayout};
+use crate::svg::utils::Defs;
+
+/// Converts a `<text>` element into glyph outlines, set in `fonts` and the
+/// bundled font.
+///
+/// The element's own `transform` attribute is applied after `transform`,
+/// so the commands end up in the same space the renderer draws into.
+/// Percentage lengths resolve against `viewport_w` x `viewport_h`.
+pub fn text_to_path(
+    tag: &Tag,
+    defs: &Defs,
+    fonts: &[Font],
+    transform: &Transform,
+    viewport_w: f32,
+    viewport_h: f32,
+) -> Vec<PathCommand> {
+    let lengths = LengthContext::new(viewport_w, viewport_h).for_element(tag);
+    text_outline(tag, defs, fonts, &transform.then(&parse_transform(tag)), &lengths)
+}
+
+/// Glyph outlines of a `<text>` element mapped through `transform`, without
+/// its own `transform` attribute. Lengths resolve in `lengths`, the context
+/// of the text element itself.
+pub(crate) fn text_outline(
+    tag: &Tag,
+    defs: &Defs,
+    fonts: &[Font],
+    transform: &Transform,
+    lengths: &LengthContext,
+) -> Vec<PathCommand> {
+    let Some(bundled) = Font::bundled() else {
+        return Vec::new();
+    };
+    let available = with_bundled(fonts, &bundled);
+    let text_layout = layout_text(tag, &available, defs, lengths);
+
+    text_layout.glyphs.iter()
+        .flat_map(|glyph| glyph_commands(available[glyph.font], &text_layout, glyph, transform))
+        .collect()
+}
+
+/// Lays out a `<text>` element into one path per span in user space, with
+/// lengths resolved in `lengths`, the context of the text element itself.
+/// Every span's bounding box is that of the whole text, which its paint
+/// servers refer to.
+pub(crate) fn text_paths(tag: &Tag, defs: &Defs, fonts: &[Font], lengths: &LengthContext) -> Vec<Path> {
+    let Some(bundled) = Font::bundled() else {
+        return Vec::new();
+    };
+    let available = with_bundled(fonts, &bundled);
+    let text_layout = layout_text(tag, &available, defs, lengths);
+
+    // Outlines grouped by span, so that each span is filled and stroked as one path
+    let mut span_paths: Vec<Vec<PathCommand>> = text_layout.spans.iter().map(|_| Vec::new()).collect();
+    for glyph in &text_layout.glyphs {
+        span_paths[glyph.span].extend(glyph_commands(available[glyph.font], &text_layout, glyph, &Transform::identity()));
+    }
+    let bbox = commands_bounds(span_paths.iter().flatten());
+
+    text_layout.spans.iter().zip(span_paths)
+        .filter(|(_, commands)| !commands.is_empty())
+        .map(|(span, commands)| {
+            let style = &span.style;
+            let lengths = lengths.with_font_size(span.font_size);
+            let fill = if style.params.contains_key("fill") {
+                get_fill(style).resolve(defs, fonts, &lengths)
+            } else {
+                Paint::Solid(0xFF000000)
+            };
+            let stroke = get_stroke(style).resolve(defs, fonts, &lengths);
+            let width = get_stroke_width(style, &lengths);
+
+            Path {
+                commands,
+                bbox,
+                fill,
+                stroke: (!stroke.is_none() && width > 0.0).then_some(Stroke { paint: stroke, width }),
+                markers: Vec::new(),
+            }
+        })
+        .collect()
+}
+
+/// Draws the spans [`text_paths`] laid out, with stroke widths scaled by `transform`.
+pub(crate) fn draw_text(spans: &[Path], defs: &Defs, canvas: &mut Canvas, transform: &Transform) {
+    let (sx, sy) = transform.get_scale();
+
+    for span in spans {
+        let Some(bbox) = span.bbox else { continue };
+        let commands = apply_transform_to_path(&span.commands, transform);
+
+        let mut fill = span.fill.clone();
+        fill.prepare(defs, transform, bbox);
+        if !fill.is_none() {
+            fill_path(canvas, &commands, &fill);
+        }
+        if let Some(stroke) = &span.stroke {
+            let mut paint = stroke.paint.clone();
+            paint.prepare(defs, transform, bbox);
+            stroke_path(canvas, &commands, &paint, stroke.width * (sx + sy) / 2.0);
+        }
+    }
+}
+
+/// The fonts text is set in: `fonts`, then the bundled font as a last resort.
+fn with_bundled<'a>(fonts: &'a [Font], bundled: &'a Font) -> Vec<&'a Font> {
+    fonts.iter().chain([bundled]).collect()
+}
+
+/// Outline of one positioned glyph, mapped through `transform`.
+fn glyph_commands(font: &Font, text_layout: &TextLayout, glyph: &PositionedGlyph, transform: &Transform) -> Vec<PathCommand> {
+    let face = &font.face;
+    let mut outline = face.glyph_outline(glyph.glyph_id);
+    if outline.is_empty() {
+        return outline;
+    }
+
+    // Warping bends straight segments, so they need enough points to follow the path
+    if glyph.warp.is_some() {
+        outline = subdivide(&outline, face.units_per_em / 32.0);
+    }
+
+    let scale = face.scale(text_layout.spans[glyph.span].font_size);
+    let map = |p: Point| {
+        // Font units are y-up
+        let (x, y) = glyph_point(text_layout, glyph, p.x * scale, -p.y * scale);
+        transform.apply_point(Point { x, y })
+    };
+
+    outline.iter().map(|cmd| match *cmd {
+        PathCommand::MoveTo(p) => PathCommand::MoveTo(map(p)),
+        PathCommand::LineTo(p) => PathCommand::LineTo(map(p)),
+        PathCommand::QuadraticBezier(cp, p) => PathCommand::QuadraticBezier(map(cp), map(p)),
+        PathCommand::CubicBezier(cp1, cp2, p) => PathCommand::CubicBezier(map(cp1), map(cp2), map(p)),
+        ref other => other.clone(),
+    }).collect()
+}
+
+/// Splits lines and curves into line segments no longer than about `max_len`.
+fn subdivide(commands: &[PathCommand], max_len: f32) -> Vec<PathCommand> {
+    let mut out = Vec::with_capacity(commands.len() * 4);
+    let mut current = Point { x: 0.0, y: 0.0 };
+    let mut start = current;
+
+    let distance = |a: Point, b: Point| ((b.x - a.x) * (b.x - a.x) + (b.y - a.y) * (b.y - a.y)).sqrt();
+
+    for cmd in commands {
+        match *cmd {
+            PathCommand::MoveTo(p) => {
+                out.push(PathCommand::MoveTo(p));
+                current = p;
+                start = p;
+            }
+            PathCommand::LineTo(p) => {
+                let steps = (distance(current, p) / max_len).ceil().max(1.0) as usize;
+                for i in 1..=steps {
+                    let t = i as f32 / steps as f32;
+                    out.push(PathCommand::LineTo(Point {
+                        x: current.x + (p.x - current.x) * t,
+                        y: current.y + (p.y - current.y) * t,
+                    }));
+                }
+                current = p;
+            }
+            PathCommand::QuadraticBezier(cp, p) => {
+                let steps = ((distance(current, cp) + distance(cp, p)) / max_len).ceil().max(1.0) as usize;
+                for i in 1..=steps {
+                    let t = i as f32 / steps as f32;
+                    let mt = 1.0 - t;
+                    out.push(PathCommand::LineTo(Point {
+                        x: mt * mt * current.x + 2.0 * mt * t * cp.x + t * t * p.x,
+                        y: mt * mt * current.y + 2.0 * mt * t * cp.y + t * t * p.y,
+                    }));
+                }
+                current = p;
+            }
+            PathCommand::CubicBezier(cp1, cp2, p) => {
+                let length = distance(current, cp1) + distance(cp1, cp2) + distance(cp2, p);
+                let steps = (length / max_len).ceil().max(1.0) as usize;
+                for i in 1..=steps {
+                    let t = i as f32 / steps as f32;
+                    let mt = 1.0 - t;
+                    let (a, b, c, d) = (mt * mt * mt, 3.0 * mt * mt * t, 3.0 * mt * t * t, t * t * t);
+                    out.push(PathCommand::LineTo(Point {
+                        x: a * current.x + b * cp1.x + c * cp2.x + d * p.x,
+                        y: a * current.y + b * cp1.y + c * cp2.y + d * p.y,
+                    }));
+                }
+                current = p;
+            }
+            PathCommand::ClosePath => {
+                out.push(PathCommand::ClosePath);
+                current = start;
+            }
+            ref other => out.push(other.clone()),
+        }
+    }
+
+    out
+}
+
+/// Maps a point given relative to the glyph origin to user space, following
+/// the glyph's rotation or its text path warp.
+fn glyph_point(text_layout: &TextLayout, glyph: &PositionedGlyph, x: f32, y: f32) -> (f32, f32) {
+    if let Some(p) = glyph.warp {
+        return text_layout.paths[p].path.map(glyph.x + x, glyph.y + y);
+    }
+    if glyph.rotate == 0.0 {
+        return (glyph.x + x, glyph.y + y);
+    }
+    let (sin, cos) = (glyph.rotate.sin(), glyph.rotate.cos());
+    (glyph.x + x * cos - y * sin, glyph.y + x * sin + y * cos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(commands: &[PathCommand]) -> Vec<Point> {
+        commands.iter().map(|cmd| match *cmd {
+            PathCommand::MoveTo(p) | PathCommand::LineTo(p) => p,
+            ref other => panic!("{:?} was not flattened", other),
+        }).collect()
+    }
+
+    #[test]
+    fn test_subdivide_flattens_cubics() {
+        let p = |x: f32, y: f32| Point { x, y };
+        let commands = [
+            PathCommand::MoveTo(p(0.0, 0.0)),
+            PathCommand::CubicBezier(p(0.0, 30.0), p(40.0, 30.0), p(40.0, 0.0)),
+            PathCommand::LineTo(p(40.0, -10.0)),
+        ];
+        let flat = points(&subdivide(&commands, 5.0));
+
+        // Segments stay short and the curve passes through its midpoint (20, 22.5)
+        for pair in flat.windows(2) {
+            let (dx, dy) = (pair[1].x - pair[0].x, pair[1].y - pair[0].y);
+            assert!((dx * dx + dy * dy).sqrt() <= 5.0, "{:?}", pair);
+        }
+        assert!(flat.iter().any(|q| (q.x - 20.0).abs() < 1e-3 && (q.y - 22.5).abs() < 1e-3));
+
+        // The line continues from the end of the curve
+        let end = flat.iter().position(|q| q.x == 40.0 && q.y == 0.0).unwrap();
+        assert_eq!(flat.len() - end, 3);
+        assert!(flat[end..].iter().all(|q| q.x == 40.0));
+    }
+}
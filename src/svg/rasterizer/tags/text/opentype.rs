@@ -0,0 +1,394 @@
+use crate::svg::rasterizer::tags::text::font::{read_u16, read_u32};
+use crate::utils::compat::Vec;
+
+
+/// Read-only view over a GSUB or GPOS table.
+#[derive(Clone, Copy)]
+pub(crate) struct LayoutTable<'a> {
+    data: &'a [u8],
+}
+
+/// A lookup with its subtables resolved through extension records.
+pub(crate) struct Lookup<'a> {
+    pub(crate) kind: u16,
+    pub(crate) flag: u16,
+    /// Mark glyph set used when the lookup flag asks for mark filtering.
+    pub(crate) mark_set: Option<u16>,
+    pub(crate) subtables: Vec<&'a [u8]>,
+}
+
+// Lookup flags
+pub(crate) const IGNORE_BASE_GLYPHS: u16 = 0x0002;
+pub(crate) const IGNORE_LIGATURES: u16 = 0x0004;
+pub(crate) const IGNORE_MARKS: u16 = 0x0008;
+pub(crate) const USE_MARK_FILTERING_SET: u16 = 0x0010;
+pub(crate) const MARK_ATTACHMENT_TYPE: u16 = 0xFF00;
+
+// GDEF glyph classes
+pub(crate) const GLYPH_CLASS_BASE: u16 = 1;
+pub(crate) const GLYPH_CLASS_LIGATURE: u16 = 2;
+pub(crate) const GLYPH_CLASS_MARK: u16 = 3;
+
+impl<'a> LayoutTable<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Option<Self> {
+        if data.len() < 10 || read_u16(data, 0) != 1 {
+            return None;
+        }
+        Some(Self { data })
+    }
+
+    fn script_list(&self) -> &'a [u8] {
+        offset_slice(self.data, read_u16(self.data, 4) as usize)
+    }
+
+    fn feature_list(&self) -> &'a [u8] {
+        offset_slice(self.data, read_u16(self.data, 6) as usize)
+    }
+
+    fn lookup_list(&self) -> &'a [u8] {
+        offset_slice(self.data, read_u16(self.data, 8) as usize)
+    }
+
+    /// Indices of every lookup referenced by a feature named `tag`, in lookup list order.
+    fn feature_lookups(&self, tag: &[u8; 4]) -> Vec<u16> {
+        let features = self.feature_list();
+        let count = read_u16(features, 0) as usize;
+        let mut lookups = Vec::new();
+
+        for i in 0..count {
+            let record = 2 + i * 6;
+            if features.get(record..record + 4) != Some(&tag[..]) {
+                continue;
+            }
+            let feature = offset_slice(features, read_u16(features, record + 4) as usize);
+            let lookup_count = read_u16(feature, 2) as usize;
+            for j in 0..lookup_count {
+                let index = read_u16(feature, 4 + j * 2);
+                if !lookups.contains(&index) {
+                    lookups.push(index);
+                }
+            }
+        }
+
+        lookups.sort_unstable();
+        lookups
+    }
+
+    /// Lookups of feature `tag` in the default language system of the first
+    /// script in `scripts` that the font supports. Fonts without any of them
+    /// fall back to every feature with that tag.
+    pub(crate) fn script_feature_lookups(&self, scripts: &[[u8; 4]], tag: &[u8; 4]) -> Vec<u16> {
+        let Some(lang_sys) = self.default_lang_sys(scripts) else {
+            return self.feature_lookups(tag);
+        };
+
+        let features = self.feature_list();
+        let feature_count = read_u16(features, 0);
+        let index_count = read_u16(lang_sys, 4) as usize;
+        let mut lookups = Vec::new();
+
+        for i in 0..index_count {
+            let feature_index = read_u16(lang_sys, 6 + i * 2);
+            if feature_index >= feature_count {
+                continue;
+            }
+            let record = 2 + feature_index as usize * 6;
+            if features.get(record..record + 4) != Some(&tag[..]) {
+                continue;
+            }
+            let feature = offset_slice(features, read_u16(features, record + 4) as usize);
+            for j in 0..read_u16(feature, 2) as usize {
+                let index = read_u16(feature, 4 + j * 2);
+                if !lookups.contains(&index) {
+                    lookups.push(index);
+                }
+            }
+        }
+
+        lookups.sort_unstable();
+        lookups
+    }
+
+    fn default_lang_sys(&self, scripts: &[[u8; 4]]) -> Option<&'a [u8]> {
+        let list = self.script_list();
+        let count = read_u16(list, 0) as usize;
+
+        scripts.iter().find_map(|wanted| {
+            (0..count).find_map(|i| {
+                let record = 2 + i * 6;
+                if list.get(record..record + 4) != Some(&wanted[..]) {
+                    return None;
+                }
+                let script = offset_slice(list, read_u16(list, record + 4) as usize);
+                match read_u16(script, 0) {
+                    0 => None,
+                    offset => Some(offset_slice(script, offset as usize)),
+                }
+            })
+        })
+    }
+
+    pub(crate) fn lookup(&self, index: u16, extension_kind: u16) -> Option<Lookup<'a>> {
+        let list = self.lookup_list();
+        if index >= read_u16(list, 0) {
+            return None;
+        }
+        let lookup = offset_slice(list, read_u16(list, 2 + index as usize * 2) as usize);
+
+        let kind = read_u16(lookup, 0);
+        let flag = read_u16(lookup, 2);
+        let count = read_u16(lookup, 4) as usize;
+        let mark_set = (flag & USE_MARK_FILTERING_SET != 0).then(|| read_u16(lookup, 6 + count * 2));
+        let mut resolved_kind = kind;
+        let mut subtables = Vec::with_capacity(count);
+
+        for i in 0..count {
+            let subtable = offset_slice(lookup, read_u16(lookup, 6 + i * 2) as usize);
+            if kind == extension_kind {
+                // Extension subtables hold the real lookup type and a 32-bit offset
+                resolved_kind = read_u16(subtable, 2);
+                subtables.push(offset_slice(subtable, read_u32(subtable, 4) as usize));
+            } else {
+                subtables.push(subtable);
+            }
+        }
+
+        Some(Lookup { kind: resolved_kind, flag, mark_set, subtables })
+    }
+}
+
+/// Glyph definition table: glyph classes and mark sets used by lookup flags.
+#[derive(Clone, Copy)]
+pub(crate) struct Gdef<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Gdef<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Option<Self> {
+        if data.len() < 12 || read_u16(data, 0) != 1 {
+            return None;
+        }
+        Some(Self { data })
+    }
+
+    pub(crate) fn glyph_class(&self, glyph: u16) -> u16 {
+        match read_u16(self.data, 4) {
+            0 => 0,
+            offset => glyph_class(offset_slice(self.data, offset as usize), glyph),
+        }
+    }
+
+    pub(crate) fn mark_attach_class(&self, glyph: u16) -> u16 {
+        match read_u16(self.data, 10) {
+            0 => 0,
+            offset => glyph_class(offset_slice(self.data, offset as usize), glyph),
+        }
+    }
+
+    /// Whether `glyph` belongs to mark glyph set `set` (GDEF 1.2).
+    pub(crate) fn in_mark_set(&self, set: u16, glyph: u16) -> bool {
+        if read_u16(self.data, 2) < 2 {
+            return false;
+        }
+        let sets = match read_u16(self.data, 12) {
+            0 => return false,
+            offset => offset_slice(self.data, offset as usize),
+        };
+        if set >= read_u16(sets, 2) {
+            return false;
+        }
+        let coverage = offset_slice(sets, read_u32(sets, 4 + set as usize * 4) as usize);
+        coverage_index(coverage, glyph).is_some()
+    }
+
+    /// Whether a lookup with `flag` skips over `glyph`.
+    pub(crate) fn ignores(&self, flag: u16, mark_set: Option<u16>, glyph: u16) -> bool {
+        let class = self.glyph_class(glyph);
+        match class {
+            GLYPH_CLASS_BASE => flag & IGNORE_BASE_GLYPHS != 0,
+            GLYPH_CLASS_LIGATURE => flag & IGNORE_LIGATURES != 0,
+            GLYPH_CLASS_MARK => {
+                if flag & IGNORE_MARKS != 0 {
+                    return true;
+                }
+                if let Some(set) = mark_set {
+                    return !self.in_mark_set(set, glyph);
+                }
+                let attach_type = (flag & MARK_ATTACHMENT_TYPE) >> 8;
+                attach_type != 0 && self.mark_attach_class(glyph) != attach_type
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Index of `glyph` in a coverage table.
+pub(crate) fn coverage_index(coverage: &[u8], glyph: u16) -> Option<usize> {
+    match read_u16(coverage, 0) {
+        1 => {
+            let count = read_u16(coverage, 2) as usize;
+            let (mut lo, mut hi) = (0, count);
+            while lo < hi {
+                let mid = (lo + hi) / 2;
+                let g = read_u16(coverage, 4 + mid * 2);
+                if glyph < g {
+                    hi = mid;
+                } else if glyph > g {
+                    lo = mid + 1;
+                } else {
+                    return Some(mid);
+                }
+            }
+            None
+        }
+        2 => {
+            let count = read_u16(coverage, 2) as usize;
+            let (mut lo, mut hi) = (0, count);
+            while lo < hi {
+                let mid = (lo + hi) / 2;
+                let record = 4 + mid * 6;
+                let start = read_u16(coverage, record);
+                let end = read_u16(coverage, record + 2);
+                if glyph < start {
+                    hi = mid;
+                } else if glyph > end {
+                    lo = mid + 1;
+                } else {
+                    return Some(read_u16(coverage, record + 4) as usize + (glyph - start) as usize);
+                }
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Class of `glyph` in a class definition table (0 when unlisted).
+pub(crate) fn glyph_class(class_def: &[u8], glyph: u16) -> u16 {
+    match read_u16(class_def, 0) {
+        1 => {
+            let start = read_u16(class_def, 2);
+            let count = read_u16(class_def, 4);
+            if glyph >= start && glyph - start < count {
+                read_u16(class_def, 6 + (glyph - start) as usize * 2)
+            } else {
+                0
+            }
+        }
+        2 => {
+            let count = read_u16(class_def, 2) as usize;
+            let (mut lo, mut hi) = (0, count);
+            while lo < hi {
+                let mid = (lo + hi) / 2;
+                let record = 4 + mid * 6;
+                let start = read_u16(class_def, record);
+                let end = read_u16(class_def, record + 2);
+                if glyph < start {
+                    hi = mid;
+                } else if glyph > end {
+                    lo = mid + 1;
+                } else {
+                    return read_u16(class_def, record + 4);
+                }
+            }
+            0
+        }
+        _ => 0,
+    }
+}
+
+/// Size in bytes of a GPOS value record with the given format.
+#[inline]
+pub(crate) fn value_record_size(format: u16) -> usize {
+    format.count_ones() as usize * 2
+}
+
+/// Placement and advance adjustments of a GPOS value record, in font units.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ValueRecord {
+    pub(crate) x_placement: i16,
+    pub(crate) y_placement: i16,
+    pub(crate) x_advance: i16,
+}
+
+pub(crate) fn value_record(record: &[u8], format: u16) -> ValueRecord {
+    let field = |bit: u16| {
+        if format & bit == 0 {
+            return 0;
+        }
+        read_u16(record, value_record_size(format & (bit - 1))) as i16
+    };
+    ValueRecord {
+        x_placement: field(0x0001),
+        y_placement: field(0x0002),
+        x_advance: field(0x0004),
+    }
+}
+
+/// An attachment point in font units.
+pub(crate) type Anchor = (f32, f32);
+
+/// Coordinates of an anchor table (all formats), in font units.
+pub(crate) fn anchor(data: &[u8]) -> Anchor {
+    (read_u16(data, 2) as i16 as f32, read_u16(data, 4) as i16 as f32)
+}
+
+#[inline]
+pub(crate) fn offset_slice(data: &[u8], offset: usize) -> &[u8] {
+    data.get(offset..).unwrap_or(&[])
+}
+
+/// Adjustments of a pair positioning subtable for `left` followed by `right`.
+pub(crate) fn pair_adjustment(subtable: &[u8], left: u16, right: u16) -> Option<(ValueRecord, ValueRecord)> {
+    let coverage = offset_slice(subtable, read_u16(subtable, 2) as usize);
+    let coverage_idx = coverage_index(coverage, left)?;
+    let format1 = read_u16(subtable, 4);
+    let format2 = read_u16(subtable, 6);
+    let size1 = value_record_size(format1);
+    let size2 = value_record_size(format2);
+
+    match read_u16(subtable, 0) {
+        1 => {
+            let set_count = read_u16(subtable, 8) as usize;
+            if coverage_idx >= set_count {
+                return None;
+            }
+            let pair_set = offset_slice(subtable, read_u16(subtable, 10 + coverage_idx * 2) as usize);
+            let count = read_u16(pair_set, 0) as usize;
+            let record_size = 2 + size1 + size2;
+
+            let (mut lo, mut hi) = (0, count);
+            while lo < hi {
+                let mid = (lo + hi) / 2;
+                let record = 2 + mid * record_size;
+                let second = read_u16(pair_set, record);
+                if right < second {
+                    hi = mid;
+                } else if right > second {
+                    lo = mid + 1;
+                } else {
+                    let values = offset_slice(pair_set, record + 2);
+                    return Some((value_record(values, format1), value_record(offset_slice(values, size1), format2)));
+                }
+            }
+            None
+        }
+        2 => {
+            let class_def1 = offset_slice(subtable, read_u16(subtable, 8) as usize);
+            let class_def2 = offset_slice(subtable, read_u16(subtable, 10) as usize);
+            let class1_count = read_u16(subtable, 12) as usize;
+            let class2_count = read_u16(subtable, 14) as usize;
+
+            let class1 = glyph_class(class_def1, left) as usize;
+            let class2 = glyph_class(class_def2, right) as usize;
+            if class1 >= class1_count || class2 >= class2_count {
+                return None;
+            }
+
+            let record = 16 + (class1 * class2_count + class2) * (size1 + size2);
+            let values = offset_slice(subtable, record);
+            Some((value_record(values, format1), value_record(offset_slice(values, size1), format2)))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,250 @@
+use crate::svg::rasterizer::raster::Point;
+use crate::svg::rasterizer::tags::path::PathCommand;
+use crate::svg::rasterizer::tags::text::font::{read_i16, read_u16, read_u32};
+use crate::utils::compat::Vec;
+
+/// Composite glyphs referencing each other deeper than this are malformed.
+const MAX_COMPONENT_DEPTH: u8 = 8;
+
+// Simple glyph point flags
+const ON_CURVE: u8 = 0x01;
+const X_SHORT: u8 = 0x02;
+const Y_SHORT: u8 = 0x04;
+const REPEAT: u8 = 0x08;
+const X_SAME_OR_POSITIVE: u8 = 0x10;
+const Y_SAME_OR_POSITIVE: u8 = 0x20;
+
+// Composite glyph component flags
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const ARGS_ARE_XY_VALUES: u16 = 0x0002;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+/// Reader for TrueType `glyf` outlines.
+#[derive(Clone)]
+pub(crate) struct GlyphOutlines {
+    glyf: &'static [u8],
+    loca: &'static [u8],
+    long_loca: bool,
+}
+
+/// 2x3 matrix applied to component glyphs, in font units.
+type Affine = [f32; 6];
+
+const IDENTITY: Affine = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+impl GlyphOutlines {
+    pub(crate) fn new(glyf: &'static [u8], loca: &'static [u8], long_loca: bool) -> Self {
+        Self { glyf, loca, long_loca }
+    }
+
+    /// Outline of a glyph in font units with the y axis pointing up.
+    /// Empty glyphs (such as spaces) yield no commands.
+    pub(crate) fn outline(&self, glyph_id: u16) -> Vec<PathCommand> {
+        let mut commands = Vec::new();
+        self.append(glyph_id, &IDENTITY, 0, &mut commands);
+        commands
+    }
+
+    fn glyph_data(&self, glyph_id: u16) -> &'static [u8] {
+        let idx = glyph_id as usize;
+        let (start, end) = if self.long_loca {
+            (read_u32(self.loca, idx * 4) as usize, read_u32(self.loca, idx * 4 + 4) as usize)
+        } else {
+            (read_u16(self.loca, idx * 2) as usize * 2, read_u16(self.loca, idx * 2 + 2) as usize * 2)
+        };
+
+        if end <= start {
+            return &[];
+        }
+        self.glyf.get(start..end).unwrap_or(&[])
+    }
+
+    fn append(&self, glyph_id: u16, matrix: &Affine, depth: u8, out: &mut Vec<PathCommand>) {
+        let data = self.glyph_data(glyph_id);
+        if data.len() < 10 {
+            return;
+        }
+
+        let contour_count = read_i16(data, 0);
+        if contour_count >= 0 {
+            append_simple(data, contour_count as usize, matrix, out);
+        } else if depth < MAX_COMPONENT_DEPTH {
+            self.append_composite(data, matrix, depth, out);
+        }
+    }
+
+    fn append_composite(&self, data: &[u8], matrix: &Affine, depth: u8, out: &mut Vec<PathCommand>) {
+        let mut pos = 10;
+
+        loop {
+            let flags = read_u16(data, pos);
+            let component = read_u16(data, pos + 2);
+            pos += 4;
+
+            let (arg1, arg2) = if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+                let args = (read_i16(data, pos) as f32, read_i16(data, pos + 2) as f32);
+                pos += 4;
+                args
+            } else {
+                let args = (data.get(pos).map(|&b| b as i8).unwrap_or(0) as f32,
+                            data.get(pos + 1).map(|&b| b as i8).unwrap_or(0) as f32);
+                pos += 2;
+                args
+            };
+
+            let f2dot14 = |offset: usize| read_i16(data, offset) as f32 / 16384.0;
+            let (mut a, mut b, mut c, mut d) = (1.0, 0.0, 0.0, 1.0);
+            if flags & WE_HAVE_A_SCALE != 0 {
+                a = f2dot14(pos);
+                d = a;
+                pos += 2;
+            } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+                a = f2dot14(pos);
+                d = f2dot14(pos + 2);
+                pos += 4;
+            } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+                a = f2dot14(pos);
+                b = f2dot14(pos + 2);
+                c = f2dot14(pos + 4);
+                d = f2dot14(pos + 6);
+                pos += 8;
+            }
+
+            // Point-matched anchoring is rare and not supported; such components are left unshifted
+            let (dx, dy) = if flags & ARGS_ARE_XY_VALUES != 0 { (arg1, arg2) } else { (0.0, 0.0) };
+
+            let component_matrix = multiply(matrix, &[a, b, c, d, dx, dy]);
+            self.append(component, &component_matrix, depth + 1, out);
+
+            if flags & MORE_COMPONENTS == 0 || pos >= data.len() {
+                break;
+            }
+        }
+    }
+}
+
+/// `outer · inner`: applies `inner` first.
+fn multiply(outer: &Affine, inner: &Affine) -> Affine {
+    let [a, b, c, d, e, f] = *outer;
+    let [ia, ib, ic, id, ie, i_f] = *inner;
+    [
+        a * ia + c * ib,
+        b * ia + d * ib,
+        a * ic + c * id,
+        b * ic + d * id,
+        a * ie + c * i_f + e,
+        b * ie + d * i_f + f,
+    ]
+}
+
+fn append_simple(data: &[u8], contour_count: usize, matrix: &Affine, out: &mut Vec<PathCommand>) {
+    let mut end_points = Vec::with_capacity(contour_count);
+    for i in 0..contour_count {
+        end_points.push(read_u16(data, 10 + i * 2) as usize);
+    }
+    let Some(&last) = end_points.last() else { return };
+    let point_count = last + 1;
+
+    let instructions_len = read_u16(data, 10 + contour_count * 2) as usize;
+    let mut pos = 12 + contour_count * 2 + instructions_len;
+
+    let mut flags = Vec::with_capacity(point_count);
+    while flags.len() < point_count {
+        let Some(&flag) = data.get(pos) else { return };
+        pos += 1;
+        flags.push(flag);
+        if flag & REPEAT != 0 {
+            let repeat = data.get(pos).copied().unwrap_or(0);
+            pos += 1;
+            for _ in 0..repeat {
+                flags.push(flag);
+            }
+        }
+    }
+    flags.truncate(point_count);
+
+    let mut xs = Vec::with_capacity(point_count);
+    let mut value = 0i32;
+    for &flag in &flags {
+        value += read_coordinate(data, &mut pos, flag, X_SHORT, X_SAME_OR_POSITIVE);
+        xs.push(value);
+    }
+
+    let mut ys = Vec::with_capacity(point_count);
+    value = 0;
+    for &flag in &flags {
+        value += read_coordinate(data, &mut pos, flag, Y_SHORT, Y_SAME_OR_POSITIVE);
+        ys.push(value);
+    }
+
+    let [a, b, c, d, e, f] = *matrix;
+    let points: Vec<(Point, bool)> = (0..point_count).map(|i| {
+        let (x, y) = (xs[i] as f32, ys[i] as f32);
+        (Point { x: a * x + c * y + e, y: b * x + d * y + f }, flags[i] & ON_CURVE != 0)
+    }).collect();
+
+    let mut start = 0;
+    for &end in &end_points {
+        if end >= point_count || end < start {
+            break;
+        }
+        append_contour(&points[start..=end], out);
+        start = end + 1;
+    }
+}
+
+fn read_coordinate(data: &[u8], pos: &mut usize, flag: u8, short: u8, same_or_positive: u8) -> i32 {
+    if flag & short != 0 {
+        let delta = data.get(*pos).copied().unwrap_or(0) as i32;
+        *pos += 1;
+        if flag & same_or_positive != 0 { delta } else { -delta }
+    } else if flag & same_or_positive != 0 {
+        0
+    } else {
+        let delta = read_i16(data, *pos) as i32;
+        *pos += 2;
+        delta
+    }
+}
+
+/// Converts one contour of on/off-curve points into quadratic path commands,
+/// inserting the implied on-curve points between consecutive off-curve ones.
+fn append_contour(points: &[(Point, bool)], out: &mut Vec<PathCommand>) {
+    if points.len() < 2 {
+        return;
+    }
+
+    let midpoint = |p: Point, q: Point| Point { x: (p.x + q.x) / 2.0, y: (p.y + q.y) / 2.0 };
+
+    // Start on an on-curve point, or between the first two off-curve points
+    let n = points.len();
+    let (start, first, remaining) = match points.iter().position(|&(_, on)| on) {
+        Some(i) => (points[i].0, i, n - 1),
+        None => (midpoint(points[0].0, points[1].0), 0, n),
+    };
+    out.push(PathCommand::MoveTo(start));
+
+    let mut control: Option<Point> = None;
+    for k in 1..=remaining {
+        let (p, on) = points[(first + k) % n];
+        if on {
+            match control.take() {
+                Some(cp) => out.push(PathCommand::QuadraticBezier(cp, p)),
+                None => out.push(PathCommand::LineTo(p)),
+            }
+        } else {
+            if let Some(cp) = control {
+                out.push(PathCommand::QuadraticBezier(cp, midpoint(cp, p)));
+            }
+            control = Some(p);
+        }
+    }
+
+    if let Some(cp) = control {
+        out.push(PathCommand::QuadraticBezier(cp, start));
+    }
+    out.push(PathCommand::ClosePath);
+}
@@ -0,0 +1,961 @@
+//! OpenType shaping of one run of text: character to glyph mapping, GSUB
+//! substitutions (including Arabic joining forms and Indic pre-base matra
+//! reordering) and GPOS positioning.
+
+use crate::svg::rasterizer::tags::text::bidi::{bidi_class, mirror, BidiClass};
+use crate::svg::rasterizer::tags::text::font::{read_u16, FontFace};
+use crate::svg::rasterizer::tags::text::opentype::{
+    anchor, Anchor, coverage_index, glyph_class, offset_slice, pair_adjustment, value_record,
+    value_record_size, Gdef, LayoutTable, Lookup, GLYPH_CLASS_MARK,
+};
+use crate::utils::compat::{Vec, vec};
+
+const GSUB_EXTENSION: u16 = 7;
+const GPOS_EXTENSION: u16 = 9;
+
+/// Limit on contextual lookups invoking further lookups.
+const MAX_NESTING: u8 = 6;
+
+// Feature masks; every glyph carries `MASK_GLOBAL` plus at most one joining form
+const MASK_GLOBAL: u32 = 1;
+const MASK_ISOL: u32 = 1 << 1;
+const MASK_FINA: u32 = 1 << 2;
+const MASK_MEDI: u32 = 1 << 3;
+const MASK_INIT: u32 = 1 << 4;
+
+const COMMON_FEATURES: &[([u8; 4], u32)] = &[
+    (*b"ccmp", MASK_GLOBAL),
+    (*b"locl", MASK_GLOBAL),
+];
+
+const ARABIC_FEATURES: &[([u8; 4], u32)] = &[
+    (*b"isol", MASK_ISOL),
+    (*b"fina", MASK_FINA),
+    (*b"medi", MASK_MEDI),
+    (*b"init", MASK_INIT),
+    (*b"rlig", MASK_GLOBAL),
+    (*b"calt", MASK_GLOBAL),
+    (*b"liga", MASK_GLOBAL),
+    (*b"mset", MASK_GLOBAL),
+];
+
+const INDIC_FEATURES: &[([u8; 4], u32)] = &[
+    (*b"nukt", MASK_GLOBAL),
+    (*b"akhn", MASK_GLOBAL),
+    (*b"rkrf", MASK_GLOBAL),
+    (*b"blwf", MASK_GLOBAL),
+    (*b"half", MASK_GLOBAL),
+    (*b"pstf", MASK_GLOBAL),
+    (*b"vatu", MASK_GLOBAL),
+    (*b"cjct", MASK_GLOBAL),
+    (*b"pres", MASK_GLOBAL),
+    (*b"abvs", MASK_GLOBAL),
+    (*b"blws", MASK_GLOBAL),
+    (*b"psts", MASK_GLOBAL),
+    (*b"haln", MASK_GLOBAL),
+    (*b"calt", MASK_GLOBAL),
+    (*b"liga", MASK_GLOBAL),
+];
+
+const DEFAULT_FEATURES: &[([u8; 4], u32)] = &[
+    (*b"rlig", MASK_GLOBAL),
+    (*b"calt", MASK_GLOBAL),
+    (*b"liga", MASK_GLOBAL),
+    (*b"clig", MASK_GLOBAL),
+];
+
+const POSITIONING_FEATURES: &[[u8; 4]] = &[*b"dist", *b"abvm", *b"blwm", *b"mark", *b"mkmk"];
+
+// OpenType script tags
+const TAG_DFLT: [u8; 4] = *b"DFLT";
+const TAG_ARAB: [u8; 4] = *b"arab";
+const TAG_BENG: [u8; 4] = *b"beng";
+const TAG_BNG2: [u8; 4] = *b"bng2";
+const TAG_CYRL: [u8; 4] = *b"cyrl";
+const TAG_DEV2: [u8; 4] = *b"dev2";
+const TAG_DEVA: [u8; 4] = *b"deva";
+const TAG_GREK: [u8; 4] = *b"grek";
+const TAG_HEBR: [u8; 4] = *b"hebr";
+const TAG_LATN: [u8; 4] = *b"latn";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Script {
+    Common,
+    Latin,
+    Greek,
+    Cyrillic,
+    Hebrew,
+    Arabic,
+    Devanagari,
+    Bengali,
+}
+
+impl Script {
+    /// OpenType script tags to try, in order of preference.
+    fn tags(self) -> &'static [[u8; 4]] {
+        match self {
+            Script::Common => &[TAG_DFLT, TAG_LATN],
+            Script::Latin => &[TAG_LATN, TAG_DFLT],
+            Script::Greek => &[TAG_GREK, TAG_DFLT],
+            Script::Cyrillic => &[TAG_CYRL, TAG_DFLT],
+            Script::Hebrew => &[TAG_HEBR, TAG_DFLT],
+            Script::Arabic => &[TAG_ARAB, TAG_DFLT],
+            Script::Devanagari => &[TAG_DEV2, TAG_DEVA, TAG_DFLT],
+            Script::Bengali => &[TAG_BNG2, TAG_BENG, TAG_DFLT],
+        }
+    }
+
+    fn is_indic(self) -> bool {
+        matches!(self, Script::Devanagari | Script::Bengali)
+    }
+}
+
+/// Script of a character; punctuation, digits and spaces are `Common`.
+pub(crate) fn char_script(c: char) -> Script {
+    match c as u32 {
+        0x0041..=0x005A | 0x0061..=0x007A | 0x00C0..=0x024F | 0x1E00..=0x1EFF => Script::Latin,
+        0x0370..=0x03FF | 0x1F00..=0x1FFF => Script::Greek,
+        0x0400..=0x052F => Script::Cyrillic,
+        0x0591..=0x05FF | 0xFB1D..=0xFB4F => Script::Hebrew,
+        0x0600..=0x06FF | 0x0750..=0x077F | 0x0870..=0x08FF | 0xFB50..=0xFDFF | 0xFE70..=0xFEFE => Script::Arabic,
+        0x0900..=0x097F => Script::Devanagari,
+        0x0980..=0x09FF => Script::Bengali,
+        _ => Script::Common,
+    }
+}
+
+/// A glyph produced by shaping. Metrics are in font units with y up.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ShapedGlyph {
+    pub(crate) id: u16,
+    /// Index of the first character the glyph was formed from.
+    pub(crate) cluster: usize,
+    pub(crate) advance: f32,
+    /// Pair adjustment between this glyph and the next one in logical order.
+    pub(crate) kern: f32,
+    pub(crate) x_offset: f32,
+    pub(crate) y_offset: f32,
+    /// Index within the run of the glyph this mark is attached to; the
+    /// offsets are then relative to that glyph's origin.
+    pub(crate) attach: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct GlyphInfo {
+    id: u16,
+    cluster: usize,
+    mask: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Position {
+    advance: f32,
+    kern: f32,
+    x_offset: f32,
+    y_offset: f32,
+    attach: Option<usize>,
+}
+
+/// Shapes one run of characters sharing a script and a bidi direction.
+/// `chars` pairs each character with its cluster index; the glyphs come
+/// back in logical order.
+pub(crate) fn shape(face: &FontFace, chars: &[(char, usize)], script: Script, rtl: bool, kerning: bool) -> Vec<ShapedGlyph> {
+    let mut chars: Vec<(char, usize)> = chars.iter()
+        .map(|&(c, cluster)| (if rtl { mirror(c) } else { c }, cluster))
+        .collect();
+
+    if script.is_indic() {
+        reorder_pre_base_matras(&mut chars);
+    }
+
+    let forms = if script == Script::Arabic { joining_forms(&chars) } else { vec![0; chars.len()] };
+
+    let mut buffer: Vec<GlyphInfo> = chars.iter().zip(&forms)
+        .filter(|((c, _), _)| !is_default_ignorable(*c))
+        .map(|(&(c, cluster), &form)| GlyphInfo { id: face.glyph_id(c), cluster, mask: MASK_GLOBAL | form })
+        .collect();
+
+    if let Some(gsub) = face.gsub {
+        let engine = Substitution { table: gsub, gdef: face.gdef };
+        let features = match script {
+            Script::Arabic => ARABIC_FEATURES,
+            s if s.is_indic() => INDIC_FEATURES,
+            _ => DEFAULT_FEATURES,
+        };
+        for (index, mask) in plan_lookups(gsub, script, COMMON_FEATURES.iter().chain(features)) {
+            engine.apply_lookup(&mut buffer, index, mask);
+        }
+    }
+
+    let mut positions: Vec<Position> = buffer.iter().map(|g| {
+        let is_mark = face.gdef.map(|gdef| gdef.glyph_class(g.id) == GLYPH_CLASS_MARK).unwrap_or(false);
+        // Marks never advance the pen, whatever their horizontal metrics say
+        let advance = if is_mark { 0.0 } else { face.glyph_advance(g.id) };
+        Position { advance, ..Position::default() }
+    }).collect();
+
+    let mut kerned = false;
+    if let Some(gpos) = face.gpos {
+        let engine = Positioning { table: gpos, gdef: face.gdef };
+        let kern = [(*b"kern", MASK_GLOBAL)];
+        let kern_features = if kerning { &kern[..] } else { &[] };
+        kerned = kerning && !gpos.script_feature_lookups(script.tags(), b"kern").is_empty();
+
+        let features: Vec<([u8; 4], u32)> = POSITIONING_FEATURES.iter().map(|&t| (t, MASK_GLOBAL)).collect();
+        for (index, _) in plan_lookups(gpos, script, kern_features.iter().chain(features.iter())) {
+            engine.apply_lookup(&buffer, &mut positions, index);
+        }
+    }
+
+    if kerning && !kerned {
+        legacy_kerning(face, &buffer, &mut positions);
+    }
+
+    buffer.iter().zip(positions).map(|(g, p)| ShapedGlyph {
+        id: g.id,
+        cluster: g.cluster,
+        advance: p.advance,
+        kern: p.kern,
+        x_offset: p.x_offset,
+        y_offset: p.y_offset,
+        attach: p.attach,
+    }).collect()
+}
+
+/// Lookup indices of the requested features for the script, in the order they
+/// must be applied, each with the masks of the features that use it.
+fn plan_lookups<'f>(table: LayoutTable, script: Script, features: impl Iterator<Item = &'f ([u8; 4], u32)>) -> Vec<(u16, u32)> {
+    let mut plan: Vec<(u16, u32)> = Vec::new();
+    for (tag, mask) in features {
+        for index in table.script_feature_lookups(script.tags(), tag) {
+            match plan.iter_mut().find(|(i, _)| *i == index) {
+                Some(entry) => entry.1 |= mask,
+                None => plan.push((index, *mask)),
+            }
+        }
+    }
+    plan.sort_unstable_by_key(|(index, _)| *index);
+    plan
+}
+
+fn legacy_kerning(face: &FontFace, buffer: &[GlyphInfo], positions: &mut [Position]) {
+    let mut prev: Option<usize> = None;
+    for i in 0..buffer.len() {
+        if positions[i].advance == 0.0 {
+            continue;
+        }
+        if let Some(p) = prev {
+            positions[p].kern += face.legacy_kerning(buffer[p].id, buffer[i].id);
+        }
+        prev = Some(i);
+    }
+}
+
+/// Characters that are never rendered (joiners, directional marks, variation
+/// selectors, soft hyphen).
+pub(crate) fn is_default_ignorable(c: char) -> bool {
+    matches!(c as u32, 0x00AD | 0x034F | 0x061C | 0x200B..=0x200F | 0x202A..=0x202E | 0x2060..=0x2069 | 0xFE00..=0xFE0F | 0xFEFF | 0xE0100..=0xE01EF)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Joining {
+    /// Non-joining
+    U,
+    /// Joins on the right (preceding) side only
+    R,
+    /// Joins on both sides
+    D,
+    /// Join causing (tatweel, ZWJ)
+    C,
+    /// Transparent (marks)
+    T,
+}
+
+fn joining_type(c: char) -> Joining {
+    match c as u32 {
+        0x200D | 0x0640 | 0x07FA => Joining::C,
+        0x0622..=0x0625 | 0x0627 | 0x0629 | 0x062F..=0x0632 | 0x0648 | 0x0671..=0x0673
+        | 0x0675..=0x0677 | 0x0688..=0x0699 | 0x06C0 | 0x06C3..=0x06CB | 0x06CD | 0x06CF
+        | 0x06D2 | 0x06D3 | 0x06D5 | 0x06EE | 0x06EF | 0x0759..=0x075B | 0x076B | 0x076C
+        | 0x0771 | 0x0773 | 0x0774 | 0x0778 | 0x0779 | 0x08AA..=0x08AC | 0x08AE | 0x08B1
+        | 0x08B2 | 0x08B9 => Joining::R,
+        0x0620 | 0x0626 | 0x0628 | 0x062A..=0x062E | 0x0633..=0x063F | 0x0641..=0x0647
+        | 0x0649 | 0x064A | 0x066E | 0x066F | 0x0678..=0x0687 | 0x069A..=0x06BF | 0x06C1
+        | 0x06C2 | 0x06CC | 0x06CE | 0x06D0 | 0x06D1 | 0x06FA..=0x06FC | 0x06FF
+        | 0x0750..=0x0758 | 0x075C..=0x076A | 0x076D..=0x0770 | 0x0772 | 0x0775..=0x0777
+        | 0x077A..=0x077F | 0x08A0..=0x08A9 | 0x08AF | 0x08B0 | 0x08B3..=0x08B8
+        | 0x08BA..=0x08C8 => Joining::D,
+        _ if bidi_class(c) == BidiClass::NSM => Joining::T,
+        _ => Joining::U,
+    }
+}
+
+/// Contextual form mask (isol, init, medi, fina) of each character.
+fn joining_forms(chars: &[(char, usize)]) -> Vec<u32> {
+    let types: Vec<Joining> = chars.iter().map(|&(c, _)| joining_type(c)).collect();
+    let mut forms = vec![0; chars.len()];
+
+    for i in 0..chars.len() {
+        let t = types[i];
+        if matches!(t, Joining::T | Joining::U) {
+            continue;
+        }
+        let prev = types[..i].iter().rev().find(|t| **t != Joining::T);
+        let next = types[i + 1..].iter().find(|t| **t != Joining::T);
+
+        let joins_prev = matches!(t, Joining::R | Joining::D | Joining::C)
+            && matches!(prev, Some(Joining::D | Joining::C));
+        let joins_next = matches!(t, Joining::D | Joining::C)
+            && matches!(next, Some(Joining::R | Joining::D | Joining::C));
+
+        forms[i] = match (joins_prev, joins_next) {
+            (true, true) => MASK_MEDI,
+            (true, false) => MASK_FINA,
+            (false, true) => MASK_INIT,
+            (false, false) => MASK_ISOL,
+        };
+    }
+
+    forms
+}
+
+fn is_indic_consonant(c: char) -> bool {
+    matches!(c as u32, 0x0915..=0x0939 | 0x0958..=0x095F | 0x0978..=0x097F | 0x0995..=0x09B9 | 0x09DC..=0x09DF)
+}
+
+fn is_indic_halant(c: char) -> bool {
+    matches!(c as u32, 0x094D | 0x09CD)
+}
+
+fn is_indic_nukta(c: char) -> bool {
+    matches!(c as u32, 0x093C | 0x09BC)
+}
+
+fn is_pre_base_matra(c: char) -> bool {
+    matches!(c as u32, 0x093F | 0x094E | 0x09BF | 0x09C7 | 0x09C8)
+}
+
+/// Moves pre-base dependent vowels in front of the consonant cluster they
+/// follow in logical order, where fonts expect to find them.
+fn reorder_pre_base_matras(chars: &mut [(char, usize)]) {
+    for i in 1..chars.len() {
+        if !is_pre_base_matra(chars[i].0) {
+            continue;
+        }
+        let mut start = i;
+        // Walk back over the cluster: consonants joined by halants, with nuktas
+        while start > 0 {
+            let prev = chars[start - 1].0;
+            if is_indic_consonant(prev) {
+                start -= 1;
+                let joined = start >= 2 && is_indic_halant(chars[start - 1].0) && is_indic_consonant(chars[start - 2].0);
+                if !joined {
+                    break;
+                }
+                start -= 1;
+            } else if is_indic_nukta(prev) || is_indic_halant(prev) {
+                start -= 1;
+            } else {
+                break;
+            }
+        }
+        chars[start..=i].rotate_right(1);
+    }
+}
+
+/// Lookup flag handling: which glyphs a lookup skips over.
+struct Skipper<'a> {
+    gdef: Option<Gdef<'a>>,
+    flag: u16,
+    mark_set: Option<u16>,
+}
+
+impl<'a> Skipper<'a> {
+    fn new(lookup: &Lookup, gdef: Option<Gdef<'a>>) -> Self {
+        Self { gdef, flag: lookup.flag, mark_set: lookup.mark_set }
+    }
+
+    fn skips(&self, glyph: u16) -> bool {
+        self.gdef.map(|g| g.ignores(self.flag, self.mark_set, glyph)).unwrap_or(false)
+    }
+
+    fn next(&self, buffer: &[GlyphInfo], from: usize) -> Option<usize> {
+        (from + 1..buffer.len()).find(|&j| !self.skips(buffer[j].id))
+    }
+
+    fn prev(&self, buffer: &[GlyphInfo], from: usize) -> Option<usize> {
+        (0..from).rev().find(|&j| !self.skips(buffer[j].id))
+    }
+
+    /// Positions of `count` glyphs after `from` accepted by `matches(k, glyph)`.
+    fn match_forward(&self, buffer: &[GlyphInfo], from: usize, count: usize, matches: impl Fn(usize, u16) -> bool) -> Option<Vec<usize>> {
+        let mut positions = Vec::with_capacity(count);
+        let mut j = from;
+        for k in 0..count {
+            j = self.next(buffer, j)?;
+            if !matches(k, buffer[j].id) {
+                return None;
+            }
+            positions.push(j);
+        }
+        Some(positions)
+    }
+
+    /// Whether `count` glyphs before `from`, closest first, are accepted by `matches(k, glyph)`.
+    fn match_backward(&self, buffer: &[GlyphInfo], from: usize, count: usize, matches: impl Fn(usize, u16) -> bool) -> bool {
+        let mut j = from;
+        for k in 0..count {
+            match self.prev(buffer, j) {
+                Some(p) if matches(k, buffer[p].id) => j = p,
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+/// Input positions of a matched (chained) context rule, with the lookup
+/// records to apply at them.
+struct ContextMatch<'a> {
+    positions: Vec<usize>,
+    records: &'a [u8],
+    record_count: usize,
+}
+
+/// Matches a contextual subtable (GSUB 5 / GPOS 7) at `i`.
+fn match_context<'a>(sub: &'a [u8], skipper: &Skipper, buffer: &[GlyphInfo], i: usize) -> Option<ContextMatch<'a>> {
+    let glyph = buffer[i].id;
+    match read_u16(sub, 0) {
+        1 | 2 => {
+            let format2 = read_u16(sub, 0) == 2;
+            let coverage_idx = coverage_index(offset_slice(sub, read_u16(sub, 2) as usize), glyph)?;
+            let class_def = offset_slice(sub, read_u16(sub, 4) as usize);
+            let (count_at, sets_at) = if format2 { (6, 8) } else { (4, 6) };
+            let set_idx = if format2 { glyph_class(class_def, glyph) as usize } else { coverage_idx };
+            if set_idx >= read_u16(sub, count_at) as usize {
+                return None;
+            }
+            let set = match read_u16(sub, sets_at + set_idx * 2) {
+                0 => return None,
+                offset => offset_slice(sub, offset as usize),
+            };
+
+            (0..read_u16(set, 0) as usize).find_map(|r| {
+                let rule = offset_slice(set, read_u16(set, 2 + r * 2) as usize);
+                let input_count = read_u16(rule, 0) as usize;
+                let record_count = read_u16(rule, 2) as usize;
+                let rest = skipper.match_forward(buffer, i, input_count.saturating_sub(1), |k, g| {
+                    let value = read_u16(rule, 4 + k * 2);
+                    if format2 { glyph_class(class_def, g) == value } else { g == value }
+                })?;
+                let mut positions = vec![i];
+                positions.extend(rest);
+                Some(ContextMatch {
+                    positions,
+                    records: offset_slice(rule, 4 + input_count.saturating_sub(1) * 2),
+                    record_count,
+                })
+            })
+        }
+        3 => {
+            let input_count = read_u16(sub, 2) as usize;
+            let record_count = read_u16(sub, 4) as usize;
+            let coverage = |k: usize| offset_slice(sub, read_u16(sub, 6 + k * 2) as usize);
+            if input_count == 0 || coverage_index(coverage(0), glyph).is_none() {
+                return None;
+            }
+            let rest = skipper.match_forward(buffer, i, input_count - 1, |k, g| coverage_index(coverage(k + 1), g).is_some())?;
+            let mut positions = vec![i];
+            positions.extend(rest);
+            Some(ContextMatch { positions, records: offset_slice(sub, 6 + input_count * 2), record_count })
+        }
+        _ => None,
+    }
+}
+
+/// Matches a chained contextual subtable (GSUB 6 / GPOS 8) at `i`.
+fn match_chain_context<'a>(sub: &'a [u8], skipper: &Skipper, buffer: &[GlyphInfo], i: usize) -> Option<ContextMatch<'a>> {
+    let glyph = buffer[i].id;
+    match read_u16(sub, 0) {
+        1 | 2 => {
+            let format2 = read_u16(sub, 0) == 2;
+            let coverage_idx = coverage_index(offset_slice(sub, read_u16(sub, 2) as usize), glyph)?;
+            let class_defs = [4, 6, 8].map(|at| offset_slice(sub, read_u16(sub, at) as usize));
+            let (count_at, sets_at) = if format2 { (10, 12) } else { (4, 6) };
+            let set_idx = if format2 { glyph_class(class_defs[1], glyph) as usize } else { coverage_idx };
+            if set_idx >= read_u16(sub, count_at) as usize {
+                return None;
+            }
+            let set = match read_u16(sub, sets_at + set_idx * 2) {
+                0 => return None,
+                offset => offset_slice(sub, offset as usize),
+            };
+
+            // Compares a rule value against a glyph, by id or by class in `class_def`
+            let accepts = |class_def: &[u8], value: u16, g: u16| {
+                if format2 { glyph_class(class_def, g) == value } else { g == value }
+            };
+
+            (0..read_u16(set, 0) as usize).find_map(|r| {
+                let rule = offset_slice(set, read_u16(set, 2 + r * 2) as usize);
+                let backtrack_count = read_u16(rule, 0) as usize;
+                let input_at = 2 + backtrack_count * 2;
+                let input_count = read_u16(rule, input_at) as usize;
+                let lookahead_at = input_at + 2 + input_count.saturating_sub(1) * 2;
+                let lookahead_count = read_u16(rule, lookahead_at) as usize;
+                let records_at = lookahead_at + 2 + lookahead_count * 2;
+
+                let backtrack_ok = skipper.match_backward(buffer, i, backtrack_count, |k, g| {
+                    accepts(class_defs[0], read_u16(rule, 2 + k * 2), g)
+                });
+                if !backtrack_ok {
+                    return None;
+                }
+                let rest = skipper.match_forward(buffer, i, input_count.saturating_sub(1), |k, g| {
+                    accepts(class_defs[1], read_u16(rule, input_at + 2 + k * 2), g)
+                })?;
+                let last = rest.last().copied().unwrap_or(i);
+                skipper.match_forward(buffer, last, lookahead_count, |k, g| {
+                    accepts(class_defs[2], read_u16(rule, lookahead_at + 2 + k * 2), g)
+                })?;
+
+                let mut positions = vec![i];
+                positions.extend(rest);
+                Some(ContextMatch {
+                    positions,
+                    records: offset_slice(rule, records_at + 2),
+                    record_count: read_u16(rule, records_at) as usize,
+                })
+            })
+        }
+        3 => {
+            let backtrack_count = read_u16(sub, 2) as usize;
+            let input_at = 4 + backtrack_count * 2;
+            let input_count = read_u16(sub, input_at) as usize;
+            let lookahead_at = input_at + 2 + input_count * 2;
+            let lookahead_count = read_u16(sub, lookahead_at) as usize;
+            let records_at = lookahead_at + 2 + lookahead_count * 2;
+            let coverage = |at: usize| offset_slice(sub, read_u16(sub, at) as usize);
+
+            if input_count == 0 || coverage_index(coverage(input_at + 2), glyph).is_none() {
+                return None;
+            }
+            if !skipper.match_backward(buffer, i, backtrack_count, |k, g| coverage_index(coverage(4 + k * 2), g).is_some()) {
+                return None;
+            }
+            let rest = skipper.match_forward(buffer, i, input_count - 1, |k, g| {
+                coverage_index(coverage(input_at + 4 + k * 2), g).is_some()
+            })?;
+            let last = rest.last().copied().unwrap_or(i);
+            skipper.match_forward(buffer, last, lookahead_count, |k, g| {
+                coverage_index(coverage(lookahead_at + 2 + k * 2), g).is_some()
+            })?;
+
+            let mut positions = vec![i];
+            positions.extend(rest);
+            Some(ContextMatch {
+                positions,
+                records: offset_slice(sub, records_at + 2),
+                record_count: read_u16(sub, records_at) as usize,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// GSUB lookup application.
+struct Substitution<'a> {
+    table: LayoutTable<'a>,
+    gdef: Option<Gdef<'a>>,
+}
+
+impl Substitution<'_> {
+    fn apply_lookup(&self, buffer: &mut Vec<GlyphInfo>, index: u16, mask: u32) {
+        let Some(lookup) = self.table.lookup(index, GSUB_EXTENSION) else { return };
+        let skipper = Skipper::new(&lookup, self.gdef);
+
+        let mut i = 0;
+        while i < buffer.len() {
+            if buffer[i].mask & mask != 0
+                && !skipper.skips(buffer[i].id)
+                && let Some(next) = self.apply_at(&lookup, &skipper, buffer, i, 0)
+            {
+                i = next.max(i + 1);
+                continue;
+            }
+            i += 1;
+        }
+    }
+
+    /// Applies the first matching subtable at `i`, returning where to continue.
+    fn apply_at(&self, lookup: &Lookup, skipper: &Skipper, buffer: &mut Vec<GlyphInfo>, i: usize, depth: u8) -> Option<usize> {
+        let glyph = buffer[i].id;
+        lookup.subtables.iter().find_map(|&sub| match lookup.kind {
+            1 => {
+                let replacement = single_substitution(sub, glyph)?;
+                buffer[i].id = replacement;
+                Some(i + 1)
+            }
+            2 | 3 => {
+                // Multiple substitution, or the first alternate of an alternate set
+                let coverage_idx = coverage_index(offset_slice(sub, read_u16(sub, 2) as usize), glyph)?;
+                if read_u16(sub, 0) != 1 || coverage_idx >= read_u16(sub, 4) as usize {
+                    return None;
+                }
+                let sequence = offset_slice(sub, read_u16(sub, 6 + coverage_idx * 2) as usize);
+                let count = read_u16(sequence, 0) as usize;
+                if lookup.kind == 3 {
+                    if count == 0 {
+                        return None;
+                    }
+                    buffer[i].id = read_u16(sequence, 2);
+                    return Some(i + 1);
+                }
+                let template = buffer[i];
+                let glyphs = (0..count).map(|k| GlyphInfo { id: read_u16(sequence, 2 + k * 2), ..template });
+                buffer.splice(i..=i, glyphs);
+                Some(i + count)
+            }
+            4 => self.ligature(sub, skipper, buffer, i),
+            5 => {
+                let matched = match_context(sub, skipper, buffer, i)?;
+                Some(self.apply_records(matched, buffer, depth))
+            }
+            6 => {
+                let matched = match_chain_context(sub, skipper, buffer, i)?;
+                Some(self.apply_records(matched, buffer, depth))
+            }
+            _ => None,
+        })
+    }
+
+    fn ligature(&self, sub: &[u8], skipper: &Skipper, buffer: &mut Vec<GlyphInfo>, i: usize) -> Option<usize> {
+        let coverage_idx = coverage_index(offset_slice(sub, read_u16(sub, 2) as usize), buffer[i].id)?;
+        if read_u16(sub, 0) != 1 || coverage_idx >= read_u16(sub, 4) as usize {
+            return None;
+        }
+        let set = offset_slice(sub, read_u16(sub, 6 + coverage_idx * 2) as usize);
+
+        for l in 0..read_u16(set, 0) as usize {
+            let ligature = offset_slice(set, read_u16(set, 2 + l * 2) as usize);
+            let component_count = read_u16(ligature, 2) as usize;
+            let matched = skipper.match_forward(buffer, i, component_count.saturating_sub(1), |k, g| {
+                g == read_u16(ligature, 4 + k * 2)
+            });
+            let Some(components) = matched else { continue };
+
+            buffer[i].id = read_u16(ligature, 0);
+            // Skipped marks in between stay, attached to the ligature
+            for &p in components.iter().rev() {
+                buffer.remove(p);
+            }
+            return Some(i + 1);
+        }
+        None
+    }
+
+    fn apply_records(&self, matched: ContextMatch, buffer: &mut Vec<GlyphInfo>, depth: u8) -> usize {
+        let ContextMatch { mut positions, records, record_count } = matched;
+
+        for r in 0..record_count {
+            let sequence_idx = read_u16(records, r * 4) as usize;
+            let lookup_idx = read_u16(records, r * 4 + 2);
+            if sequence_idx >= positions.len() || depth >= MAX_NESTING {
+                continue;
+            }
+            let Some(lookup) = self.table.lookup(lookup_idx, GSUB_EXTENSION) else { continue };
+            let skipper = Skipper::new(&lookup, self.gdef);
+            let position = positions[sequence_idx];
+            if position >= buffer.len() {
+                continue;
+            }
+
+            let before = buffer.len();
+            self.apply_at(&lookup, &skipper, buffer, position, depth + 1);
+            let delta = buffer.len() as isize - before as isize;
+            if delta != 0 {
+                for p in positions.iter_mut().skip(sequence_idx + 1) {
+                    *p = (*p as isize + delta).max(0) as usize;
+                }
+            }
+        }
+
+        positions.last().map(|p| p + 1).unwrap_or(0)
+    }
+}
+
+fn single_substitution(sub: &[u8], glyph: u16) -> Option<u16> {
+    let coverage_idx = coverage_index(offset_slice(sub, read_u16(sub, 2) as usize), glyph)?;
+    match read_u16(sub, 0) {
+        1 => Some(glyph.wrapping_add(read_u16(sub, 4))),
+        2 if coverage_idx < read_u16(sub, 4) as usize => Some(read_u16(sub, 6 + coverage_idx * 2)),
+        _ => None,
+    }
+}
+
+/// GPOS lookup application.
+struct Positioning<'a> {
+    table: LayoutTable<'a>,
+    gdef: Option<Gdef<'a>>,
+}
+
+impl Positioning<'_> {
+    fn apply_lookup(&self, buffer: &[GlyphInfo], positions: &mut [Position], index: u16) {
+        let Some(lookup) = self.table.lookup(index, GPOS_EXTENSION) else { return };
+        let skipper = Skipper::new(&lookup, self.gdef);
+
+        let mut i = 0;
+        while i < buffer.len() {
+            if !skipper.skips(buffer[i].id)
+                && let Some(next) = self.apply_at(&lookup, &skipper, buffer, positions, i, 0)
+            {
+                i = next.max(i + 1);
+                continue;
+            }
+            i += 1;
+        }
+    }
+
+    fn is_mark(&self, glyph: u16) -> bool {
+        self.gdef.map(|g| g.glyph_class(glyph) == GLYPH_CLASS_MARK).unwrap_or(false)
+    }
+
+    fn apply_at(&self, lookup: &Lookup, skipper: &Skipper, buffer: &[GlyphInfo], positions: &mut [Position], i: usize, depth: u8) -> Option<usize> {
+        let glyph = buffer[i].id;
+        lookup.subtables.iter().find_map(|&sub| match lookup.kind {
+            1 => {
+                let coverage_idx = coverage_index(offset_slice(sub, read_u16(sub, 2) as usize), glyph)?;
+                let format = read_u16(sub, 4);
+                let record = match read_u16(sub, 0) {
+                    1 => offset_slice(sub, 6),
+                    2 if coverage_idx < read_u16(sub, 6) as usize => {
+                        offset_slice(sub, 8 + coverage_idx * value_record_size(format))
+                    }
+                    _ => return None,
+                };
+                let value = value_record(record, format);
+                let p = &mut positions[i];
+                p.advance += value.x_advance as f32;
+                p.x_offset += value.x_placement as f32;
+                p.y_offset += value.y_placement as f32;
+                Some(i + 1)
+            }
+            2 => {
+                let j = skipper.next(buffer, i)?;
+                let (first, second) = pair_adjustment(sub, glyph, buffer[j].id)?;
+                positions[i].kern += first.x_advance as f32;
+                positions[i].x_offset += first.x_placement as f32;
+                positions[i].y_offset += first.y_placement as f32;
+                positions[j].advance += second.x_advance as f32;
+                positions[j].x_offset += second.x_placement as f32;
+                positions[j].y_offset += second.y_placement as f32;
+                Some(i + 1)
+            }
+            4..=6 => {
+                let (base, base_anchor, mark_anchor) = self.mark_attachment(lookup.kind, sub, skipper, buffer, i)?;
+                let p = &mut positions[i];
+                p.attach = Some(base);
+                p.x_offset = base_anchor.0 - mark_anchor.0;
+                p.y_offset = base_anchor.1 - mark_anchor.1;
+                Some(i + 1)
+            }
+            7 => {
+                let matched = match_context(sub, skipper, buffer, i)?;
+                Some(self.apply_records(matched, buffer, positions, depth))
+            }
+            8 => {
+                let matched = match_chain_context(sub, skipper, buffer, i)?;
+                Some(self.apply_records(matched, buffer, positions, depth))
+            }
+            _ => None,
+        })
+    }
+
+    /// Resolves mark-to-base (4), mark-to-ligature (5) and mark-to-mark (6)
+    /// attachment: the glyph attached to and both anchors.
+    fn mark_attachment(&self, kind: u16, sub: &[u8], skipper: &Skipper, buffer: &[GlyphInfo], i: usize) -> Option<(usize, Anchor, Anchor)> {
+        let mark_idx = coverage_index(offset_slice(sub, read_u16(sub, 2) as usize), buffer[i].id)?;
+
+        let target = if kind == 6 {
+            skipper.prev(buffer, i).filter(|&p| self.is_mark(buffer[p].id))?
+        } else {
+            // Bases and ligatures are found by skipping every mark
+            (0..i).rev().find(|&p| !self.is_mark(buffer[p].id))?
+        };
+        let target_idx = coverage_index(offset_slice(sub, read_u16(sub, 4) as usize), buffer[target].id)?;
+
+        let class_count = read_u16(sub, 6) as usize;
+        let mark_array = offset_slice(sub, read_u16(sub, 8) as usize);
+        let target_array = offset_slice(sub, read_u16(sub, 10) as usize);
+
+        if mark_idx >= read_u16(mark_array, 0) as usize || target_idx >= read_u16(target_array, 0) as usize {
+            return None;
+        }
+        let mark_class = read_u16(mark_array, 2 + mark_idx * 4) as usize;
+        let mark_anchor = anchor(offset_slice(mark_array, read_u16(mark_array, 4 + mark_idx * 4) as usize));
+        if mark_class >= class_count {
+            return None;
+        }
+
+        let (anchors, row) = if kind == 5 {
+            // Marks attach to the last component of a ligature
+            let attach = offset_slice(target_array, read_u16(target_array, 2 + target_idx * 2) as usize);
+            let components = read_u16(attach, 0) as usize;
+            if components == 0 {
+                return None;
+            }
+            (attach, components - 1)
+        } else {
+            (target_array, target_idx)
+        };
+
+        let anchor_offset = read_u16(anchors, 2 + (row * class_count + mark_class) * 2) as usize;
+        if anchor_offset == 0 {
+            return None;
+        }
+        Some((target, anchor(offset_slice(anchors, anchor_offset)), mark_anchor))
+    }
+
+    fn apply_records(&self, matched: ContextMatch, buffer: &[GlyphInfo], positions: &mut [Position], depth: u8) -> usize {
+        let ContextMatch { positions: matched_positions, records, record_count } = matched;
+
+        for r in 0..record_count {
+            let sequence_idx = read_u16(records, r * 4) as usize;
+            let lookup_idx = read_u16(records, r * 4 + 2);
+            if sequence_idx >= matched_positions.len() || depth >= MAX_NESTING {
+                continue;
+            }
+            let Some(lookup) = self.table.lookup(lookup_idx, GPOS_EXTENSION) else { continue };
+            let skipper = Skipper::new(&lookup, self.gdef);
+            self.apply_at(&lookup, &skipper, buffer, positions, matched_positions[sequence_idx], depth + 1);
+        }
+
+        matched_positions.last().map(|p| p + 1).unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svg::rasterizer::tags::text::font::Font;
+    use crate::svg::rasterizer::tags::text::test_font::{
+        kern_table, layout_table, ligature_substitution, lookup, pair_positioning, single_substitution, FontBuilder,
+    };
+
+    fn chars(text: &str) -> Vec<(char, usize)> {
+        text.chars().enumerate().map(|(i, c)| (c, i)).collect()
+    }
+
+    /// A font mapping `A` and `V` to glyphs 1 and 2, with `tables` added.
+    fn kerning_font(tables: &[(&[u8; 4], Vec<u8>)]) -> FontFace {
+        let mut font = FontBuilder::new(3, 500).map(&[('A', 1), ('V', 2)]);
+        for (tag, data) in tables {
+            font = font.table(tag, data.clone());
+        }
+        font.build()
+    }
+
+    fn kerns(face: &FontFace, text: &str, kerning: bool) -> Vec<f32> {
+        shape(face, &chars(text), Script::Latin, false, kerning).iter().map(|g| g.kern).collect()
+    }
+
+    #[test]
+    fn test_kern_table_pairs_are_applied() {
+        let face = kerning_font(&[(b"kern", kern_table(&[(1, 2, -80), (2, 1, -40)]))]);
+        assert_eq!(kerns(&face, "AVAA", true), [-80.0, -40.0, 0.0, 0.0]);
+        assert_eq!(kerns(&face, "AVAA", false), [0.0; 4]);
+    }
+
+    #[test]
+    fn test_gpos_kerning_is_applied() {
+        let gpos = layout_table(b"latn", &[(b"kern", &[0])], &[lookup(2, &[pair_positioning(&[(1, 2, -80), (2, 1, -40)])])]);
+        let face = kerning_font(&[(b"GPOS", gpos)]);
+        assert_eq!(kerns(&face, "AVAA", true), [-80.0, -40.0, 0.0, 0.0]);
+        assert_eq!(kerns(&face, "AVAA", false), [0.0; 4]);
+    }
+
+    #[test]
+    fn test_gpos_kerning_replaces_the_kern_table() {
+        let gpos = layout_table(b"latn", &[(b"kern", &[0])], &[lookup(2, &[pair_positioning(&[(1, 2, -120)])])]);
+        let face = kerning_font(&[(b"kern", kern_table(&[(1, 2, -80), (2, 1, -40)])), (b"GPOS", gpos)]);
+        assert_eq!(kerns(&face, "AVA", true), [-120.0, 0.0, 0.0]);
+    }
+
+    fn glyph_ids(face: &FontFace, text: &str, script: Script) -> Vec<u16> {
+        shape(face, &chars(text), script, script == Script::Arabic, true).iter().map(|g| g.id).collect()
+    }
+
+    /// Beh (1), alef (2) and lam (3) with contextual forms: beh initial (4),
+    /// medial (5) and final (6), alef final (7), lam initial (8) and the
+    /// lam-alef ligature (9).
+    fn arabic_font() -> FontFace {
+        let lookups = [
+            lookup(1, &[single_substitution(&[(1, 6), (2, 7)])]),
+            lookup(1, &[single_substitution(&[(1, 5)])]),
+            lookup(1, &[single_substitution(&[(1, 4), (3, 8)])]),
+            lookup(4, &[ligature_substitution(&[8, 7], 9)]),
+        ];
+        let features: [(&[u8; 4], &[u16]); 4] = [(b"fina", &[0]), (b"medi", &[1]), (b"init", &[2]), (b"rlig", &[3])];
+        FontBuilder::new(10, 500)
+            .map(&[('\u{0628}', 1), ('\u{0627}', 2), ('\u{0644}', 3)])
+            .table(b"GSUB", layout_table(b"arab", &features, &lookups))
+            .build()
+    }
+
+    #[test]
+    fn test_arabic_letters_take_their_joining_forms() {
+        let face = arabic_font();
+        assert_eq!(glyph_ids(&face, "\u{0628}", Script::Arabic), [1]);
+        assert_eq!(glyph_ids(&face, "\u{0628}\u{0628}\u{0628}", Script::Arabic), [4, 5, 6]);
+        assert_eq!(glyph_ids(&face, "\u{0628}\u{0627}", Script::Arabic), [4, 7]);
+        // Alef never joins the letter after it
+        assert_eq!(glyph_ids(&face, "\u{0627}\u{0628}", Script::Arabic), [2, 1]);
+        assert_eq!(glyph_ids(&face, "\u{0628}\u{0627}\u{0628}", Script::Arabic), [4, 7, 1]);
+    }
+
+    #[test]
+    fn test_lam_alef_forms_its_required_ligature() {
+        let face = arabic_font();
+        let shaped = shape(&face, &chars("\u{0628}\u{0644}\u{0627}"), Script::Arabic, true, true);
+        let glyphs: Vec<(u16, usize)> = shaped.iter().map(|g| (g.id, g.cluster)).collect();
+        // Lam joins the beh before it, so it is medial and ligates no further
+        assert_eq!(glyphs, [(4, 0), (3, 1), (7, 2)]);
+        assert_eq!(glyph_ids(&face, "\u{0644}\u{0627}", Script::Arabic), [9]);
+        assert_eq!(glyph_ids(&face, "\u{0627}\u{0644}\u{0627}", Script::Arabic), [2, 9]);
+    }
+
+    /// Ka (1), ssa (2), virama (3), the i matra (4) and half ka (5), which
+    /// the `half` feature forms from ka and virama.
+    fn devanagari_font() -> FontFace {
+        let lookups = [lookup(4, &[ligature_substitution(&[1, 3], 5)])];
+        FontBuilder::new(6, 500)
+            .map(&[('\u{0915}', 1), ('\u{0937}', 2), ('\u{094D}', 3), ('\u{093F}', 4)])
+            .table(b"GSUB", layout_table(b"dev2", &[(b"half", &[0])], &lookups))
+            .build()
+    }
+
+    #[test]
+    fn test_pre_base_matra_moves_before_its_consonant() {
+        let face = devanagari_font();
+        let shaped = shape(&face, &chars("\u{0915}\u{093F}"), Script::Devanagari, false, true);
+        let glyphs: Vec<(u16, usize)> = shaped.iter().map(|g| (g.id, g.cluster)).collect();
+        assert_eq!(glyphs, [(4, 1), (1, 0)]);
+    }
+
+    #[test]
+    fn test_pre_base_matra_moves_before_the_whole_conjunct() {
+        let face = devanagari_font();
+        assert_eq!(glyph_ids(&face, "\u{0915}\u{094D}\u{0937}\u{093F}", Script::Devanagari), [4, 5, 2]);
+        // A matra after a plain consonant pair only moves past the last one
+        assert_eq!(glyph_ids(&face, "\u{0915}\u{0937}\u{093F}", Script::Devanagari), [1, 4, 2]);
+    }
+
+    #[test]
+    fn test_variation_selectors_are_not_rendered() {
+        let face = Font::bundled().unwrap().face;
+        let shaped = shape(&face, &chars("A\u{FE0F}B\u{E0100}"), Script::Latin, false, true);
+        let ids: Vec<u16> = shaped.iter().map(|g| g.id).collect();
+        assert_eq!(ids, [face.glyph_id('A'), face.glyph_id('B')]);
+        assert_eq!(shaped[1].cluster, 2);
+    }
+}
@@ -0,0 +1,223 @@
+//! Synthetic fonts for the text tests: just the tables a test needs, so
+//! shaping and kerning can be checked glyph by glyph without real fonts.
+
+use crate::svg::rasterizer::tags::text::font::{Font, FontFace};
+use crate::utils::compat::{Box, Vec, vec};
+
+/// Units per em of every built font, so advances read as thousandths of the font size.
+pub(crate) const UNITS_PER_EM: u16 = 1000;
+
+/// Builds a font whose glyph 0 is `.notdef` and whose every glyph advances
+/// by `advance` unless set otherwise.
+pub(crate) struct FontBuilder {
+    advances: Vec<u16>,
+    cmap: Vec<(char, u16)>,
+    tables: Vec<([u8; 4], Vec<u8>)>,
+}
+
+impl FontBuilder {
+    pub(crate) fn new(glyph_count: u16, advance: u16) -> Self {
+        Self { advances: vec![advance; glyph_count as usize], cmap: Vec::new(), tables: Vec::new() }
+    }
+
+    /// Maps each character to its glyph.
+    pub(crate) fn map(mut self, chars: &[(char, u16)]) -> Self {
+        self.cmap.extend_from_slice(chars);
+        self
+    }
+
+    pub(crate) fn advance(mut self, glyph: u16, advance: u16) -> Self {
+        self.advances[glyph as usize] = advance;
+        self
+    }
+
+    /// Names the font's family in a `name` table.
+    pub(crate) fn family(self, family: &str) -> Self {
+        let name: Vec<u8> = family.encode_utf16().flat_map(u16_bytes).collect();
+        let record = [u16_bytes(3), u16_bytes(1), u16_bytes(0x0409), u16_bytes(1), u16_bytes(name.len() as u16), u16_bytes(0)].concat();
+        self.table(b"name", [u16_bytes(0), u16_bytes(1), u16_bytes(18), record, name].concat())
+    }
+
+    pub(crate) fn table(mut self, tag: &[u8; 4], data: Vec<u8>) -> Self {
+        self.tables.push((*tag, data));
+        self
+    }
+
+    /// The sfnt bytes, leaked since fonts borrow their data for good.
+    pub(crate) fn bytes(mut self) -> &'static [u8] {
+        let mut head = vec![0; 54];
+        head[18..20].copy_from_slice(&UNITS_PER_EM.to_be_bytes());
+
+        let mut hhea = vec![0; 36];
+        hhea[4..6].copy_from_slice(&800i16.to_be_bytes());
+        hhea[6..8].copy_from_slice(&(-200i16).to_be_bytes());
+        hhea[34..36].copy_from_slice(&(self.advances.len() as u16).to_be_bytes());
+
+        let hmtx = self.advances.iter().flat_map(|advance| [u16_bytes(*advance), u16_bytes(0)].concat()).collect();
+
+        // A format 12 subtable with one group per character
+        self.cmap.sort_unstable();
+        let mut cmap = [u16_bytes(0), u16_bytes(1), u16_bytes(3), u16_bytes(10), u32_bytes(12)].concat();
+        cmap.extend([u16_bytes(12), u16_bytes(0), u32_bytes(16 + 12 * self.cmap.len() as u32), u32_bytes(0)].concat());
+        cmap.extend(u32_bytes(self.cmap.len() as u32));
+        for &(c, glyph) in &self.cmap {
+            cmap.extend([u32_bytes(c as u32), u32_bytes(c as u32), u32_bytes(glyph as u32)].concat());
+        }
+
+        let mut tables = vec![(*b"cmap", cmap), (*b"head", head), (*b"hhea", hhea), (*b"hmtx", hmtx)];
+        tables.append(&mut self.tables);
+
+        let mut font = [u32_bytes(0x0001_0000), u16_bytes(tables.len() as u16), vec![0; 6]].concat();
+        let mut offset = 12 + 16 * tables.len();
+        for (tag, data) in &tables {
+            font.extend([tag.to_vec(), u32_bytes(0), u32_bytes(offset as u32), u32_bytes(data.len() as u32)].concat());
+            offset += data.len().next_multiple_of(4);
+        }
+        for (_, data) in &tables {
+            font.extend(data);
+            font.resize(font.len().next_multiple_of(4), 0);
+        }
+        Box::leak(font.into_boxed_slice())
+    }
+
+    pub(crate) fn build(self) -> FontFace {
+        FontFace::load(self.bytes()).unwrap()
+    }
+
+    pub(crate) fn font(self) -> Font {
+        Font::from_bytes(self.bytes()).unwrap()
+    }
+}
+
+fn u16_bytes(value: u16) -> Vec<u8> {
+    value.to_be_bytes().to_vec()
+}
+
+fn u32_bytes(value: u32) -> Vec<u8> {
+    value.to_be_bytes().to_vec()
+}
+
+/// A version 0 `kern` table with one format 0 subtable of `(left, right, value)` pairs.
+pub(crate) fn kern_table(pairs: &[(u16, u16, i16)]) -> Vec<u8> {
+    let mut pairs = pairs.to_vec();
+    pairs.sort_unstable();
+    let length = 14 + 6 * pairs.len() as u16;
+    let mut kern = [u16_bytes(0), u16_bytes(1), u16_bytes(0), u16_bytes(length), u16_bytes(0x0001)].concat();
+    kern.extend([u16_bytes(pairs.len() as u16), vec![0; 6]].concat());
+    for (left, right, value) in pairs {
+        kern.extend([u16_bytes(left), u16_bytes(right), value.to_be_bytes().to_vec()].concat());
+    }
+    kern
+}
+
+/// A GSUB or GPOS table whose `script` has every feature in its default
+/// language system; each feature lists indices into `lookups`.
+pub(crate) fn layout_table(script: &[u8; 4], features: &[(&[u8; 4], &[u16])], lookups: &[Vec<u8>]) -> Vec<u8> {
+    let lang_sys = [u16_bytes(0), u16_bytes(0xFFFF), u16_bytes(features.len() as u16)].concat();
+    let lang_sys = [lang_sys, (0..features.len() as u16).flat_map(u16_bytes).collect()].concat();
+    let script_table = [u16_bytes(4), u16_bytes(0), lang_sys].concat();
+    let script_list = [u16_bytes(1), script.to_vec(), u16_bytes(8), script_table].concat();
+
+    let mut feature_list = u16_bytes(features.len() as u16);
+    let mut feature_tables = Vec::new();
+    for (tag, indices) in features {
+        let offset = 2 + 6 * features.len() + feature_tables.len();
+        feature_list.extend([tag.to_vec(), u16_bytes(offset as u16)].concat());
+        feature_tables.extend([u16_bytes(0), u16_bytes(indices.len() as u16)].concat());
+        feature_tables.extend(indices.iter().flat_map(|index| u16_bytes(*index)));
+    }
+    feature_list.extend(feature_tables);
+
+    let lookup_list = offset_list(lookups, 0);
+    let scripts_at = 10;
+    let features_at = scripts_at + script_list.len();
+    let lookups_at = features_at + feature_list.len();
+    [
+        u16_bytes(1),
+        u16_bytes(0),
+        u16_bytes(scripts_at as u16),
+        u16_bytes(features_at as u16),
+        u16_bytes(lookups_at as u16),
+        script_list,
+        feature_list,
+        lookup_list,
+    ].concat()
+}
+
+/// A count followed by 16-bit offsets to `items`, which follow in order.
+/// Offsets count from `base` bytes before the count.
+fn offset_list(items: &[Vec<u8>], base: usize) -> Vec<u8> {
+    let mut list = u16_bytes(items.len() as u16);
+    let mut offset = base + 2 + 2 * items.len();
+    for item in items {
+        list.extend(u16_bytes(offset as u16));
+        offset += item.len();
+    }
+    list.extend(items.concat());
+    list
+}
+
+/// A lookup of `kind` without flags.
+pub(crate) fn lookup(kind: u16, subtables: &[Vec<u8>]) -> Vec<u8> {
+    [u16_bytes(kind), u16_bytes(0), offset_list(subtables, 4)].concat()
+}
+
+/// A format 1 coverage table of `glyphs`, which must be sorted.
+fn coverage(glyphs: &[u16]) -> Vec<u8> {
+    [u16_bytes(1), u16_bytes(glyphs.len() as u16), glyphs.iter().flat_map(|g| u16_bytes(*g)).collect()].concat()
+}
+
+/// A format 2 single substitution subtable (GSUB lookup type 1).
+pub(crate) fn single_substitution(pairs: &[(u16, u16)]) -> Vec<u8> {
+    let mut pairs = pairs.to_vec();
+    pairs.sort_unstable();
+    let inputs: Vec<u16> = pairs.iter().map(|(input, _)| *input).collect();
+    let header = 6 + 2 * pairs.len();
+    [
+        u16_bytes(2),
+        u16_bytes(header as u16),
+        u16_bytes(pairs.len() as u16),
+        pairs.iter().flat_map(|(_, output)| u16_bytes(*output)).collect(),
+        coverage(&inputs),
+    ].concat()
+}
+
+/// A ligature substitution subtable (GSUB lookup type 4) forming one
+/// ligature from `components`.
+pub(crate) fn ligature_substitution(components: &[u16], ligature: u16) -> Vec<u8> {
+    let (first, rest) = components.split_first().unwrap();
+    let ligature_table = [u16_bytes(ligature), u16_bytes(components.len() as u16), rest.iter().flat_map(|g| u16_bytes(*g)).collect()].concat();
+    let ligature_set = [u16_bytes(1), u16_bytes(4), ligature_table].concat();
+    [u16_bytes(1), u16_bytes(8 + ligature_set.len() as u16), u16_bytes(1), u16_bytes(8), ligature_set, coverage(&[*first])].concat()
+}
+
+/// A format 1 pair adjustment subtable (GPOS lookup type 2) changing the
+/// advance of the left glyph of each `(left, right, value)` pair.
+pub(crate) fn pair_positioning(pairs: &[(u16, u16, i16)]) -> Vec<u8> {
+    let mut lefts: Vec<u16> = pairs.iter().map(|(left, _, _)| *left).collect();
+    lefts.sort_unstable();
+    lefts.dedup();
+
+    let sets: Vec<Vec<u8>> = lefts.iter().map(|left| {
+        let mut records: Vec<(u16, i16)> = pairs.iter().filter(|p| p.0 == *left).map(|p| (p.1, p.2)).collect();
+        records.sort_unstable();
+        let mut set = u16_bytes(records.len() as u16);
+        for (right, value) in records {
+            set.extend([u16_bytes(right), value.to_be_bytes().to_vec()].concat());
+        }
+        set
+    }).collect();
+
+    let header = 10 + 2 * sets.len();
+    let sets_len: usize = sets.iter().map(Vec::len).sum();
+    let mut subtable = [u16_bytes(1), u16_bytes((header + sets_len) as u16), u16_bytes(0x0004), u16_bytes(0)].concat();
+    let mut offset = header;
+    subtable.extend(u16_bytes(sets.len() as u16));
+    for set in &sets {
+        subtable.extend(u16_bytes(offset as u16));
+        offset += set.len();
+    }
+    subtable.extend(sets.concat());
+    subtable.extend(coverage(&lefts));
+    subtable
+}
@@ -0,0 +1,232 @@
+use crate::svg::parser::tags::Tag;
+use crate::svg::rasterizer::raster::PathRasterizer;
+use crate::svg::rasterizer::tags::path::{apply_transform_to_path, parse_path_data};
+use crate::svg::utils::coords::{Axis, Length, LengthContext, Unit};
+use crate::svg::utils::transform::parse_transform;
+use crate::utils::compat::Vec;
+#[cfg(not(feature = "std"))]
+use crate::utils::compat::FloatExt;
+use crate::svg::utils::Defs;
+
+/// Flattening tolerance for text paths, in user units.
+const TOLERANCE: f32 = 0.05;
+
+/// A path flattened into line segments with their cumulative length,
+/// used to place glyphs along a `<textPath>`.
+pub(crate) struct FlatPath {
+    /// `(x0, y0, x1, y1, distance at segment start)`
+    segments: Vec<(f32, f32, f32, f32, f32)>,
+    pub(crate) length: f32,
+}
+
+/// Layout parameters of one `<textPath>` element.
+pub(crate) struct TextPath {
+    pub(crate) path: FlatPath,
+    pub(crate) start_offset: f32,
+    pub(crate) stretch: bool,
+}
+
+impl FlatPath {
+    fn from_commands_data(d: &str, path_tag: Option<&Tag>) -> Option<Self> {
+        let mut commands = parse_path_data(d);
+        if let Some(path_tag) = path_tag {
+            commands = apply_transform_to_path(&commands, &parse_transform(path_tag));
+        }
+
+        let mut rasterizer = PathRasterizer::new();
+        rasterizer.build_lines_from_path(&commands, 1.0, TOLERANCE, 0.0);
+
+        let mut segments = Vec::with_capacity(rasterizer.lines.len());
+        let mut length = 0.0;
+        for line in &rasterizer.lines {
+            let seg_len = (line.dx * line.dx + line.dy * line.dy).sqrt();
+            if seg_len < 1e-6 {
+                continue;
+            }
+            segments.push((line.x0, line.y0, line.x1, line.y1, length));
+            length += seg_len;
+        }
+
+        if segments.is_empty() { None } else { Some(Self { segments, length }) }
+    }
+
+    /// Reverses the direction of travel (`side="right"`).
+    fn reverse(&mut self) {
+        let mut reversed = Vec::with_capacity(self.segments.len());
+        let mut length = 0.0;
+        for &(x0, y0, x1, y1, _) in self.segments.iter().rev() {
+            reversed.push((x1, y1, x0, y0, length));
+            length += ((x1 - x0) * (x1 - x0) + (y1 - y0) * (y1 - y0)).sqrt();
+        }
+        self.segments = reversed;
+        self.length = length;
+    }
+
+    /// Point and unit tangent at `distance` along the path, or `None` past either end.
+    pub(crate) fn sample(&self, distance: f32) -> Option<(f32, f32, f32, f32)> {
+        if distance < 0.0 || distance > self.length {
+            return None;
+        }
+
+        let idx = match self.segments.binary_search_by(|s| s.4.partial_cmp(&distance).unwrap_or(core::cmp::Ordering::Less)) {
+            Ok(i) => i,
+            Err(i) => i.saturating_sub(1),
+        };
+        let (x0, y0, x1, y1, start) = self.segments[idx];
+
+        let dx = x1 - x0;
+        let dy = y1 - y0;
+        let len = (dx * dx + dy * dy).sqrt();
+        let t = ((distance - start) / len).clamp(0.0, 1.0);
+
+        Some((x0 + dx * t, y0 + dy * t, dx / len, dy / len))
+    }
+
+    /// Maps a point given as (distance along the path, offset along the normal)
+    /// to user space, extrapolating along the end tangents.
+    pub(crate) fn map(&self, distance: f32, offset: f32) -> (f32, f32) {
+        let d = distance.clamp(0.0, self.length);
+        let (px, py, tx, ty) = self.sample(d).unwrap_or((0.0, 0.0, 1.0, 0.0));
+        let overshoot = distance - d;
+        (px + tx * overshoot - ty * offset, py + ty * overshoot + tx * offset)
+    }
+}
+
+/// Resolves a `<textPath>` element: its `href`ed `<path>` (or SVG 2 `path`
+/// attribute), `side`, `startOffset` and `method`.
+pub(crate) fn load_text_path(tag: &Tag, defs: &Defs, lengths: &LengthContext) -> Option<TextPath> {
+    // Ratio between the author's pathLength and the computed length
+    let mut length_scale = 1.0;
+
+    let mut path = match tag.params.get("path") {
+        Some(d) => FlatPath::from_commands_data(d, None)?,
+        None => {
+            let href = tag.params.get("href").or_else(|| tag.params.get("xlink:href"))?;
+            let path_tag = defs.get(href.trim().trim_start_matches('#'))?;
+            let flat = FlatPath::from_commands_data(path_tag.params.get("d")?, Some(path_tag))?;
+
+            let author_len = path_tag.params.get("pathLength")
+                .and_then(|s| s.parse::<f32>().ok())
+                .filter(|len| *len > 0.0);
+            if let Some(author_len) = author_len {
+                length_scale = flat.length / author_len;
+            }
+            flat
+        }
+    };
+
+    if tag.params.get("side").map(|s| s.trim() == "right").unwrap_or(false) {
+        path.reverse();
+    }
+
+    // Ems are the text path's own, percentages are of the path length
+    let start_offset = match tag.params.get("startOffset").and_then(|s| Length::parse(s)) {
+        Some(Length { number, unit: Unit::Percent }) => number / 100.0 * path.length,
+        Some(length) => lengths.for_element(tag).resolve(length, Axis::Horizontal) * length_scale,
+        None => 0.0,
+    };
+
+    let stretch = tag.params.get("method").map(|s| s.trim() == "stretch").unwrap_or(false);
+
+    Some(TextPath { path, start_offset, stretch })
+}
+
+#[cfg(test)]
+mod tests {
+    use core::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+    use crate::svg::parser::parse::parse_xml;
+    use crate::svg::rasterizer::tags::text::font::Font;
+    use crate::svg::rasterizer::tags::text::layout::{layout_text, PositionedGlyph};
+    use crate::svg::testing::render;
+    use crate::svg::utils::coords::LengthContext;
+    use crate::svg::utils::collect_defs;
+    use crate::utils::compat::{format, Vec};
+
+    /// Glyphs of "MW" placed on a path with data `d`.
+    fn place(d: &str, attributes: &str) -> Vec<PositionedGlyph> {
+        let svg = format!(r##"<svg><path id="p" d="{}"/><text font-size="10"><textPath href="#p" {}>MW</textPath></text></svg>"##, d, attributes);
+        let root = parse_xml(svg.as_bytes()).unwrap().remove(0);
+        let defs = collect_defs(&root);
+        let font = Font::bundled().unwrap();
+        layout_text(&root.children[1], &[&font], &defs, &LengthContext::default()).glyphs
+    }
+
+    /// Where the middle of a glyph's advance sits on its baseline.
+    fn midpoint(glyph: &PositionedGlyph) -> (f32, f32) {
+        let half = glyph.advance / 2.0;
+        (glyph.x + glyph.rotate.cos() * half, glyph.y + glyph.rotate.sin() * half)
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-3, "{} != {}", actual, expected);
+    }
+
+    fn render_offset(start_offset: &str) -> Vec<u32> {
+        render(&format!(
+            r##"<svg width="80" height="20">
+                <path id="p" d="M0 15 H80"/>
+                <text font-size="8"><textPath href="#p" font-size="10" startOffset="{}">MW</textPath></text>
+            </svg>"##,
+            start_offset
+        ), 80, 20)
+    }
+
+    #[test]
+    fn test_glyphs_follow_straight_paths() {
+        let glyphs = place("M0 15 H80", r#"startOffset="5""#);
+        assert_eq!(glyphs.len(), 2);
+        assert_close(glyphs[0].x, 5.0);
+        assert_close(glyphs[1].x, 5.0 + glyphs[0].advance);
+        assert!(glyphs.iter().all(|g| g.y == 15.0 && g.rotate == 0.0));
+
+        // Running down the page, glyphs turn a quarter clockwise
+        let glyphs = place("M10 0 V80", "");
+        for glyph in &glyphs {
+            assert_close(glyph.rotate, FRAC_PI_2);
+            assert_close(midpoint(glyph).0, 10.0);
+        }
+        assert_close(midpoint(&glyphs[1]).1, glyphs[0].advance + glyphs[1].advance / 2.0);
+
+        let glyphs = place("M0 0 L80 80", "");
+        assert_close(glyphs[0].rotate, FRAC_PI_4);
+        let (x, y) = midpoint(&glyphs[0]);
+        assert_close(x, y);
+    }
+
+    #[test]
+    fn test_glyphs_are_rotated_about_their_midpoint_on_curves() {
+        // A circle of radius 40 round (50, 50), clockwise from the left
+        let glyphs = place("M10 50 A40 40 0 0 1 90 50 A40 40 0 0 1 10 50", "");
+        for glyph in &glyphs {
+            let (x, y) = midpoint(glyph);
+            let (dx, dy) = (x - 50.0, y - 50.0);
+            assert!(((dx * dx + dy * dy).sqrt() - 40.0).abs() < 0.05, "{:?}", (x, y));
+            // The baseline is tangent to the circle there, give or take the
+            // angle of a flattened segment
+            assert!((dx * glyph.rotate.cos() + dy * glyph.rotate.sin()).abs() / 40.0 < 0.06);
+        }
+        assert!(glyphs[1].rotate > glyphs[0].rotate);
+    }
+
+    #[test]
+    fn test_side_right_runs_backwards() {
+        let glyphs = place("M0 15 H80", r#"side="right""#);
+        assert_close(glyphs[0].rotate.abs(), core::f32::consts::PI);
+        assert_close(midpoint(&glyphs[0]).0, 80.0 - glyphs[0].advance / 2.0);
+    }
+
+    #[test]
+    fn test_start_offset_in_ems_of_the_text_path() {
+        assert_eq!(render_offset("2em"), render_offset("20"));
+        assert_ne!(render_offset("2em"), render_offset("16"));
+        assert_eq!(render_offset("25%"), render_offset("20"));
+    }
+
+    #[test]
+    fn test_invalid_and_huge_start_offsets() {
+        assert_eq!(render_offset("2 em"), render_offset("0"));
+        // Glyphs past the end of the path are not drawn
+        assert_eq!(render_offset("1e30em"), render_offset("200"));
+        assert!(place("M0 15 H80", r#"startOffset="75""#).len() < 2);
+    }
+}
@@ -0,0 +1,152 @@
+use core::ops::Range;
+use crate::svg::parser::tags::Tag;
+use crate::svg::rasterizer::tags::text::linebreak::{line_breaks, Break};
+use crate::svg::utils::coords::{Axis, LengthContext};
+use crate::svg::utils::style::get_property;
+use crate::utils::compat::Vec;
+use crate::svg::utils::Defs;
+
+/// SVG 2 text area that wraps a `<text>` element into lines.
+pub(crate) enum TextArea {
+    /// `inline-size`: lines no wider than this, anchored at the text position.
+    Inline(f32),
+    /// `shape-inside` referencing a `<rect>`; lines that do not fit are dropped.
+    Rect { x: f32, y: f32, width: f32, height: f32 },
+}
+
+/// Reads `shape-inside` (which takes precedence) or `inline-size` from a
+/// text element, declared in `style` or as attributes. Lengths resolve in
+/// the text element's context, so `em` and percentages work.
+pub(crate) fn load_text_area(
+    tag: &Tag,
+    defs: &Defs,
+    lengths: &LengthContext,
+) -> Option<TextArea> {
+    let shape = get_property(tag, "shape-inside")
+        .filter(|s| s.starts_with("url(#") && s.ends_with(')'))
+        .and_then(|s| defs.get(&s[5..s.len() - 1]).copied())
+        .filter(|shape| shape.name == "rect");
+
+    if let Some(rect) = shape {
+        let length = |key: &str, axis: Axis| lengths.attribute(rect, key, axis).unwrap_or(0.0);
+        let (width, height) = (length("width", Axis::Horizontal), length("height", Axis::Vertical));
+        if width > 0.0 && height > 0.0 {
+            return Some(TextArea::Rect {
+                x: length("x", Axis::Horizontal),
+                y: length("y", Axis::Vertical),
+                width,
+                height,
+            });
+        }
+    }
+
+    get_property(tag, "inline-size")
+        .and_then(|s| lengths.length(s, Axis::Horizontal))
+        .filter(|size| *size > 0.0)
+        .map(TextArea::Inline)
+}
+
+/// Splits a paragraph into lines no wider than `max_width`, given the advance
+/// of every character. Lines break at UAX #14 opportunities; a word wider
+/// than the line overflows it. Trailing spaces hang and are left out of the
+/// returned ranges.
+pub(crate) fn break_lines(text: &[char], widths: &[f32], max_width: f32) -> Vec<Range<usize>> {
+    let breaks = line_breaks(text);
+    let mut lines = Vec::new();
+
+    let mut start = 0;
+    while start < text.len() {
+        let mut end = text.len();
+        let mut last_break = None;
+        let mut width = 0.0;
+
+        for i in start..text.len() {
+            if i > start {
+                match breaks[i] {
+                    Break::Mandatory => {
+                        end = i;
+                        break;
+                    }
+                    Break::Allowed => last_break = Some(i),
+                    Break::Never => {}
+                }
+            }
+
+            width += widths[i];
+            if i > start && width > max_width && !is_trailing(text[i]) {
+                end = last_break.unwrap_or_else(|| {
+                    (i + 1..text.len()).find(|&j| breaks[j] != Break::Never).unwrap_or(text.len())
+                });
+                break;
+            }
+        }
+
+        let mut trimmed = end;
+        while trimmed > start && is_trailing(text[trimmed - 1]) {
+            trimmed -= 1;
+        }
+        lines.push(start..trimmed);
+        start = end;
+    }
+
+    lines
+}
+
+/// Spaces and line separators, which take no room at the end of a line.
+fn is_trailing(c: char) -> bool {
+    matches!(c, ' ' | '\n' | '\r' | '\u{000B}' | '\u{000C}' | '\u{0085}' | '\u{2028}' | '\u{2029}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(style: &str, font_size: f32) -> (Tag, LengthContext) {
+        let mut tag = Tag::new();
+        tag.name = "text".into();
+        tag.set_attribute("style", style);
+        (tag, LengthContext::new(200.0, 100.0).with_font_size(font_size))
+    }
+
+    #[test]
+    fn test_inline_size_from_style_in_ems() {
+        let (tag, lengths) = text("inline-size: 3em", 10.0);
+        assert!(matches!(load_text_area(&tag, &Defs::new(), &lengths), Some(TextArea::Inline(size)) if size == 30.0));
+    }
+
+    #[test]
+    fn test_inline_size_percentage_of_viewport_width() {
+        let (tag, lengths) = text("inline-size: 25%", 10.0);
+        assert!(matches!(load_text_area(&tag, &Defs::new(), &lengths), Some(TextArea::Inline(size)) if size == 50.0));
+    }
+
+    #[test]
+    fn test_shape_inside_from_style() {
+        let (tag, lengths) = text("shape-inside: url(#area)", 10.0);
+        let mut rect = Tag::new();
+        rect.name = "rect".into();
+        rect.set_attribute("width", "2em");
+        rect.set_attribute("height", "50%");
+        let mut defs = Defs::new();
+        defs.insert("area", &rect);
+        assert!(matches!(
+            load_text_area(&tag, &defs, &lengths),
+            Some(TextArea::Rect { width, height, .. }) if width == 20.0 && height == 50.0
+        ));
+    }
+
+    #[test]
+    fn test_degenerate_areas_do_not_wrap() {
+        for style in ["inline-size: 0", "inline-size: -5px", "inline-size: 1e39"] {
+            let (tag, lengths) = text(style, 10.0);
+            assert!(load_text_area(&tag, &Defs::new(), &lengths).is_none(), "{}", style);
+        }
+    }
+
+    #[test]
+    fn test_word_wider_than_the_line_overflows_it() {
+        let text: Vec<char> = "a verylongword b".chars().collect();
+        let widths = [1.0; 16];
+        assert_eq!(break_lines(&text, &widths, 0.5), [0..1, 2..14, 15..16]);
+    }
+}
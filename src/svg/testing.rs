@@ -0,0 +1,52 @@
+//! Helpers shared by the unit tests.
+
+use crate::svg::utils::data_uri::encode_data_uri;
+use crate::utils::compat::{format, String, Vec};
+
+/// Renders a complete SVG document, panicking if it fails to parse.
+pub(crate) fn render(svg: &str, width: usize, height: usize) -> Vec<u32> {
+    crate::svg::render(svg.as_bytes(), width, height).unwrap()
+}
+
+/// Runs `primitives` as a filter over the whole `size`×`size` canvas, applied to
+/// the `source` elements.
+pub(crate) fn render_filter(primitives: &str, source: &str, size: usize) -> Vec<u32> {
+    render(&format!(
+        r#"<svg width="{size}" height="{size}"><filter id="f" filterUnits="userSpaceOnUse" x="0" y="0" width="{size}" height="{size}">{primitives}</filter><g filter="url(#f)">{source}</g></svg>"#
+    ), size, size)
+}
+
+/// Whether each ARGB channel of `a` is within `tolerance` of `b`, for output
+/// that is dithered or blurred.
+pub(crate) fn near(a: u32, b: u32, tolerance: u8) -> bool {
+    (0..4).all(|i| {
+        let (ca, cb) = ((a >> (i * 8)) & 0xFF, (b >> (i * 8)) & 0xFF);
+        ca.abs_diff(cb) <= tolerance as u32
+    })
+}
+
+/// The inclusive `(left, top, right, bottom)` of every pixel that is not white.
+pub(crate) fn ink_bounds(pixels: &[u32], width: usize) -> Option<(usize, usize, usize, usize)> {
+    let mut bounds: Option<(usize, usize, usize, usize)> = None;
+    for (i, &pixel) in pixels.iter().enumerate() {
+        if pixel == 0xFFFFFFFF {
+            continue;
+        }
+        let (x, y) = (i % width, i / width);
+        bounds = Some(match bounds {
+            None => (x, y, x, y),
+            Some((l, t, r, b)) => (l.min(x), t.min(y), r.max(x), b.max(y)),
+        });
+    }
+    bounds
+}
+
+/// A 4x2 uncompressed TGA, red on the left half and blue on the right, as
+/// a `data:` URI.
+pub(crate) fn red_blue_image() -> String {
+    let mut tga = Vec::from([0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4, 0, 2, 0, 24, 0x20]);
+    for _ in 0..2 {
+        tga.extend_from_slice(&[0, 0, 255, 0, 0, 255, 255, 0, 0, 255, 0, 0]);
+    }
+    encode_data_uri(&tga)
+}
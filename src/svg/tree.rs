@@ -0,0 +1,545 @@
+//! The rendering tree: elements simplified into typed nodes.
+//!
+//! Conversion reads the structure and geometry of the document once. Shapes
+//! become paths in user units, text is laid out into glyph outlines, images
+//! are decoded, paints are resolved against the document's paint servers
+//! (pattern tiles converted along with them), `<use>` is expanded, nested
+//! viewports are mapped and markers are placed, so drawing parses no strings.
+//!
+//! Filters are the exception: their primitives work in pixels of a buffer
+//! that depends on the device transform, so a [`Filter`] keeps the
+//! `<filter>` elements (for CSS filter functions, an equivalent element
+//! built from the property) and they are parsed each time they are drawn.
+
+use crate::utils::compat::{Box, String, ToString, Vec, vec};
+use crate::svg::parser::tags::Tag;
+use crate::svg::rasterizer::bbox::element_bbox;
+use crate::svg::rasterizer::filters::blend::BlendMode;
+use crate::svg::rasterizer::filters::css::resolve_filter_list;
+use crate::svg::rasterizer::raster::{Bounds, Point};
+use crate::svg::rasterizer::tags::image::{load_image_element, Image};
+use crate::svg::rasterizer::tags::marker::place_markers;
+use crate::svg::rasterizer::tags::path::{commands_bounds, parse_path_data, PathCommand};
+use crate::svg::rasterizer::tags::text::{text_paths, Font};
+use crate::svg::utils::color::{get_fill, get_stroke, try_parse_color, Paint};
+use crate::svg::utils::coords::{Axis, LengthContext};
+use crate::svg::utils::effects::get_stroke_width;
+use crate::svg::utils::style::get_property;
+use crate::svg::utils::transform::{parse_transform, view_box_transform, Transform};
+use crate::svg::utils::Defs;
+
+/// Deepest chain of `<use>` elements and markers expanded inside one another.
+const MAX_EXPANSION_DEPTH: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    /// The element's own `transform`, applied to it and its children.
+    pub transform: Transform,
+    pub blend_mode: BlendMode,
+    pub isolate: bool,
+    pub filter: Option<Filter>,
+    /// Rectangle the node is clipped to, in the space it is drawn into
+    /// before its `transform`; set on viewports whose overflow is hidden.
+    pub clip: Option<Bounds>,
+    pub kind: NodeKind,
+    pub children: Vec<Node>,
+}
+
+#[derive(Debug, Clone)]
+pub enum NodeKind {
+    /// A container, or an element that draws nothing itself.
+    Group,
+    Path(Box<Path>),
+    /// A `<text>` element laid out into one path per span.
+    Text(Vec<Path>),
+    Image(Box<Image>),
+}
+
+/// A shape reduced to its outline, with everything needed to paint it.
+#[derive(Debug, Clone)]
+pub struct Path {
+    /// The outline in user space.
+    pub commands: Vec<PathCommand>,
+    /// Bounds of `commands`, which bounding box units of paint servers refer to.
+    pub bbox: Option<Bounds>,
+    pub fill: Paint,
+    pub stroke: Option<Stroke>,
+    pub markers: Vec<Marker>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Stroke {
+    pub paint: Paint,
+    pub width: f32,
+}
+
+/// A marker placed on one vertex of a path.
+#[derive(Debug, Clone)]
+pub struct Marker {
+    /// Maps the marker viewport to the user space of the path.
+    pub transform: Transform,
+    /// The marker's children, mapped into and clipped to the viewport.
+    pub content: Node,
+}
+
+/// A resolved `filter` property.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    /// `<filter>` elements, applied in order and parsed when drawn.
+    pub chain: Vec<Tag>,
+    /// Bounding box of the filtered element in its user space.
+    pub bbox: Option<Bounds>,
+    /// Context the filter regions and primitive lengths resolve in.
+    pub lengths: LengthContext,
+    /// Fonts for text that `feImage` draws.
+    pub fonts: Vec<Font>,
+}
+
+/// The viewport a nested `<svg>` establishes.
+pub(crate) struct Viewport {
+    /// Position and size in the parent's user space.
+    pub(crate) bounds: Bounds,
+    /// Maps the content, through `viewBox`, into `bounds`.
+    pub(crate) transform: Transform,
+    /// Context of the content.
+    pub(crate) lengths: LengthContext,
+}
+
+impl Node {
+    /// Converts `tag` and its descendants, or `None` for elements that are
+    /// never rendered directly, such as `<defs>` or gradients. Lengths
+    /// resolve in `lengths`, the context of the element's parent, and text
+    /// is set in `fonts` before the bundled font.
+    pub fn from_tag(tag: &Tag, defs: &Defs, fonts: &[Font], lengths: &LengthContext) -> Option<Node> {
+        Converter::new(defs, fonts, *lengths).convert(tag)
+    }
+
+    /// Converts the outermost `<svg>` like [`Node::from_tag`], without
+    /// establishing its viewport, which the caller maps onto the canvas.
+    pub(crate) fn from_root(root: &Tag, defs: &Defs, fonts: &[Font], lengths: &LengthContext) -> Option<Node> {
+        let mut converter = Converter::new(defs, fonts, *lengths);
+        converter.enter(root);
+        converter.convert_element(root, true, None)
+    }
+
+    pub(crate) fn group(transform: Transform, children: Vec<Node>) -> Node {
+        Node {
+            transform,
+            blend_mode: BlendMode::Normal,
+            isolate: false,
+            filter: None,
+            clip: None,
+            kind: NodeKind::Group,
+            children,
+        }
+    }
+}
+
+pub(crate) struct Converter<'a> {
+    pub(crate) defs: &'a Defs<'a>,
+    /// Fonts text is set in before the bundled font.
+    pub(crate) fonts: &'a [Font],
+    /// Context of the element being converted.
+    pub(crate) lengths: LengthContext,
+    /// The element's `color`, which `currentColor` refers to.
+    pub(crate) color: u32,
+    /// Ids of the `<use>` targets and markers being expanded.
+    expanding: Vec<String>,
+}
+
+impl<'a> Converter<'a> {
+    pub(crate) fn new(defs: &'a Defs<'a>, fonts: &'a [Font], lengths: LengthContext) -> Self {
+        Self { defs, fonts, lengths, color: 0xFF000000, expanding: Vec::new() }
+    }
+
+    pub(crate) fn convert(&mut self, tag: &Tag) -> Option<Node> {
+        self.convert_instance(tag, None)
+    }
+
+    /// Converts `tag` like [`convert`](Self::convert), as the target of
+    /// `instance` when a `<use>` references it.
+    fn convert_instance(&mut self, tag: &Tag, instance: Option<&Tag>) -> Option<Node> {
+        let (parent, parent_color) = (self.lengths, self.color);
+        self.enter(tag);
+        let node = self.convert_element(tag, false, instance);
+        (self.lengths, self.color) = (parent, parent_color);
+        node
+    }
+
+    /// Switches to the context of `tag`, taking its font size and `color`.
+    fn enter(&mut self, tag: &Tag) {
+        self.lengths = self.lengths.for_element(tag);
+        // `color` is inherited; `currentColor` and invalid values keep the parent's
+        if let Some(color) = get_property(tag, "color").and_then(try_parse_color) {
+            self.color = color;
+        }
+    }
+
+    /// Converts `tag` in its own context; an `<svg>` that is not `outermost`
+    /// establishes a new viewport, and so does a `<symbol>` a `<use>` instances.
+    fn convert_element(&mut self, tag: &Tag, outermost: bool, instance: Option<&Tag>) -> Option<Node> {
+        let nested = !outermost && (tag.name == "svg" || (tag.name == "symbol" && instance.is_some()));
+        let mut children = Vec::new();
+        let kind = match tag.name.as_str() {
+            "svg" | "symbol" if nested => {
+                children.push(self.viewport_content(tag, instance)?);
+                NodeKind::Group
+            }
+            "clipPath" | "defs" | "linearGradient" | "radialGradient" | "conicGradient" |
+            "meshgradient" | "meshGradient" | "symbol" |
+            "pattern" | "mask" | "marker" | "filter" => return None,
+            _ if tag.is_text_node() => return None,
+
+            // Spans are part of the text layout, not nodes of their own
+            "text" => NodeKind::Text(match instance {
+                Some(instance) => text_paths(&text_instance(tag, instance), self.defs, self.fonts, &self.lengths),
+                None => text_paths(tag, self.defs, self.fonts, &self.lengths),
+            }),
+            "image" => match load_image_element(tag, &self.lengths) {
+                Some(image) => NodeKind::Image(Box::new(image)),
+                None => NodeKind::Group,
+            },
+            "use" => {
+                children.extend(self.use_content(tag));
+                NodeKind::Group
+            }
+            _ => match shape_path(tag, &self.lengths) {
+                Some(commands) => NodeKind::Path(Box::new(self.path(tag, commands, instance))),
+                None => NodeKind::Group,
+            },
+        };
+
+        if !nested && !matches!(kind, NodeKind::Text(..)) {
+            children.extend(self.convert_all(&tag.children));
+        }
+
+        let filter = get_property(tag, "filter").and_then(|value| {
+            let bbox = element_bbox(tag, self.defs, self.fonts, &Transform::identity(), &self.lengths);
+            resolve_filter_list(value, self.defs, bbox, &self.lengths, self.color).map(|chain| Filter { chain, bbox, lengths: self.lengths, fonts: self.fonts.to_vec() })
+        });
+
+        Some(Node {
+            transform: parse_transform(tag),
+            blend_mode: get_property(tag, "mix-blend-mode").and_then(BlendMode::parse).unwrap_or(BlendMode::Normal),
+            isolate: get_property(tag, "isolation") == Some("isolate"),
+            filter,
+            clip: None,
+            kind,
+            children,
+        })
+    }
+
+    pub(crate) fn convert_all(&mut self, tags: &[Tag]) -> Vec<Node> {
+        tags.iter().filter_map(|tag| self.convert(tag)).collect()
+    }
+
+    /// Runs `convert` with `id` marked as being expanded, or returns `None`
+    /// when `id` is already being expanded or the nesting is too deep.
+    pub(crate) fn expand<T>(&mut self, id: &str, convert: impl FnOnce(&mut Self) -> T) -> Option<T> {
+        if self.expanding.len() >= MAX_EXPANSION_DEPTH || self.expanding.iter().any(|e| e == id) {
+            return None;
+        }
+        self.expanding.push(id.to_string());
+        let converted = convert(self);
+        self.expanding.pop();
+        Some(converted)
+    }
+
+    /// The element a `<use>` references, placed at its `x` and `y`.
+    fn use_content(&mut self, tag: &Tag) -> Option<Node> {
+        let link = tag.params.get("href").or_else(|| tag.params.get("xlink:href"))?;
+        let id = link.trim().trim_start_matches('#');
+        let target = *self.defs.get(id)?;
+
+        let x = self.lengths.attribute(tag, "x", Axis::Horizontal).unwrap_or(0.0);
+        let y = self.lengths.attribute(tag, "y", Axis::Vertical).unwrap_or(0.0);
+        let content = self.expand(id, |converter| converter.convert_instance(target, Some(tag)))??;
+        Some(Node::group(Transform::translate(x, y), vec![content]))
+    }
+
+    /// The children of a nested `<svg>`, or of a `<symbol>` or `<svg>` that
+    /// `instance` references, mapped into its viewport and clipped to it
+    /// unless its overflow is visible.
+    fn viewport_content(&mut self, tag: &Tag, instance: Option<&Tag>) -> Option<Node> {
+        // Either takes the size of the <use>
+        let size = |key: &str, axis: Axis| instance.and_then(|instance| self.lengths.attribute(instance, key, axis));
+        let size = (size("width", Axis::Horizontal), size("height", Axis::Vertical));
+        let viewport = viewport(tag, size, &self.lengths)?;
+        let outer = self.lengths;
+        self.lengths = viewport.lengths;
+        let content = self.convert_all(&tag.children);
+        self.lengths = outer;
+
+        let visible = matches!(get_property(tag, "overflow"), Some("visible" | "auto"));
+        Some(Node {
+            clip: (!visible).then_some(viewport.bounds),
+            ..Node::group(viewport.transform, content)
+        })
+    }
+
+    fn path(&mut self, tag: &Tag, commands: Vec<PathCommand>, instance: Option<&Tag>) -> Path {
+        let stroke = get_stroke(styled_by(tag, instance, "stroke")).resolve(self.defs, self.fonts, &self.lengths);
+        let width = get_stroke_width(styled_by(tag, instance, "stroke-width"), &self.lengths);
+        let markers = if matches!(tag.name.as_str(), "path" | "line" | "polyline" | "polygon") {
+            place_markers(tag, styled_by(tag, instance, "stroke-width"), &commands, self)
+        } else {
+            Vec::new()
+        };
+
+        Path {
+            bbox: commands_bounds(commands.iter()),
+            fill: get_fill(styled_by(tag, instance, "fill")).resolve(self.defs, self.fonts, &self.lengths),
+            stroke: (!stroke.is_none() && width > 0.0).then_some(Stroke { paint: stroke, width }),
+            markers,
+            commands,
+        }
+    }
+}
+
+/// The element whose `property` applies to `tag`: `tag` itself, or the
+/// `<use>` instancing it when `tag` sets none.
+fn styled_by<'t>(tag: &'t Tag, instance: Option<&'t Tag>, property: &str) -> &'t Tag {
+    match instance {
+        Some(instance) if get_property(tag, property).is_none() => instance,
+        _ => tag,
+    }
+}
+
+/// A `<text>` with the paint and stroke width it takes from `instance`.
+fn text_instance(tag: &Tag, instance: &Tag) -> Tag {
+    let mut text = tag.clone();
+    for key in ["fill", "stroke", "stroke-width"] {
+        if get_property(tag, key).is_none()
+            && let Some(value) = get_property(instance, key)
+        {
+            text.params.insert(key.to_string(), value.to_string());
+        }
+    }
+    text
+}
+
+/// The viewport of a nested `<svg>` whose attributes resolve in `lengths`,
+/// or `None` when it has no area, which disables rendering. A `size` given
+/// by a `<use>` overrides the element's `width` and `height`.
+pub(crate) fn viewport(tag: &Tag, size: (Option<f32>, Option<f32>), lengths: &LengthContext) -> Option<Viewport> {
+    let (width, height) = lengths.viewport;
+    let length = |key: &str, axis: Axis, default: f32| lengths.attribute(tag, key, axis).unwrap_or(default);
+    let bounds = Bounds {
+        x: length("x", Axis::Horizontal, 0.0),
+        y: length("y", Axis::Vertical, 0.0),
+        width: size.0.unwrap_or_else(|| length("width", Axis::Horizontal, width)),
+        height: size.1.unwrap_or_else(|| length("height", Axis::Vertical, height)),
+    };
+    if bounds.width <= 0.0 || bounds.height <= 0.0 {
+        return None;
+    }
+
+    let origin = Transform::translate(bounds.x, bounds.y);
+    let (transform, size) = match tag.view_box() {
+        Some(view_box) => (
+            origin.then(&view_box_transform(view_box, bounds.width, bounds.height, tag.attribute("preserveAspectRatio"))),
+            (view_box[2], view_box[3]),
+        ),
+        None => (origin, (bounds.width, bounds.height)),
+    };
+    Some(Viewport { bounds, transform, lengths: lengths.with_viewport(size.0, size.1) })
+}
+
+/// The outline of a basic shape or `<path>` in user units, or `None` for
+/// other elements and shapes that disable rendering.
+pub(crate) fn shape_path(tag: &Tag, lengths: &LengthContext) -> Option<Vec<PathCommand>> {
+    let length = |key: &str, axis: Axis| lengths.attribute(tag, key, axis);
+    let number = |key: &str, axis: Axis| length(key, axis).unwrap_or(0.0);
+    let (width, height) = (Axis::Horizontal, Axis::Vertical);
+
+    let commands = match tag.name.as_str() {
+        "path" => parse_path_data(tag.params.get("d")?),
+        "rect" => {
+            let (x, y) = (number("x", width), number("y", height));
+            let (w, h) = (number("width", width), number("height", height));
+            if w <= 0.0 || h <= 0.0 {
+                return None;
+            }
+
+            // A missing radius takes the other one's value
+            let (rx, ry) = (length("rx", width), length("ry", height));
+            let (rx, ry) = (rx.or(ry).unwrap_or(0.0), ry.or(rx).unwrap_or(0.0));
+            rect_path(x, y, w, h, rx.min(w / 2.0), ry.min(h / 2.0))
+        }
+        "circle" | "ellipse" => {
+            let (rx, ry) = if tag.name == "circle" {
+                let r = length("r", Axis::Diagonal)?;
+                (r, r)
+            } else {
+                (number("rx", width), number("ry", height))
+            };
+            if rx <= 0.0 || ry <= 0.0 {
+                return None;
+            }
+
+            let (cx, cy) = (number("cx", width), number("cy", height));
+            let arc = |x: f32| PathCommand::Arc {
+                rx,
+                ry,
+                x_axis_rotation: 0.0,
+                large_arc_flag: true,
+                sweep_flag: false,
+                end: Point { x, y: cy },
+            };
+            vec![PathCommand::MoveTo(Point { x: cx - rx, y: cy }), arc(cx + rx), arc(cx - rx)]
+        }
+        "line" => vec![
+            PathCommand::MoveTo(Point { x: number("x1", width), y: number("y1", height) }),
+            PathCommand::LineTo(Point { x: number("x2", width), y: number("y2", height) }),
+        ],
+        "polyline" | "polygon" => {
+            let points = get_points(tag);
+            let (first, rest) = points.split_first()?;
+            let mut commands = vec![PathCommand::MoveTo(*first)];
+            commands.extend(rest.iter().map(|p| PathCommand::LineTo(*p)));
+            if tag.name == "polygon" {
+                commands.push(PathCommand::ClosePath);
+            }
+            commands
+        }
+        _ => return None,
+    };
+    Some(commands)
+}
+
+fn rect_path(x: f32, y: f32, w: f32, h: f32, rx: f32, ry: f32) -> Vec<PathCommand> {
+    let line = |x: f32, y: f32| PathCommand::LineTo(Point { x, y });
+    if rx == 0.0 || ry == 0.0 {
+        return vec![
+            PathCommand::MoveTo(Point { x, y }),
+            line(x + w, y),
+            line(x + w, y + h),
+            line(x, y + h),
+            PathCommand::ClosePath,
+        ];
+    }
+
+    let arc = |x: f32, y: f32| PathCommand::Arc {
+        rx,
+        ry,
+        x_axis_rotation: 0.0,
+        large_arc_flag: false,
+        sweep_flag: true,
+        end: Point { x, y },
+    };
+    vec![
+        PathCommand::MoveTo(Point { x: x + rx, y }),
+        line(x + w - rx, y),
+        arc(x + w, y + ry),
+        line(x + w, y + h - ry),
+        arc(x + w - rx, y + h),
+        line(x + rx, y + h),
+        arc(x, y + h - ry),
+        line(x, y + ry),
+        arc(x + rx, y),
+        PathCommand::ClosePath,
+    ]
+}
+
+fn get_points(tag: &Tag) -> Vec<Point> {
+    let Some(points_str) = tag.params.get("points") else {
+        return Vec::new();
+    };
+
+    let nums: Vec<f32> = points_str
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .filter_map(|s| s.parse::<f32>().ok())
+        .collect();
+
+    nums.chunks_exact(2)
+        .map(|chunk| Point { x: chunk[0], y: chunk[1] })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svg::document::{RenderOptions, SvgDocument};
+    use crate::svg::testing::{ink_bounds, red_blue_image, render};
+    use crate::utils::compat::format;
+
+    /// The nodes of the root's children for a 40x20 canvas.
+    fn convert(svg: &str) -> Vec<Node> {
+        let document = SvgDocument::parse(svg.as_bytes()).unwrap();
+        document.tree(40.0, 20.0, &RenderOptions::default()).unwrap().children
+    }
+
+    #[test]
+    fn test_use_sizes_a_symbol_viewport() {
+        let svg = r##"<svg width="10" height="10">
+            <symbol id="s" viewBox="0 0 1 1"><rect width="1" height="1" fill="red"/></symbol>
+            <use href="#s" x="2" y="3" width="4" height="5"/>
+        </svg>"##;
+        let pixels = render(svg, 10, 10);
+        assert_eq!(ink_bounds(&pixels, 10), Some((2, 4, 5, 7)));
+        assert_eq!(pixels[5 * 10 + 3], 0xFFFF0000);
+    }
+
+    #[test]
+    fn test_symbol_is_only_drawn_through_use() {
+        let svg = r##"<svg width="10" height="10">
+            <symbol id="s"><rect width="4" height="4" fill="red"/></symbol>
+        </svg>"##;
+        assert_eq!(ink_bounds(&render(svg, 10, 10), 10), None);
+        let used = svg.replace("</svg>", r##"<use href="#s" x="1" y="1"/></svg>"##);
+        assert_eq!(ink_bounds(&render(&used, 10, 10), 10), Some((1, 1, 4, 4)));
+    }
+
+    #[test]
+    fn test_use_paints_targets_that_set_no_paint() {
+        let svg = r##"<svg width="10" height="4">
+            <defs>
+                <rect id="plain" width="4" height="4"/>
+                <rect id="red" width="4" height="4" style="fill: red"/>
+            </defs>
+            <use href="#plain" fill="blue"/>
+            <use href="#red" x="6" fill="blue"/>
+        </svg>"##;
+        let pixels = render(svg, 10, 4);
+        assert_eq!(pixels[10 + 1], 0xFF0000FF);
+        assert_eq!(pixels[10 + 7], 0xFFFF0000);
+    }
+
+    #[test]
+    fn test_use_paints_text() {
+        let svg = r##"<svg width="40" height="20">
+            <defs><text id="t" y="15" font-size="16">H</text></defs>
+            <use href="#t" fill="blue"/>
+        </svg>"##;
+        let pixels = render(svg, 40, 20);
+        assert!(pixels.contains(&0xFF0000FF));
+        // Edges blend blue into white
+        assert!(pixels.iter().all(|&p| p & 0xFF == 0xFF && (p >> 16) & 0xFF == (p >> 8) & 0xFF));
+    }
+
+    #[test]
+    fn test_text_is_laid_out_when_converted() {
+        let nodes = convert(r#"<svg><text y="15" fill="red">A<tspan fill="blue">B</tspan></text></svg>"#);
+        let NodeKind::Text(spans) = &nodes[0].kind else { panic!("expected text") };
+        assert_eq!(spans.len(), 2);
+        assert!(matches!(spans[0].fill, Paint::Solid(0xFFFF0000)));
+        assert!(matches!(spans[1].fill, Paint::Solid(0xFF0000FF)));
+        assert!(spans.iter().all(|span| !span.commands.is_empty()));
+
+        // Paint servers of every span refer to the box of the whole text
+        let bbox = spans[0].bbox.unwrap();
+        assert_eq!(spans[1].bbox.map(|b| (b.x, b.width)), Some((bbox.x, bbox.width)));
+        assert!(nodes[0].children.is_empty());
+    }
+
+    #[test]
+    fn test_images_are_decoded_when_converted() {
+        let nodes = convert(&format!(
+            r#"<svg><image href="{}" width="8" height="4"/><image href="data:image/png;base64,iVBORw0KGgo=" width="8" height="4"/></svg>"#,
+            red_blue_image()
+        ));
+        assert!(matches!(nodes[0].kind, NodeKind::Image(_)));
+        assert!(matches!(nodes[1].kind, NodeKind::Group));
+    }
+}
@@ -1,47 +1,62 @@
 use crate::svg::parser::tags::Tag;
-use crate::utils::compat::HashMap;
+use crate::svg::utils::style::get_property;
 use crate::svg::rasterizer::tags::lineargradient::{LinearGradient, load_linear_gradient};
 use crate::svg::rasterizer::tags::radialgradient::{RadialGradient, load_radial_gradient};
+use crate::svg::rasterizer::tags::pattern::{Pattern, load_pattern};
+use crate::svg::rasterizer::tags::text::Font;
+#[cfg(feature = "conic-gradient")]
+use crate::svg::rasterizer::tags::conicgradient::{ConicGradient, load_conic_gradient};
+use crate::svg::rasterizer::tags::meshgradient::{MeshGradient, load_mesh_gradient};
+use crate::svg::rasterizer::raster::Bounds;
+use crate::svg::utils::coords::{Length, LengthContext, Unit};
+use crate::svg::utils::transform::Transform;
 use crate::utils::compat::{String, ToString, Vec};
+use crate::svg::utils::Defs;
 
 #[derive(Debug, Clone)]
 pub enum Paint {
     Solid(u32),
     LinearGradient(LinearGradient),
     RadialGradient(RadialGradient),
+    #[cfg(feature = "conic-gradient")]
+    ConicGradient(ConicGradient),
+    MeshGradient(MeshGradient),
+    Pattern(Pattern),
     Reference(String),
     None,
 }
 
 impl Paint {
-    pub fn scale(&mut self, scale: f32) {
-        match self {
-            Paint::LinearGradient(gradient) => gradient.scale(scale),
-            Paint::RadialGradient(_) => {},
-            _ => {}
-        }
-    }
-    pub fn resolve(&self, defs: &HashMap<String, Tag>) -> Paint {
+    /// Loads the paint server a reference points to, resolving its
+    /// user-space lengths in `lengths`; text in pattern content is set in
+    /// `fonts`. A missing reference, or one to an element that is not a
+    /// paint server, paints nothing.
+    pub fn resolve(&self, defs: &Defs, fonts: &[Font], lengths: &LengthContext) -> Paint {
         match self {
             Paint::Reference(id) => {
 
-                if let Some(tag) = defs.get(id) {
+                if let Some(&tag) = defs.get(id.as_str()) {
                     match tag.name.as_str() {
                         "linearGradient" => Paint::LinearGradient(
-                            load_linear_gradient(tag),
+                            load_linear_gradient(tag, defs, lengths),
                         ),
                         "radialGradient" => Paint::RadialGradient(
-                             load_radial_gradient(tag),
+                             load_radial_gradient(tag, defs, lengths),
+                        ),
+                        #[cfg(feature = "conic-gradient")]
+                        "conicGradient" => Paint::ConicGradient(
+                            load_conic_gradient(tag, defs, lengths),
+                        ),
+                        "meshgradient" | "meshGradient" => Paint::MeshGradient(
+                            load_mesh_gradient(tag, defs, lengths),
                         ),
-                        _ => {
-                            #[cfg(feature = "std")]
-                            std::println!("ID {} found but tag name is {}", id, tag.name);
-                            Paint::None
-                        },
+                        "pattern" => Paint::Pattern(
+                            load_pattern(id, tag, defs, fonts, lengths),
+                        ),
+                        // Validation reports references to anything else
+                        _ => Paint::None,
                     }
                 } else {
-                    #[cfg(feature = "std")]
-                    std::println!("ID not found in defs: '{}'", id);
                     Paint::None
                 }
             }
@@ -49,11 +64,30 @@ impl Paint {
         }
     }
 
-    pub fn get_color_at(&self, x: f32, y: f32, bbox_x: f32, bbox_y: f32, bbox_w: f32, bbox_h: f32) -> u32 {
+    /// Prepares paint servers for the painted element's user-space `bbox` and
+    /// `transform`: gradients resolve their coordinate system, while a mesh
+    /// gradient or pattern renders its pixels.
+    pub fn prepare(&mut self, defs: &Defs, transform: &Transform, bbox: Bounds) {
+        match self {
+            Paint::LinearGradient(gradient) => gradient.prepare(transform, bbox),
+            Paint::RadialGradient(gradient) => gradient.prepare(transform, bbox),
+            #[cfg(feature = "conic-gradient")]
+            Paint::ConicGradient(gradient) => gradient.prepare(transform, bbox),
+            Paint::MeshGradient(gradient) => gradient.prepare(transform, bbox),
+            Paint::Pattern(pattern) => pattern.prepare(defs, transform, bbox),
+            _ => {}
+        }
+    }
+
+    pub fn get_color_at(&self, x: f32, y: f32) -> u32 {
         match self {
             Paint::Solid(color) => *color,
-            Paint::LinearGradient(gradient) => gradient.interpolate(x, y, bbox_x, bbox_y, bbox_w, bbox_h),
-            Paint::RadialGradient(gradient) => gradient.interpolate(x, y, bbox_x, bbox_y, bbox_w, bbox_h),
+            Paint::LinearGradient(gradient) => gradient.interpolate(x, y),
+            Paint::RadialGradient(gradient) => gradient.interpolate(x, y),
+            #[cfg(feature = "conic-gradient")]
+            Paint::ConicGradient(gradient) => gradient.interpolate(x, y),
+            Paint::MeshGradient(gradient) => gradient.sample(x, y),
+            Paint::Pattern(pattern) => pattern.sample(x, y),
             Paint::None | Paint::Reference(_) => 0x00000000,
         }
     }
@@ -65,92 +99,97 @@ impl Paint {
 
 #[inline]
 pub fn get_fill(tag: &Tag) -> Paint {
-    tag.params
-        .get("fill")
-        .map(|c| parse_paint(c))
+    get_property(tag, "fill")
+        .map(parse_paint)
         .unwrap_or(Paint::Solid(0x0000_0000))
 }
 
 #[inline]
 pub fn get_stroke(tag: &Tag) -> Paint {
-    tag.params
-        .get("stroke")
-        .map(|c| parse_paint(c))
+    get_property(tag, "stroke")
+        .map(parse_paint)
         .unwrap_or(Paint::None)
 }
 
-fn parse_paint(s: &str) -> Paint {
+/// Whether `name` is an element `Paint::resolve` can load.
+pub(crate) fn is_paint_server(name: &str) -> bool {
+    matches!(name, "linearGradient" | "radialGradient" | "meshgradient" | "meshGradient" | "pattern")
+        || (name == "conicGradient" && cfg!(feature = "conic-gradient"))
+}
+
+pub(crate) fn parse_paint(s: &str) -> Paint {
+    try_parse_paint(s).unwrap_or(Paint::Solid(0x0000_0000))
+}
+
+/// Parses `none`, a color, or a `url(#id)` reference with an optional
+/// fallback color; `None` when `s` is none of them.
+pub(crate) fn try_parse_paint(s: &str) -> Option<Paint> {
     let s = s.trim();
 
     if s.eq_ignore_ascii_case("none") {
-        return Paint::None;
+        return Some(Paint::None);
     }
 
-    if s.starts_with("url(#") {
-        let id = s.trim_start_matches("url(#").trim_end_matches(')');
-        return Paint::Reference(id.to_string());
+    if let Some(rest) = s.strip_prefix("url(#") {
+        let (id, fallback) = rest.split_once(')')?;
+        let fallback = fallback.trim();
+        if !fallback.is_empty() && !fallback.eq_ignore_ascii_case("none") {
+            try_parse_color(fallback)?;
+        }
+        return Some(Paint::Reference(id.to_string()));
     }
 
-    Paint::Solid(parse_color_value(&s))
+    try_parse_color(s).map(Paint::Solid)
 }
 
+/// Parses a color, transparent when `c` is not one.
 pub(crate) fn parse_color_value(c: &str) -> u32 {
+    try_parse_color(c).unwrap_or(0x0000_0000)
+}
+
+/// Parses a named, `#` hex, `rgb[a]()` or `hsl[a]()` color.
+pub(crate) fn try_parse_color(c: &str) -> Option<u32> {
     let c = c.trim().to_lowercase();
 
     if let Some(named_color) = named(&c) {
-        return named_color;
+        return Some(named_color);
     }
 
-    if c.starts_with("#") {
-        return parse_hex(&c);
+    if let Some(hex) = c.strip_prefix('#') {
+        return parse_hex(hex);
     }
 
-    if c.starts_with("rgb(") || c.starts_with("rgba(") {
-        return parse_rgb(&c);
+    if let Some(args) = c.strip_prefix("rgba(").or_else(|| c.strip_prefix("rgb(")) {
+        return parse_rgb(args.strip_suffix(')')?);
     }
 
-    if c.starts_with("hsl(") || c.starts_with("hsla(") {
-        return parse_hsl(&c);
+    if let Some(args) = c.strip_prefix("hsla(").or_else(|| c.strip_prefix("hsl(")) {
+        return parse_hsl(args.strip_suffix(')')?);
     }
 
-            0x0000_0000}
-
-fn parse_hsl(hsl: &str) -> u32 {
-    let is_hsla = hsl.starts_with("hsla(");
-    let inner = hsl
-        .trim_start_matches("hsl(")
-        .trim_start_matches("hsla(")
-        .trim_end_matches(')');
+    None
+}
 
+/// Comma-separated function arguments: three, and an optional alpha.
+fn color_arguments(inner: &str) -> Option<Vec<&str>> {
     let parts: Vec<&str> = inner.split(',').map(|s| s.trim()).collect();
+    matches!(parts.len(), 3 | 4).then_some(parts)
+}
 
-    if parts.len() < 3 {
-        return 0xFF000000;
-    }
+fn parse_hsl(inner: &str) -> Option<u32> {
+    let parts = color_arguments(inner)?;
 
-    let h = parts[0].parse::<f32>().ok().unwrap_or(0.0);
-    let s = parts[1]
-        .trim_end_matches('%')
-        .parse::<f32>()
-        .ok()
-        .unwrap_or(0.0)
-        / 100.0;
-    let l = parts[2]
-        .trim_end_matches('%')
-        .parse::<f32>()
-        .ok()
-        .unwrap_or(0.0)
-        / 100.0;
-
-    let a = if is_hsla && parts.len() >= 4 {
-        parse_alpha(parts[3])
-    } else {
-        255
+    let h = number(parts[0].strip_suffix("deg").unwrap_or(parts[0]))?;
+    let s = number(parts[1].strip_suffix('%')?)? / 100.0;
+    let l = number(parts[2].strip_suffix('%')?)? / 100.0;
+    let a = match parts.get(3) {
+        Some(alpha) => parse_alpha(alpha)?,
+        None => 255,
     };
 
-    let (r, g, b) = hsl_to_rgb(h, s, l);
+    let (r, g, b) = hsl_to_rgb(h, s.clamp(0.0, 1.0), l.clamp(0.0, 1.0));
 
-    (a as u32) << 24 | (r as u32) << 16 | (g as u32) << 8 | (b as u32)
+    Some((a as u32) << 24 | (r as u32) << 16 | (g as u32) << 8 | (b as u32))
 }
 
 fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (u8, u8, u8) {
@@ -190,67 +229,51 @@ fn hue_to_rgb(p: f32, q: f32, mut t: f32) -> f32 {
     }
 }
 
-fn parse_rgb(rgb: &str) -> u32 {
-    let is_rgba = rgb.starts_with("rgba(");
-    let inner = rgb
-        .trim_start_matches("rgb(")
-        .trim_start_matches("rgba(")
-        .trim_end_matches(')');
-
-    let parts: Vec<&str> = inner.split(',').map(|s| s.trim()).collect();
-
-    if parts.len() < 3 {
-        return 0xFF000000;
-    }
-
-    let r = parse_color_component(parts[0]);
-    let g = parse_color_component(parts[1]);
-    let b = parse_color_component(parts[2]);
+fn parse_rgb(inner: &str) -> Option<u32> {
+    let parts = color_arguments(inner)?;
 
-    let a = if is_rgba && parts.len() >= 4 {
-        parse_alpha(parts[3])
-    } else {
-        255
+    let r = parse_color_component(parts[0])?;
+    let g = parse_color_component(parts[1])?;
+    let b = parse_color_component(parts[2])?;
+    let a = match parts.get(3) {
+        Some(alpha) => parse_alpha(alpha)?,
+        None => 255,
     };
 
-    (a as u32) << 24 | (r as u32) << 16 | (g as u32) << 8 | (b as u32)
+    Some((a as u32) << 24 | (r as u32) << 16 | (g as u32) << 8 | (b as u32))
 }
 
-fn parse_color_component(s: &str) -> u8 {
-    if s.ends_with('%') {
-        let percent = s.trim_end_matches('%').parse::<f32>().ok().unwrap_or(0.0);
-        (percent * 2.55).clamp(0.0, 255.0) as u8
-    } else {
-        s.parse::<u8>().ok().unwrap_or(0)
+/// A number as the length parser reads it, so `5.` and `.5` both count.
+fn number(s: &str) -> Option<f32> {
+    Length::parse(s).filter(|length| length.unit == Unit::None).map(|length| length.number)
+}
+
+fn parse_color_component(s: &str) -> Option<u8> {
+    match s.strip_suffix('%') {
+        Some(percent) => Some((number(percent)? * 2.55).clamp(0.0, 255.0) as u8),
+        None => Some(number(s)?.clamp(0.0, 255.0) as u8),
     }
 }
 
-fn parse_alpha(s: &str) -> u8 {
-    if s.ends_with('%') {
-        let percent = s.trim_end_matches('%').parse::<f32>().ok().unwrap_or(0.0);
-        (percent * 2.55).clamp(0.0, 255.0) as u8
-    } else {
-        let alpha = s.parse::<f32>().ok().unwrap_or(0.0);
-        (alpha * 255.0).clamp(0.0, 255.0) as u8
+fn parse_alpha(s: &str) -> Option<u8> {
+    match s.strip_suffix('%') {
+        Some(percent) => Some((number(percent)? * 2.55).clamp(0.0, 255.0) as u8),
+        None => Some((number(s)? * 255.0).clamp(0.0, 255.0) as u8),
     }
 }
 
-fn parse_hex(hex: &str) -> u32 {
-    let hex = hex.trim_start_matches('#');
+fn parse_hex(hex: &str) -> Option<u32> {
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let digit = |i: usize| u32::from_str_radix(&hex[i..i + 1], 16).ok().map(|d| d * 17);
 
     match hex.len() {
-        3 => {
-            let r = u8::from_str_radix(&hex[0..1], 16).ok().unwrap_or(0);
-            let g = u8::from_str_radix(&hex[1..2], 16).ok().unwrap_or(0);
-            let b = u8::from_str_radix(&hex[2..3], 16).ok().unwrap_or(0);
-            0xFF000000 | ((r as u32 * 17) << 16) | ((g as u32 * 17) << 8) | (b as u32 * 17)
-        }
-        6 => {
-            let rgb = u32::from_str_radix(hex, 16).ok().unwrap_or(0);
-            0xFF000000 | rgb
-        }
-        8 => u32::from_str_radix(hex, 16).ok().unwrap_or(0xFF000000),
-        _ => 0xFF000000,
+        3 => Some(0xFF000000 | digit(0)? << 16 | digit(1)? << 8 | digit(2)?),
+        6 => Some(0xFF000000 | u32::from_str_radix(hex, 16).ok()?),
+        // #RRGGBBAA, with alpha last
+        8 => u32::from_str_radix(hex, 16).ok().map(|rgba| rgba.rotate_right(8)),
+        _ => None,
     }
 }
 
@@ -409,3 +432,18 @@ pub fn named(str: &str) -> Option<u32> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_colors() {
+        assert_eq!(try_parse_color("#f80"), Some(0xFFFF8800));
+        assert_eq!(try_parse_color("#FF8000"), Some(0xFFFF8000));
+        // Alpha comes last in CSS
+        assert_eq!(try_parse_color("#ff800040"), Some(0x40FF8000));
+        assert_eq!(try_parse_color("#ff80004"), None);
+        assert_eq!(try_parse_color("#gg8000"), None);
+    }
+}
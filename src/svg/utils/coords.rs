@@ -1,53 +1,241 @@
 use crate::svg::parser::tags::Tag;
+use crate::svg::utils::style::get_property;
+use crate::utils::compat::FloatExt;
+
+/// User units per inch, as CSS defines for `px`.
+pub const DEFAULT_DPI: f32 = 96.0;
+/// Font size of the root element when the document sets none.
+pub const DEFAULT_FONT_SIZE: f32 = 16.0;
 
 #[inline]
-pub fn get_width(tag: &mut Tag) -> usize {
-    let w = tag.params.get("width");
+pub fn get_width(tag: &Tag, lengths: &LengthContext) -> f32 {
+    lengths.attribute(tag, "width", Axis::Horizontal).unwrap_or(0.0)
+}
 
-    if w.is_none() {
-        0
-    } else {
-        w.unwrap().parse::<usize>().unwrap()
-    }
+#[inline]
+pub fn get_height(tag: &Tag, lengths: &LengthContext) -> f32 {
+    lengths.attribute(tag, "height", Axis::Vertical).unwrap_or(0.0)
 }
 
 #[inline]
-pub fn get_height(tag: &mut Tag) -> usize {
-    let h = tag.params.get("height");
+pub fn get_x(tag: &Tag, lengths: &LengthContext) -> f32 {
+    lengths.attribute(tag, "x", Axis::Horizontal).unwrap_or(0.0)
+}
 
-    if h.is_none() {
-        0
-    } else {
-        h.unwrap().parse::<usize>().unwrap()
+#[inline]
+pub fn get_y(tag: &Tag, lengths: &LengthContext) -> f32 {
+    lengths.attribute(tag, "y", Axis::Vertical).unwrap_or(0.0)
+}
+
+/// Parses a length or percentage with the default context, percentages
+/// taken of `reference`; `default` when `s` is not a length.
+pub fn parse_length(s: &str, default: f32, reference: f32) -> f32 {
+    let Some(length) = Length::parse(s) else {
+        return default;
+    };
+    match length.unit {
+        Unit::Percent => length.number / 100.0 * reference,
+        _ => LengthContext::default().resolve(length, Axis::Horizontal),
     }
 }
 
-#[inline]
-pub fn get_x(tag: &mut Tag) -> usize {
-    let x = tag.params.get("x");
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    /// A bare number, in user units.
+    None,
+    Px,
+    Pt,
+    Pc,
+    Mm,
+    Cm,
+    In,
+    /// Quarter-millimetres.
+    Q,
+    Em,
+    Ex,
+    Ch,
+    Rem,
+    Vw,
+    Vh,
+    Vmin,
+    Vmax,
+    Percent,
+}
 
-    if x.is_none() {
-        0
-    } else {
-        x.unwrap().parse::<usize>().unwrap()
+const UNITS: [(&str, Unit); 16] = [
+    ("px", Unit::Px), ("pt", Unit::Pt), ("pc", Unit::Pc), ("mm", Unit::Mm),
+    ("cm", Unit::Cm), ("in", Unit::In), ("q", Unit::Q), ("em", Unit::Em),
+    ("ex", Unit::Ex), ("ch", Unit::Ch), ("rem", Unit::Rem), ("vw", Unit::Vw),
+    ("vh", Unit::Vh), ("vmin", Unit::Vmin), ("vmax", Unit::Vmax), ("%", Unit::Percent),
+];
+
+/// A number with a CSS unit, as written in an attribute or property.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Length {
+    pub number: f32,
+    pub unit: Unit,
+}
+
+impl Length {
+    pub const fn new(number: f32, unit: Unit) -> Self {
+        Self { number, unit }
+    }
+
+    /// Parses a length such as `12`, `1.5em` or `50%`, ignoring surrounding
+    /// white space. Units are case-insensitive.
+    pub fn parse(s: &str) -> Option<Length> {
+        let s = s.trim();
+        let split = number_end(s);
+        let number = s[..split].parse::<f32>().ok().filter(|n| n.is_finite())?;
+        let suffix = &s[split..];
+
+        let unit = if suffix.is_empty() {
+            Unit::None
+        } else {
+            UNITS.iter().find(|(name, _)| name.eq_ignore_ascii_case(suffix))?.1
+        };
+        Some(Length { number, unit })
     }
 }
 
-#[inline]
-pub fn get_y(tag: &mut Tag) -> usize {
-    let y = tag.params.get("y");
+/// Byte length of the number at the start of `s`. An `e` only starts an
+/// exponent when digits follow, so `1em` is a number and a unit.
+pub(crate) fn number_end(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let digits = |mut i: usize| {
+        while i < bytes.len() && bytes[i].is_ascii_digit() {
+            i += 1;
+        }
+        i
+    };
 
-    if y.is_none() {
-        0
-    } else {
-        y.unwrap().parse::<usize>().unwrap()
+    let mut end = if matches!(bytes.first(), Some(b'+' | b'-')) { 1 } else { 0 };
+    end = digits(end);
+    if bytes.get(end) == Some(&b'.') {
+        end = digits(end + 1);
+    }
+    if matches!(bytes.get(end), Some(b'e' | b'E')) {
+        let sign = usize::from(matches!(bytes.get(end + 1), Some(b'+' | b'-')));
+        if bytes.get(end + 1 + sign).is_some_and(u8::is_ascii_digit) {
+            end = digits(end + 1 + sign);
+        }
     }
+    end
 }
 
-pub fn parse_length(s: &str, default: f32, reference: f32) -> f32 {
-    if s.trim().ends_with('%') {
-        s.trim_end_matches('%').parse::<f32>().unwrap_or(default * 100.0) / 100.0 * reference
-    } else {
-        s.parse::<f32>().unwrap_or(default)
+/// Which viewport dimension a percentage refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    Horizontal,
+    Vertical,
+    /// The normalized diagonal, `sqrt((width² + height²) / 2)`, used by
+    /// lengths with no direction such as `r` or `stroke-width`.
+    Diagonal,
+}
+
+/// What lengths resolve against: the nearest viewport for percentages, the
+/// element's font size for `em` and `ex`, and the root for `rem` and
+/// viewport units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LengthContext {
+    /// User units per inch.
+    pub dpi: f32,
+    pub font_size: f32,
+    pub root_font_size: f32,
+    /// Size of the nearest viewport in user units.
+    pub viewport: (f32, f32),
+    /// Size of the outermost viewport, which `vw` and `vh` refer to.
+    pub root_viewport: (f32, f32),
+}
+
+impl Default for LengthContext {
+    fn default() -> Self {
+        Self::new(100.0, 100.0)
+    }
+}
+
+impl LengthContext {
+    /// A context for a root viewport of `width` x `height` user units.
+    pub fn new(width: f32, height: f32) -> Self {
+        Self {
+            dpi: DEFAULT_DPI,
+            font_size: DEFAULT_FONT_SIZE,
+            root_font_size: DEFAULT_FONT_SIZE,
+            viewport: (width, height),
+            root_viewport: (width, height),
+        }
+    }
+
+    /// The same context inside a nested viewport.
+    pub fn with_viewport(&self, width: f32, height: f32) -> Self {
+        Self { viewport: (width, height), ..*self }
+    }
+
+    pub fn with_font_size(&self, font_size: f32) -> Self {
+        Self { font_size, ..*self }
+    }
+
+    /// The context for the attributes of `tag`: its `font-size`, if any,
+    /// resolved against this context as its parent's.
+    pub fn for_element(&self, tag: &Tag) -> Self {
+        match get_property(tag, "font-size").and_then(|s| self.font_size_of(s)) {
+            Some(font_size) => self.with_font_size(font_size),
+            None => *self,
+        }
+    }
+
+    /// `length` in user units, percentages taken along `axis` of the viewport.
+    pub fn resolve(&self, length: Length, axis: Axis) -> f32 {
+        let (vw, vh) = self.root_viewport;
+        let scale = match length.unit {
+            Unit::None | Unit::Px => 1.0,
+            Unit::Pt => self.dpi / 72.0,
+            Unit::Pc => self.dpi / 6.0,
+            Unit::Mm => self.dpi / 25.4,
+            Unit::Cm => self.dpi / 2.54,
+            Unit::In => self.dpi,
+            Unit::Q => self.dpi / 101.6,
+            Unit::Em => self.font_size,
+            // Without font metrics at hand, both are taken as half an em
+            Unit::Ex | Unit::Ch => self.font_size / 2.0,
+            Unit::Rem => self.root_font_size,
+            Unit::Vw => vw / 100.0,
+            Unit::Vh => vh / 100.0,
+            Unit::Vmin => vw.min(vh) / 100.0,
+            Unit::Vmax => vw.max(vh) / 100.0,
+            Unit::Percent => self.reference(axis) / 100.0,
+        };
+        length.number * scale
+    }
+
+    /// Parses and resolves a length; `None` when `s` is not one.
+    pub fn length(&self, s: &str, axis: Axis) -> Option<f32> {
+        Length::parse(s).map(|length| self.resolve(length, axis))
+    }
+
+    /// An attribute of `tag` as a length in user units.
+    pub fn attribute(&self, tag: &Tag, key: &str, axis: Axis) -> Option<f32> {
+        tag.params.get(key).and_then(|s| self.length(s, axis))
+    }
+
+    /// Resolves a `font-size` value, where `em`, `ex` and percentages are
+    /// relative to this context's font size.
+    pub fn font_size_of(&self, s: &str) -> Option<f32> {
+        let length = Length::parse(s)?;
+        let size = match length.unit {
+            Unit::Percent => length.number / 100.0 * self.font_size,
+            _ => self.resolve(length, Axis::Vertical),
+        };
+        Some(size).filter(|size| *size >= 0.0)
+    }
+
+    /// The viewport size percentages along `axis` refer to.
+    pub fn reference(&self, axis: Axis) -> f32 {
+        let (width, height) = self.viewport;
+        match axis {
+            Axis::Horizontal => width,
+            Axis::Vertical => height,
+            Axis::Diagonal => ((width * width + height * height) / 2.0).sqrt(),
+        }
     }
 }
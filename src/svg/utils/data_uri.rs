@@ -0,0 +1,88 @@
+use crate::utils::compat::{String, Vec};
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Decodes the payload of a `data:` URI, either base64 or percent-encoded.
+/// The media type is ignored; callers sniff the bytes instead.
+pub fn parse_data_uri(uri: &str) -> Option<Vec<u8>> {
+    let rest = uri.trim().strip_prefix("data:")?;
+    let (header, payload) = rest.split_once(',')?;
+
+    if header.split(';').any(|param| param.trim().eq_ignore_ascii_case("base64")) {
+        decode_base64(payload)
+    } else {
+        Some(percent_decode(payload))
+    }
+}
+
+/// Wraps bytes in a base64 `data:` URI.
+pub fn encode_data_uri(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(13 + bytes.len().div_ceil(3) * 4);
+    out.push_str("data:;base64,");
+
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+/// Decodes base64 (standard or URL-safe alphabet), skipping whitespace and
+/// percent-encoded bytes that some generators leave in URIs.
+fn decode_base64(payload: &str) -> Option<Vec<u8>> {
+    let decoded = percent_decode(payload);
+    let mut out = Vec::with_capacity(decoded.len() / 4 * 3);
+    let mut acc = 0u32;
+    let mut bits = 0;
+
+    for &c in &decoded {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            c if c.is_ascii_whitespace() => continue,
+            _ => return None,
+        };
+        acc = acc << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+
+    Some(out)
+}
+
+fn percent_decode(payload: &str) -> Vec<u8> {
+    let bytes = payload.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = |b: u8| (b as char).to_digit(16);
+        if bytes[i] == b'%'
+            && let Some(hi) = bytes.get(i + 1).and_then(|b| hex(*b))
+            && let Some(lo) = bytes.get(i + 2).and_then(|b| hex(*b))
+        {
+            out.push((hi * 16 + lo) as u8);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    out
+}
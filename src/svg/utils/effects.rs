@@ -1,8 +1,8 @@
 use crate::svg::parser::tags::Tag;
+use crate::svg::utils::style::get_property;
+use crate::svg::utils::coords::{Axis, LengthContext};
 
 #[inline]
-pub fn get_stroke_width(tag: &mut Tag) -> f32 {
-    let w = tag.params.get("stroke-width");
-
-    if w.is_none() { 0.0 } else { w.unwrap().parse::<f32>().unwrap_or(0.0) }
-}
\ No newline at end of file
+pub fn get_stroke_width(tag: &Tag, lengths: &LengthContext) -> f32 {
+    get_property(tag, "stroke-width").and_then(|s| lengths.length(s, Axis::Diagonal)).unwrap_or(0.0)
+}
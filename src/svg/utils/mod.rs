@@ -1,11 +1,48 @@
 pub mod color;
 pub mod coords;
+pub mod data_uri;
 pub mod effects;
+pub mod style;
 pub mod transform;
 
 use crate::svg::parser::tags::Tag;
-use crate::utils::compat::String;
+use crate::utils::compat::{HashMap, String};
+
+/// Elements by id, as references resolve while rendering. The elements are
+/// borrowed from the document tree, so collecting them copies nothing.
+pub type Defs<'a> = HashMap<&'a str, &'a Tag>;
 
 pub fn get_id(tag: &Tag) -> Option<&String> {
     tag.params.get("id")
-}
\ No newline at end of file
+}
+
+/// Collects every element of `root` that has an id; with duplicate ids the
+/// first in document order wins.
+pub fn collect_defs(root: &Tag) -> Defs<'_> {
+    let mut defs = Defs::new();
+    collect_recursive(&mut defs, root);
+    defs
+}
+
+fn collect_recursive<'a>(defs: &mut Defs<'a>, tag: &'a Tag) {
+    if let Some(id) = get_id(tag) {
+        defs.entry(id.as_str()).or_insert(tag);
+    }
+    for child in &tag.children {
+        collect_recursive(defs, child);
+    }
+}
+#[cfg(test)]
+mod tests {
+    use crate::svg::testing::render;
+
+    #[test]
+    fn test_duplicate_ids_resolve_to_the_first() {
+        let pixels = render(r##"<svg width="2" height="1">
+            <linearGradient id="p"><stop stop-color="red"/></linearGradient>
+            <linearGradient id="p"><stop stop-color="blue"/></linearGradient>
+            <rect width="2" height="1" fill="url(#p)"/>
+        </svg>"##, 2, 1);
+        assert_eq!(pixels, [0xFFFF0000; 2]);
+    }
+}
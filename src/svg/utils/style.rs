@@ -0,0 +1,12 @@
+use crate::svg::parser::tags::Tag;
+
+/// Looks up a CSS property, with a declaration in the element's `style`
+/// attribute taking precedence over the presentation attribute.
+pub fn get_property<'a>(tag: &'a Tag, name: &str) -> Option<&'a str> {
+    let declared = tag.params.get("style").and_then(|style| {
+        style.split(';').filter_map(|declaration| declaration.split_once(':')).find_map(|(key, value)| {
+            (key.trim() == name).then(|| value.trim().trim_end_matches("!important").trim())
+        })
+    });
+    declared.or_else(|| tag.params.get(name).map(|s| s.trim()))
+}
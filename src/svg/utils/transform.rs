@@ -209,6 +209,42 @@ pub fn parse_transform(tag: &Tag) -> Transform {
     }
 }
 
+/// Parses a `viewBox` attribute (`min-x min-y width height`). Boxes without
+/// a positive size disable rendering and yield `None`.
+pub fn parse_view_box(s: &str) -> Option<[f32; 4]> {
+    let parts = parse_args(s);
+    match parts[..] {
+        [x, y, w, h] if w > 0.0 && h > 0.0 => Some([x, y, w, h]),
+        _ => None,
+    }
+}
+
+/// Maps `view_box` onto a `width` x `height` viewport at the origin, following
+/// `preserveAspectRatio` (`xMidYMid meet` when absent).
+pub fn view_box_transform(view_box: [f32; 4], width: f32, height: f32, preserve_aspect_ratio: Option<&str>) -> Transform {
+    let [vb_x, vb_y, vb_w, vb_h] = view_box;
+    let mut sx = width / vb_w;
+    let mut sy = height / vb_h;
+
+    let mut parts = preserve_aspect_ratio.unwrap_or("").split_whitespace();
+    let align = parts.next().unwrap_or("xMidYMid");
+    let slice = parts.next() == Some("slice");
+
+    if align != "none" {
+        let scale = if slice { sx.max(sy) } else { sx.min(sy) };
+        sx = scale;
+        sy = scale;
+    }
+
+    let fraction = |min: &str, mid: &str| {
+        if align.contains(min) { 0.0 } else if align.contains(mid) || align == "none" { 0.5 } else { 1.0 }
+    };
+    let tx = (width - vb_w * sx) * fraction("xMin", "xMid") - vb_x * sx;
+    let ty = (height - vb_h * sy) * fraction("YMin", "YMid") - vb_y * sy;
+
+    Transform::translate(tx, ty).then(&Transform::scale(sx, sy))
+}
+
 fn parse_args(s: &str) -> Vec<f32> {
     let mut args = Vec::new();
     let mut current = String::new();
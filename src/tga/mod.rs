@@ -4,6 +4,12 @@ use crate::tga::header::{TgaHeader, ImageType};
 use crate::utils::image::resize_image;
 use crate::utils::compat::{Vec, String, ToString, vec, format};
 
+/// Reads the native size of a TGA from its header.
+pub fn dimensions(data: &[u8]) -> Option<(usize, usize)> {
+    let header = TgaHeader::parse(data).ok()?;
+    Some((header.width as usize, header.height as usize))
+}
+
 pub fn render(data: &[u8], width: usize, height: usize) -> Result<Vec<u32>, String> {
     let header = TgaHeader::parse(data)?;
 
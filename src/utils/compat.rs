@@ -22,6 +22,11 @@ pub use ::std::string::{String, ToString};
 #[cfg(not(feature = "std"))]
 pub use ::alloc::string::{String, ToString};
 
+#[cfg(feature = "std")]
+pub use ::std::boxed::Box;
+#[cfg(not(feature = "std"))]
+pub use ::alloc::boxed::Box;
+
 #[cfg(feature = "std")]
 pub use ::std::format;
 #[cfg(not(feature = "std"))]
@@ -39,6 +44,9 @@ pub trait FloatExt {
     fn ceil(self) -> Self;
     fn abs(self) -> Self;
     fn powi(self, n: i32) -> Self;
+    fn powf(self, n: Self) -> Self;
+    fn exp(self) -> Self;
+    fn ln(self) -> Self;
     fn atan2(self, other: Self) -> Self;
     fn acos(self) -> Self;
     fn max(self, other: Self) -> Self;
@@ -109,6 +117,27 @@ impl FloatExt for f32 {
         return math::powi(self, n);
     }
 
+    fn powf(self, n: Self) -> Self {
+        #[cfg(feature = "std")]
+        return self.powf(n);
+        #[cfg(not(feature = "std"))]
+        return math::powf(self, n);
+    }
+
+    fn exp(self) -> Self {
+        #[cfg(feature = "std")]
+        return self.exp();
+        #[cfg(not(feature = "std"))]
+        return math::exp(self);
+    }
+
+    fn ln(self) -> Self {
+        #[cfg(feature = "std")]
+        return self.ln();
+        #[cfg(not(feature = "std"))]
+        return math::ln(self);
+    }
+
     fn atan2(self, other: Self) -> Self {
         #[cfg(feature = "std")]
         return self.atan2(other);
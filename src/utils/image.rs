@@ -36,6 +36,32 @@ pub fn resize_image(original: &[u32], src_w: usize, src_h: usize, dst_w: usize,
     resized
 }
 
+/// Bilinear sample at pixel-space position (`x`, `y`), clamping at the edges.
+/// Pixel centres sit at half-integer coordinates.
+pub fn sample_bilinear(pixels: &[u32], w: usize, h: usize, x: f32, y: f32) -> u32 {
+    let gx = x - 0.5;
+    let gy = y - 0.5;
+    let gxi = gx.floor() as isize;
+    let gyi = gy.floor() as isize;
+
+    let a = lerp_color(
+        get_pixel_safe(pixels, w, h, gxi, gyi),
+        get_pixel_safe(pixels, w, h, gxi + 1, gyi),
+        gx - gxi as f32,
+    );
+    let b = lerp_color(
+        get_pixel_safe(pixels, w, h, gxi, gyi + 1),
+        get_pixel_safe(pixels, w, h, gxi + 1, gyi + 1),
+        gx - gxi as f32,
+    );
+    lerp_color(a, b, gy - gyi as f32)
+}
+
+/// Nearest-neighbour sample at pixel-space position (`x`, `y`).
+pub fn sample_nearest(pixels: &[u32], w: usize, h: usize, x: f32, y: f32) -> u32 {
+    get_pixel_safe(pixels, w, h, x.floor() as isize, y.floor() as isize)
+}
+
 #[inline]
 fn get_pixel_safe(pixels: &[u32], w: usize, h: usize, x: isize, y: isize) -> u32 {
     let x = x.clamp(0, w as isize - 1) as usize;
@@ -1,9 +1,11 @@
-#[cfg(not(feature = "std"))]
+#[cfg(any(not(feature = "std"), test))]
 pub fn sqrt(x: f32) -> f32 {
-    if x < 0.0 { return f32::NAN; }
-    if x == 0.0 { return 0.0; }
-    let mut z = x;
-    for _ in 0..10 {
+    if x < 0.0 || x.is_nan() { return f32::NAN; }
+    if x == 0.0 || x.is_infinite() { return x; }
+    // Halving the exponent gives a guess within a few percent, which
+    // Newton's method then doubles the correct digits of each step
+    let mut z = f32::from_bits((x.to_bits() >> 1) + 0x1FC0_0000);
+    for _ in 0..4 {
         z = 0.5 * (z + x / z);
     }
     z
@@ -88,23 +90,158 @@ pub fn powi(base: f32, exp: i32) -> f32 {
     res
 }
 
-#[cfg(not(feature = "std"))]
+#[cfg(any(not(feature = "std"), test))]
 pub fn atan2(y: f32, x: f32) -> f32 {
-    // Very rough approximation
-    if x == 0.0 {
-        if y > 0.0 { return 1.5707963; }
-        if y < 0.0 { return -1.5707963; }
-        return 0.0;
+    use core::f32::consts::{FRAC_PI_2, FRAC_PI_6, PI};
+    if x.is_nan() || y.is_nan() { return f32::NAN; }
+    if x == 0.0 && y == 0.0 {
+        return if x.is_sign_negative() { PI.copysign(y) } else { y };
     }
-    let z = y / x;
-    let at = z / (1.0 + 0.28 * z * z);
-    if x < 0.0 && y >= 0.0 { return at + 3.14159265; }
-    if x < 0.0 && y < 0.0 { return at - 3.14159265; }
-    at
+
+    // Reduce to the first octant, then to within tan(PI/12) of zero so a
+    // short series is accurate
+    let (ax, ay) = (x.abs(), y.abs());
+    let ratio = if ay > ax { ax / ay } else { ay / ax };
+    let (t, base) = if ratio > 0.267_949_2 {
+        let sqrt3 = 1.732_050_8;
+        ((ratio * sqrt3 - 1.0) / (ratio + sqrt3), FRAC_PI_6)
+    } else {
+        (ratio, 0.0)
+    };
+    let t2 = t * t;
+    let mut angle = base + t * (1.0 - t2 * (1.0 / 3.0 - t2 * (1.0 / 5.0 - t2 * (1.0 / 7.0 - t2 / 9.0))));
+
+    if ay > ax { angle = FRAC_PI_2 - angle; }
+    if x < 0.0 { angle = PI - angle; }
+    if y < 0.0 { -angle } else { angle }
 }
 
-#[cfg(not(feature = "std"))]
+#[cfg(any(not(feature = "std"), test))]
 pub fn acos(x: f32) -> f32 {
-    // Approximation: acos(x) = pi/2 - asin(x) ~= pi/2 - x
-    1.5707963 - x 
+    if !(-1.0..=1.0).contains(&x) { return f32::NAN; }
+    // The factored form keeps precision near +-1
+    atan2(sqrt((1.0 - x) * (1.0 + x)), x)
+}
+
+#[cfg(not(feature = "std"))]
+pub fn ln(x: f32) -> f32 {
+    if x < 0.0 || x.is_nan() { return f32::NAN; }
+    if x == 0.0 { return f32::NEG_INFINITY; }
+    if x.is_infinite() { return x; }
+
+    // x = m * 2^e with m in [sqrt(2)/2, sqrt(2))
+    let bits = x.to_bits();
+    let mut e = ((bits >> 23) & 0xFF) as i32 - 127;
+    let mut m = f32::from_bits((bits & 0x007F_FFFF) | 0x3F80_0000);
+    if m > 1.4142135 {
+        m *= 0.5;
+        e += 1;
+    }
+
+    // ln(m) = 2 atanh((m - 1) / (m + 1))
+    let s = (m - 1.0) / (m + 1.0);
+    let s2 = s * s;
+    let series = 1.0 + s2 * (1.0 / 3.0 + s2 * (1.0 / 5.0 + s2 * (1.0 / 7.0 + s2 / 9.0)));
+    e as f32 * core::f32::consts::LN_2 + 2.0 * s * series
+}
+
+#[cfg(not(feature = "std"))]
+pub fn exp(x: f32) -> f32 {
+    if x.is_nan() { return x; }
+    if x > 88.7 { return f32::INFINITY; }
+    if x < -103.0 { return 0.0; }
+
+    // e^x = 2^k * e^r with |r| <= ln(2) / 2
+    let k = round(x / core::f32::consts::LN_2);
+    let r = x - k * core::f32::consts::LN_2;
+    let er = 1.0 + r * (1.0 + r * (0.5 + r * (1.0 / 6.0 + r * (1.0 / 24.0 + r * (1.0 / 120.0 + r / 720.0)))));
+    er * powi(2.0, k as i32)
+}
+
+#[cfg(not(feature = "std"))]
+pub fn powf(base: f32, exp_: f32) -> f32 {
+    if exp_ == 0.0 { return 1.0; }
+    if base == 0.0 { return if exp_ > 0.0 { 0.0 } else { f32::INFINITY }; }
+    if base < 0.0 {
+        // Only integral exponents are defined for negative bases
+        return if floor(exp_) == exp_ { powi(base, exp_ as i32) } else { f32::NAN };
+    }
+    exp(exp_ * ln(base))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::f32::consts::{FRAC_PI_2, FRAC_PI_3, FRAC_PI_4, FRAC_PI_6, PI};
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() <= 1e-6, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn test_atan2_in_every_octant() {
+        let sqrt3 = 1.732_050_8;
+        for (y, x, angle) in [
+            (0.0, 1.0, 0.0),
+            (1.0, sqrt3, FRAC_PI_6),
+            (1.0, 1.0, FRAC_PI_4),
+            (sqrt3, 1.0, FRAC_PI_3),
+            (1.0, 0.0, FRAC_PI_2),
+            (1.0, -1.0, 3.0 * FRAC_PI_4),
+            (0.0, -1.0, PI),
+            (-1.0, -sqrt3, -5.0 * FRAC_PI_6),
+            (-1.0, 0.0, -FRAC_PI_2),
+            (-sqrt3, 1.0, -FRAC_PI_3),
+            (1e-30, 1e30, 1e-60),
+            (1e30, 1e-30, FRAC_PI_2),
+        ] {
+            assert_close(atan2(y, x), angle);
+            assert_close(atan2(y * 1e20, x * 1e20), angle);
+        }
+        assert_eq!(atan2(0.0, 0.0), 0.0);
+        assert!(atan2(f32::NAN, 1.0).is_nan());
+        assert_close(atan2(f32::INFINITY, 1.0), FRAC_PI_2);
+    }
+
+    #[test]
+    fn test_atan2_is_smooth() {
+        // Walks round the circle by rotating in f64, with steps small enough
+        // that their sine and cosine series are exact; the old rational
+        // approximation was off by 0.07 near 45 degrees
+        let step = core::f64::consts::PI / 1000.0;
+        let s2 = step * step;
+        let (sin_step, cos_step) = (step * (1.0 - s2 / 6.0 * (1.0 - s2 / 20.0)), 1.0 - s2 / 2.0 * (1.0 - s2 / 12.0));
+        let (mut y, mut x) = (-(step / 2.0), -1.0);
+        for i in 0..2000 {
+            let expected = (-core::f64::consts::PI + step * (i as f64 + 0.5)) as f32;
+            assert!((atan2(y as f32, x as f32) - expected).abs() <= 2e-6, "{}", expected);
+            (y, x) = (y * cos_step + x * sin_step, x * cos_step - y * sin_step);
+        }
+    }
+
+    #[test]
+    fn test_acos() {
+        for (x, angle) in [
+            (1.0, 0.0),
+            (0.866_025_4, FRAC_PI_6),
+            (0.5, FRAC_PI_3),
+            (0.0, FRAC_PI_2),
+            (-0.5, 2.0 * FRAC_PI_3),
+            (-1.0, PI),
+        ] {
+            assert_close(acos(x), angle);
+        }
+        assert!(acos(1.5).is_nan());
+        assert!(acos(f32::NAN).is_nan());
+    }
+
+    #[test]
+    fn test_sqrt() {
+        for x in [1e-30, 2e-6, 0.5, 2.0, 1e6, 3e30] {
+            let root = sqrt(x);
+            assert!((root * root - x).abs() <= x * 1e-6, "{}", x);
+        }
+        assert_eq!(sqrt(0.0), 0.0);
+        assert!(sqrt(-1.0).is_nan());
+    }
 }